@@ -13,6 +13,16 @@ use crate::{
     STACK_REGISTER,
 };
 
+// the cpu state which is enough to resume the execution (used by save states)
+#[derive(Clone, Copy)]
+pub struct M68kState {
+    pub registers: [u32; 17], // D0-D7, A0-A7, USP
+    pub pc: u32,
+    pub sr: u16,
+    pub trap: Option<u32>,
+    pub cycles_counter: i32,
+}
+
 pub struct M68k<T: 'static + BusM68k> {
     pub(crate) register_set: RegisterSet,
     pub(crate) trap: Option<u32>,
@@ -57,6 +67,24 @@ where
         self.bus = Some(bus);
     }
 
+    pub fn state(&self) -> M68kState {
+        M68kState {
+            registers: self.register_set.registers(),
+            pc: self.register_set.pc,
+            sr: self.register_set.sr.get_sr(),
+            trap: self.trap,
+            cycles_counter: self.cycles_counter,
+        }
+    }
+
+    pub fn set_state(&mut self, state: M68kState) {
+        self.register_set.set_registers(state.registers);
+        self.register_set.pc = state.pc;
+        self.register_set.sr.set_sr(state.sr as u32);
+        self.trap = state.trap;
+        self.cycles_counter = state.cycles_counter;
+    }
+
     pub fn reset(&mut self) {
         let stack_pointer = self.read_header(RESET_SP);
         let stack_register = self
@@ -26,6 +26,14 @@ impl RegisterSet {
         }
     }
 
+    pub(crate) fn registers(&self) -> [u32; 17] {
+        self.registers
+    }
+
+    pub(crate) fn set_registers(&mut self, registers: [u32; 17]) {
+        self.registers = registers;
+    }
+
     #[inline]
     pub(crate) fn get_and_increment_pc(&mut self) -> u32 {
         let v = self.pc;
@@ -0,0 +1,5 @@
+// the external bus of the cpu, the addresses are passed without the cache area bits
+pub trait BusSh2 {
+    fn read(&self, address: u32, amount: usize) -> Result<u32, ()>;
+    fn write(&mut self, data: u32, address: u32, amount: usize) -> Result<(), ()>;
+}
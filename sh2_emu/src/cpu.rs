@@ -0,0 +1,301 @@
+use std::{cell::RefCell, rc::Rc};
+
+use log::{debug, warn};
+
+use crate::{
+    bus::BusSh2,
+    instruction_set::{decode, Handler},
+    onchip::{OnChip, AR, CACHE_SIZE, CHANNEL_STRIDE, CHCR0, DAR0, DE, DMAOR, DME, ONCHIP_BASE, SAR0, TCR0, TE},
+    SR_MASK,
+};
+
+const RESET_PC: u32 = 0x00;
+const RESET_SP: u32 = 0x04;
+pub(crate) const ILLEGAL_INSTRUCTION: u32 = 4;
+// the external interrupts take the auto vectors 64-71, a vector per two levels
+const AUTO_VECTORS: u8 = 64;
+
+// the cpu state which is enough to resume the execution (used by save states)
+#[derive(Clone)]
+pub struct Sh2State {
+    pub registers: [u32; 16],
+    pub pc: u32,
+    pub sr: u32,
+    pub gbr: u32,
+    pub vbr: u32,
+    pub mach: u32,
+    pub macl: u32,
+    pub pr: u32,
+    pub delay_slot: Option<u32>,
+    pub sleeping: bool,
+    pub onchip_registers: Vec<u8>,
+    pub cache: Vec<u8>,
+    pub frt_clocks: u32,
+}
+
+pub struct Sh2<T: 'static + BusSh2> {
+    pub(crate) r: [u32; 16],
+    pub(crate) sr: u32,
+    pub(crate) gbr: u32,
+    pub(crate) vbr: u32,
+    pub(crate) mach: u32,
+    pub(crate) macl: u32,
+    pub(crate) pr: u32,
+    pub(crate) pc: u32, // the address of the executed instruction
+    pub(crate) next_pc: u32,
+    pub(crate) delay_slot: Option<u32>, // the branch target, it is taken after the next instruction
+    pub(crate) sleeping: bool,
+    pub(crate) onchip: OnChip,
+
+    bus: Option<Rc<RefCell<T>>>,
+    handlers: Vec<Handler<T>>,
+}
+
+impl<T> Default for Sh2<T>
+where
+    T: BusSh2,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Sh2<T>
+where
+    T: BusSh2,
+{
+    pub fn new() -> Self {
+        Self {
+            r: [0; 16],
+            sr: 0x00F0,
+            gbr: 0,
+            vbr: 0,
+            mach: 0,
+            macl: 0,
+            pr: 0,
+            pc: 0,
+            next_pc: 0,
+            delay_slot: None,
+            sleeping: false,
+            onchip: OnChip::new(),
+
+            bus: None,
+            handlers: (0..=0xFFFF).map(decode).collect(),
+        }
+    }
+
+    pub fn set_bus(&mut self, bus: Rc<RefCell<T>>) {
+        self.bus = Some(bus);
+    }
+
+    pub fn state(&self) -> Sh2State {
+        Sh2State {
+            registers: self.r,
+            pc: self.pc,
+            sr: self.sr,
+            gbr: self.gbr,
+            vbr: self.vbr,
+            mach: self.mach,
+            macl: self.macl,
+            pr: self.pr,
+            delay_slot: self.delay_slot,
+            sleeping: self.sleeping,
+            onchip_registers: self.onchip.registers.clone(),
+            cache: self.onchip.cache.clone(),
+            frt_clocks: self.onchip.frt_clocks,
+        }
+    }
+
+    pub fn set_state(&mut self, state: Sh2State) {
+        self.r = state.registers;
+        self.pc = state.pc;
+        self.sr = state.sr & SR_MASK;
+        self.gbr = state.gbr;
+        self.vbr = state.vbr;
+        self.mach = state.mach;
+        self.macl = state.macl;
+        self.pr = state.pr;
+        self.delay_slot = state.delay_slot;
+        self.sleeping = state.sleeping;
+        self.onchip.registers = state.onchip_registers;
+        self.onchip.cache = state.cache;
+        self.onchip.frt_clocks = state.frt_clocks;
+    }
+
+    // the power on reset, the pc and the stack pointer are read from the vectors at 0
+    pub fn reset(&mut self) {
+        self.onchip = OnChip::new();
+        self.vbr = 0;
+        self.sr = 0x00F0;
+        self.delay_slot = None;
+        self.sleeping = false;
+        self.pc = self.read(RESET_PC, 4);
+        self.r[15] = self.read(RESET_SP, 4);
+        debug!("SH2: reset to {:08X}", self.pc);
+    }
+
+    // executes a single instruction and returns the amount of spent cycles
+    pub fn clock(&mut self) -> i32 {
+        if let Some((level, vector)) = self.onchip.pending_interrupt() {
+            if self.delay_slot.is_none() && level as u32 > self.interrupt_mask() {
+                self.accept_interrupt(level, vector);
+            }
+        }
+        if self.sleeping {
+            self.onchip.clock(1);
+            return 1;
+        }
+        let branch = self.delay_slot.take();
+        let opcode = self.read(self.pc, 2) as u16;
+        self.next_pc = self.pc.wrapping_add(2);
+        let handler = self.handlers[opcode as usize];
+        let cycles = handler(self, opcode);
+        self.pc = branch.unwrap_or(self.next_pc);
+        self.onchip.clock(cycles as u32);
+        cycles
+    }
+
+    // the external interrupt, returns false if it is masked and has to be kept by the caller
+    pub fn interrupt(&mut self, level: u8) -> bool {
+        if self.delay_slot.is_some() || level as u32 <= self.interrupt_mask() {
+            return false;
+        }
+        self.accept_interrupt(level, AUTO_VECTORS + level / 2);
+        true
+    }
+
+    fn interrupt_mask(&self) -> u32 {
+        (self.sr >> 4) & 0x0F
+    }
+
+    fn accept_interrupt(&mut self, level: u8, vector: u8) {
+        debug!("SH2: interrupt level {} vector {}", level, vector);
+        self.pc = self.enter_exception(vector as u32, self.pc);
+        self.sr = self.sr & !0x00F0 | (level as u32) << 4;
+        self.sleeping = false;
+    }
+
+    // saves the status and the return address, returns the handler address
+    pub(crate) fn enter_exception(&mut self, vector: u32, return_address: u32) -> u32 {
+        self.push(self.sr);
+        self.push(return_address);
+        self.read(self.vbr.wrapping_add(vector * 4), 4)
+    }
+
+    pub(crate) fn push(&mut self, data: u32) {
+        self.r[15] = self.r[15].wrapping_sub(4);
+        self.write(self.r[15], data, 4);
+    }
+
+    pub(crate) fn pop(&mut self) -> u32 {
+        let data = self.read(self.r[15], 4);
+        self.r[15] = self.r[15].wrapping_add(4);
+        data
+    }
+
+    // the cached and the cache through areas are the same memory, the cache is not emulated
+    pub(crate) fn read(&mut self, address: u32, amount: usize) -> u32 {
+        match address >> 29 {
+            0 | 1 => {
+                let bus = self.bus.as_ref().unwrap().borrow();
+                bus.read(address & 0x1FFFFFFF, amount).unwrap_or_else(|_| {
+                    warn!("SH2: bus read error at {:08X}", address);
+                    0
+                })
+            }
+            // a misaligned access is forced to the access size, the address error is not emulated
+            6 => {
+                let offset = address as usize & (CACHE_SIZE - 1) & !(amount - 1);
+                self.onchip.cache[offset..offset + amount].iter().fold(0, |data, byte| data << 8 | *byte as u32)
+            }
+            7 if address >= ONCHIP_BASE => self.onchip.read(address, amount),
+            _ => {
+                debug!("SH2: read of the unmapped address {:08X}", address);
+                0
+            }
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u32, data: u32, amount: usize) {
+        match address >> 29 {
+            0 | 1 => {
+                let mut bus = self.bus.as_ref().unwrap().borrow_mut();
+                if bus.write(data, address & 0x1FFFFFFF, amount).is_err() {
+                    warn!("SH2: bus write error at {:08X}", address);
+                }
+            }
+            6 => {
+                let offset = address as usize & (CACHE_SIZE - 1) & !(amount - 1);
+                self.onchip.cache[offset..offset + amount].copy_from_slice(&data.to_be_bytes()[4 - amount..]);
+            }
+            7 if address >= ONCHIP_BASE => {
+                if self.onchip.write(address, data, amount) {
+                    self.start_dma();
+                }
+            }
+            // the cache purge and the address array writes
+            _ => debug!("SH2: write {:08X} to the unmapped address {:08X}", data, address),
+        }
+    }
+
+    fn dma_enabled(&self, channel: usize) -> bool {
+        let chcr = self.onchip.long(CHCR0 + channel * CHANNEL_STRIDE);
+        // the address error and the nmi flags stop all channels
+        chcr & (DE | TE) == DE && self.onchip.long(DMAOR) & 0x07 == DME
+    }
+
+    // the auto request channels do the whole transfer at once
+    fn start_dma(&mut self) {
+        for channel in 0..2 {
+            let chcr = self.onchip.long(CHCR0 + channel * CHANNEL_STRIDE);
+            if chcr & AR != 0 && self.dma_enabled(channel) {
+                debug!("SH2: dma channel {} auto request transfer", channel);
+                while self.dma_step(channel) {}
+            }
+        }
+    }
+
+    // the external request of the channel, returns false if the channel does not wait for it
+    pub fn dreq(&mut self, channel: usize) -> bool {
+        let chcr = self.onchip.long(CHCR0 + channel * CHANNEL_STRIDE);
+        if chcr & AR != 0 || !self.dma_enabled(channel) {
+            return false;
+        }
+        self.dma_step(channel);
+        true
+    }
+
+    // moves a single unit, returns false after the last one
+    fn dma_step(&mut self, channel: usize) -> bool {
+        let base = channel * CHANNEL_STRIDE;
+        let chcr = self.onchip.long(CHCR0 + base);
+        let size = match (chcr >> 10) & 0x03 {
+            0 => 1,
+            1 => 2,
+            2 => 4,
+            _ => 16, // four longs
+        };
+        let unit = size.min(4);
+        let (source_mode, destination_mode) = ((chcr >> 12) & 0x03, (chcr >> 14) & 0x03);
+        let source = self.onchip.long(SAR0 + base);
+        let destination = self.onchip.long(DAR0 + base);
+        for i in 0..(size / unit) as u32 {
+            let offset = |mode: u32| if mode == 0 { 0 } else { i * unit as u32 };
+            let data = self.read(source.wrapping_add(offset(source_mode)), unit);
+            self.write(destination.wrapping_add(offset(destination_mode)), data, unit);
+        }
+        let next = |mode: u32, address: u32| match mode {
+            1 => address.wrapping_add(size as u32),
+            2 => address.wrapping_sub(size as u32),
+            _ => address,
+        };
+        self.onchip.set_long(SAR0 + base, next(source_mode, source));
+        self.onchip.set_long(DAR0 + base, next(destination_mode, destination));
+        let count = self.onchip.long(TCR0 + base).wrapping_sub(1) & 0x00FFFFFF;
+        self.onchip.set_long(TCR0 + base, count);
+        if count == 0 {
+            self.onchip.set_long(CHCR0 + base, chcr | TE);
+        }
+        count != 0
+    }
+}
@@ -0,0 +1,985 @@
+use log::warn;
+
+use crate::{
+    bus::BusSh2,
+    cpu::{Sh2, ILLEGAL_INSTRUCTION},
+    sign_extend, M_BIT, Q_BIT, SR_MASK, S_BIT, T_BIT,
+};
+
+// executes the instruction and returns its cycles
+pub(crate) type Handler<T> = fn(&mut Sh2<T>, u16) -> i32;
+
+fn rn(opcode: u16) -> usize {
+    (opcode >> 8) as usize & 0x0F
+}
+
+fn rm(opcode: u16) -> usize {
+    (opcode >> 4) as usize & 0x0F
+}
+
+fn imm(opcode: u16) -> u32 {
+    opcode as u32 & 0xFF
+}
+
+fn disp8(opcode: u16) -> u32 {
+    opcode as u8 as i8 as i32 as u32
+}
+
+fn disp12(opcode: u16) -> u32 {
+    ((opcode << 4) as i16 >> 4) as i32 as u32
+}
+
+pub(crate) fn decode<T: BusSh2>(opcode: u32) -> Handler<T> {
+    let opcode = opcode as u16;
+    let low = opcode & 0x00FF;
+    match (opcode >> 12, opcode & 0x000F) {
+        (0x0, 0x2) => match low >> 4 {
+            0 => stc_sr,
+            1 => stc_gbr,
+            2 => stc_vbr,
+            _ => illegal,
+        },
+        (0x0, 0x3) => match low >> 4 {
+            0 => bsrf,
+            2 => braf,
+            _ => illegal,
+        },
+        (0x0, 0x4) => mov_store_r0::<T, 1>,
+        (0x0, 0x5) => mov_store_r0::<T, 2>,
+        (0x0, 0x6) => mov_store_r0::<T, 4>,
+        (0x0, 0x7) => mul_l,
+        (0x0, 0x8) => match opcode {
+            0x0008 => clrt,
+            0x0018 => sett,
+            0x0028 => clrmac,
+            _ => illegal,
+        },
+        (0x0, 0x9) => match low >> 4 {
+            0 if opcode == 0x0009 => nop,
+            1 if opcode == 0x0019 => div0u,
+            2 => movt,
+            _ => illegal,
+        },
+        (0x0, 0xA) => match low >> 4 {
+            0 => sts_mach,
+            1 => sts_macl,
+            2 => sts_pr,
+            _ => illegal,
+        },
+        (0x0, 0xB) => match opcode {
+            0x000B => rts,
+            0x001B => sleep,
+            0x002B => rte,
+            _ => illegal,
+        },
+        (0x0, 0xC) => mov_load_r0::<T, 1>,
+        (0x0, 0xD) => mov_load_r0::<T, 2>,
+        (0x0, 0xE) => mov_load_r0::<T, 4>,
+        (0x0, 0xF) => mac_l,
+        (0x1, _) => mov_l_store_disp,
+        (0x2, 0x0) => mov_store::<T, 1>,
+        (0x2, 0x1) => mov_store::<T, 2>,
+        (0x2, 0x2) => mov_store::<T, 4>,
+        (0x2, 0x4) => mov_store_predec::<T, 1>,
+        (0x2, 0x5) => mov_store_predec::<T, 2>,
+        (0x2, 0x6) => mov_store_predec::<T, 4>,
+        (0x2, 0x7) => div0s,
+        (0x2, 0x8) => tst,
+        (0x2, 0x9) => and,
+        (0x2, 0xA) => xor,
+        (0x2, 0xB) => or,
+        (0x2, 0xC) => cmp_str,
+        (0x2, 0xD) => xtrct,
+        (0x2, 0xE) => mulu_w,
+        (0x2, 0xF) => muls_w,
+        (0x3, 0x0) => cmp_eq,
+        (0x3, 0x2) => cmp_hs,
+        (0x3, 0x3) => cmp_ge,
+        (0x3, 0x4) => div1,
+        (0x3, 0x5) => dmulu_l,
+        (0x3, 0x6) => cmp_hi,
+        (0x3, 0x7) => cmp_gt,
+        (0x3, 0x8) => sub,
+        (0x3, 0xA) => subc,
+        (0x3, 0xB) => subv,
+        (0x3, 0xC) => add,
+        (0x3, 0xD) => dmuls_l,
+        (0x3, 0xE) => addc,
+        (0x3, 0xF) => addv,
+        (0x4, 0xF) => mac_w,
+        (0x4, _) => match low {
+            0x00 => shll,
+            0x01 => shlr,
+            0x02 => sts_l_mach,
+            0x03 => stc_l_sr,
+            0x04 => rotl,
+            0x05 => rotr,
+            0x06 => lds_l_mach,
+            0x07 => ldc_l_sr,
+            0x08 => shll_n::<T, 2>,
+            0x09 => shlr_n::<T, 2>,
+            0x0A => lds_mach,
+            0x0B => jsr,
+            0x0E => ldc_sr,
+            0x10 => dt,
+            0x11 => cmp_pz,
+            0x12 => sts_l_macl,
+            0x13 => stc_l_gbr,
+            0x15 => cmp_pl,
+            0x16 => lds_l_macl,
+            0x17 => ldc_l_gbr,
+            0x18 => shll_n::<T, 8>,
+            0x19 => shlr_n::<T, 8>,
+            0x1A => lds_macl,
+            0x1B => tas,
+            0x1E => ldc_gbr,
+            0x20 => shll,
+            0x21 => shar,
+            0x22 => sts_l_pr,
+            0x23 => stc_l_vbr,
+            0x24 => rotcl,
+            0x25 => rotcr,
+            0x26 => lds_l_pr,
+            0x27 => ldc_l_vbr,
+            0x28 => shll_n::<T, 16>,
+            0x29 => shlr_n::<T, 16>,
+            0x2A => lds_pr,
+            0x2B => jmp,
+            0x2E => ldc_vbr,
+            _ => illegal,
+        },
+        (0x5, _) => mov_l_load_disp,
+        (0x6, 0x0) => mov_load::<T, 1>,
+        (0x6, 0x1) => mov_load::<T, 2>,
+        (0x6, 0x2) => mov_load::<T, 4>,
+        (0x6, 0x3) => mov,
+        (0x6, 0x4) => mov_load_postinc::<T, 1>,
+        (0x6, 0x5) => mov_load_postinc::<T, 2>,
+        (0x6, 0x6) => mov_load_postinc::<T, 4>,
+        (0x6, 0x7) => not,
+        (0x6, 0x8) => swap_b,
+        (0x6, 0x9) => swap_w,
+        (0x6, 0xA) => negc,
+        (0x6, 0xB) => neg,
+        (0x6, 0xC) => extu::<T, 1>,
+        (0x6, 0xD) => extu::<T, 2>,
+        (0x6, 0xE) => exts::<T, 1>,
+        (0x6, 0xF) => exts::<T, 2>,
+        (0x7, _) => add_imm,
+        (0x8, _) => match (opcode >> 8) & 0x0F {
+            0x0 => mov_store_disp_r0::<T, 1>,
+            0x1 => mov_store_disp_r0::<T, 2>,
+            0x4 => mov_load_disp_r0::<T, 1>,
+            0x5 => mov_load_disp_r0::<T, 2>,
+            0x8 => cmp_eq_imm,
+            0x9 => bt,
+            0xB => bf,
+            0xD => bt_s,
+            0xF => bf_s,
+            _ => illegal,
+        },
+        (0x9, _) => mov_w_pc,
+        (0xA, _) => bra,
+        (0xB, _) => bsr,
+        (0xC, _) => match (opcode >> 8) & 0x0F {
+            0x0 => mov_store_gbr::<T, 1>,
+            0x1 => mov_store_gbr::<T, 2>,
+            0x2 => mov_store_gbr::<T, 4>,
+            0x3 => trapa,
+            0x4 => mov_load_gbr::<T, 1>,
+            0x5 => mov_load_gbr::<T, 2>,
+            0x6 => mov_load_gbr::<T, 4>,
+            0x7 => mova,
+            0x8 => tst_imm,
+            0x9 => and_imm,
+            0xA => xor_imm,
+            0xB => or_imm,
+            0xC => tst_b,
+            0xD => and_b,
+            0xE => xor_b,
+            _ => or_b,
+        },
+        (0xD, _) => mov_l_pc,
+        (0xE, _) => mov_imm,
+        _ => illegal,
+    }
+}
+
+impl<T: BusSh2> Sh2<T> {
+    fn t(&self) -> u32 {
+        self.sr & T_BIT
+    }
+
+    fn set_t(&mut self, t: bool) {
+        self.sr = self.sr & !T_BIT | t as u32;
+    }
+
+    fn set_flag(&mut self, flag: u32, value: bool) {
+        self.sr = if value { self.sr | flag } else { self.sr & !flag };
+    }
+
+    // the pc relative addressing counts from the second instruction after the current one
+    fn pc_base(&self) -> u32 {
+        self.pc.wrapping_add(4)
+    }
+
+    fn mac(&self) -> i64 {
+        ((self.mach as u64) << 32 | self.macl as u64) as i64
+    }
+
+    fn set_mac(&mut self, mac: i64) {
+        self.mach = (mac >> 32) as u32;
+        self.macl = mac as u32;
+    }
+}
+
+fn illegal<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    warn!("SH2: illegal instruction {:04X} at {:08X}", opcode, cpu.pc);
+    cpu.next_pc = cpu.enter_exception(ILLEGAL_INSTRUCTION, cpu.pc);
+    8
+}
+
+// the data transfer
+
+fn mov_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = disp8(opcode);
+    1
+}
+
+fn mov_w_pc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.pc_base().wrapping_add(imm(opcode) * 2);
+    cpu.r[rn(opcode)] = sign_extend(cpu.read(address, 2), 2);
+    1
+}
+
+fn mov_l_pc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = (cpu.pc_base() & !0x03).wrapping_add(imm(opcode) * 4);
+    cpu.r[rn(opcode)] = cpu.read(address, 4);
+    1
+}
+
+fn mov<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.r[rm(opcode)];
+    1
+}
+
+fn mov_store<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.write(cpu.r[rn(opcode)], cpu.r[rm(opcode)], SIZE);
+    1
+}
+
+fn mov_load<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = sign_extend(cpu.read(cpu.r[rm(opcode)], SIZE), SIZE);
+    1
+}
+
+fn mov_store_predec<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_sub(SIZE as u32);
+    cpu.write(cpu.r[n], cpu.r[rm(opcode)], SIZE);
+    1
+}
+
+fn mov_load_postinc<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let (n, m) = (rn(opcode), rm(opcode));
+    let data = sign_extend(cpu.read(cpu.r[m], SIZE), SIZE);
+    if n != m {
+        cpu.r[m] = cpu.r[m].wrapping_add(SIZE as u32);
+    }
+    cpu.r[n] = data;
+    1
+}
+
+fn mov_store_r0<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rn(opcode)].wrapping_add(cpu.r[0]);
+    cpu.write(address, cpu.r[rm(opcode)], SIZE);
+    1
+}
+
+fn mov_load_r0<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rm(opcode)].wrapping_add(cpu.r[0]);
+    cpu.r[rn(opcode)] = sign_extend(cpu.read(address, SIZE), SIZE);
+    1
+}
+
+fn mov_l_store_disp<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rn(opcode)].wrapping_add((opcode as u32 & 0x0F) * 4);
+    cpu.write(address, cpu.r[rm(opcode)], 4);
+    1
+}
+
+fn mov_l_load_disp<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rm(opcode)].wrapping_add((opcode as u32 & 0x0F) * 4);
+    cpu.r[rn(opcode)] = cpu.read(address, 4);
+    1
+}
+
+// the byte and the word displacement forms keep the base register in the m field
+fn mov_store_disp_r0<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rm(opcode)].wrapping_add((opcode as u32 & 0x0F) * SIZE as u32);
+    cpu.write(address, cpu.r[0], SIZE);
+    1
+}
+
+fn mov_load_disp_r0<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rm(opcode)].wrapping_add((opcode as u32 & 0x0F) * SIZE as u32);
+    cpu.r[0] = sign_extend(cpu.read(address, SIZE), SIZE);
+    1
+}
+
+fn mov_store_gbr<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.gbr.wrapping_add(imm(opcode) * SIZE as u32);
+    cpu.write(address, cpu.r[0], SIZE);
+    1
+}
+
+fn mov_load_gbr<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.gbr.wrapping_add(imm(opcode) * SIZE as u32);
+    cpu.r[0] = sign_extend(cpu.read(address, SIZE), SIZE);
+    1
+}
+
+fn mova<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[0] = (cpu.pc_base() & !0x03).wrapping_add(imm(opcode) * 4);
+    1
+}
+
+fn movt<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.t();
+    1
+}
+
+fn swap_b<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let data = cpu.r[rm(opcode)];
+    cpu.r[rn(opcode)] = data & 0xFFFF0000 | (data & 0xFF) << 8 | (data >> 8) & 0xFF;
+    1
+}
+
+fn swap_w<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.r[rm(opcode)].rotate_left(16);
+    1
+}
+
+fn xtrct<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[rm(opcode)] << 16 | cpu.r[n] >> 16;
+    1
+}
+
+// the arithmetic
+
+fn add<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_add(cpu.r[rm(opcode)]);
+    1
+}
+
+fn add_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_add(disp8(opcode));
+    1
+}
+
+fn addc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let (sum, carry_1) = cpu.r[n].overflowing_add(cpu.r[rm(opcode)]);
+    let (sum, carry_2) = sum.overflowing_add(cpu.t());
+    cpu.r[n] = sum;
+    cpu.set_t(carry_1 || carry_2);
+    1
+}
+
+fn addv<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let (sum, overflow) = (cpu.r[n] as i32).overflowing_add(cpu.r[rm(opcode)] as i32);
+    cpu.r[n] = sum as u32;
+    cpu.set_t(overflow);
+    1
+}
+
+fn sub<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_sub(cpu.r[rm(opcode)]);
+    1
+}
+
+fn subc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let (difference, borrow_1) = cpu.r[n].overflowing_sub(cpu.r[rm(opcode)]);
+    let (difference, borrow_2) = difference.overflowing_sub(cpu.t());
+    cpu.r[n] = difference;
+    cpu.set_t(borrow_1 || borrow_2);
+    1
+}
+
+fn subv<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let (difference, overflow) = (cpu.r[n] as i32).overflowing_sub(cpu.r[rm(opcode)] as i32);
+    cpu.r[n] = difference as u32;
+    cpu.set_t(overflow);
+    1
+}
+
+fn neg<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = 0u32.wrapping_sub(cpu.r[rm(opcode)]);
+    1
+}
+
+fn negc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let (difference, borrow_1) = 0u32.overflowing_sub(cpu.r[rm(opcode)]);
+    let (difference, borrow_2) = difference.overflowing_sub(cpu.t());
+    cpu.r[rn(opcode)] = difference;
+    cpu.set_t(borrow_1 || borrow_2);
+    1
+}
+
+fn cmp_eq<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] == cpu.r[rm(opcode)]);
+    1
+}
+
+fn cmp_eq_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[0] == disp8(opcode));
+    1
+}
+
+fn cmp_hs<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] >= cpu.r[rm(opcode)]);
+    1
+}
+
+fn cmp_ge<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] as i32 >= cpu.r[rm(opcode)] as i32);
+    1
+}
+
+fn cmp_hi<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] > cpu.r[rm(opcode)]);
+    1
+}
+
+fn cmp_gt<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] as i32 > cpu.r[rm(opcode)] as i32);
+    1
+}
+
+fn cmp_pz<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] as i32 >= 0);
+    1
+}
+
+fn cmp_pl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] as i32 > 0);
+    1
+}
+
+// true if any byte of the registers is equal
+fn cmp_str<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let difference = cpu.r[rn(opcode)] ^ cpu.r[rm(opcode)];
+    cpu.set_t(difference.to_be_bytes().contains(&0));
+    1
+}
+
+fn div0s<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let q = cpu.r[rn(opcode)] & 0x80000000 != 0;
+    let m = cpu.r[rm(opcode)] & 0x80000000 != 0;
+    cpu.set_flag(Q_BIT, q);
+    cpu.set_flag(M_BIT, m);
+    cpu.set_t(q != m);
+    1
+}
+
+fn div0u<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.sr &= !(Q_BIT | M_BIT | T_BIT);
+    1
+}
+
+// a single step of the non restoring division, the divisor is subtracted while Q equals M
+fn div1<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let old_q = cpu.sr & Q_BIT != 0;
+    let m = cpu.sr & M_BIT != 0;
+    let q = cpu.r[n] & 0x80000000 != 0;
+    let dividend = cpu.r[n] << 1 | cpu.t();
+    let (result, carry) = if old_q == m {
+        dividend.overflowing_sub(cpu.r[rm(opcode)])
+    } else {
+        dividend.overflowing_add(cpu.r[rm(opcode)])
+    };
+    cpu.r[n] = result;
+    let q = q ^ m ^ carry;
+    cpu.set_flag(Q_BIT, q);
+    cpu.set_t(q == m);
+    1
+}
+
+fn dmuls_l<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let product = cpu.r[rn(opcode)] as i32 as i64 * cpu.r[rm(opcode)] as i32 as i64;
+    cpu.set_mac(product);
+    2
+}
+
+fn dmulu_l<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let product = cpu.r[rn(opcode)] as u64 * cpu.r[rm(opcode)] as u64;
+    cpu.set_mac(product as i64);
+    2
+}
+
+fn dt<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_sub(1);
+    cpu.set_t(cpu.r[n] == 0);
+    1
+}
+
+fn exts<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = sign_extend(cpu.r[rm(opcode)], SIZE);
+    1
+}
+
+fn extu<T: BusSh2, const SIZE: usize>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let mask = if SIZE == 1 { 0xFF } else { 0xFFFF };
+    cpu.r[rn(opcode)] = cpu.r[rm(opcode)] & mask;
+    1
+}
+
+// the saturation limits the sum to 48 bits
+fn mac_l<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let (n, m) = (rn(opcode), rm(opcode));
+    let first = cpu.read(cpu.r[n], 4) as i32 as i64;
+    cpu.r[n] = cpu.r[n].wrapping_add(4);
+    let second = cpu.read(cpu.r[m], 4) as i32 as i64;
+    cpu.r[m] = cpu.r[m].wrapping_add(4);
+    let mut sum = cpu.mac().wrapping_add(first * second);
+    if cpu.sr & S_BIT != 0 {
+        sum = sum.clamp(-(1 << 47), (1 << 47) - 1);
+    }
+    cpu.set_mac(sum);
+    3
+}
+
+// the saturation limits the sum to 32 bits and marks the overflow in MACH
+fn mac_w<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let (n, m) = (rn(opcode), rm(opcode));
+    let first = cpu.read(cpu.r[n], 2) as i16 as i64;
+    cpu.r[n] = cpu.r[n].wrapping_add(2);
+    let second = cpu.read(cpu.r[m], 2) as i16 as i64;
+    cpu.r[m] = cpu.r[m].wrapping_add(2);
+    if cpu.sr & S_BIT != 0 {
+        let sum = cpu.macl as i32 as i64 + first * second;
+        let saturated = sum.clamp(i32::MIN as i64, i32::MAX as i64);
+        if saturated != sum {
+            cpu.mach |= 0x01;
+        }
+        cpu.macl = saturated as u32;
+    } else {
+        cpu.set_mac(cpu.mac().wrapping_add(first * second));
+    }
+    3
+}
+
+fn mul_l<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.macl = cpu.r[rn(opcode)].wrapping_mul(cpu.r[rm(opcode)]);
+    2
+}
+
+fn muls_w<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.macl = (cpu.r[rn(opcode)] as i16 as i32).wrapping_mul(cpu.r[rm(opcode)] as i16 as i32) as u32;
+    1
+}
+
+fn mulu_w<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.macl = (cpu.r[rn(opcode)] & 0xFFFF) * (cpu.r[rm(opcode)] & 0xFFFF);
+    1
+}
+
+// the logic
+
+fn and<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] &= cpu.r[rm(opcode)];
+    1
+}
+
+fn and_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[0] &= imm(opcode);
+    1
+}
+
+fn or<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] |= cpu.r[rm(opcode)];
+    1
+}
+
+fn or_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[0] |= imm(opcode);
+    1
+}
+
+fn xor<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] ^= cpu.r[rm(opcode)];
+    1
+}
+
+fn xor_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[0] ^= imm(opcode);
+    1
+}
+
+fn not<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = !cpu.r[rm(opcode)];
+    1
+}
+
+fn tst<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[rn(opcode)] & cpu.r[rm(opcode)] == 0);
+    1
+}
+
+fn tst_imm<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.set_t(cpu.r[0] & imm(opcode) == 0);
+    1
+}
+
+// the byte at @(R0,GBR) is changed by the read-modify-write cycle
+fn modify_gbr_byte<T: BusSh2>(cpu: &mut Sh2<T>, operation: impl Fn(u32) -> u32) {
+    let address = cpu.gbr.wrapping_add(cpu.r[0]);
+    let data = operation(cpu.read(address, 1));
+    cpu.write(address, data, 1);
+}
+
+fn and_b<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    modify_gbr_byte(cpu, |data| data & imm(opcode));
+    3
+}
+
+fn or_b<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    modify_gbr_byte(cpu, |data| data | imm(opcode));
+    3
+}
+
+fn xor_b<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    modify_gbr_byte(cpu, |data| data ^ imm(opcode));
+    3
+}
+
+fn tst_b<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let data = cpu.read(cpu.gbr.wrapping_add(cpu.r[0]), 1);
+    cpu.set_t(data & imm(opcode) == 0);
+    3
+}
+
+fn tas<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let address = cpu.r[rn(opcode)];
+    let data = cpu.read(address, 1);
+    cpu.set_t(data == 0);
+    cpu.write(address, data | 0x80, 1);
+    4
+}
+
+// the shifts and the rotations put the shifted out bit into T
+
+fn shll<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.set_t(cpu.r[n] & 0x80000000 != 0);
+    cpu.r[n] <<= 1;
+    1
+}
+
+fn shlr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.set_t(cpu.r[n] & 0x01 != 0);
+    cpu.r[n] >>= 1;
+    1
+}
+
+fn shar<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.set_t(cpu.r[n] & 0x01 != 0);
+    cpu.r[n] = (cpu.r[n] as i32 >> 1) as u32;
+    1
+}
+
+fn shll_n<T: BusSh2, const SHIFT: u32>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] <<= SHIFT;
+    1
+}
+
+fn shlr_n<T: BusSh2, const SHIFT: u32>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] >>= SHIFT;
+    1
+}
+
+fn rotl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.set_t(cpu.r[n] & 0x80000000 != 0);
+    cpu.r[n] = cpu.r[n].rotate_left(1);
+    1
+}
+
+fn rotr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    cpu.set_t(cpu.r[n] & 0x01 != 0);
+    cpu.r[n] = cpu.r[n].rotate_right(1);
+    1
+}
+
+fn rotcl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let t = cpu.t();
+    cpu.set_t(cpu.r[n] & 0x80000000 != 0);
+    cpu.r[n] = cpu.r[n] << 1 | t;
+    1
+}
+
+fn rotcr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    let n = rn(opcode);
+    let t = cpu.t();
+    cpu.set_t(cpu.r[n] & 0x01 != 0);
+    cpu.r[n] = cpu.r[n] >> 1 | t << 31;
+    1
+}
+
+// the branches, the delayed ones execute the next instruction before the jump
+
+fn bf<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    if cpu.t() != 0 {
+        return 1;
+    }
+    cpu.next_pc = cpu.pc_base().wrapping_add(disp8(opcode) << 1);
+    3
+}
+
+fn bt<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    if cpu.t() == 0 {
+        return 1;
+    }
+    cpu.next_pc = cpu.pc_base().wrapping_add(disp8(opcode) << 1);
+    3
+}
+
+fn bf_s<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    if cpu.t() != 0 {
+        return 1;
+    }
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(disp8(opcode) << 1));
+    2
+}
+
+fn bt_s<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    if cpu.t() == 0 {
+        return 1;
+    }
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(disp8(opcode) << 1));
+    2
+}
+
+fn bra<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(disp12(opcode) << 1));
+    2
+}
+
+fn bsr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.pr = cpu.pc_base();
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(disp12(opcode) << 1));
+    2
+}
+
+fn braf<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(cpu.r[rn(opcode)]));
+    2
+}
+
+fn bsrf<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.pr = cpu.pc_base();
+    cpu.delay_slot = Some(cpu.pc_base().wrapping_add(cpu.r[rn(opcode)]));
+    2
+}
+
+fn jmp<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.delay_slot = Some(cpu.r[rn(opcode)]);
+    2
+}
+
+fn jsr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.pr = cpu.pc_base();
+    cpu.delay_slot = Some(cpu.r[rn(opcode)]);
+    2
+}
+
+fn rts<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.delay_slot = Some(cpu.pr);
+    2
+}
+
+fn rte<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    let pc = cpu.pop();
+    cpu.sr = cpu.pop() & SR_MASK;
+    cpu.delay_slot = Some(pc);
+    4
+}
+
+fn trapa<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.next_pc = cpu.enter_exception(imm(opcode), cpu.next_pc);
+    8
+}
+
+// the system control
+
+fn nop<T: BusSh2>(_: &mut Sh2<T>, _: u16) -> i32 {
+    1
+}
+
+// the cpu waits for an interrupt
+fn sleep<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.sleeping = true;
+    3
+}
+
+fn clrt<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.set_t(false);
+    1
+}
+
+fn sett<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.set_t(true);
+    1
+}
+
+fn clrmac<T: BusSh2>(cpu: &mut Sh2<T>, _: u16) -> i32 {
+    cpu.set_mac(0);
+    1
+}
+
+fn ldc_sr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.sr = cpu.r[rn(opcode)] & SR_MASK;
+    1
+}
+
+fn ldc_gbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.gbr = cpu.r[rn(opcode)];
+    1
+}
+
+fn ldc_vbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.vbr = cpu.r[rn(opcode)];
+    1
+}
+
+fn lds_mach<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.mach = cpu.r[rn(opcode)];
+    1
+}
+
+fn lds_macl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.macl = cpu.r[rn(opcode)];
+    1
+}
+
+fn lds_pr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.pr = cpu.r[rn(opcode)];
+    1
+}
+
+fn stc_sr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.sr;
+    1
+}
+
+fn stc_gbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.gbr;
+    1
+}
+
+fn stc_vbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.vbr;
+    1
+}
+
+fn sts_mach<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.mach;
+    1
+}
+
+fn sts_macl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.macl;
+    1
+}
+
+fn sts_pr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.r[rn(opcode)] = cpu.pr;
+    1
+}
+
+// the control registers are loaded from @Rn+ and stored to @-Rn
+fn load_postinc<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> u32 {
+    let n = rn(opcode);
+    let data = cpu.read(cpu.r[n], 4);
+    cpu.r[n] = cpu.r[n].wrapping_add(4);
+    data
+}
+
+fn store_predec<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16, data: u32) {
+    let n = rn(opcode);
+    cpu.r[n] = cpu.r[n].wrapping_sub(4);
+    cpu.write(cpu.r[n], data, 4);
+}
+
+fn ldc_l_sr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.sr = load_postinc(cpu, opcode) & SR_MASK;
+    3
+}
+
+fn ldc_l_gbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.gbr = load_postinc(cpu, opcode);
+    3
+}
+
+fn ldc_l_vbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.vbr = load_postinc(cpu, opcode);
+    3
+}
+
+fn lds_l_mach<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.mach = load_postinc(cpu, opcode);
+    1
+}
+
+fn lds_l_macl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.macl = load_postinc(cpu, opcode);
+    1
+}
+
+fn lds_l_pr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    cpu.pr = load_postinc(cpu, opcode);
+    1
+}
+
+fn stc_l_sr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.sr);
+    2
+}
+
+fn stc_l_gbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.gbr);
+    2
+}
+
+fn stc_l_vbr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.vbr);
+    2
+}
+
+fn sts_l_mach<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.mach);
+    1
+}
+
+fn sts_l_macl<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.macl);
+    1
+}
+
+fn sts_l_pr<T: BusSh2>(cpu: &mut Sh2<T>, opcode: u16) -> i32 {
+    store_predec(cpu, opcode, cpu.pr);
+    1
+}
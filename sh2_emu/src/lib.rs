@@ -0,0 +1,20 @@
+pub mod bus;
+pub mod cpu;
+
+mod instruction_set;
+mod onchip;
+
+// the status register bits
+pub(crate) const T_BIT: u32 = 0x0001;
+pub(crate) const S_BIT: u32 = 0x0002;
+pub(crate) const Q_BIT: u32 = 0x0100;
+pub(crate) const M_BIT: u32 = 0x0200;
+pub(crate) const SR_MASK: u32 = 0x03F3;
+
+pub(crate) fn sign_extend(data: u32, size: usize) -> u32 {
+    match size {
+        1 => data as u8 as i8 as i32 as u32,
+        2 => data as u16 as i16 as i32 as u32,
+        _ => data,
+    }
+}
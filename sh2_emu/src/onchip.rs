@@ -0,0 +1,152 @@
+use log::debug;
+
+pub(crate) const ONCHIP_BASE: u32 = 0xFFFFFE00;
+pub(crate) const CACHE_SIZE: usize = 0x1000;
+
+// the register offsets from 0xFFFFFE00
+const FTCSR: usize = 0x011;
+const FRC: usize = 0x012;
+const OCRA: usize = 0x014;
+const TCR: usize = 0x016;
+const IPRA: usize = 0x0E2;
+const DVSR: usize = 0x100;
+const DVDNT: usize = 0x104;
+const DVCR: usize = 0x108;
+const DVDNTH: usize = 0x110;
+const DVDNTL: usize = 0x114;
+pub(crate) const SAR0: usize = 0x180;
+pub(crate) const DAR0: usize = 0x184;
+pub(crate) const TCR0: usize = 0x188;
+pub(crate) const CHCR0: usize = 0x18C;
+pub(crate) const CHANNEL_STRIDE: usize = 0x10;
+const VCRDMA0: usize = 0x1A0;
+pub(crate) const DMAOR: usize = 0x1B0;
+
+// CHCR bits
+pub(crate) const DE: u32 = 0x0001;
+pub(crate) const TE: u32 = 0x0002;
+const IE: u32 = 0x0004;
+pub(crate) const AR: u32 = 0x0200;
+// DMAOR bits
+pub(crate) const DME: u32 = 0x0001;
+
+// the modules of the SH7604 chip at 0xFFFFFE00: the divider, the dma controller and the free running timer
+// do the work, the other registers only keep the written values; the cache works as 4 KB of ram
+pub(crate) struct OnChip {
+    pub(crate) registers: Vec<u8>,
+    pub(crate) cache: Vec<u8>,
+    pub(crate) frt_clocks: u32,
+}
+
+impl OnChip {
+    pub(crate) fn new() -> Self {
+        Self {
+            registers: vec![0; 0x200],
+            cache: vec![0; CACHE_SIZE],
+            frt_clocks: 0,
+        }
+    }
+
+    // the divider registers are mirrored at 0xFFFFFF20
+    fn register_offset(address: u32) -> usize {
+        let offset = (address - ONCHIP_BASE) as usize;
+        match offset {
+            0x120..=0x13F => offset - 0x20,
+            offset => offset,
+        }
+    }
+
+    pub(crate) fn long(&self, offset: usize) -> u32 {
+        u32::from_be_bytes(self.registers[offset..offset + 4].try_into().unwrap())
+    }
+
+    pub(crate) fn set_long(&mut self, offset: usize, data: u32) {
+        self.registers[offset..offset + 4].copy_from_slice(&data.to_be_bytes());
+    }
+
+    fn word(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.registers[offset], self.registers[offset + 1]])
+    }
+
+    pub(crate) fn read(&self, address: u32, amount: usize) -> u32 {
+        let offset = Self::register_offset(address);
+        self.registers[offset..offset + amount]
+            .iter()
+            .fold(0, |data, byte| data << 8 | *byte as u32)
+    }
+
+    // returns true if the dma controller is written
+    pub(crate) fn write(&mut self, address: u32, data: u32, amount: usize) -> bool {
+        let offset = Self::register_offset(address);
+        let mut data = data;
+        // the transfer end flag is only cleared by the writes
+        if amount == 4 && (offset == CHCR0 || offset == CHCR0 + CHANNEL_STRIDE) {
+            data = data & !TE | self.long(offset) & data & TE;
+        }
+        self.registers[offset..offset + amount].copy_from_slice(&data.to_be_bytes()[4 - amount..]);
+        match offset {
+            DVDNT => self.divide_32(),
+            DVDNTL => self.divide_64(),
+            _ => debug!("SH2: on-chip register {:08X} is written with {:08X}", address, data),
+        }
+        (SAR0..DMAOR + 4).contains(&offset)
+    }
+
+    fn divide_32(&mut self) {
+        let dividend = self.long(DVDNT) as i32;
+        self.set_long(DVDNTH, (dividend >> 31) as u32);
+        self.set_long(DVDNTL, dividend as u32);
+        self.divide_64();
+    }
+
+    // the signed 64 by 32 bits division, the overflow saturates the quotient
+    fn divide_64(&mut self) {
+        let divisor = self.long(DVSR) as i32 as i64;
+        let dividend = ((self.long(DVDNTH) as u64) << 32 | self.long(DVDNTL) as u64) as i64;
+        let quotient = dividend.checked_div(divisor).filter(|quotient| i32::try_from(*quotient).is_ok());
+        match quotient {
+            Some(quotient) => {
+                self.set_long(DVDNTH, (dividend % divisor) as u32);
+                self.set_long(DVDNTL, quotient as u32);
+            }
+            None => {
+                debug!("SH2: division overflow {:016X} / {:08X}", dividend, divisor);
+                self.set_long(DVCR, self.long(DVCR) | 0x01);
+                let negative = (dividend < 0) != (divisor < 0);
+                self.set_long(DVDNTL, if negative { 0x80000000 } else { 0x7FFFFFFF });
+            }
+        }
+        self.set_long(DVDNT, self.long(DVDNTL));
+    }
+
+    // the counter steps by 8, 32 or 128 cpu clocks, the compare match A can clear it
+    pub(crate) fn clock(&mut self, cycles: u32) {
+        let divider = match self.registers[TCR] & 0x03 {
+            0 => 8,
+            1 => 32,
+            2 => 128,
+            _ => return, // the external clock is not connected
+        };
+        self.frt_clocks += cycles;
+        while self.frt_clocks >= divider {
+            self.frt_clocks -= divider;
+            let mut counter = self.word(FRC).wrapping_add(1);
+            if counter == self.word(OCRA) {
+                self.registers[FTCSR] |= 0x08;
+                if self.registers[FTCSR] & 0x01 != 0 {
+                    counter = 0;
+                }
+            }
+            self.registers[FRC..FRC + 2].copy_from_slice(&counter.to_be_bytes());
+        }
+    }
+
+    // the level and the vector of the finished dma transfer with the enabled interrupt
+    pub(crate) fn pending_interrupt(&self) -> Option<(u8, u8)> {
+        let level = (self.word(IPRA) >> 8) as u8 & 0x0F;
+        (0..2)
+            .find(|channel| self.long(CHCR0 + channel * CHANNEL_STRIDE) & (TE | IE) == TE | IE)
+            .filter(|_| level != 0)
+            .map(|channel| (level, self.registers[VCRDMA0 + channel * 8 + 3] & 0x7F))
+    }
+}
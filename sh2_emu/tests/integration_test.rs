@@ -0,0 +1,133 @@
+use std::{cell::RefCell, rc::Rc};
+
+use sh2_emu::{bus::BusSh2, cpu::Sh2};
+
+struct Bus {
+    ram: Vec<u8>,
+}
+
+impl Bus {
+    // the program words at their addresses and the reset vectors
+    fn new(program: &[(u32, &[u16])], pc: u32) -> Self {
+        let mut ram = vec![0; 0x10000];
+        ram[0..4].copy_from_slice(&pc.to_be_bytes());
+        ram[4..8].copy_from_slice(&0x8000u32.to_be_bytes());
+        for (address, words) in program {
+            for (i, word) in words.iter().enumerate() {
+                let address = *address as usize + i * 2;
+                ram[address..address + 2].copy_from_slice(&word.to_be_bytes());
+            }
+        }
+        Self { ram }
+    }
+}
+
+impl BusSh2 for Bus {
+    fn read(&self, address: u32, amount: usize) -> Result<u32, ()> {
+        let address = address as usize & 0xFFFF;
+        Ok(self.ram[address..address + amount].iter().fold(0, |data, byte| data << 8 | *byte as u32))
+    }
+
+    fn write(&mut self, data: u32, address: u32, amount: usize) -> Result<(), ()> {
+        let address = address as usize & 0xFFFF;
+        self.ram[address..address + amount].copy_from_slice(&data.to_be_bytes()[4 - amount..]);
+        Ok(())
+    }
+}
+
+fn cpu_with(bus: Bus) -> Sh2<Bus> {
+    let mut cpu = Sh2::new();
+    cpu.set_bus(Rc::new(RefCell::new(bus)));
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn runs_loops_calls_and_divider() {
+    let program = [
+        0xE00A, // mov #10, r0
+        0xE100, // mov #0, r1
+        0x310C, // add r0, r1
+        0x4010, // dt r0
+        0x8BFC, // bf *-4
+        0xB00B, // bsr $124
+        0xE205, // mov #5, r2 in the delay slot
+        0xD33C, // mov.l @($200, pc), r3
+        0xE464, // mov #100, r4
+        0xE507, // mov #7, r5
+        0x2352, // mov.l r5, @r3 the divisor
+        0x1341, // mov.l r4, @(4, r3) the dividend starts the division
+        0x5631, // mov.l @(4, r3), r6
+        0x5734, // mov.l @(16, r3), r7
+        0xAFFE, // bra *
+        0x0009, // nop
+    ];
+    let subroutine = [
+        0x000B, // rts
+        0x7203, // add #3, r2 in the delay slot
+    ];
+    let bus = Bus::new(&[(0x100, &program), (0x124, &subroutine), (0x200, &[0xFFFF, 0xFF00])], 0x100);
+    let mut cpu = cpu_with(bus);
+    for _ in 0..100 {
+        cpu.clock();
+    }
+    let state = cpu.state();
+    assert_eq!(state.registers[1], 55);
+    assert_eq!(state.registers[2], 8);
+    assert_eq!((state.registers[6], state.registers[7]), (14, 2));
+    assert!((0x11C..=0x11E).contains(&state.pc));
+}
+
+#[test]
+fn interrupt_returns_by_rte() {
+    let program = [
+        0xE000, // mov #0, r0
+        0x400E, // ldc r0, sr
+        0xAFFE, // bra *
+        0x0009, // nop
+    ];
+    let handler = [
+        0x7101, // add #1, r1
+        0x002B, // rte
+        0x0009, // nop
+    ];
+    // the level 8 takes the auto vector 68
+    let bus = Bus::new(&[(0x400, &program), (0x500, &handler), (68 * 4, &[0x0000, 0x0500])], 0x400);
+    let mut cpu = cpu_with(bus);
+    assert!(!cpu.interrupt(8), "the reset masks all levels");
+    for _ in 0..2 {
+        for _ in 0..10 {
+            cpu.clock();
+        }
+        while !cpu.interrupt(8) {
+            cpu.clock();
+        }
+        assert!(!cpu.interrupt(8), "the handler masks its own level");
+    }
+    for _ in 0..10 {
+        cpu.clock();
+    }
+    let state = cpu.state();
+    assert_eq!(state.registers[1], 2);
+    assert_eq!((state.sr >> 4) & 0x0F, 0);
+    assert_eq!(state.registers[15], 0x8000);
+}
+
+#[test]
+fn misaligned_cache_access_is_forced_to_the_access_size() {
+    let program = [
+        0xD302, // mov.l @($10C, pc), r3
+        0xE45A, // mov #$5A, r4
+        0x2342, // mov.l r4, @r3 at the end of the cache array
+        0x6532, // mov.l @r3, r5
+        0xAFFE, // bra *
+        0x0009, // nop
+        0xC000, 0x0FFE, // the cache data array
+    ];
+    let bus = Bus::new(&[(0x100, &program)], 0x100);
+    let mut cpu = cpu_with(bus);
+    for _ in 0..10 {
+        cpu.clock();
+    }
+    assert_eq!(cpu.state().registers[5], 0x5A);
+}
@@ -0,0 +1,77 @@
+// the model 1 board has a single pole low-pass filter with the cutoff about 3.4 kHz
+const LOW_PASS_CUTOFF: f64 = 3390.0; // Hz
+
+// mixes the sound chips at the ym2612 rate, filters and resamples the result to the sink rate
+pub(crate) struct Mixer {
+    input_rate: f64,
+    output_rate: u32,
+    step: f64,     // input samples per output sample
+    position: f64, // position of the next output sample between the previous and the current input ones
+
+    filter_factor: f64,
+    filtered: (f64, f64),
+    previous: (f64, f64),
+
+    output: Vec<(i16, i16)>,
+}
+
+impl Mixer {
+    pub(crate) fn new(input_rate: f64, output_rate: u32) -> Self {
+        let time_constant = 1.0 / (2.0 * std::f64::consts::PI * LOW_PASS_CUTOFF);
+        let sample_time = 1.0 / input_rate;
+        Self {
+            input_rate,
+            output_rate,
+            step: input_rate / output_rate as f64,
+            position: 0.0,
+
+            filter_factor: sample_time / (time_constant + sample_time),
+            filtered: (0.0, 0.0),
+            previous: (0.0, 0.0),
+
+            output: vec![],
+        }
+    }
+
+    pub(crate) fn set_output_rate(&mut self, output_rate: u32) {
+        *self = Self::new(self.input_rate, output_rate);
+    }
+
+    pub(crate) fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    // fm is a stereo ym2612 sample, psg is a mono sn76489 one
+    pub(crate) fn push(&mut self, fm: (i32, i32), psg: i32) {
+        let (left, right) = ((fm.0 + psg) as f64, (fm.1 + psg) as f64);
+        let (filtered_left, filtered_right) = self.filtered;
+        let current = (
+            filtered_left + (left - filtered_left) * self.filter_factor,
+            filtered_right + (right - filtered_right) * self.filter_factor,
+        );
+        self.filtered = current;
+
+        // linear interpolation between the previous and the current input samples
+        while self.position <= 1.0 {
+            let interpolate = |previous: f64, current: f64| {
+                let sample = previous + (current - previous) * self.position;
+                sample.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+            };
+            self.output.push((
+                interpolate(self.previous.0, current.0),
+                interpolate(self.previous.1, current.1),
+            ));
+            self.position += self.step;
+        }
+        self.position -= 1.0;
+        self.previous = current;
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.output.len()
+    }
+
+    pub(crate) fn take_output(&mut self) -> Vec<(i16, i16)> {
+        std::mem::take(&mut self.output)
+    }
+}
@@ -0,0 +1,12 @@
+pub(crate) mod mixer;
+pub mod wav_sink;
+
+pub const SAMPLE_RATE_44100: u32 = 44_100;
+pub const SAMPLE_RATE_48000: u32 = 48_000;
+
+// consumer of the mixed stereo sound, e.g. a sound card or a file
+pub trait AudioSink {
+    // the mixer resamples the sound to this rate
+    fn sample_rate(&self) -> u32;
+    fn write(&mut self, samples: &[(i16, i16)]);
+}
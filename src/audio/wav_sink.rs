@@ -0,0 +1,122 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use log::error;
+
+use super::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+
+// 16 bit stereo PCM file, the chunk sizes are updated on `finish` or drop
+pub struct WavSink<W: Write + Seek> {
+    writer: Option<W>,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        write_header(&mut writer, sample_rate, 0)?;
+        Ok(Self {
+            writer: Some(writer),
+            sample_rate,
+            data_size: 0,
+        })
+    }
+
+    // completes the header and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.update_header()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn update_header(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        writer.seek(SeekFrom::Start(0))?;
+        write_header(writer, self.sample_rate, self.data_size)?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()
+    }
+
+    fn write_samples(&mut self, samples: &[(i16, i16)]) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        for (left, right) in samples {
+            writer.write_all(&left.to_le_bytes())?;
+            writer.write_all(&right.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * (CHANNELS * BITS_PER_SAMPLE / 8) as u32;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&mut self, samples: &[(i16, i16)]) {
+        if let Err(e) = self.write_samples(samples) {
+            error!("WavSink: write: {}", e);
+        }
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            if let Err(e) = self.update_header() {
+                error!("WavSink: drop: {}", e);
+            }
+        }
+    }
+}
+
+fn write_header<W: Write>(writer: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> {
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::WavSink;
+    use crate::audio::AudioSink;
+
+    #[test]
+    fn header_describes_written_samples() {
+        let mut sink = WavSink::new(Cursor::new(vec![]), 48000).unwrap();
+        sink.write(&[(1, -1), (0x1234, 0)]);
+        let data = sink.finish().unwrap().into_inner();
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 48000);
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 8);
+        assert_eq!(&data[44..], &[0x01, 0x00, 0xFF, 0xFF, 0x34, 0x12, 0x00, 0x00]);
+    }
+}
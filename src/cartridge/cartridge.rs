@@ -0,0 +1,239 @@
+use std::fmt::Display;
+
+use crate::cartridge::Region;
+
+const HEADER_START: usize = 0x100;
+const HEADER_END: usize = 0x200;
+
+// external memory descriptor at 0x1B0: "RA", type, 0x20, start and end addresses
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SramDescriptor {
+    pub kind: u8, // bit 6 - battery backup, bits 3-4 - bus layout (0 - word, 2 - even bytes, 3 - odd bytes)
+    pub start: u32,
+    pub end: u32,
+}
+
+// the rom header at 0x100-0x1FF
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cartridge {
+    pub console_name: String,
+    pub copyright: String,
+    pub domestic_title: String,
+    pub overseas_title: String,
+    pub serial: String,
+    pub checksum: u16,
+    pub io_support: String,
+    pub rom_range: (u32, u32),
+    pub ram_range: (u32, u32),
+    pub sram: Option<SramDescriptor>,
+    pub modem: String,
+    pub notes: String,
+    pub region_code: String,
+    pub regions: Vec<Region>,
+}
+
+impl Cartridge {
+    // a rom shorter than the header is treated as padded with zeros
+    pub fn new(rom: &[u8]) -> Self {
+        let mut header = [0; HEADER_END];
+        let size = rom.len().min(HEADER_END);
+        header[..size].copy_from_slice(&rom[..size]);
+
+        let text = |start: usize, end: usize| header_text(&header[start..end]);
+        let long = |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+
+        let sram = if &header[0x1B0..0x1B2] == b"RA" {
+            Some(SramDescriptor {
+                kind: header[0x1B2],
+                start: long(0x1B4),
+                end: long(0x1B8),
+            })
+        } else {
+            None
+        };
+
+        let region_code = text(0x1F0, 0x1F3);
+        Self {
+            console_name: text(HEADER_START, 0x110),
+            copyright: text(0x110, 0x120),
+            domestic_title: text(0x120, 0x150),
+            overseas_title: text(0x150, 0x180),
+            serial: text(0x180, 0x18E),
+            checksum: u16::from_be_bytes([header[0x18E], header[0x18F]]),
+            io_support: text(0x190, 0x1A0),
+            rom_range: (long(0x1A0), long(0x1A4)),
+            ram_range: (long(0x1A8), long(0x1AC)),
+            sram,
+            modem: text(0x1BC, 0x1C8),
+            notes: text(0x1C8, 0x1F0),
+            regions: parse_regions(&region_code),
+            region_code,
+        }
+    }
+
+    // the region the console is configured for, games without a valid region field run as american ones
+    pub fn preferred_region(&self) -> Region {
+        [Region::Americas, Region::Europe, Region::Japan, Region::Asia]
+            .into_iter()
+            .find(|region| self.regions.contains(region))
+            .unwrap_or(Region::Americas)
+    }
+
+    // the "6" of the I/O support field, the other games get the 3 button pad
+    pub fn six_button_pad(&self) -> bool {
+        self.io_support.contains('6')
+    }
+
+    // the header title to show, the overseas one is usually readable ascii
+    pub fn title(&self) -> &str {
+        if self.overseas_title.is_empty() {
+            &self.domestic_title
+        } else {
+            &self.overseas_title
+        }
+    }
+}
+
+impl Display for Cartridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let regions = self
+            .regions
+            .iter()
+            .map(|region| region.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        writeln!(f, "Console:        {}", self.console_name)?;
+        writeln!(f, "Copyright:      {}", self.copyright)?;
+        writeln!(f, "Domestic title: {}", self.domestic_title)?;
+        writeln!(f, "Overseas title: {}", self.overseas_title)?;
+        writeln!(f, "Serial:         {}", self.serial)?;
+        writeln!(f, "Checksum:       {:04X}", self.checksum)?;
+        writeln!(f, "I/O support:    {}", self.io_support)?;
+        writeln!(f, "ROM:            {:06X}-{:06X}", self.rom_range.0, self.rom_range.1)?;
+        writeln!(f, "RAM:            {:06X}-{:06X}", self.ram_range.0, self.ram_range.1)?;
+        match &self.sram {
+            Some(sram) => writeln!(
+                f,
+                "SRAM:           {:06X}-{:06X} (type {:02X})",
+                sram.start, sram.end, sram.kind
+            )?,
+            None => writeln!(f, "SRAM:           none")?,
+        }
+        writeln!(f, "Modem:          {}", self.modem)?;
+        writeln!(f, "Notes:          {}", self.notes)?;
+        write!(f, "Region:         {} ({})", self.region_code, regions)
+    }
+}
+
+// the word sum of the rom after the header, the games compare it with the header checksum
+pub fn rom_checksum(rom: &[u8]) -> u16 {
+    rom.get(HEADER_END..)
+        .unwrap_or_default()
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)]))
+        .fold(0, u16::wrapping_add)
+}
+
+// the text fields are padded with spaces, the long titles have space runs in the middle
+fn header_text(data: &[u8]) -> String {
+    let text = data
+        .iter()
+        .map(|byte| match byte {
+            0x20..=0x7E => *byte as char,
+            _ => ' ', // shift-jis of the domestic titles and the zero padding
+        })
+        .collect::<String>();
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// the old style field lists the letters "J", "U" and "E", the new style one is a single hex digit:
+// bit 0 - Japan, bit 1 - Asia, bit 2 - Americas, bit 3 - Europe
+fn parse_regions(region_code: &str) -> Vec<Region> {
+    let mut regions = vec![];
+    let mut add = |region: Region| {
+        if !regions.contains(&region) {
+            regions.push(region);
+        }
+    };
+    let new_style = region_code.len() == 1 && region_code != "E";
+    match u8::from_str_radix(region_code, 16) {
+        Ok(bits) if new_style => {
+            for (bit, region) in [Region::Japan, Region::Asia, Region::Americas, Region::Europe]
+                .into_iter()
+                .enumerate()
+            {
+                if bits & (1 << bit) != 0 {
+                    add(region);
+                }
+            }
+        }
+        _ => {
+            for code in region_code.chars() {
+                match code {
+                    'J' => add(Region::Japan),
+                    'U' | 'B' | '4' => add(Region::Americas), // Brazil uses the american model
+                    'E' => add(Region::Europe),
+                    'A' => add(Region::Asia),
+                    _ => (),
+                }
+            }
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Cartridge, SramDescriptor};
+    use crate::cartridge::Region;
+
+    fn header(region_code: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0x20; 0x200];
+        rom[0x100..0x110].copy_from_slice(b"SEGA MEGA DRIVE ");
+        rom[0x150..0x16A].copy_from_slice(b"SONIC THE         HEDGEHOG");
+        rom[0x180..0x18E].copy_from_slice(b"GM 00001009-00");
+        rom[0x18E..0x190].copy_from_slice(&[0x26, 0x4A]);
+        rom[0x1A0..0x1A8].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0xFF, 0xFF]);
+        rom[0x1B0..0x1BC].copy_from_slice(&[
+            b'R', b'A', 0xF8, 0x20, 0x00, 0x20, 0x00, 0x01, 0x00, 0x20, 0x3F, 0xFF,
+        ]);
+        rom[0x1F0..0x1F0 + region_code.len()].copy_from_slice(region_code);
+        rom
+    }
+
+    #[test]
+    fn parses_header_fields() {
+        let cartridge = Cartridge::new(&header(b"JUE"));
+        assert_eq!(cartridge.console_name, "SEGA MEGA DRIVE");
+        assert_eq!(cartridge.title(), "SONIC THE HEDGEHOG");
+        assert_eq!(cartridge.serial, "GM 00001009-00");
+        assert_eq!(cartridge.checksum, 0x264A);
+        assert_eq!(cartridge.rom_range, (0, 0x7FFFF));
+        assert_eq!(
+            cartridge.sram,
+            Some(SramDescriptor { kind: 0xF8, start: 0x200001, end: 0x203FFF })
+        );
+        assert_eq!(cartridge.regions, [Region::Japan, Region::Americas, Region::Europe]);
+        assert_eq!(cartridge.preferred_region(), Region::Americas);
+        assert!(!cartridge.six_button_pad());
+
+        let mut rom = header(b"JUE");
+        rom[0x190..0x192].copy_from_slice(b"J6");
+        assert!(Cartridge::new(&rom).six_button_pad());
+    }
+
+    #[test]
+    fn parses_region_codes() {
+        let regions = |code: &[u8]| Cartridge::new(&header(code)).regions;
+        assert_eq!(regions(b"E"), [Region::Europe]);
+        assert_eq!(regions(b"JE"), [Region::Japan, Region::Europe]);
+        assert_eq!(regions(b"4"), [Region::Americas]);
+        assert_eq!(regions(b"8"), [Region::Europe]);
+        assert_eq!(regions(b"F"), [Region::Japan, Region::Asia, Region::Americas, Region::Europe]);
+        assert_eq!(regions(b"1"), [Region::Japan]);
+        assert!(regions(b"   ").is_empty());
+
+        assert_eq!(Cartridge::new(&header(b"A")).preferred_region(), Region::Europe);
+        assert_eq!(Cartridge::new(&[]).preferred_region(), Region::Americas);
+    }
+}
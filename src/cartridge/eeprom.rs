@@ -0,0 +1,504 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EepromType {
+    X24C01, // 7 bit address with the r/w bit, without the device address
+    C24C01,
+    C24C02,
+    C24C04,
+    C24C08,
+    C24C16,
+    C24C32,
+    C24C64,
+    C24C65,
+}
+
+impl EepromType {
+    // the chip name of the rom database, "X24C01", "24C02" etc.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let kind = match name.to_uppercase().as_str() {
+            "X24C01" => EepromType::X24C01,
+            "24C01" => EepromType::C24C01,
+            "24C02" => EepromType::C24C02,
+            "24C04" => EepromType::C24C04,
+            "24C08" => EepromType::C24C08,
+            "24C16" => EepromType::C24C16,
+            "24C32" => EepromType::C24C32,
+            "24C64" => EepromType::C24C64,
+            "24C65" => EepromType::C24C65,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            EepromType::X24C01 | EepromType::C24C01 => 0x80,
+            EepromType::C24C02 => 0x100,
+            EepromType::C24C04 => 0x200,
+            EepromType::C24C08 => 0x400,
+            EepromType::C24C16 => 0x800,
+            EepromType::C24C32 => 0x1000,
+            EepromType::C24C64 | EepromType::C24C65 => 0x2000,
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        match self {
+            EepromType::X24C01 => 4,
+            EepromType::C24C01 | EepromType::C24C02 => 8,
+            EepromType::C24C04 | EepromType::C24C08 | EepromType::C24C16 => 16,
+            EepromType::C24C32 | EepromType::C24C64 => 32,
+            EepromType::C24C65 => 64,
+        }
+    }
+
+    // the larger chips take two word address bytes
+    fn two_address_bytes(&self) -> bool {
+        matches!(self, EepromType::C24C32 | EepromType::C24C64 | EepromType::C24C65)
+    }
+}
+
+// cartridge addresses and data bits of the SDA and SCL lines
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EepromLines {
+    pub sda_in: (u32, u8),
+    pub sda_out: (u32, u8),
+    pub scl: (u32, u8),
+}
+
+impl EepromLines {
+    // the wiring of a publisher: "sega", "ea", "acclaim_old", "acclaim" or "codemasters"
+    pub fn from_name(name: &str) -> Option<Self> {
+        let lines = match name.to_lowercase().as_str() {
+            "sega" => SEGA_LINES,
+            "ea" => EA_LINES,
+            "acclaim_old" => ACCLAIM_OLD_LINES,
+            "acclaim" => ACCLAIM_LINES,
+            "codemasters" => CODEMASTERS_LINES,
+            _ => return None,
+        };
+        Some(lines)
+    }
+}
+
+const SEGA_LINES: EepromLines = EepromLines {
+    sda_in: (0x200001, 0),
+    sda_out: (0x200001, 0),
+    scl: (0x200001, 1),
+};
+const EA_LINES: EepromLines = EepromLines {
+    sda_in: (0x200001, 7),
+    sda_out: (0x200001, 7),
+    scl: (0x200001, 6),
+};
+const ACCLAIM_OLD_LINES: EepromLines = EepromLines {
+    sda_in: (0x200001, 0),
+    sda_out: (0x200001, 1),
+    scl: (0x200001, 1),
+};
+const ACCLAIM_LINES: EepromLines = EepromLines {
+    sda_in: (0x200001, 0),
+    sda_out: (0x200001, 0),
+    scl: (0x200000, 0),
+};
+const CODEMASTERS_LINES: EepromLines = EepromLines {
+    sda_in: (0x300000, 0),
+    sda_out: (0x380001, 7),
+    scl: (0x300000, 1),
+};
+
+// games with the serial eeprom, the key is a part of the header serial
+const EEPROM_GAMES: [(&str, EepromType, EepromLines); 27] = [
+    // Sega
+    ("T-12046", EepromType::X24C01, SEGA_LINES),     // Mega Man - The Wily Wars
+    ("T-12053", EepromType::X24C01, SEGA_LINES),     // Rockman Mega World
+    ("MK-1215", EepromType::X24C01, SEGA_LINES),     // Evander Holyfield's Real Deal Boxing
+    ("MK-1228", EepromType::X24C01, SEGA_LINES),     // Greatest Heavyweights
+    ("G-5538", EepromType::X24C01, SEGA_LINES),      // Greatest Heavyweights (J)
+    ("PR-1993", EepromType::X24C01, SEGA_LINES),     // Greatest Heavyweights (E)
+    ("G-4060", EepromType::X24C01, SEGA_LINES),      // Wonder Boy in Monster World
+    ("00001211", EepromType::X24C01, SEGA_LINES),    // Sports Talk Baseball
+    ("00004076", EepromType::X24C01, SEGA_LINES),    // Honoo no Toukyuuji Dodge Danpei
+    ("G-4524", EepromType::X24C01, SEGA_LINES),      // Ninja Burai Densetsu
+    ("00054503", EepromType::X24C01, SEGA_LINES),    // Game Toshokan
+    // Electronic Arts
+    ("T-50176", EepromType::X24C01, EA_LINES),       // Rings of Power
+    ("T-50396", EepromType::X24C01, EA_LINES),       // NHLPA Hockey 93
+    ("T-50446", EepromType::X24C01, EA_LINES),       // John Madden Football 93
+    ("T-50516", EepromType::X24C01, EA_LINES),       // John Madden Football 93 Championship Edition
+    ("T-50606", EepromType::X24C01, EA_LINES),       // Bill Walsh College Football
+    // Acclaim
+    ("T-081326", EepromType::C24C02, ACCLAIM_OLD_LINES), // NBA Jam
+    ("T-81033", EepromType::C24C02, ACCLAIM_OLD_LINES),  // NBA Jam (J)
+    ("T-081276", EepromType::C24C02, ACCLAIM_LINES), // NFL Quarterback Club
+    ("T-81406", EepromType::C24C04, ACCLAIM_LINES),  // NBA Jam Tournament Edition
+    ("T-081586", EepromType::C24C16, ACCLAIM_LINES), // NFL Quarterback Club 96
+    ("T-81576", EepromType::C24C65, ACCLAIM_LINES),  // College Slam
+    ("T-81476", EepromType::C24C65, ACCLAIM_LINES),  // Frank Thomas Big Hurt Baseball
+    // Codemasters
+    ("T-120106", EepromType::C24C08, CODEMASTERS_LINES), // Brian Lara Cricket
+    ("T-120146", EepromType::C24C08, CODEMASTERS_LINES), // Brian Lara Cricket 96
+    ("T-120096", EepromType::C24C16, CODEMASTERS_LINES), // Micro Machines 2
+    ("T-120166", EepromType::C24C65, CODEMASTERS_LINES), // Micro Machines Military
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Standby,
+    DeviceAddress, // also the 7 bit address of X24C01
+    AddressHigh,
+    AddressLow,
+    Write,
+    Read,
+}
+
+impl State {
+    fn from_u8(value: u8) -> Option<Self> {
+        let state = match value {
+            0 => State::Standby,
+            1 => State::DeviceAddress,
+            2 => State::AddressHigh,
+            3 => State::AddressLow,
+            4 => State::Write,
+            5 => State::Read,
+            _ => return None,
+        };
+        Some(state)
+    }
+}
+
+// I2C serial eeprom, the cpu drives the lines by the cartridge area writes
+pub(crate) struct Eeprom {
+    kind: EepromType,
+    lines: EepromLines,
+    data: Vec<u8>,
+
+    scl: bool,
+    sda: bool,
+    sda_out: bool,
+
+    state: State,
+    bit: u8, // clock of the current 9 clocks frame
+    shift: u8,
+    address: u16,
+    transmitting: bool, // the current frame is a byte sent by the eeprom
+    changed: bool,
+}
+
+impl Eeprom {
+    pub(crate) fn new(kind: EepromType, lines: EepromLines) -> Self {
+        Self {
+            kind,
+            lines,
+            data: vec![0xFF; kind.size()],
+
+            scl: true,
+            sda: true,
+            sda_out: true,
+
+            state: State::Standby,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            transmitting: false,
+            changed: false,
+        }
+    }
+
+    // looks for the game in the eeprom table
+    pub(crate) fn for_serial(serial: &str) -> Option<Self> {
+        EEPROM_GAMES
+            .iter()
+            .find(|(key, _, _)| serial.contains(key))
+            .map(|(_, kind, lines)| Self::new(*kind, *lines))
+    }
+
+    pub(crate) fn contains(&self, address: u32) -> bool {
+        address == self.lines.sda_in.0 || address == self.lines.sda_out.0 || address == self.lines.scl.0
+    }
+
+    pub(crate) fn read(&self, address: u32) -> Option<u8> {
+        let (sda_out_address, sda_out_bit) = self.lines.sda_out;
+        if address == sda_out_address {
+            Some((self.sda_out as u8) << sda_out_bit)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u32, data: u8) {
+        let mut sda = self.sda;
+        let mut scl = self.scl;
+        if address == self.lines.sda_in.0 {
+            sda = data >> self.lines.sda_in.1 & 0x01 != 0;
+        }
+        if address == self.lines.scl.0 {
+            scl = data >> self.lines.scl.1 & 0x01 != 0;
+        }
+        self.set_lines(scl, sda);
+    }
+
+    fn set_lines(&mut self, scl: bool, sda: bool) {
+        if scl && self.scl && sda != self.sda {
+            if !sda {
+                // start condition
+                self.state = State::DeviceAddress;
+                self.bit = 0;
+                self.shift = 0;
+                self.transmitting = false;
+            } else {
+                // stop condition
+                self.state = State::Standby;
+            }
+            self.sda_out = true;
+        } else if scl && !self.scl {
+            self.clock_rising(sda);
+        } else if !scl && self.scl {
+            self.clock_falling();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn clock_rising(&mut self, sda: bool) {
+        if self.state == State::Standby {
+            return;
+        }
+        self.bit += 1;
+        if self.transmitting {
+            // the master acknowledges the byte to continue the sequential read
+            if self.bit == 9 {
+                if sda {
+                    self.state = State::Standby;
+                } else {
+                    self.address = self.wrap_address(self.address + 1);
+                }
+            }
+        } else if self.bit <= 8 {
+            self.shift = if self.kind == EepromType::X24C01 {
+                // the X24C01 sends the bits starting from the lsb
+                self.shift >> 1 | (sda as u8) << 7
+            } else {
+                self.shift << 1 | sda as u8
+            };
+            if self.bit == 8 {
+                self.receive_byte();
+            }
+        }
+    }
+
+    fn clock_falling(&mut self) {
+        if self.state == State::Standby {
+            self.sda_out = true;
+            return;
+        }
+        match self.bit {
+            8 if !self.transmitting => self.sda_out = false, // acknowledge
+            9 => {
+                self.bit = 0;
+                self.shift = 0;
+                self.transmitting = self.state == State::Read;
+                self.sda_out = !self.transmitting || self.output_bit(0);
+            }
+            bit @ 1..=7 if self.transmitting => self.sda_out = self.output_bit(bit),
+            _ => self.sda_out = true,
+        }
+    }
+
+    fn output_bit(&self, bit: u8) -> bool {
+        let data = self.data[self.address as usize];
+        if self.kind == EepromType::X24C01 {
+            data >> bit & 0x01 != 0
+        } else {
+            data >> (7 - bit) & 0x01 != 0
+        }
+    }
+
+    fn receive_byte(&mut self) {
+        let data = self.shift;
+        match self.state {
+            State::DeviceAddress if self.kind == EepromType::X24C01 => {
+                self.address = (data & 0x7F) as u16;
+                self.state = if data & 0x80 != 0 { State::Read } else { State::Write };
+            }
+            State::DeviceAddress => {
+                if data & 0xF0 != 0xA0 {
+                    self.state = State::Standby;
+                    return;
+                }
+                if !self.kind.two_address_bytes() {
+                    // the block select bits of the smaller chips
+                    let block = ((data >> 1) & 0x07) as u16;
+                    self.address = self.wrap_address(block << 8 | self.address & 0xFF);
+                }
+                self.state = if data & 0x01 != 0 {
+                    State::Read
+                } else if self.kind.two_address_bytes() {
+                    State::AddressHigh
+                } else {
+                    State::AddressLow
+                };
+            }
+            State::AddressHigh => {
+                self.address = (data as u16) << 8;
+                self.state = State::AddressLow;
+            }
+            State::AddressLow => {
+                self.address = self.wrap_address(self.address & 0xFF00 | data as u16);
+                self.state = State::Write;
+            }
+            State::Write => {
+                let address = self.address as usize;
+                self.changed |= self.data[address] != data;
+                self.data[address] = data;
+                // the address rolls over inside the page
+                let page_mask = self.kind.page_size() - 1;
+                let next = address & !page_mask | (address + 1) & page_mask;
+                self.address = next as u16;
+            }
+            State::Standby | State::Read => (),
+        }
+    }
+
+    fn wrap_address(&self, address: u16) -> u16 {
+        address & (self.data.len() - 1) as u16
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn load(&mut self, data: &[u8]) {
+        let size = data.len().min(self.data.len());
+        self.data[..size].copy_from_slice(&data[..size]);
+        self.changed = false;
+    }
+
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_bool(self.scl);
+        writer.write_bool(self.sda);
+        writer.write_bool(self.sda_out);
+        writer.write_u8(self.state as u8);
+        writer.write_u8(self.bit);
+        writer.write_u8(self.shift);
+        writer.write_u16(self.address);
+        writer.write_bool(self.transmitting);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.data)?;
+        self.scl = reader.read_bool()?;
+        self.sda = reader.read_bool()?;
+        self.sda_out = reader.read_bool()?;
+        self.state = State::from_u8(reader.read_u8()?)
+            .ok_or(StateError::InvalidValue("eeprom state"))?;
+        self.bit = reader.read_u8()? % 10;
+        self.shift = reader.read_u8()?;
+        self.address = self.wrap_address(reader.read_u16()?);
+        self.transmitting = reader.read_bool()?;
+        self.changed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Eeprom, EepromType, ACCLAIM_LINES};
+
+    // bit-bangs the I2C frames like the games do
+    struct Master {
+        eeprom: Eeprom,
+    }
+
+    impl Master {
+        fn lines(&mut self, scl: bool, sda: bool) {
+            let scl = (scl as u8) << ACCLAIM_LINES.scl.1;
+            let sda = (sda as u8) << ACCLAIM_LINES.sda_in.1;
+            self.eeprom.write(ACCLAIM_LINES.scl.0, scl);
+            self.eeprom.write(ACCLAIM_LINES.sda_in.0, sda);
+        }
+
+        fn start(&mut self) {
+            self.lines(true, true);
+            self.lines(true, false);
+            self.lines(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.lines(false, false);
+            self.lines(true, false);
+            self.lines(true, true);
+        }
+
+        // returns the acknowledge bit
+        fn send(&mut self, byte: u8) -> bool {
+            for bit in (0..8).rev() {
+                let sda = byte >> bit & 0x01 != 0;
+                self.lines(false, sda);
+                self.lines(true, sda);
+                self.lines(false, sda);
+            }
+            self.lines(false, true);
+            self.lines(true, true);
+            let ack = self.sda();
+            self.lines(false, true);
+            !ack
+        }
+
+        fn receive(&mut self, ack: bool) -> u8 {
+            let mut byte = 0;
+            for _ in 0..8 {
+                self.lines(false, true);
+                self.lines(true, true);
+                byte = byte << 1 | self.sda() as u8;
+                self.lines(false, true);
+            }
+            self.lines(false, !ack);
+            self.lines(true, !ack);
+            self.lines(false, !ack);
+            byte
+        }
+
+        fn sda(&self) -> bool {
+            let (address, bit) = ACCLAIM_LINES.sda_out;
+            self.eeprom.read(address).unwrap() >> bit & 0x01 != 0
+        }
+    }
+
+    #[test]
+    fn page_write_and_sequential_read() {
+        let mut master = Master { eeprom: Eeprom::new(EepromType::C24C04, ACCLAIM_LINES) };
+        master.start();
+        assert!(master.send(0xA2)); // block 1
+        assert!(master.send(0x0E));
+        for data in [0x11, 0x22, 0x33] {
+            assert!(master.send(data));
+        }
+        master.stop();
+        // the page of 16 bytes rolls over to its start
+        assert_eq!(&master.eeprom.data()[0x10E..0x110], &[0x11, 0x22]);
+        assert_eq!(master.eeprom.data()[0x100], 0x33);
+        assert!(master.eeprom.take_changed());
+
+        // random read: dummy write of the address and the read command
+        master.start();
+        assert!(master.send(0xA2));
+        assert!(master.send(0x0F));
+        master.start();
+        assert!(master.send(0xA3));
+        assert_eq!(master.receive(true), 0x22);
+        assert_eq!(master.receive(false), 0xFF);
+        master.stop();
+
+        // another device address is not acknowledged
+        master.start();
+        assert!(!master.send(0x52));
+        master.stop();
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    cartridge::cartridge::Cartridge,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+// the cartridge area of the m68k address space
+pub(crate) const CARTRIDGE_AREA_SIZE: usize = 0x400000;
+
+const SEGA_MAPPER_BANK_SIZE: usize = 0x80000;
+
+// translates the cartridge area addresses to the rom offsets
+pub(crate) trait Mapper {
+    // takes an address of 0x000000-0x3FFFFF
+    fn rom_offset(&self, address: u32) -> usize;
+
+    // the registers at 0xA13000-0xA130FF, the odd address of the written byte
+    fn write_register(&mut self, address: u32, data: u8);
+
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError>;
+}
+
+// the rom is wired straight to the cartridge area
+pub(crate) struct LinearMapper;
+
+impl Mapper for LinearMapper {
+    fn rom_offset(&self, address: u32) -> usize {
+        address as usize
+    }
+
+    fn write_register(&mut self, _address: u32, _data: u8) {}
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+// Sega 315-5779 of Super Street Fighter II: eight 512 KB windows,
+// the registers at 0xA130F3-0xA130FF select the rom banks of the windows 1-7
+pub(crate) struct SegaMapper {
+    banks: [u8; 8],
+}
+
+impl SegaMapper {
+    pub(crate) fn new() -> Self {
+        Self { banks: [0, 1, 2, 3, 4, 5, 6, 7] }
+    }
+}
+
+impl Mapper for SegaMapper {
+    fn rom_offset(&self, address: u32) -> usize {
+        let address = address as usize;
+        let window = address / SEGA_MAPPER_BANK_SIZE;
+        self.banks[window] as usize * SEGA_MAPPER_BANK_SIZE + address % SEGA_MAPPER_BANK_SIZE
+    }
+
+    fn write_register(&mut self, address: u32, data: u8) {
+        // 0xA130F1 is the sram control, the first window is fixed
+        if (0xA130F3..=0xA130FF).contains(&address) {
+            let window = ((address - 0xA130F1) / 2) as usize;
+            self.banks[window] = data & 0x3F;
+        }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.banks);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.banks)?;
+        for bank in self.banks.iter_mut() {
+            *bank &= 0x3F;
+        }
+        Ok(())
+    }
+}
+
+// the roms over 4 MB need the bank switching, smaller ones may declare it in the console name
+pub(crate) fn for_cartridge(cartridge: &Cartridge, rom_size: usize) -> Box<dyn Mapper> {
+    if rom_size > CARTRIDGE_AREA_SIZE || cartridge.console_name.starts_with("SEGA SSF") {
+        Box::new(SegaMapper::new())
+    } else {
+        Box::new(LinearMapper)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mapper, SegaMapper};
+
+    #[test]
+    fn sega_mapper_switches_windows() {
+        let mut mapper = SegaMapper::new();
+        assert_eq!(mapper.rom_offset(0x380010), 0x380010);
+
+        mapper.write_register(0xA130FF, 0x09);
+        mapper.write_register(0xA130F3, 0x0A);
+        assert_eq!(mapper.rom_offset(0x380010), 0x480010);
+        assert_eq!(mapper.rom_offset(0x080000), 0x500000);
+        // the sram control does not switch the first window
+        mapper.write_register(0xA130F1, 0x03);
+        assert_eq!(mapper.rom_offset(0x000100), 0x000100);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+use crate::vdp_emu::DisplayMod;
+
+pub mod cartridge;
+pub mod eeprom;
+pub(crate) mod mapper;
+pub mod patch;
+pub mod rom_database;
+pub mod rom_loader;
+pub(crate) mod sram;
+
+// console regions, the order of the variants is the auto detection priority
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    Americas, // NTSC-U
+    Europe,   // PAL
+    Japan,    // NTSC-J
+    Asia,     // PAL domestic
+}
+
+impl Region {
+    pub(crate) fn display_mod(&self) -> DisplayMod {
+        match self {
+            Region::Americas | Region::Japan => DisplayMod::NTSC,
+            Region::Europe | Region::Asia => DisplayMod::PAL,
+        }
+    }
+
+    // bit 7 - overseas model, bit 6 - PAL
+    pub(crate) fn version_register(&self) -> u8 {
+        match self {
+            Region::Japan => 0x00,
+            Region::Asia => 0x40,
+            Region::Americas => 0x80,
+            Region::Europe => 0xC0,
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Region::Americas => "Americas",
+            Region::Europe => "Europe",
+            Region::Japan => "Japan",
+            Region::Asia => "Asia",
+        };
+        write!(f, "{}", name)
+    }
+}
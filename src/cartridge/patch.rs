@@ -0,0 +1,380 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::cartridge::cartridge::rom_checksum;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// source, target and patch crc32
+const FOOTER_SIZE: usize = 12;
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+const CHECKSUM_OFFSET: usize = 0x18E;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PatchFormat {
+    Ips,
+    Ups,
+    Bps,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    UnexpectedEnd,
+    OutOfBounds,
+    PatchCrc,
+    SourceCrc(u32, u32), // expected, actual
+    TargetCrc(u32, u32),
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an ips, ups or bps patch"),
+            PatchError::UnexpectedEnd => write!(f, "patch is truncated"),
+            PatchError::OutOfBounds => write!(f, "patch refers outside of the rom"),
+            PatchError::PatchCrc => write!(f, "patch is corrupted"),
+            PatchError::SourceCrc(expected, actual) => {
+                write!(f, "patch is made for the rom {:08X}, this one is {:08X}", expected, actual)
+            }
+            PatchError::TargetCrc(expected, actual) => {
+                write!(f, "patched rom is {:08X} instead of {:08X}", actual, expected)
+            }
+        }
+    }
+}
+
+impl PatchFormat {
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(IPS_MAGIC) {
+            Some(PatchFormat::Ips)
+        } else if patch.starts_with(UPS_MAGIC) {
+            Some(PatchFormat::Ups)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Some(PatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+}
+
+// the patch with the rom name and a patch extension
+pub fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    match PatchFormat::detect(patch).ok_or(PatchError::UnknownFormat)? {
+        PatchFormat::Ips => apply_ips(rom, patch),
+        PatchFormat::Ups => apply_ups(rom, patch),
+        PatchFormat::Bps => apply_bps(rom, patch),
+    }
+}
+
+// writes the computed checksum to the header, the hacks usually leave the original one
+pub fn fix_checksum(rom: &mut [u8]) {
+    if rom.len() >= CHECKSUM_OFFSET + 2 {
+        let checksum = rom_checksum(rom);
+        rom[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 2].copy_from_slice(&checksum.to_be_bytes());
+    }
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl PatchReader<'_> {
+    fn bytes(&mut self, amount: usize) -> Result<&[u8], PatchError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + amount)
+            .ok_or(PatchError::UnexpectedEnd)?;
+        self.position += amount;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // big endian numbers of ips
+    fn number(&mut self, size: usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(size)?.iter().fold(0, |number, byte| number << 8 | *byte as usize))
+    }
+
+    // the variable length numbers of ups and bps, each byte adds 7 bits, the last one has the bit 7
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut number = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.u8()?;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::OutOfBounds)?;
+            number = number.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+}
+
+// records of 3 bytes offset and 2 bytes size, the zero size is a run of one byte,
+// the optional 3 bytes after the end mark truncate the rom
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader { data: patch, position: IPS_MAGIC.len() };
+    loop {
+        if reader.data[reader.position..].starts_with(IPS_EOF) {
+            reader.position += IPS_EOF.len();
+            if let Ok(size) = reader.number(3) {
+                target.truncate(size);
+            }
+            return Ok(target);
+        }
+        let offset = reader.number(3)?;
+        let size = reader.number(2)?;
+        let (size, bytes) = if size == 0 {
+            let size = reader.number(2)?;
+            (size, vec![reader.u8()?; size])
+        } else {
+            (size, reader.bytes(size)?.to_vec())
+        };
+        if target.len() < offset + size {
+            target.resize(offset + size, 0);
+        }
+        target[offset..offset + size].copy_from_slice(&bytes);
+    }
+}
+
+// checks the crc32 footer and splits it off
+fn checked_body(patch: &[u8]) -> Result<(&[u8], u32, u32), PatchError> {
+    if patch.len() < FOOTER_SIZE + 4 {
+        return Err(PatchError::UnexpectedEnd);
+    }
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+    let crc = |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    if crc32fast::hash(&patch[..patch.len() - 4]) != crc(2) {
+        return Err(PatchError::PatchCrc);
+    }
+    Ok((body, crc(0), crc(1)))
+}
+
+fn check_source(rom: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(rom);
+    if actual != expected {
+        return Err(PatchError::SourceCrc(expected, actual));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32fast::hash(target);
+    if actual != expected {
+        return Err(PatchError::TargetCrc(expected, actual));
+    }
+    Ok(())
+}
+
+// the xor runs between the skipped bytes, each run ends with a zero byte
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, source_crc, target_crc) = checked_body(patch)?;
+    check_source(rom, source_crc)?;
+    let mut reader = PatchReader { data: body, position: UPS_MAGIC.len() };
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut position = 0usize;
+    while reader.position < body.len() {
+        position = position.checked_add(reader.varint()?).ok_or(PatchError::OutOfBounds)?;
+        loop {
+            let byte = reader.u8()?;
+            if byte == 0 {
+                position += 1;
+                break;
+            }
+            *target.get_mut(position).ok_or(PatchError::OutOfBounds)? ^= byte;
+            position += 1;
+        }
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// the commands copy the source or the target bytes at the relative offsets or read the new bytes
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (body, source_crc, target_crc) = checked_body(patch)?;
+    check_source(rom, source_crc)?;
+    let mut reader = PatchReader { data: body, position: BPS_MAGIC.len() };
+    let _source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0usize;
+    let mut target_offset = 0usize;
+    while reader.position < body.len() {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        match data & 0x03 {
+            0 => {
+                // source read
+                let start = target.len();
+                let bytes = rom.get(start..start + length).ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+            }
+            1 => {
+                // target read
+                target.extend_from_slice(reader.bytes(length)?);
+            }
+            2 => {
+                source_offset = relative_offset(source_offset, reader.varint()?)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::OutOfBounds)?;
+                target.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            _ => {
+                // target copy, the copied bytes may overlap the written ones
+                target_offset = relative_offset(target_offset, reader.varint()?)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+        if target.len() > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+    }
+    if target.len() != target_size {
+        return Err(PatchError::UnexpectedEnd);
+    }
+    check_target(&target, target_crc)?;
+    Ok(target)
+}
+
+// the bit 0 is the sign
+fn relative_offset(offset: usize, data: usize) -> Result<usize, PatchError> {
+    let delta = data >> 1;
+    let offset = if data & 0x01 != 0 {
+        offset.checked_sub(delta)
+    } else {
+        offset.checked_add(delta)
+    };
+    offset.ok_or(PatchError::OutOfBounds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_patch, fix_checksum, PatchError};
+    use crate::cartridge::cartridge::rom_checksum;
+
+    fn varint(mut number: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let byte = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(byte | 0x80);
+                return bytes;
+            }
+            bytes.push(byte);
+            number -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn applies_ips_records() {
+        let rom = vec![0x00; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x04, 0xCC]); // run up to 0x0A
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply_patch(&rom, &patch).unwrap(),
+            [0x00, 0xAA, 0xBB, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC, 0xCC]
+        );
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply_patch(&rom, &patch).unwrap(), [0x00, 0xAA]);
+        assert_eq!(apply_patch(&rom, &patch[..9]), Err(PatchError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn applies_ups_and_bps_with_crc_checks() {
+        let source = b"SEGA GENESIS".to_vec();
+        let target = b"SEGA MEGA DRIVE!".to_vec();
+
+        // the xor runs of the changed bytes and the appended ones
+        let mut ups = b"UPS1".to_vec();
+        ups.extend(varint(source.len()));
+        ups.extend(varint(target.len()));
+        let xor = |i: usize| target[i] ^ source.get(i).copied().unwrap_or(0);
+        let (mut i, mut hunk_end) = (0, 0);
+        while i < target.len() {
+            if xor(i) == 0 {
+                i += 1;
+                continue;
+            }
+            ups.extend(varint(i - hunk_end));
+            while i < target.len() && xor(i) != 0 {
+                ups.push(xor(i));
+                i += 1;
+            }
+            ups.push(0);
+            i += 1;
+            hunk_end = i;
+        }
+        let ups = with_footer(ups, &source, &target);
+        assert_eq!(apply_patch(&source, &ups).unwrap(), target);
+
+        // source read "SEGA ", target read "MEGA", source copy " ", target copy "DRIVE!" is read
+        let mut bps = b"BPS1".to_vec();
+        bps.extend(varint(source.len()));
+        bps.extend(varint(target.len()));
+        bps.extend(varint(0));
+        bps.extend(varint((5 - 1) << 2));
+        bps.extend(varint((4 - 1) << 2 | 1));
+        bps.extend(b"MEGA");
+        bps.extend(varint(2)); // the source copy of 1 byte
+        bps.extend(varint(4 << 1)); // the space at 4
+        bps.extend(varint((6 - 1) << 2 | 1));
+        bps.extend(b"DRIVE!");
+        let bps = with_footer(bps, &source, &target);
+        assert_eq!(apply_patch(&source, &bps).unwrap(), target);
+
+        let other = b"SEGA CD".to_vec();
+        assert!(matches!(apply_patch(&other, &bps), Err(PatchError::SourceCrc(_, _))));
+        let mut broken = ups.clone();
+        broken[8] ^= 0x01;
+        assert_eq!(apply_patch(&source, &broken), Err(PatchError::PatchCrc));
+    }
+
+    #[test]
+    fn fixes_header_checksum() {
+        let mut rom = (0..0x400).map(|i| i as u8).collect::<Vec<u8>>();
+        fix_checksum(&mut rom);
+        assert_eq!(u16::from_be_bytes([rom[0x18E], rom[0x18F]]), rom_checksum(&rom));
+    }
+}
@@ -0,0 +1,321 @@
+use std::{fmt::Display, iter::Peekable, str::Chars};
+
+use crate::{
+    cartridge::{
+        cartridge::{rom_checksum, Cartridge, SramDescriptor},
+        eeprom::{EepromLines, EepromType},
+        Region,
+    },
+    controller::PadType,
+};
+
+const BUNDLED_DAT: &str = include_str!("segaret.dat");
+
+#[derive(Debug, PartialEq)]
+pub enum DatError {
+    UnexpectedEnd,
+    UnexpectedToken(String),
+    InvalidValue(&'static str, String),
+}
+
+impl Display for DatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatError::UnexpectedEnd => write!(f, "dat file is truncated"),
+            DatError::UnexpectedToken(token) => write!(f, "unexpected \"{}\" in the dat file", token),
+            DatError::InvalidValue(field, value) => write!(f, "invalid {} \"{}\" in the dat file", field, value),
+        }
+    }
+}
+
+// a game of the dat file with the hardware it needs
+#[derive(Clone, PartialEq, Debug)]
+pub struct DatEntry {
+    pub name: String,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha1: Option<String>, // lowercase hex
+    pub region: Option<Region>,
+    pub sram: Option<SramDescriptor>,
+    pub eeprom: Option<(EepromType, EepromLines)>,
+    pub pad: Option<PadType>,
+}
+
+pub struct RomDatabase {
+    entries: Vec<DatEntry>,
+}
+
+impl RomDatabase {
+    // the dat file compiled into the emulator
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_DAT).expect("the bundled dat file is broken")
+    }
+
+    // the clrmamepro text format: `game ( name "..." rom ( size 524288 crc F9394E97 sha1 ... ) )`
+    pub fn parse(text: &str) -> Result<Self, DatError> {
+        let mut tokens = Tokens { chars: text.chars().peekable() };
+        let mut entries = vec![];
+        while let Some(key) = tokens.next() {
+            let value = parse_value(&mut tokens)?;
+            if key == "game" {
+                entries.push(parse_game(value)?);
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[DatEntry] {
+        &self.entries
+    }
+
+    pub fn find(&self, size: usize, crc32: u32, sha1: &str) -> Option<&DatEntry> {
+        self.entries.iter().find(|entry| {
+            entry.size == size && entry.crc32 == crc32 && entry.sha1.as_ref().is_none_or(|entry_sha1| entry_sha1 == sha1)
+        })
+    }
+}
+
+// the checks of the loaded rom
+#[derive(Clone, PartialEq, Debug)]
+pub struct RomInfo {
+    pub checksum: u16, // computed, the header one is in the cartridge
+    pub checksum_valid: bool,
+    pub crc32: u32,
+    pub sha1: String,
+    pub entry: Option<DatEntry>,
+}
+
+impl RomInfo {
+    pub fn new(rom: &[u8], cartridge: &Cartridge, database: &RomDatabase) -> Self {
+        let checksum = rom_checksum(rom);
+        let crc32 = crc32fast::hash(rom);
+        let sha1 = sha1_smol::Sha1::from(rom).digest().to_string();
+        let entry = database.find(rom.len(), crc32, &sha1).cloned();
+        Self {
+            checksum,
+            checksum_valid: checksum == cartridge.checksum,
+            crc32,
+            sha1,
+            entry,
+        }
+    }
+}
+
+impl Display for RomInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let checksum_state = if self.checksum_valid { "valid" } else { "mismatch" };
+        writeln!(f, "Computed sum:   {:04X} ({})", self.checksum, checksum_state)?;
+        writeln!(f, "CRC32:          {:08X}", self.crc32)?;
+        writeln!(f, "SHA1:           {}", self.sha1)?;
+        match &self.entry {
+            Some(entry) => write!(f, "Database:       {}", entry.name),
+            None => write!(f, "Database:       unknown rom"),
+        }
+    }
+}
+
+enum DatValue {
+    Text(String),
+    Block(Vec<(String, DatValue)>),
+}
+
+struct Tokens<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = String;
+
+    // "(", ")", a quoted string or a word
+    fn next(&mut self) -> Option<String> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = self.chars.next()?;
+        let token = match first {
+            '(' | ')' => first.to_string(),
+            '"' => {
+                let text = self.chars.by_ref().take_while(|c| *c != '"').collect::<String>();
+                format!("\"{}", text)
+            }
+            _ => {
+                let mut word = first.to_string();
+                while let Some(c) = self.chars.next_if(|c| !c.is_whitespace() && *c != '(' && *c != ')') {
+                    word.push(c);
+                }
+                word
+            }
+        };
+        Some(token)
+    }
+}
+
+fn parse_value(tokens: &mut Tokens) -> Result<DatValue, DatError> {
+    let token = tokens.next().ok_or(DatError::UnexpectedEnd)?;
+    match token.as_str() {
+        "(" => {
+            let mut fields = vec![];
+            loop {
+                let key = tokens.next().ok_or(DatError::UnexpectedEnd)?;
+                if key == ")" {
+                    return Ok(DatValue::Block(fields));
+                }
+                if key == "(" || key.starts_with('"') {
+                    return Err(DatError::UnexpectedToken(key));
+                }
+                fields.push((key, parse_value(tokens)?));
+            }
+        }
+        ")" => Err(DatError::UnexpectedToken(token)),
+        _ => Ok(DatValue::Text(token.trim_start_matches('"').to_string())),
+    }
+}
+
+fn fields(value: DatValue) -> Result<Vec<(String, DatValue)>, DatError> {
+    match value {
+        DatValue::Block(fields) => Ok(fields),
+        DatValue::Text(text) => Err(DatError::UnexpectedToken(text)),
+    }
+}
+
+fn text(value: DatValue) -> Result<String, DatError> {
+    match value {
+        DatValue::Text(text) => Ok(text),
+        DatValue::Block(_) => Err(DatError::UnexpectedToken("(".to_string())),
+    }
+}
+
+fn hex(field: &'static str, value: DatValue) -> Result<u32, DatError> {
+    let text = text(value)?;
+    u32::from_str_radix(&text, 16).map_err(|_| DatError::InvalidValue(field, text))
+}
+
+fn parse_game(value: DatValue) -> Result<DatEntry, DatError> {
+    let mut entry = DatEntry {
+        name: String::new(),
+        size: 0,
+        crc32: 0,
+        sha1: None,
+        region: None,
+        sram: None,
+        eeprom: None,
+        pad: None,
+    };
+    for (key, value) in fields(value)? {
+        match key.as_str() {
+            "name" => entry.name = text(value)?,
+            "region" => entry.region = parse_region(&text(value)?),
+            "pad" => {
+                entry.pad = match text(value)?.as_str() {
+                    "3button" => Some(PadType::ThreeButton),
+                    "6button" => Some(PadType::SixButton),
+                    other => return Err(DatError::InvalidValue("pad", other.to_string())),
+                }
+            }
+            "rom" => {
+                for (key, value) in fields(value)? {
+                    match key.as_str() {
+                        "size" => {
+                            let size = text(value)?;
+                            entry.size = size.parse().map_err(|_| DatError::InvalidValue("size", size))?;
+                        }
+                        "crc" => entry.crc32 = hex("crc", value)?,
+                        "sha1" => entry.sha1 = Some(text(value)?.to_lowercase()),
+                        _ => (),
+                    }
+                }
+            }
+            "sram" => entry.sram = Some(parse_sram(value)?),
+            "eeprom" => entry.eeprom = Some(parse_eeprom(value)?),
+            _ => (), // description, year and the others
+        }
+    }
+    Ok(entry)
+}
+
+// the first region of the No-Intro names, the "World" games follow the header
+fn parse_region(region: &str) -> Option<Region> {
+    match region.split(',').next()?.trim() {
+        "USA" | "Brazil" => Some(Region::Americas),
+        "Europe" => Some(Region::Europe),
+        "Japan" => Some(Region::Japan),
+        "Asia" | "Korea" => Some(Region::Asia),
+        _ => None,
+    }
+}
+
+// `sram ( start 200001 end 203FFF layout odd )`, the layout is "word", "even" or "odd"
+fn parse_sram(value: DatValue) -> Result<SramDescriptor, DatError> {
+    let mut descriptor = SramDescriptor { kind: 0xE0, start: 0x200000, end: 0x20FFFF };
+    for (key, value) in fields(value)? {
+        match key.as_str() {
+            "start" => descriptor.start = hex("sram start", value)?,
+            "end" => descriptor.end = hex("sram end", value)?,
+            "layout" => {
+                descriptor.kind = match text(value)?.as_str() {
+                    "word" => 0xE0,
+                    "even" => 0xF0,
+                    "odd" => 0xF8,
+                    other => return Err(DatError::InvalidValue("sram layout", other.to_string())),
+                }
+            }
+            _ => return Err(DatError::UnexpectedToken(key)),
+        }
+    }
+    Ok(descriptor)
+}
+
+// `eeprom ( type 24C02 lines acclaim )`
+fn parse_eeprom(value: DatValue) -> Result<(EepromType, EepromLines), DatError> {
+    let mut kind = None;
+    let mut lines = None;
+    for (key, value) in fields(value)? {
+        let value = text(value)?;
+        match key.as_str() {
+            "type" => kind = Some(EepromType::from_name(&value).ok_or(DatError::InvalidValue("eeprom type", value))?),
+            "lines" => lines = Some(EepromLines::from_name(&value).ok_or(DatError::InvalidValue("eeprom lines", value))?),
+            _ => return Err(DatError::UnexpectedToken(key)),
+        }
+    }
+    match (kind, lines) {
+        (Some(kind), Some(lines)) => Ok((kind, lines)),
+        _ => Err(DatError::InvalidValue("eeprom", "incomplete".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DatError, RomDatabase};
+    use crate::{
+        cartridge::{eeprom::{EepromLines, EepromType}, Region},
+        controller::PadType,
+    };
+
+    #[test]
+    fn parses_dat_games() {
+        assert!(!RomDatabase::bundled().entries().is_empty());
+
+        let database = RomDatabase::parse(
+            r#"
+            clrmamepro ( name "test" )
+            game (
+                name "NBA Jam (USA, Europe)"
+                region "USA, Europe"
+                pad 6button
+                eeprom ( type 24C02 lines acclaim_old )
+                rom ( name "NBA Jam (USA, Europe).md" size 1048576 crc 0A1B2C3D sha1 ABCDEF )
+            )
+            "#,
+        )
+        .unwrap();
+        let entry = database.find(1048576, 0x0A1B2C3D, "abcdef").unwrap();
+        assert_eq!(entry.name, "NBA Jam (USA, Europe)");
+        assert_eq!(entry.region, Some(Region::Americas));
+        assert_eq!(entry.pad, Some(PadType::SixButton));
+        assert_eq!(entry.eeprom, Some((EepromType::C24C02, EepromLines::from_name("acclaim_old").unwrap())));
+        assert!(database.find(1048576, 0x0A1B2C3D, "012345").is_none());
+
+        assert_eq!(
+            RomDatabase::parse("game ( rom ( size 1 ) ").err(),
+            Some(DatError::UnexpectedEnd)
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use zip::ZipArchive;
+
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+const SMD_HEADER_SIZE: usize = 0x200;
+const SMD_BLOCK_SIZE: usize = 0x4000;
+const ROM_EXTENSIONS: [&str; 8] = ["bin", "md", "smd", "gen", "68k", "32x", "sms", "gg"];
+
+// the layout of the dump file
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RomFormat {
+    Bin,         // plain big endian image, also most of the .md and .gen files
+    ByteSwapped, // the bytes of each word are swapped
+    Smd,         // Super Magic Drive: 512 bytes header and 16 KB blocks of the odd and the even bytes
+    Mgd,         // Multi Game Doctor: the odd bytes in the first half, the even bytes in the second one
+    Copier,      // the Master System dump with the 512 bytes copier header
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(std::io::Error),
+    BrokenZip,
+    NoRomInZip,
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "can't read the rom: {}", error),
+            RomError::BrokenZip => write!(f, "broken zip archive"),
+            RomError::NoRomInZip => write!(f, "no rom in the zip archive"),
+        }
+    }
+}
+
+impl From<std::io::Error> for RomError {
+    fn from(error: std::io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+// reads the rom file or the rom of the zip archive
+pub fn load_rom(path: &Path) -> Result<(Vec<u8>, RomFormat), RomError> {
+    Ok(decode_rom(read_rom_file(path)?))
+}
+
+// the Master System and the Game Gear dumps have no Mega Drive header to detect the format by
+pub fn load_master_system_rom(path: &Path) -> Result<(Vec<u8>, RomFormat), RomError> {
+    Ok(decode_master_system_rom(read_rom_file(path)?))
+}
+
+fn read_rom_file(path: &Path) -> Result<Vec<u8>, RomError> {
+    let mut data = vec![];
+    File::open(path)?.read_to_end(&mut data)?;
+    if data.starts_with(&ZIP_MAGIC) {
+        data = unzip_rom(data)?;
+    }
+    Ok(data)
+}
+
+// picks the file with a rom extension or the largest one
+fn unzip_rom(data: Vec<u8>) -> Result<Vec<u8>, RomError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(|_| RomError::BrokenZip)?;
+    let mut candidate: Option<(usize, u64, bool)> = None; // index, size, rom extension
+    for index in 0..archive.len() {
+        let file = archive.by_index(index).map_err(|_| RomError::BrokenZip)?;
+        if file.is_dir() {
+            continue;
+        }
+        let rom_extension = Path::new(file.name())
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        let better = match candidate {
+            None => true,
+            Some((_, size, candidate_extension)) => {
+                (rom_extension, file.size()) > (candidate_extension, size)
+            }
+        };
+        if better {
+            candidate = Some((index, file.size(), rom_extension));
+        }
+    }
+    let (index, _, _) = candidate.ok_or(RomError::NoRomInZip)?;
+    let mut rom = vec![];
+    archive
+        .by_index(index)
+        .map_err(|_| RomError::BrokenZip)?
+        .read_to_end(&mut rom)
+        .map_err(|_| RomError::BrokenZip)?;
+    Ok(rom)
+}
+
+// converts the dump to the plain image, the format is detected by the "SEGA" signature of the header
+pub fn decode_rom(data: Vec<u8>) -> (Vec<u8>, RomFormat) {
+    if has_signature(&data) {
+        (data, RomFormat::Bin)
+    } else if has_signature(&swap_bytes(&data[..data.len().min(0x200)])) {
+        (swap_bytes(&data), RomFormat::ByteSwapped)
+    } else if is_smd(&data) {
+        (deinterleave_smd(&data[SMD_HEADER_SIZE..]), RomFormat::Smd)
+    } else if has_signature(&deinterleave_mgd(&data)) {
+        (deinterleave_mgd(&data), RomFormat::Mgd)
+    } else {
+        (data, RomFormat::Bin) // a homebrew rom may have no header
+    }
+}
+
+// the copier header is the only thing to strip, the size of a plain dump is a multiple of 16 KB
+pub fn decode_master_system_rom(mut data: Vec<u8>) -> (Vec<u8>, RomFormat) {
+    if data.len() % SMD_BLOCK_SIZE == SMD_HEADER_SIZE {
+        data.drain(..SMD_HEADER_SIZE);
+        (data, RomFormat::Copier)
+    } else {
+        (data, RomFormat::Bin)
+    }
+}
+
+// "SEGA" at 0x100, a few games start the console name with a space
+fn has_signature(rom: &[u8]) -> bool {
+    rom.get(0x100..0x104) == Some(b"SEGA") || rom.get(0x101..0x105) == Some(b"SEGA")
+}
+
+// the copier header has the 0xAA 0xBB marker, the headers of some dumps are blank
+fn is_smd(data: &[u8]) -> bool {
+    if data.len() <= SMD_HEADER_SIZE || !(data.len() - SMD_HEADER_SIZE).is_multiple_of(SMD_BLOCK_SIZE) {
+        return false;
+    }
+    let marker = data[8] == 0xAA && data[9] == 0xBB;
+    marker || has_signature(&deinterleave_smd(&data[SMD_HEADER_SIZE..SMD_HEADER_SIZE + SMD_BLOCK_SIZE]))
+}
+
+fn swap_bytes(data: &[u8]) -> Vec<u8> {
+    let mut rom = data.to_vec();
+    for word in rom.chunks_exact_mut(2) {
+        word.swap(0, 1);
+    }
+    rom
+}
+
+// each block keeps the odd bytes in the first half
+fn deinterleave_smd(data: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0; data.len()];
+    for (block, rom_block) in data.chunks(SMD_BLOCK_SIZE).zip(rom.chunks_mut(SMD_BLOCK_SIZE)) {
+        let half = block.len() / 2;
+        for i in 0..half {
+            rom_block[i * 2] = block[half + i];
+            rom_block[i * 2 + 1] = block[i];
+        }
+    }
+    rom
+}
+
+fn deinterleave_mgd(data: &[u8]) -> Vec<u8> {
+    let half = data.len() / 2;
+    let mut rom = vec![0; half * 2];
+    for i in 0..half {
+        rom[i * 2] = data[half + i];
+        rom[i * 2 + 1] = data[i];
+    }
+    rom
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::{decode_master_system_rom, decode_rom, unzip_rom, RomFormat, SMD_BLOCK_SIZE, SMD_HEADER_SIZE};
+
+    fn plain_rom() -> Vec<u8> {
+        let mut rom = (0..SMD_BLOCK_SIZE * 2).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        rom[0x100..0x110].copy_from_slice(b"SEGA MEGA DRIVE ");
+        rom
+    }
+
+    #[test]
+    fn decodes_dump_formats() {
+        let rom = plain_rom();
+        assert_eq!(decode_rom(rom.clone()), (rom.clone(), RomFormat::Bin));
+
+        let swapped = rom.chunks(2).flat_map(|word| [word[1], word[0]]).collect::<Vec<u8>>();
+        assert_eq!(decode_rom(swapped), (rom.clone(), RomFormat::ByteSwapped));
+
+        let mut smd = vec![0; SMD_HEADER_SIZE];
+        smd[1] = 0x03;
+        smd[8] = 0xAA;
+        smd[9] = 0xBB;
+        for block in rom.chunks(SMD_BLOCK_SIZE) {
+            smd.extend(block.iter().skip(1).step_by(2));
+            smd.extend(block.iter().step_by(2));
+        }
+        assert_eq!(decode_rom(smd), (rom.clone(), RomFormat::Smd));
+
+        let mut mgd = rom.iter().skip(1).step_by(2).copied().collect::<Vec<u8>>();
+        mgd.extend(rom.iter().step_by(2));
+        assert_eq!(decode_rom(mgd), (rom.clone(), RomFormat::Mgd));
+    }
+
+    #[test]
+    fn strips_master_system_copier_header() {
+        let rom = plain_rom();
+        assert_eq!(decode_master_system_rom(rom.clone()), (rom.clone(), RomFormat::Bin));
+
+        let mut headered = vec![0; SMD_HEADER_SIZE];
+        headered.extend(&rom);
+        assert_eq!(decode_master_system_rom(headered), (rom, RomFormat::Copier));
+    }
+
+    #[test]
+    fn unzips_rom_file() {
+        let rom = plain_rom();
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        writer.start_file("readme.txt", SimpleFileOptions::default()).unwrap();
+        writer.write_all(&[0x20; SMD_BLOCK_SIZE * 4]).unwrap();
+        writer.start_file("Game (U).MD", SimpleFileOptions::default()).unwrap();
+        writer.write_all(&rom).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        assert_eq!(unzip_rom(archive).unwrap(), rom);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    cartridge::cartridge::SramDescriptor,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum SramLayout {
+    Word, // both bytes of the bus
+    Even, // the upper byte only
+    Odd,  // the lower byte only
+}
+
+// battery backed ram of the cartridge
+pub(crate) struct Sram {
+    data: Vec<u8>,
+    start: u32,
+    end: u32,
+    layout: SramLayout,
+
+    mapped: bool, // 0xA130F1 bit 0, the ram replaces the rom in its range
+    write_protected: bool, // 0xA130F1 bit 1
+    changed: bool, // written since the last `take_changed`
+}
+
+impl Sram {
+    // the ram beyond the rom end is mapped from the start, the overlapped one is switched by 0xA130F1
+    pub(crate) fn new(descriptor: &SramDescriptor, rom_size: usize) -> Self {
+        let layout = match (descriptor.kind >> 3) & 0x03 {
+            2 => SramLayout::Even,
+            3 => SramLayout::Odd,
+            _ => SramLayout::Word,
+        };
+        let start = descriptor.start & 0x3FFFFF;
+        let end = (descriptor.end & 0x3FFFFF).max(start);
+        let size = match layout {
+            SramLayout::Word => end - start + 1,
+            _ => (end - start) / 2 + 1,
+        };
+        Self {
+            data: vec![0xFF; size as usize],
+            start,
+            end,
+            layout,
+
+            mapped: start as usize >= rom_size,
+            write_protected: false,
+            changed: false,
+        }
+    }
+
+    pub(crate) fn contains(&self, address: u32) -> bool {
+        self.mapped && address >= self.start & !0x01 && address <= self.end | 0x01
+    }
+
+    pub(crate) fn read(&self, address: u32) -> u8 {
+        match self.offset(address) {
+            Some(offset) => self.data[offset],
+            None => 0xFF,
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u32, data: u8) {
+        if self.write_protected {
+            return;
+        }
+        if let Some(offset) = self.offset(address) {
+            self.changed |= self.data[offset] != data;
+            self.data[offset] = data;
+        }
+    }
+
+    // the 0xA130F1 register
+    pub(crate) fn write_control(&mut self, data: u8) {
+        self.mapped = data & 0x01 != 0;
+        self.write_protected = data & 0x02 != 0;
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // a save file of another size is loaded partially
+    pub(crate) fn load(&mut self, data: &[u8]) {
+        let size = data.len().min(self.data.len());
+        self.data[..size].copy_from_slice(&data[..size]);
+        self.changed = false;
+    }
+
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn offset(&self, address: u32) -> Option<usize> {
+        let offset = match self.layout {
+            SramLayout::Word => address.checked_sub(self.start)?,
+            SramLayout::Even if address & 0x01 == 0 => address.checked_sub(self.start & !0x01)? >> 1,
+            SramLayout::Odd if address & 0x01 != 0 => address.checked_sub(self.start & !0x01)? >> 1,
+            _ => return None, // the other byte of the bus is not connected
+        } as usize;
+        if offset < self.data.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.data);
+        writer.write_bool(self.mapped);
+        writer.write_bool(self.write_protected);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.data)?;
+        self.mapped = reader.read_bool()?;
+        self.write_protected = reader.read_bool()?;
+        self.changed = true; // the save file follows the loaded state
+        Ok(())
+    }
+}
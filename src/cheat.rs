@@ -0,0 +1,214 @@
+use std::fmt::Display;
+
+// 5 bits per character
+const GAME_GENIE_CHARS: &[u8; 32] = b"ABCDEFGHJKLMNPRSTVWXYZ0123456789";
+// the code bits in the order of the characters: the capitals are the address bits from A - bit 23,
+// the small letters are the data bits from a - bit 15
+const GAME_GENIE_BITS: &[u8; 40] = b"ijklmnopIJKLMNOPABCDEFGHdefghabcQRSTUVWX";
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    InvalidCode(String),
+    InvalidLine(usize, String),
+}
+
+impl Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(f, "invalid cheat code \"{}\"", code),
+            CheatError::InvalidLine(line, code) => {
+                write!(f, "invalid cheat code \"{}\" at the line {}", code, line)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CheatEffect {
+    // the word at the even address of the cartridge area replaces the rom data on the reads
+    RomPatch { address: u32, data: u16 },
+    // the value is written to the m68k ram after each frame
+    RamWrite { address: u32, data: u16, word: bool },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cheat {
+    pub code: String,
+    pub description: String,
+    pub enabled: bool,
+    pub effect: CheatEffect,
+}
+
+impl Cheat {
+    // a Game Genie "ABCD-EFGH" or a Pro Action Replay "FF0000:0001" code
+    pub fn new(code: &str, description: &str) -> Result<Self, CheatError> {
+        let effect = if code.contains(':') {
+            decode_action_replay(code)?
+        } else {
+            let (address, data) = decode_game_genie(code)?;
+            CheatEffect::RomPatch { address, data }
+        };
+        Ok(Self {
+            code: code.to_uppercase(),
+            description: description.to_string(),
+            enabled: true,
+            effect,
+        })
+    }
+}
+
+// returns the address and the data of the patch
+pub fn decode_game_genie(code: &str) -> Result<(u32, u16), CheatError> {
+    let invalid = || CheatError::InvalidCode(code.to_string());
+    let chars = code.to_uppercase().bytes().filter(|c| *c != b'-').collect::<Vec<u8>>();
+    if chars.len() != 8 {
+        return Err(invalid());
+    }
+    let mut address = 0u32;
+    let mut data = 0u16;
+    for (index, c) in chars.iter().enumerate() {
+        let value = GAME_GENIE_CHARS.iter().position(|gg| gg == c).ok_or_else(invalid)?;
+        for bit in 0..5 {
+            if value & (0x10 >> bit) == 0 {
+                continue;
+            }
+            match GAME_GENIE_BITS[index * 5 + bit] {
+                letter @ b'A'..=b'X' => address |= 1 << (23 - (letter - b'A')),
+                letter => data |= 1 << (15 - (letter - b'a')),
+            }
+        }
+    }
+    if address & 0x01 != 0 {
+        return Err(invalid());
+    }
+    Ok((address, data))
+}
+
+// "AAAAAA:DDDD" writes a word, "AAAAAA:DD" writes a byte, the cartridge addresses patch the rom
+pub fn decode_action_replay(code: &str) -> Result<CheatEffect, CheatError> {
+    let invalid = || CheatError::InvalidCode(code.to_string());
+    let (address, data) = code.split_once(':').ok_or_else(invalid)?;
+    if address.len() != 6 || !(data.len() == 2 || data.len() == 4) {
+        return Err(invalid());
+    }
+    let word = data.len() == 4;
+    let address = u32::from_str_radix(address, 16).map_err(|_| invalid())?;
+    let data = u16::from_str_radix(data, 16).map_err(|_| invalid())?;
+    match address {
+        0x000000..=0x3FFFFF if word && address & 0x01 == 0 => Ok(CheatEffect::RomPatch { address, data }),
+        // the ram is mirrored over 0xE00000-0xFFFFFF
+        0xE00000..=0xFFFFFF if !word || address & 0x01 == 0 => Ok(CheatEffect::RamWrite {
+            address: address & 0xFFFF,
+            data,
+            word,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+// a code and the description per line, "!" before the code disables it, "#" starts a comment line
+pub fn parse_cheat_file(text: &str) -> Result<Vec<Cheat>, CheatError> {
+    let mut cheats = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let (code, enabled) = match code.strip_prefix('!') {
+            Some(code) => (code, false),
+            None => (code, true),
+        };
+        let mut cheat = Cheat::new(code, description.trim())
+            .map_err(|_| CheatError::InvalidLine(index + 1, code.to_string()))?;
+        cheat.enabled = enabled;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
+
+pub fn write_cheat_file(cheats: &[Cheat]) -> String {
+    cheats
+        .iter()
+        .map(|cheat| {
+            let disabled = if cheat.enabled { "" } else { "!" };
+            format!("{}{} {}\n", disabled, cheat.code, cheat.description)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        decode_action_replay, decode_game_genie, parse_cheat_file, write_cheat_file, CheatEffect,
+        CheatError, GAME_GENIE_BITS, GAME_GENIE_CHARS,
+    };
+
+    fn encode_game_genie(address: u32, data: u16) -> String {
+        let mut code = String::new();
+        for index in 0..8 {
+            let mut value = 0;
+            for bit in 0..5 {
+                let set = match GAME_GENIE_BITS[index * 5 + bit] {
+                    letter @ b'A'..=b'X' => address & (1 << (23 - (letter - b'A'))) != 0,
+                    letter => data & (1 << (15 - (letter - b'a'))) != 0,
+                };
+                value |= (set as usize) << (4 - bit);
+            }
+            code.push(GAME_GENIE_CHARS[value] as char);
+            if index == 3 {
+                code.push('-');
+            }
+        }
+        code
+    }
+
+    #[test]
+    fn decodes_game_genie_codes() {
+        assert_eq!(decode_game_genie("AAAA-AAAA"), Ok((0, 0)));
+        // the bits are shuffled, the last bit of the 5th character is the data bit 12
+        assert_eq!(decode_game_genie("AAAA-BAAA"), Ok((0, 0x1000)));
+        assert_eq!(decode_game_genie("AAAB-AAAA"), Ok((0x100000, 0)));
+        for (address, data) in [(0x000200, 0x4E71), (0x3FFFFE, 0xFFFF), (0x01F3A4, 0x6002)] {
+            assert_eq!(decode_game_genie(&encode_game_genie(address, data)), Ok((address, data)));
+        }
+        assert!(decode_game_genie("AAAA-AAAI").is_err());
+        assert!(decode_game_genie("AAA-AAAA").is_err());
+    }
+
+    #[test]
+    fn decodes_action_replay_codes() {
+        assert_eq!(
+            decode_action_replay("FFA010:0063"),
+            Ok(CheatEffect::RamWrite { address: 0xA010, data: 0x63, word: true })
+        );
+        assert_eq!(
+            decode_action_replay("FFA011:09"),
+            Ok(CheatEffect::RamWrite { address: 0xA011, data: 0x09, word: false })
+        );
+        assert_eq!(
+            decode_action_replay("0012F4:4E71"),
+            Ok(CheatEffect::RomPatch { address: 0x12F4, data: 0x4E71 })
+        );
+        assert!(decode_action_replay("FFA011:0063").is_err());
+        assert!(decode_action_replay("A10000:0001").is_err());
+    }
+
+    #[test]
+    fn reads_cheat_file() {
+        let text = "# Game\nFFA010:0063 Infinite lives\n\n!AAAB-AAAA Level select\n";
+        let cheats = parse_cheat_file(text).unwrap();
+        assert_eq!(cheats.len(), 2);
+        assert_eq!(cheats[0].description, "Infinite lives");
+        assert!(cheats[0].enabled);
+        assert!(!cheats[1].enabled);
+        assert_eq!(
+            write_cheat_file(&cheats),
+            "FFA010:0063 Infinite lives\n!AAAB-AAAA Level select\n"
+        );
+        assert_eq!(
+            parse_cheat_file("FFA010:0063\nXYZ"),
+            Err(CheatError::InvalidLine(2, "XYZ".to_string()))
+        );
+    }
+}
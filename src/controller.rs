@@ -1,5 +1,10 @@
-use spriter::{if_holded, Key};
+use crate::save_state::{StateError, StateReader, StateWriter};
 
+// the 6 button pad resets its TH counter if TH is not toggled for ~1.5 ms
+const TH_COUNTER_TIMEOUT: u32 = 80_000; // master clocks
+const BUTTONS_MASK: u16 = 0x0FFF;
+
+// the values are the bit numbers in the pad data
 pub enum Button {
     Up = 0,
     Down = 1,
@@ -9,86 +14,171 @@ pub enum Button {
     C = 5,
     A = 6,
     Start = 7,
+    Z = 8,
+    Y = 9,
+    X = 10,
+    Mode = 11,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PadType {
+    ThreeButton,
+    SixButton,
 }
 
 pub struct Controller {
-    button_selector: bool,
-    data: u8,
+    pad_type: PadType,
+    button_selector: bool, // TH line
+    data: u16,             // active low buttons
+    th_counter: u8,        // TH low to high transitions, the 6 button pad only
+    th_timeout: u32,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Controller {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         Self {
+            pad_type: PadType::ThreeButton,
             button_selector: false,
-            data: 0xFF,
+            data: 0xFFFF,
+            th_counter: 0,
+            th_timeout: 0,
         }
     }
 
-    pub(crate) fn clock(&mut self) {
-        self.data = 0xFF;
-        if_holded!(Key::Space, {
-            self.press_button(Button::Start);
-        });
-        if_holded!(Key::Right, {
-            self.press_button(Button::Right);
-        });
-        if_holded!(Key::Left, {
-            self.press_button(Button::Left);
-        });
-        if_holded!(Key::Up, {
-            self.press_button(Button::Up);
-        });
-        if_holded!(Key::Down, {
-            self.press_button(Button::Down);
-        });
-        if_holded!(Key::B, {
-            self.press_button(Button::A);
-        });
-        if_holded!(Key::N, {
-            self.press_button(Button::B);
-        });
-        if_holded!(Key::M, {
-            self.press_button(Button::C);
-        });
+    pub fn pad_type(&self) -> PadType {
+        self.pad_type
+    }
+
+    pub fn set_pad_type(&mut self, pad_type: PadType) {
+        self.pad_type = pad_type;
+        self.th_counter = 0;
+        self.th_timeout = 0;
+    }
+
+    pub fn release_all(&mut self) {
+        self.data = 0xFFFF;
+    }
+
+    // counts down the TH counter reset, takes master clocks
+    pub(crate) fn clock(&mut self, master_cycles: u32) {
+        if self.th_timeout > 0 {
+            self.th_timeout = self.th_timeout.saturating_sub(master_cycles);
+            if self.th_timeout == 0 {
+                self.th_counter = 0;
+            }
+        }
     }
 
     pub(crate) fn read(&self) -> u8 {
+        let data = self.data as u8;
+        let start_a = data >> 6 & 0x3;
+        let six_button = self.pad_type == PadType::SixButton;
         if self.button_selector {
-            self.data & 0x3F
+            if six_button && self.th_counter == 4 {
+                // C B Mode X Y Z
+                data & 0x30 | (self.data >> 8) as u8 & 0x0F
+            } else {
+                data & 0x3F
+            }
+        } else if six_button && self.th_counter == 3 {
+            start_a << 4 // the low nibble is cleared to identify the 6 button pad
+        } else if six_button && self.th_counter == 4 {
+            start_a << 4 | 0x0F
         } else {
-            let down_up = self.data & 0x3;
-            let start_a = self.data >> 6 & 0x3;
+            let down_up = data & 0x3;
             start_a << 4 | down_up
         }
     }
 
     pub(crate) fn write(&mut self, data: u8) {
-        self.button_selector = data & 0x40 != 0;
-    }
-
-    pub(crate) fn press_button(&mut self, button: Button) {
-        self.data &= match button {
-            Button::Up => 0xFE,
-            Button::Down => 0xFD,
-            Button::Left => 0xFB,
-            Button::Right => 0xF7,
-            Button::B => 0xEF,
-            Button::C => 0xDF,
-            Button::A => 0xBF,
-            Button::Start => 0x7F,
+        let button_selector = data & 0x40 != 0;
+        if self.pad_type == PadType::SixButton && button_selector && !self.button_selector {
+            self.th_counter = if self.th_counter >= 4 { 1 } else { self.th_counter + 1 };
+            self.th_timeout = TH_COUNTER_TIMEOUT;
         }
+        self.button_selector = button_selector;
+    }
+
+    // the pressed buttons, the bit numbers are the `Button` values
+    pub fn buttons(&self) -> u16 {
+        !self.data & BUTTONS_MASK
+    }
+
+    pub fn set_buttons(&mut self, buttons: u16) {
+        self.data = !(buttons & BUTTONS_MASK);
     }
 
-    pub(crate) fn release_button(&mut self, button: Button) {
-        self.data |= match button {
-            Button::Up => 0x01,
-            Button::Down => 0x02,
-            Button::Left => 0x04,
-            Button::Right => 0x08,
-            Button::B => 0x10,
-            Button::C => 0x20,
-            Button::A => 0x40,
-            Button::Start => 0x80,
+    pub fn press_button(&mut self, button: Button) {
+        self.data &= !(1 << button as u16);
+    }
+
+    pub fn release_button(&mut self, button: Button) {
+        self.data |= 1 << button as u16;
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.button_selector);
+        writer.write_u16(self.data);
+        writer.write_u8(self.th_counter);
+        writer.write_u32(self.th_timeout);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.button_selector = reader.read_bool()?;
+        self.data = reader.read_u16()?;
+        self.th_counter = reader.read_u8()?;
+        if self.th_counter > 4 {
+            return Err(StateError::InvalidValue("controller TH counter"));
+        }
+        self.th_timeout = reader.read_u32()?.min(TH_COUNTER_TIMEOUT);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Button, Controller, PadType, TH_COUNTER_TIMEOUT};
+
+    // reads the pad after each of 4 TH high and low writes
+    fn read_cycle(controller: &mut Controller) -> Vec<u8> {
+        let mut reads = vec![];
+        for _ in 0..4 {
+            controller.write(0x40);
+            reads.push(controller.read());
+            controller.write(0x00);
+            reads.push(controller.read());
         }
+        reads
+    }
+
+    #[test]
+    fn six_button_pad_protocol() {
+        let mut controller = Controller::new();
+        controller.set_pad_type(PadType::SixButton);
+        controller.press_button(Button::Up);
+        controller.press_button(Button::A);
+        controller.press_button(Button::X);
+        controller.press_button(Button::Mode);
+
+        let reads = read_cycle(&mut controller);
+        assert_eq!(&reads[..5], &[0x3E, 0x22, 0x3E, 0x22, 0x3E]);
+        assert_eq!(reads[5], 0x20); // SA0000
+        assert_eq!(reads[6], 0x33); // CBMXYZ
+        assert_eq!(reads[7], 0x2F); // SA1111
+
+        // the counter continues without a pause and resets after the timeout
+        controller.write(0x40);
+        controller.write(0x00);
+        controller.clock(TH_COUNTER_TIMEOUT);
+        assert_eq!(read_cycle(&mut controller)[5], 0x20);
+
+        controller.set_pad_type(PadType::ThreeButton);
+        assert_eq!(read_cycle(&mut controller)[5..], [0x22, 0x3E, 0x22]);
     }
 }
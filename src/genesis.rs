@@ -0,0 +1,1076 @@
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    rc::Rc,
+};
+
+use log::debug;
+use m68k_emu::cpu::{M68k, M68kState};
+use sh2_emu::cpu::{Sh2, Sh2State};
+use z80_emu::cpu::{Z80State, Z80};
+
+use crate::{
+    audio::{mixer::Mixer, AudioSink, SAMPLE_RATE_44100},
+    cartridge::{
+        cartridge::Cartridge,
+        eeprom::Eeprom,
+        mapper,
+        rom_database::{RomDatabase, RomInfo},
+        sram::Sram,
+        Region,
+    },
+    cheat::{Cheat, CheatEffect},
+    controller::{Controller, PadType},
+    master_system::MasterSystem,
+    mega_cd::{disc::Disc, MegaCd, SUB_CPU_CLOCK},
+    memory_space::MemorySpace,
+    movie::{Movie, MovieError, MovieMode, MovieSession, MovieStart},
+    multitap::FourWayPlay,
+    ram_search::SearchRegion,
+    save_state::{rom_fingerprint, StateError, StateReader, StateWriter},
+    sega_32x::{Sega32x, Sh2Bus, MASTER, SLAVE},
+    signal_bus::{Signal, SignalBus},
+    tmss::Tmss,
+    sn76489::sn76489::Sn76489,
+    vdp_emu::{vdp_emu::Vdp, DisplayMod},
+    vgm::vgm_logger::VgmLogger,
+    ym2612::ym2612::Ym2612,
+};
+
+pub use crate::vdp_emu::vdp_emu::{VRAM_TABLE_HEIGHT, VRAM_TABLE_WIDTH};
+
+pub const MASTER_CLOCK_NTSC: u64 = 53_693_175; // Hz
+pub const MASTER_CLOCK_PAL: u64 = 53_203_424; // Hz
+
+pub(crate) const M68K_CLOCK_DIVIDER: u64 = 7;
+pub(crate) const Z80_CLOCK_DIVIDER: u64 = 15;
+pub(crate) const YM2612_CLOCK_DIVIDER: u64 = M68K_CLOCK_DIVIDER * 6;
+pub(crate) const YM2612_SAMPLE_DIVIDER: u64 = YM2612_CLOCK_DIVIDER * 24; // the sound chips output rate is ~53 kHz
+pub(crate) const PSG_CLOCK_DIVIDER: u64 = Z80_CLOCK_DIVIDER * 16;
+// the m68k is off in the Master System mode, the time moves by the z80 steps
+pub(crate) const MASTER_SYSTEM_STEP: u64 = Z80_CLOCK_DIVIDER * 4;
+// the sh2s of the 32X run at 3/7 of the master clock
+pub(crate) const SH2_CLOCK_MULTIPLIER: u64 = 3;
+// output samples collected by the mixer before they are passed to the sink
+pub(crate) const AUDIO_FLUSH_SIZE: usize = 2048;
+
+pub(crate) type GenesisBus = MemorySpace<Vdp, Ym2612, Sn76489>;
+
+// The whole console without any frontend: the caller feeds the controllers,
+// steps the machine and takes the rendered frame from `frame_buffer`
+pub struct Genesis {
+    cartridge: Cartridge,
+    rom_info: RomInfo,
+    m68k: M68k<GenesisBus>,
+    z80: Z80<GenesisBus>,
+    vdp: Rc<RefCell<Vdp>>,
+    ym2612: Rc<RefCell<Ym2612>>,
+    psg: Rc<RefCell<Sn76489>>,
+    memory_space: Rc<RefCell<GenesisBus>>,
+    signal_bus: Rc<RefCell<SignalBus>>,
+
+    controller_1: Rc<RefCell<Controller>>,
+    controller_2: Rc<RefCell<Controller>>,
+    // the multitap pads, they are connected by `connect_four_way_play`
+    controller_3: Rc<RefCell<Controller>>,
+    controller_4: Rc<RefCell<Controller>>,
+
+    display_mod: DisplayMod,
+    z80_bus_request: bool,
+
+    // master clock timestamps of the moment when each chip is ready for its next step
+    m68k_cycle: u64,
+    z80_cycle: u64,
+    vdp_cycle: u64,
+    ym2612_cycle: u64,
+    psg_cycle: u64,
+
+    mixer: Mixer,
+    audio_sink: Option<Box<dyn AudioSink>>,
+
+    vgm_logger: Option<Rc<RefCell<VgmLogger>>>,
+
+    cheats: Vec<Cheat>,
+
+    mega_cd: Option<Rc<RefCell<MegaCd>>>,
+    sub_m68k: Option<M68k<MegaCd>>,
+    sub_cycle: u64, // sub cpu clocks from the power on
+
+    sega_32x: Option<Rc<RefCell<Sega32x>>>,
+    sh2: Option<[Sh2<Sh2Bus>; 2]>, // the master and the slave
+    sh2_cycles: [u64; 2], // sh2 clocks from the power on
+
+    master_system: Option<Rc<RefCell<MasterSystem>>>,
+
+    movie: Option<MovieSession>,
+    // the movie input is polled by the first clock of each frame
+    frame_start: bool,
+}
+
+impl Genesis {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self::with_database(rom, &RomDatabase::bundled())
+    }
+
+    // the database entry of the rom overrides the devices declared by the header
+    pub fn with_database(rom: Vec<u8>, database: &RomDatabase) -> Self {
+        let cartridge = Cartridge::new(&rom);
+        let rom_info = RomInfo::new(&rom, &cartridge, database);
+        let entry = rom_info.entry.as_ref();
+        let region = entry
+            .and_then(|entry| entry.region)
+            .unwrap_or_else(|| cartridge.preferred_region());
+        let mapper = mapper::for_cartridge(&cartridge, rom.len());
+        let eeprom = match entry.and_then(|entry| entry.eeprom) {
+            Some((kind, lines)) => Some(Eeprom::new(kind, lines)),
+            None => Eeprom::for_serial(&cartridge.serial),
+        };
+        // the eeprom games also declare the external memory in the header
+        let sram = match (eeprom.is_some(), entry.and_then(|entry| entry.sram)) {
+            (true, _) => None,
+            (false, Some(descriptor)) => Some(Sram::new(&descriptor, rom.len())),
+            (false, None) => cartridge.sram.map(|descriptor| Sram::new(&descriptor, rom.len())),
+        };
+        let pad_type = entry
+            .and_then(|entry| entry.pad)
+            .or_else(|| cartridge.six_button_pad().then_some(PadType::SixButton));
+
+        let genesis = Self::with_cartridge(rom, cartridge, rom_info, region);
+        {
+            let mut memory_space = genesis.memory_space.borrow_mut();
+            memory_space.sram = sram;
+            memory_space.eeprom = eeprom;
+            memory_space.mapper = mapper;
+        }
+        if let Some(pad_type) = pad_type {
+            genesis.controller_1.borrow_mut().set_pad_type(pad_type);
+            genesis.controller_2.borrow_mut().set_pad_type(pad_type);
+        }
+        genesis
+    }
+
+    // the console with the plain rom and no cartridge devices
+    fn with_cartridge(rom: Vec<u8>, cartridge: Cartridge, rom_info: RomInfo, region: Region) -> Self {
+        let display_mod = region.display_mod();
+        let controller_1 = Rc::new(RefCell::new(Controller::new()));
+        let controller_2 = Rc::new(RefCell::new(Controller::new()));
+        let signal_bus = Rc::new(RefCell::new(SignalBus::new()));
+        let vdp = Rc::new(RefCell::new(Vdp::new(signal_bus.clone(), display_mod)));
+        let ym2612 = Rc::new(RefCell::new(Ym2612::new()));
+        let psg = Rc::new(RefCell::new(Sn76489::new()));
+        let memory_space = Rc::new(RefCell::new(MemorySpace::new(
+            rom,
+            region,
+            vdp.clone(),
+            ym2612.clone(),
+            psg.clone(),
+            controller_1.clone(),
+            controller_2.clone(),
+            signal_bus.clone(),
+        )));
+        vdp.borrow_mut().set_bus(memory_space.clone());
+
+        let mut m68k = M68k::new();
+        m68k.set_bus(memory_space.clone());
+        m68k.reset();
+
+        let mut z80 = Z80::new();
+        z80.set_bus(memory_space.clone());
+
+        let native_sample_rate = master_clock(display_mod) as f64 / YM2612_SAMPLE_DIVIDER as f64;
+        Self {
+            cartridge,
+            rom_info,
+            m68k,
+            z80,
+            vdp,
+            ym2612,
+            psg,
+            memory_space,
+            signal_bus,
+
+            controller_1,
+            controller_2,
+            controller_3: Rc::new(RefCell::new(Controller::new())),
+            controller_4: Rc::new(RefCell::new(Controller::new())),
+
+            display_mod,
+            z80_bus_request: false,
+
+            m68k_cycle: 0,
+            z80_cycle: 0,
+            vdp_cycle: 0,
+            ym2612_cycle: 0,
+            psg_cycle: 0,
+
+            mixer: Mixer::new(native_sample_rate, SAMPLE_RATE_44100),
+            audio_sink: None,
+
+            vgm_logger: None,
+
+            cheats: vec![],
+
+            mega_cd: None,
+            sub_m68k: None,
+            sub_cycle: 0,
+
+            sega_32x: None,
+            sh2: None,
+            sh2_cycles: [0; 2],
+
+            master_system: None,
+
+            movie: None,
+            frame_start: true,
+        }
+    }
+
+    // the console with the Mega-CD, the bios takes the place of the cartridge rom
+    pub fn with_mega_cd(bios: Vec<u8>, disc: Option<Disc>) -> Self {
+        // the bios header only gives the region, the cartridge devices are not detected from it
+        let cartridge = Cartridge::new(&bios);
+        let region = cartridge.preferred_region();
+        let rom_info = RomInfo::new(&bios, &cartridge, &RomDatabase::bundled());
+        let mut genesis = Self::with_cartridge(bios.clone(), cartridge, rom_info, region);
+        let sample_rate = master_clock(genesis.display_mod) as f64 / YM2612_SAMPLE_DIVIDER as f64;
+        let mega_cd = Rc::new(RefCell::new(MegaCd::new(bios, disc, sample_rate)));
+        genesis.memory_space.borrow_mut().mega_cd = Some(mega_cd.clone());
+        let mut sub_m68k = M68k::new();
+        sub_m68k.set_bus(mega_cd.clone());
+        genesis.mega_cd = Some(mega_cd);
+        genesis.sub_m68k = Some(sub_m68k);
+        genesis
+    }
+
+    pub fn mega_cd_attached(&self) -> bool {
+        self.mega_cd.is_some()
+    }
+
+    // the console with the 32X, the sh2s start from their boot roms when the m68k releases them
+    pub fn with_32x(rom: Vec<u8>, m68k_bios: Vec<u8>, master_bios: Vec<u8>, slave_bios: Vec<u8>) -> Self {
+        let mut genesis = Self::new(rom);
+        let pal = matches!(genesis.display_mod, DisplayMod::PAL);
+        let rom = genesis.memory_space.borrow().rom.clone();
+        let sega_32x = Rc::new(RefCell::new(Sega32x::new(rom, m68k_bios, master_bios, slave_bios, pal)));
+        genesis.memory_space.borrow_mut().sega_32x = Some(sega_32x.clone());
+        let sh2 = [MASTER, SLAVE].map(|cpu| {
+            let mut sh2 = Sh2::new();
+            sh2.set_bus(Rc::new(RefCell::new(Sh2Bus::new(sega_32x.clone(), cpu))));
+            sh2
+        });
+        genesis.sega_32x = Some(sega_32x);
+        genesis.sh2 = Some(sh2);
+        genesis
+    }
+
+    pub fn sega_32x_attached(&self) -> bool {
+        self.sega_32x.is_some()
+    }
+
+    // the Power Base Converter mode, the z80 runs the Master System or the Game Gear cartridge and
+    // the vdp works in the mode 4
+    pub fn with_master_system(rom: Vec<u8>, game_gear: bool) -> Self {
+        // the cartridge has no Mega Drive header to detect the devices by
+        let cartridge = Cartridge::new(&[]);
+        let rom_info = RomInfo::new(&rom, &cartridge, &RomDatabase::bundled());
+        let mut genesis = Self::with_cartridge(rom, cartridge, rom_info, Region::Americas);
+        let rom = genesis.memory_space.borrow().rom.clone();
+        let master_system = Rc::new(RefCell::new(MasterSystem::new(
+            rom,
+            game_gear,
+            genesis.vdp.clone(),
+            genesis.psg.clone(),
+            genesis.controller_1.clone(),
+            genesis.controller_2.clone(),
+        )));
+        genesis.memory_space.borrow_mut().master_system = Some(master_system.clone());
+        genesis.vdp.borrow_mut().enable_mode4(game_gear);
+        genesis.master_system = Some(master_system);
+        genesis
+    }
+
+    pub fn master_system_attached(&self) -> bool {
+        self.master_system.is_some()
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+        self.update_rom_patches();
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.cheats.get_mut(index) {
+            cheat.enabled = enabled;
+            self.update_rom_patches();
+        }
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) {
+        if index < self.cheats.len() {
+            self.cheats.remove(index);
+            self.update_rom_patches();
+        }
+    }
+
+    // the rom patches are applied by the cartridge reads
+    fn update_rom_patches(&mut self) {
+        self.memory_space.borrow_mut().rom_patches = self
+            .cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.effect {
+                CheatEffect::RomPatch { address, data } => Some((address, data)),
+                CheatEffect::RamWrite { .. } => None,
+            })
+            .collect();
+    }
+
+    // the ram values are written once per frame
+    fn apply_ram_cheats(&mut self) {
+        let mut memory_space = self.memory_space.borrow_mut();
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            if let CheatEffect::RamWrite { address, data, word } = cheat.effect {
+                let address = address as usize;
+                if word {
+                    memory_space.m68k_ram[address..address + 2].copy_from_slice(&data.to_be_bytes());
+                } else {
+                    memory_space.m68k_ram[address] = data as u8;
+                }
+            }
+        }
+    }
+
+    // starts from the power on if the console has not run yet, otherwise from a save state
+    pub fn start_movie_recording(&mut self) {
+        let start = match self.m68k_cycle {
+            0 => MovieStart::PowerOn,
+            _ => MovieStart::SaveState(self.save_state()),
+        };
+        let movie = Movie::new(self.rom_info.crc32, &self.rom_info.sha1, start);
+        self.movie = Some(MovieSession::new(movie, MovieMode::Recording));
+        self.frame_start = true;
+    }
+
+    // the movie input replaces the controllers input of the ports 1 and 2 until the last frame
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), MovieError> {
+        if movie.rom_crc32 != self.rom_info.crc32 || movie.rom_sha1 != self.rom_info.sha1 {
+            return Err(MovieError::RomMismatch);
+        }
+        match &movie.start {
+            MovieStart::PowerOn if self.m68k_cycle != 0 => return Err(MovieError::NotPowerOn),
+            MovieStart::PowerOn => (),
+            MovieStart::SaveState(state) => self.load_state(state)?,
+        }
+        self.movie = Some(MovieSession::new(movie, MovieMode::Playing));
+        self.frame_start = true;
+        Ok(())
+    }
+
+    // returns the recorded or the played movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|session| session.movie)
+    }
+
+    pub fn movie_mode(&self) -> Option<MovieMode> {
+        self.movie.as_ref().map(|session| session.mode)
+    }
+
+    // frames recorded or played so far
+    pub fn movie_frame(&self) -> usize {
+        self.movie.as_ref().map_or(0, |session| session.frame)
+    }
+
+    // the first frame where the ram differs from the recording
+    pub fn movie_desync(&self) -> Option<usize> {
+        self.movie.as_ref().and_then(|session| session.desync)
+    }
+
+    fn poll_movie_input(&mut self) {
+        let Some(session) = self.movie.as_mut() else {
+            return;
+        };
+        let ram_hash = session
+            .sync_frame()
+            .then(|| crc32fast::hash(&self.memory_space.borrow().m68k_ram[..]));
+        let mut controller_1 = self.controller_1.borrow_mut();
+        let mut controller_2 = self.controller_2.borrow_mut();
+        session.poll([&mut controller_1, &mut controller_2], ram_hash);
+    }
+
+    // the checksum, the hashes and the database entry of the rom
+    pub fn rom_info(&self) -> &RomInfo {
+        &self.rom_info
+    }
+
+    // battery backed ram or eeprom of the cartridge, None if the cartridge has no one
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        if let Some(master_system) = &self.master_system {
+            return master_system.borrow().save_data().map(|data| data.to_vec());
+        }
+        if let Some(mega_cd) = &self.mega_cd {
+            return Some(mega_cd.borrow().backup_ram().to_vec());
+        }
+        let memory_space = self.memory_space.borrow();
+        match (&memory_space.sram, &memory_space.eeprom) {
+            (Some(sram), _) => Some(sram.data().to_vec()),
+            (_, Some(eeprom)) => Some(eeprom.data().to_vec()),
+            _ => None,
+        }
+    }
+
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(master_system) = &self.master_system {
+            master_system.borrow_mut().load_save_data(data);
+            return;
+        }
+        if let Some(mega_cd) = &self.mega_cd {
+            mega_cd.borrow_mut().load_backup_ram(data);
+            return;
+        }
+        let mut memory_space = self.memory_space.borrow_mut();
+        if let Some(sram) = memory_space.sram.as_mut() {
+            sram.load(data);
+        }
+        if let Some(eeprom) = memory_space.eeprom.as_mut() {
+            eeprom.load(data);
+        }
+    }
+
+    // true if the save data is changed since the previous call
+    pub fn save_data_changed(&mut self) -> bool {
+        if let Some(master_system) = &self.master_system {
+            return master_system.borrow_mut().take_changed();
+        }
+        if let Some(mega_cd) = &self.mega_cd {
+            return mega_cd.borrow_mut().take_changed();
+        }
+        let mut memory_space = self.memory_space.borrow_mut();
+        let sram_changed = memory_space.sram.as_mut().is_some_and(|sram| sram.take_changed());
+        let eeprom_changed = memory_space.eeprom.as_mut().is_some_and(|eeprom| eeprom.take_changed());
+        sram_changed || eeprom_changed
+    }
+
+    pub fn master_clock_frequency(&self) -> u64 {
+        master_clock(self.display_mod)
+    }
+
+    // the sound is resampled to the sink rate, without a sink it is dropped
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        if audio_sink.sample_rate() != self.mixer.output_rate() {
+            self.mixer.set_output_rate(audio_sink.sample_rate());
+        }
+        self.audio_sink = Some(audio_sink);
+    }
+
+    pub fn take_audio_sink(&mut self) -> Option<Box<dyn AudioSink>> {
+        self.flush_audio();
+        self.audio_sink.take()
+    }
+
+    fn flush_audio(&mut self) {
+        let samples = self.mixer.take_output();
+        if let Some(audio_sink) = self.audio_sink.as_mut() {
+            audio_sink.write(&samples);
+        }
+    }
+
+    // starts recording of the sound chips writes, the log begins with the current state of the chips
+    pub fn start_vgm_log(&mut self) {
+        let master_clock = master_clock(self.display_mod);
+        let frame_rate = match self.display_mod {
+            DisplayMod::PAL => 50,
+            DisplayMod::NTSC => 60,
+        };
+        let vgm_logger = Rc::new(RefCell::new(VgmLogger::new(
+            master_clock,
+            frame_rate,
+            (master_clock / Z80_CLOCK_DIVIDER) as u32,
+            (master_clock / M68K_CLOCK_DIVIDER) as u32,
+            self.m68k_cycle,
+        )));
+        {
+            let mut logger = vgm_logger.borrow_mut();
+            for (part, register, data) in self.ym2612.borrow().register_dump() {
+                logger.ym2612_write(part, register, data);
+            }
+            for data in self.psg.borrow().register_dump() {
+                logger.psg_write(data);
+            }
+        }
+        self.ym2612.borrow_mut().set_vgm_logger(Some(vgm_logger.clone()));
+        self.psg.borrow_mut().set_vgm_logger(Some(vgm_logger.clone()));
+        self.vgm_logger = Some(vgm_logger);
+    }
+
+    // returns the VGM file of the writes since `start_vgm_log`
+    pub fn stop_vgm_log(&mut self) -> Option<Vec<u8>> {
+        let vgm_logger = self.vgm_logger.take()?;
+        self.ym2612.borrow_mut().set_vgm_logger(None);
+        self.psg.borrow_mut().set_vgm_logger(None);
+        let mut vgm_logger = Rc::try_unwrap(vgm_logger).ok()?.into_inner();
+        vgm_logger.set_cycle(self.m68k_cycle);
+        Some(vgm_logger.finish())
+    }
+
+    pub fn vgm_log_active(&self) -> bool {
+        self.vgm_logger.is_some()
+    }
+
+    // master clocks passed from the power on
+    pub fn master_cycle(&self) -> u64 {
+        self.m68k_cycle
+    }
+
+    // brings the z80 and vdp up to the m68k time and executes a single m68k instruction
+    // returns true when the frame is ready
+    pub fn clock(&mut self) -> bool {
+        if self.frame_start {
+            self.frame_start = false;
+            self.poll_movie_input();
+            self.poll_pause();
+        }
+        let frame_done = self.run_until(self.m68k_cycle);
+        self.handle_signals();
+        if self.signal_bus.borrow_mut().handle_signal(Signal::CpuHalt) {
+            // the bus is busy by dma, the cpu waits for a single vdp clock
+            self.m68k_cycle += self.vdp.borrow().clock_divider();
+        } else if self.master_system.is_some() {
+            self.m68k_cycle += MASTER_SYSTEM_STEP;
+        } else {
+            if let Some(vgm_logger) = &self.vgm_logger {
+                vgm_logger.borrow_mut().set_cycle(self.m68k_cycle);
+            }
+            let m68k_cycles = self.m68k.clock() as u64 * M68K_CLOCK_DIVIDER;
+            self.m68k_cycle += m68k_cycles;
+            for controller in self.controllers() {
+                controller.borrow_mut().clock(m68k_cycles as u32);
+            }
+        }
+        self.run_sub_cpu();
+        if frame_done {
+            self.compose_32x();
+        }
+        self.run_32x();
+        if frame_done || self.mixer.pending() >= AUDIO_FLUSH_SIZE {
+            self.flush_audio();
+        }
+        if frame_done {
+            self.apply_ram_cheats();
+            self.frame_start = true;
+        }
+        frame_done
+    }
+
+    // runs until the frame is ready or the cpu hits a breakpoint
+    pub fn run_frame(&mut self) {
+        while !self.clock() {
+            if self.m68k.breakpoint_hit {
+                debug!("Genesis: frame is interrupted by a breakpoint");
+                break;
+            }
+        }
+    }
+
+    // runs at least `master_cycles` master clocks, returns true if a frame was completed meanwhile
+    pub fn run_cycles(&mut self, master_cycles: u64) -> bool {
+        let target_cycle = self.m68k_cycle + master_cycles;
+        let mut frame_done = false;
+        while self.m68k_cycle < target_cycle {
+            frame_done |= self.clock();
+            if self.m68k.breakpoint_hit {
+                debug!("Genesis: cycles run is interrupted by a breakpoint");
+                break;
+            }
+        }
+        frame_done
+    }
+
+    // the pause button of the Master System is connected to the nmi of the z80
+    fn poll_pause(&mut self) {
+        let pause = self.master_system.as_ref().is_some_and(|master_system| master_system.borrow_mut().take_pause());
+        if pause {
+            self.z80.nmi();
+        }
+    }
+
+    // brings the sub cpu of the Mega-CD up to the m68k time
+    fn run_sub_cpu(&mut self) {
+        let (Some(mega_cd), Some(sub_m68k)) = (&self.mega_cd, self.sub_m68k.as_mut()) else {
+            return;
+        };
+        let target_cycle = (self.m68k_cycle as u128 * SUB_CPU_CLOCK as u128 / master_clock(self.display_mod) as u128) as u64;
+        while self.sub_cycle < target_cycle {
+            if mega_cd.borrow_mut().take_sub_reset() {
+                sub_m68k.reset();
+            }
+            let running = mega_cd.borrow().sub_running();
+            if running && mega_cd.borrow().interrupt_pending() {
+                let mask = (sub_m68k.state().sr >> 8) as u8 & 0x07;
+                let level = mega_cd.borrow_mut().take_interrupt(mask);
+                if let Some(level) = level {
+                    sub_m68k.interrupt(level as u32);
+                }
+            }
+            // the stopped cpu waits for a bus cycle
+            let cycles = if running { sub_m68k.clock() as u64 } else { 4 };
+            mega_cd.borrow_mut().clock(cycles);
+            self.sub_cycle += cycles;
+        }
+    }
+
+    // brings the sh2s of the 32X up to the m68k time, the slave follows the master
+    fn run_32x(&mut self) {
+        let (Some(sega_32x), Some(sh2)) = (&self.sega_32x, self.sh2.as_mut()) else {
+            return;
+        };
+        {
+            let vdp = self.vdp.borrow();
+            let (width, height) = vdp.screen_size();
+            let hblank = vdp.h_counter as usize >= width;
+            sega_32x.borrow_mut().set_line(vdp.v_counter, height as u16, hblank);
+        }
+        let target_cycle = self.m68k_cycle * SH2_CLOCK_MULTIPLIER / M68K_CLOCK_DIVIDER;
+        if sega_32x.borrow_mut().take_sh2_reset() {
+            for cpu in sh2.iter_mut() {
+                cpu.reset();
+            }
+        }
+        if !sega_32x.borrow().sh2_running() {
+            self.sh2_cycles = [target_cycle; 2];
+            return;
+        }
+        for (index, cpu) in sh2.iter_mut().enumerate() {
+            while self.sh2_cycles[index] < target_cycle {
+                // the bus borrows the board, so it is released before each cpu access
+                let level = sega_32x.borrow().interrupt_level(index);
+                if let Some(level) = level {
+                    cpu.interrupt(level);
+                }
+                if index == MASTER {
+                    loop {
+                        let dreq = sega_32x.borrow().dreq_pending();
+                        if !dreq || !cpu.dreq(0) {
+                            break;
+                        }
+                    }
+                }
+                let cycles = cpu.clock() as u64;
+                if index == MASTER {
+                    sega_32x.borrow_mut().clock(cycles as u32);
+                }
+                self.sh2_cycles[index] += cycles;
+            }
+        }
+    }
+
+    // the 32X image is drawn over the finished frame before the frame buffers swap in the vblank
+    fn compose_32x(&mut self) {
+        if let Some(sega_32x) = &self.sega_32x {
+            let sega_32x = sega_32x.borrow();
+            self.vdp.borrow_mut().overlay(|line, dots, backdrop| sega_32x.compose_line(line, dots, backdrop));
+        }
+    }
+
+    // steps the z80, the vdp and the sound chips in master clock order until all of them reach the `cycle`
+    fn run_until(&mut self, cycle: u64) -> bool {
+        let mut frame_done = false;
+        while self.z80_cycle < cycle
+            || self.vdp_cycle < cycle
+            || self.ym2612_cycle < cycle
+            || self.psg_cycle < cycle
+        {
+            let cpu_cycle = self.vdp_cycle.min(self.z80_cycle);
+            if self.psg_cycle < cpu_cycle.min(self.ym2612_cycle) {
+                self.psg.borrow_mut().clock();
+                self.psg_cycle += PSG_CLOCK_DIVIDER;
+            } else if self.ym2612_cycle < cpu_cycle {
+                if let Some(mut fm) = self.ym2612.borrow_mut().clock() {
+                    if let Some(mega_cd) = &self.mega_cd {
+                        let cdda = mega_cd.borrow_mut().cdda_sample();
+                        fm = (fm.0 + cdda.0, fm.1 + cdda.1);
+                    }
+                    if let Some(sega_32x) = &self.sega_32x {
+                        let pwm = sega_32x.borrow().pwm_sample();
+                        fm = (fm.0 + pwm.0, fm.1 + pwm.1);
+                    }
+                    self.mixer.push(fm, self.psg.borrow_mut().sample());
+                }
+                self.ym2612_cycle += YM2612_CLOCK_DIVIDER;
+            } else if self.vdp_cycle <= self.z80_cycle {
+                let mut vdp = self.vdp.borrow_mut();
+                frame_done |= vdp.clock();
+                self.vdp_cycle += vdp.clock_divider();
+            } else {
+                self.handle_signals();
+                let z80_reset = *self.memory_space.borrow().z80_res_req.borrow();
+                let z80_cycles = if self.z80_bus_request || z80_reset {
+                    1 // the z80 is stopped while the m68k owns its bus or holds it in reset
+                } else {
+                    if let Some(vgm_logger) = &self.vgm_logger {
+                        vgm_logger.borrow_mut().set_cycle(self.z80_cycle);
+                    }
+                    self.z80.clock() as u64
+                };
+                self.z80_cycle += z80_cycles * Z80_CLOCK_DIVIDER;
+            }
+        }
+        frame_done
+    }
+
+    fn handle_signals(&mut self) {
+        if self.signal_bus.borrow_mut().handle_signal(Signal::VInterrupt) {
+            self.m68k.interrupt(6);
+        }
+        if self.signal_bus.borrow_mut().handle_signal(Signal::HInterrupt) {
+            self.m68k.interrupt(4);
+        }
+        if self.signal_bus.borrow_mut().handle_signal(Signal::Z80BusRequest) {
+            self.z80_bus_request = true;
+        }
+        if self.signal_bus.borrow_mut().handle_signal(Signal::Z80BusFree) {
+            self.z80_bus_request = false;
+        }
+        if self.signal_bus.borrow_mut().handle_signal(Signal::Z80Reset) {
+            self.z80.restart();
+        }
+        if self.signal_bus.borrow_mut().handle_signal(Signal::Z80INT) {
+            self.z80.int(0);
+        }
+        // the mode 4 interrupt line is held until the status is read
+        if self.vdp.borrow().mode4_interrupt() {
+            self.z80.int(0);
+        }
+    }
+
+    // serializes the whole machine into the versioned state format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new(self.rom_fingerprint());
+
+        write_m68k_state(&mut writer, &self.m68k.state());
+
+        let z80 = self.z80.state();
+        writer.write_bytes(&z80.registers);
+        writer.write_u16(z80.index_registers[0]);
+        writer.write_u16(z80.index_registers[1]);
+        writer.write_u16(z80.stack_pointer);
+        writer.write_u8(z80.interrupt_vector);
+        writer.write_u8(z80.memory_refresh);
+        writer.write_u16(z80.program_counter);
+        writer.write_u8(z80.interrupt_mode);
+        writer.write_u8(z80.iff1);
+        writer.write_u8(z80.iff2);
+
+        self.vdp.borrow().save_state(&mut writer);
+        self.ym2612.borrow().save_state(&mut writer);
+        self.psg.borrow().save_state(&mut writer);
+        self.memory_space.borrow().save_state(&mut writer);
+        self.controller_1.borrow().save_state(&mut writer);
+        self.controller_2.borrow().save_state(&mut writer);
+        self.controller_3.borrow().save_state(&mut writer);
+        self.controller_4.borrow().save_state(&mut writer);
+        self.signal_bus.borrow().save_state(&mut writer);
+
+        writer.write_bool(self.z80_bus_request);
+        writer.write_u64(self.m68k_cycle);
+        writer.write_u64(self.z80_cycle);
+        writer.write_u64(self.vdp_cycle);
+        writer.write_u64(self.ym2612_cycle);
+        writer.write_u64(self.psg_cycle);
+
+        if let (Some(mega_cd), Some(sub_m68k)) = (&self.mega_cd, &self.sub_m68k) {
+            mega_cd.borrow().save_state(&mut writer);
+            write_m68k_state(&mut writer, &sub_m68k.state());
+            writer.write_u64(self.sub_cycle);
+        }
+
+        if let (Some(sega_32x), Some(sh2)) = (&self.sega_32x, &self.sh2) {
+            sega_32x.borrow().save_state(&mut writer);
+            for (cpu, cycle) in sh2.iter().zip(self.sh2_cycles) {
+                write_sh2_state(&mut writer, &cpu.state());
+                writer.write_u64(cycle);
+            }
+        }
+
+        if let Some(master_system) = &self.master_system {
+            master_system.borrow().save_state(&mut writer);
+        }
+
+        writer.finish()
+    }
+
+    // restores the state saved by `save_state`, the machine remains untouched on error
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data, self.rom_fingerprint())?;
+        let backup = self.save_state();
+        if let Err(error) = self.read_state(&mut reader) {
+            let mut backup_reader = StateReader::new(&backup, self.rom_fingerprint()).unwrap();
+            self.read_state(&mut backup_reader).unwrap();
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.m68k.set_state(read_m68k_state(reader)?);
+
+        let mut registers = [0; 16];
+        reader.read_bytes(&mut registers)?;
+        let index_registers = [reader.read_u16()?, reader.read_u16()?];
+        let stack_pointer = reader.read_u16()?;
+        let interrupt_vector = reader.read_u8()?;
+        let memory_refresh = reader.read_u8()?;
+        let program_counter = reader.read_u16()?;
+        let interrupt_mode = reader.read_u8()?;
+        if interrupt_mode > 2 {
+            return Err(StateError::InvalidValue("z80 interrupt mode"));
+        }
+        let iff1 = reader.read_u8()?;
+        let iff2 = reader.read_u8()?;
+        self.z80.set_state(Z80State {
+            registers,
+            index_registers,
+            stack_pointer,
+            interrupt_vector,
+            memory_refresh,
+            program_counter,
+            interrupt_mode,
+            iff1,
+            iff2,
+        });
+
+        self.vdp.borrow_mut().load_state(reader)?;
+        self.ym2612.borrow_mut().load_state(reader)?;
+        self.psg.borrow_mut().load_state(reader)?;
+        self.memory_space.borrow_mut().load_state(reader)?;
+        self.controller_1.borrow_mut().load_state(reader)?;
+        self.controller_2.borrow_mut().load_state(reader)?;
+        self.controller_3.borrow_mut().load_state(reader)?;
+        self.controller_4.borrow_mut().load_state(reader)?;
+        self.signal_bus.borrow_mut().load_state(reader)?;
+
+        self.z80_bus_request = reader.read_bool()?;
+        self.m68k_cycle = reader.read_u64()?;
+        self.z80_cycle = reader.read_u64()?;
+        self.vdp_cycle = reader.read_u64()?;
+        self.ym2612_cycle = reader.read_u64()?;
+        self.psg_cycle = reader.read_u64()?;
+
+        if let (Some(mega_cd), Some(sub_m68k)) = (&self.mega_cd, self.sub_m68k.as_mut()) {
+            mega_cd.borrow_mut().load_state(reader)?;
+            sub_m68k.set_state(read_m68k_state(reader)?);
+            self.sub_cycle = reader.read_u64()?;
+        }
+
+        if let (Some(sega_32x), Some(sh2)) = (&self.sega_32x, self.sh2.as_mut()) {
+            sega_32x.borrow_mut().load_state(reader)?;
+            for (cpu, cycle) in sh2.iter_mut().zip(self.sh2_cycles.iter_mut()) {
+                cpu.set_state(read_sh2_state(reader, cpu.state())?);
+                *cycle = reader.read_u64()?;
+            }
+        }
+
+        if let Some(master_system) = &self.master_system {
+            master_system.borrow_mut().load_state(reader)?;
+        }
+
+        if !reader.is_finished() {
+            return Err(StateError::InvalidValue("length"));
+        }
+        Ok(())
+    }
+
+    fn rom_fingerprint(&self) -> u32 {
+        rom_fingerprint(&self.memory_space.borrow().rom)
+    }
+
+    pub fn set_breakpoints(&mut self, breakpoints: &Vec<u32>) {
+        self.m68k.set_breakpoints(breakpoints);
+    }
+
+    pub fn breakpoint_hit(&self) -> bool {
+        self.m68k.breakpoint_hit
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.vdp.borrow().screen_size()
+    }
+
+    pub fn frame_buffer(&self) -> Ref<'_, [u32]> {
+        Ref::map(self.vdp.borrow(), |vdp| vdp.frame_buffer())
+    }
+
+    pub fn vram_table(&self) -> Vec<u32> {
+        self.vdp.borrow().vram_table()
+    }
+
+    pub fn m68k_ram(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.memory_space.borrow(), |memory_space| {
+            memory_space.m68k_ram.as_slice()
+        })
+    }
+
+    // the 8 KB ram of the Master System in its mode
+    pub fn z80_ram(&self) -> Ref<'_, [u8]> {
+        if let Some(master_system) = &self.master_system {
+            return Ref::map(master_system.borrow(), |master_system| master_system.ram());
+        }
+        Ref::map(self.memory_space.borrow(), |memory_space| {
+            memory_space.z80_ram.as_slice()
+        })
+    }
+
+    pub fn vram(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.vdp.borrow(), |vdp| vdp.vram.as_slice())
+    }
+
+    // the memory of the ram search
+    pub fn memory(&self, region: SearchRegion) -> Ref<'_, [u8]> {
+        match region {
+            SearchRegion::M68kRam => self.m68k_ram(),
+            SearchRegion::Z80Ram => self.z80_ram(),
+            SearchRegion::Vram => self.vram(),
+        }
+    }
+
+    pub fn controller_1(&self) -> RefMut<'_, Controller> {
+        self.controller_1.borrow_mut()
+    }
+
+    pub fn controller_2(&self) -> RefMut<'_, Controller> {
+        self.controller_2.borrow_mut()
+    }
+
+    pub fn controller_3(&self) -> RefMut<'_, Controller> {
+        self.controller_3.borrow_mut()
+    }
+
+    pub fn controller_4(&self) -> RefMut<'_, Controller> {
+        self.controller_4.borrow_mut()
+    }
+
+    // plugs the EA 4-Way Play into both ports, the controllers 1-4 become its pads
+    pub fn connect_four_way_play(&mut self) {
+        let pads = [
+            self.controller_1.clone(),
+            self.controller_2.clone(),
+            self.controller_3.clone(),
+            self.controller_4.clone(),
+        ];
+        self.memory_space.borrow_mut().four_way_play = Some(FourWayPlay::new(pads));
+    }
+
+    // the vdp lock of the later model 1 consoles and the optional boot rom, the m68k restarts
+    // from the boot rom vectors, so the console should be at the power on
+    pub fn enable_tmss(&mut self, bios: Option<Vec<u8>>) {
+        self.memory_space.borrow_mut().tmss = Some(Tmss::new(bios));
+        self.m68k.reset();
+    }
+
+    // true if the game has accessed the vdp before unlocking it
+    pub fn tmss_vdp_blocked(&self) -> bool {
+        self.memory_space.borrow().tmss.as_ref().is_some_and(|tmss| tmss.vdp_blocked())
+    }
+
+    fn controllers(&self) -> [&Rc<RefCell<Controller>>; 4] {
+        [
+            &self.controller_1,
+            &self.controller_2,
+            &self.controller_3,
+            &self.controller_4,
+        ]
+    }
+}
+
+fn write_m68k_state(writer: &mut StateWriter, m68k: &M68kState) {
+    for register in m68k.registers {
+        writer.write_u32(register);
+    }
+    writer.write_u32(m68k.pc);
+    writer.write_u16(m68k.sr);
+    writer.write_bool(m68k.trap.is_some());
+    writer.write_u32(m68k.trap.unwrap_or(0));
+    writer.write_u32(m68k.cycles_counter as u32);
+}
+
+fn read_m68k_state(reader: &mut StateReader) -> Result<M68kState, StateError> {
+    let mut registers = [0; 17];
+    for register in registers.iter_mut() {
+        *register = reader.read_u32()?;
+    }
+    let pc = reader.read_u32()?;
+    let sr = reader.read_u16()?;
+    let trap_pending = reader.read_bool()?;
+    let trap = reader.read_u32()?;
+    let cycles_counter = reader.read_u32()? as i32;
+    Ok(M68kState {
+        registers,
+        pc,
+        sr,
+        trap: if trap_pending { Some(trap) } else { None },
+        cycles_counter,
+    })
+}
+
+fn write_sh2_state(writer: &mut StateWriter, sh2: &Sh2State) {
+    for register in sh2.registers {
+        writer.write_u32(register);
+    }
+    for register in [sh2.pc, sh2.sr, sh2.gbr, sh2.vbr, sh2.mach, sh2.macl, sh2.pr] {
+        writer.write_u32(register);
+    }
+    writer.write_bool(sh2.delay_slot.is_some());
+    writer.write_u32(sh2.delay_slot.unwrap_or(0));
+    writer.write_bool(sh2.sleeping);
+    writer.write_bytes(&sh2.onchip_registers);
+    writer.write_bytes(&sh2.cache);
+    writer.write_u32(sh2.frt_clocks);
+}
+
+// the on-chip memories are read into the ones of the current state
+fn read_sh2_state(reader: &mut StateReader, current: Sh2State) -> Result<Sh2State, StateError> {
+    let mut registers = [0; 16];
+    for register in registers.iter_mut() {
+        *register = reader.read_u32()?;
+    }
+    let mut control = [0; 7];
+    for register in control.iter_mut() {
+        *register = reader.read_u32()?;
+    }
+    let [pc, sr, gbr, vbr, mach, macl, pr] = control;
+    let delay_pending = reader.read_bool()?;
+    let delay_slot = reader.read_u32()?;
+    let sleeping = reader.read_bool()?;
+    let Sh2State { mut onchip_registers, mut cache, .. } = current;
+    reader.read_bytes(&mut onchip_registers)?;
+    reader.read_bytes(&mut cache)?;
+    let frt_clocks = reader.read_u32()?;
+    Ok(Sh2State {
+        registers,
+        pc,
+        sr,
+        gbr,
+        vbr,
+        mach,
+        macl,
+        pr,
+        delay_slot: if delay_pending { Some(delay_slot) } else { None },
+        sleeping,
+        onchip_registers,
+        cache,
+        frt_clocks,
+    })
+}
+
+fn master_clock(display_mod: DisplayMod) -> u64 {
+    match display_mod {
+        DisplayMod::PAL => MASTER_CLOCK_PAL,
+        DisplayMod::NTSC => MASTER_CLOCK_NTSC,
+    }
+}
@@ -0,0 +1,23 @@
+pub mod audio;
+pub mod cartridge;
+pub mod cheat;
+pub mod controller;
+pub mod genesis;
+pub mod mega_cd;
+pub mod movie;
+pub mod ram_search;
+pub mod save_state;
+pub mod vgm;
+
+mod m68k_bus;
+mod master_system;
+mod memory_space;
+mod multitap;
+mod sega_32x;
+mod signal_bus;
+mod sn76489;
+mod tmss;
+mod vdp_bus;
+mod vdp_emu;
+mod z80_bus;
+mod ym2612;
@@ -3,7 +3,14 @@ use m68k_emu::bus::BusM68k;
 use z80_emu::bus::BusZ80;
 
 use crate::{
-    memory_space::MemorySpace, signal_bus::Signal, vdp_emu::vdp_port::VdpPorts, ym2612::Ym2612Ports,
+    mega_cd::MAIN_REGISTERS,
+    memory_space::MemorySpace,
+    sega_32x::{MAIN_REGISTERS as SEGA_32X_REGISTERS, MAIN_REGISTERS_END as SEGA_32X_REGISTERS_END, MARS_ID},
+    signal_bus::Signal,
+    sn76489::PsgPorts,
+    tmss::{TMSS_CART_SWAP, TMSS_KEY},
+    vdp_emu::vdp_port::VdpPorts,
+    ym2612::Ym2612Ports,
 };
 
 const VERSION_REGISTER: u32 = 0xA10001;
@@ -14,11 +21,16 @@ const CONTROLLER_B_DATA: u32 = 0xA10004;
 // const EXPANSION_PORT_CONTROL: u32 = 0xA1000C;
 const Z80_REQUEST_BUS: u32 = 0xA11100;
 const Z80_RESET: u32 = 0xA11200;
+const TIME_AREA: u32 = 0xA13000;
+const SRAM_CONTROL: u32 = 0xA130F1;
+const VDP_PORTS: u32 = 0xC00000;
+const PSG_PORT: u32 = 0xC00010;
 
-impl<T, Y> BusM68k for MemorySpace<T, Y>
+impl<T, Y, P> BusM68k for MemorySpace<T, Y, P>
 where
     T: VdpPorts,
     Y: Ym2612Ports,
+    P: PsgPorts,
 {
     fn read(&self, address: u32, amount: usize) -> Result<u32, ()> {
         let address = address & 0x00FFFFFF;
@@ -26,24 +38,54 @@ where
         // for Size::Word/Size::Byte, actual information contains in last buffer bytes
         let buff_chunk = &mut buff[size_of::<u32>() - amount..];
         debug!("CPU reads address {:08X}\tsize: {}", address, amount);
+        if (VDP_PORTS..PSG_PORT).contains(&address) && self.vdp_locked(address) {
+            return Ok(0);
+        }
+        if let Some(sega_32x) = &self.sega_32x {
+            let sega_32x = sega_32x.borrow();
+            if let Some(offset) = sega_32x.rom_offset(address) {
+                for (i, byte) in buff_chunk.iter_mut().enumerate() {
+                    *byte = self.read_cartridge(offset + i as u32);
+                }
+                return Ok(u32::from_be_bytes(buff));
+            }
+            if sega_32x.frame_buffer_mapped(address) || sega_32x_register(address) {
+                for (i, byte) in buff_chunk.iter_mut().enumerate() {
+                    *byte = sega_32x.main_read(address + i as u32);
+                }
+                return Ok(u32::from_be_bytes(buff));
+            }
+        }
         if address <= 0x3FFFFF {
-            let memory_chunk = self.rom[address as usize..].split_at(amount).0;
-            buff_chunk.copy_from_slice(memory_chunk);
+            let cartridge = !self.bios_mapped();
+            match (&self.eeprom, &self.sram) {
+                (Some(eeprom), _) if cartridge && (0..amount as u32).any(|i| eeprom.contains(address + i)) => {
+                    for (i, byte) in buff_chunk.iter_mut().enumerate() {
+                        let address = address + i as u32;
+                        *byte = eeprom.read(address).unwrap_or_else(|| self.read_rom(address));
+                    }
+                }
+                (_, Some(sram)) if cartridge && sram.contains(address) => {
+                    for (i, byte) in buff_chunk.iter_mut().enumerate() {
+                        *byte = sram.read(address + i as u32);
+                    }
+                }
+                _ => {
+                    for (i, byte) in buff_chunk.iter_mut().enumerate() {
+                        *byte = self.read_rom(address + i as u32);
+                    }
+                }
+            }
         } else if address >= 0xA00000 && address <= 0xA0FFFF {
             let address = (address & 0xFFFF) as u16;
             // TODO may be there should be a z80 bus register check
-            let data = <MemorySpace<T, Y> as BusZ80>::read(&self, address, amount)? as u32;
+            let data = <MemorySpace<T, Y, P> as BusZ80>::read(self, address, amount)? as u32;
             debug!("BusM68k::read: M68000 read value from Z80 memory space (address: {:04x}, data: {:04X}), size: {}", address, data, amount);
             return Ok(data);
         } else if address >= 0xA10000 && address < 0xA20000 {
             return if address == VERSION_REGISTER {
-                let program_region = self.rom[0x1F0];
-                match program_region {
-                    0x55 => Ok(0x80),
-                    0x45 => Ok(0xC0),
-                    0x4A => Ok(0x00),
-                    _ => panic!("unexpected program region code {:02X}", program_region),
-                }
+                // the tmss consoles are the hardware version 1
+                Ok((self.region.version_register() | self.tmss.is_some() as u8) as u32)
             } else if address == Z80_REQUEST_BUS {
                 let bus_state = *self.z80_bus_req.borrow();
                 debug!("Z80 bus state flag is {}", bus_state);
@@ -53,10 +95,18 @@ where
                     bus_status_bit.as_ref().unwrap()
                 );
                 bus_status_bit
+            } else if let Some(mega_cd) = self.mega_cd.as_ref().filter(|_| (MAIN_REGISTERS..MAIN_REGISTERS + 0x40).contains(&address)) {
+                let mega_cd = mega_cd.borrow();
+                let data = match amount {
+                    1 => mega_cd.read_main_register(address).to_be_bytes()[(address & 0x01) as usize] as u32,
+                    2 => mega_cd.read_main_register(address) as u32,
+                    _ => (mega_cd.read_main_register(address) as u32) << 16 | mega_cd.read_main_register(address + 2) as u32,
+                };
+                Ok(data)
             } else if address == CONTROLLER_A_DATA || address == CONTROLLER_A_DATA + 1 {
-                Ok(self.controller_1.borrow().read() as u32)
+                Ok(self.read_controller_port(0) as u32)
             } else if address == CONTROLLER_B_DATA || address == CONTROLLER_B_DATA + 1 {
-                Ok(self.controller_2.borrow().read() as u32)
+                Ok(self.read_controller_port(1) as u32)
             } else {
                 let address = (address & 0x3f) as usize;
                 let memory_chunk = self.io_area_read[address..].split_at(amount).0;
@@ -93,13 +143,42 @@ where
             "CPU writes address {:08X}\tdata {:08X}\tsize: {}",
             address, data, amount
         );
-        if address <= 0x3FFFFF {
-            let address = address as usize;
-            self.rom[address..address + amount].copy_from_slice(chunk);
+        if (VDP_PORTS..PSG_PORT).contains(&address) && self.vdp_locked(address) {
+            return Ok(());
+        }
+        if let Some(sega_32x) = &self.sega_32x {
+            let mut sega_32x = sega_32x.borrow_mut();
+            if sega_32x.frame_buffer_mapped(address) || sega_32x_register(address) {
+                for (i, byte) in chunk.iter().enumerate() {
+                    sega_32x.main_write(address + i as u32, *byte);
+                }
+                return Ok(());
+            }
+        }
+        if let Some(mega_cd) = self.mega_cd.as_ref().filter(|_| address <= 0x3FFFFF) {
+            let mut mega_cd = mega_cd.borrow_mut();
+            for (i, byte) in chunk.iter().enumerate() {
+                mega_cd.main_write(address + i as u32, *byte);
+            }
+        } else if address <= 0x3FFFFF {
+            let cartridge = !self.bios_mapped();
+            match (self.eeprom.as_mut(), self.sram.as_mut()) {
+                (Some(eeprom), _) if cartridge && (0..amount as u32).any(|i| eeprom.contains(address + i)) => {
+                    for (i, byte) in chunk.iter().enumerate() {
+                        eeprom.write(address + i as u32, *byte);
+                    }
+                }
+                (_, Some(sram)) if cartridge && sram.contains(address) => {
+                    for (i, byte) in chunk.iter().enumerate() {
+                        sram.write(address + i as u32, *byte);
+                    }
+                }
+                _ => debug!("BusM68k::write: write to the rom area {:06X} is ignored", address),
+            }
         } else if address >= 0xA00000 && address <= 0xA0FFFF {
             let address = (address & 0xFFFF) as u16;
             // TODO may be there should be a z80 bus register check
-            <MemorySpace<T, Y> as BusZ80>::write(self, data as u16, address, amount)?;
+            <MemorySpace<T, Y, P> as BusZ80>::write(self, data as u16, address, amount)?;
         } else if address >= 0xA10000 && address < 0xA20000 {
             if address == Z80_REQUEST_BUS {
                 *self.z80_bus_req.borrow_mut() = data != 0;
@@ -116,10 +195,27 @@ where
                 debug!("Z80 send reset signal with data {:04X}", data);
                 *self.z80_res_req.borrow_mut() = data == 0;
                 self.signal_bus.borrow_mut().push_signal(Signal::Z80Reset);
+            } else if address == SRAM_CONTROL - 1 || address == SRAM_CONTROL {
+                if let Some(sram) = self.sram.as_mut() {
+                    sram.write_control(data as u8);
+                }
+            } else if (TIME_AREA..TIME_AREA + 0x100).contains(&address) {
+                self.mapper.write_register(address | 0x01, data as u8);
+            } else if self.tmss.is_some() && (TMSS_KEY..TMSS_CART_SWAP + 2).contains(&address) {
+                if let Some(tmss) = self.tmss.as_mut() {
+                    for (i, byte) in chunk.iter().enumerate() {
+                        tmss.write(address + i as u32, *byte);
+                    }
+                }
+            } else if let Some(mega_cd) = self.mega_cd.as_ref().filter(|_| (MAIN_REGISTERS..MAIN_REGISTERS + 0x40).contains(&address)) {
+                let mut mega_cd = mega_cd.borrow_mut();
+                for (i, byte) in chunk.iter().enumerate() {
+                    mega_cd.write_main_register(address + i as u32, *byte);
+                }
             } else if address == CONTROLLER_A_DATA || address == CONTROLLER_A_DATA + 1 {
-                self.controller_1.borrow_mut().write(data as u8);
+                self.write_controller_port(0, data as u8);
             } else if address == CONTROLLER_B_DATA || address == CONTROLLER_B_DATA + 1 {
-                self.controller_2.borrow_mut().write(data as u8);
+                self.write_controller_port(1, data as u8);
             } else {
                 let address = (address & 0x3f) as usize;
                 self.io_area_m68k[address..address + amount].copy_from_slice(chunk);
@@ -140,6 +236,11 @@ where
             } else {
                 vdp_port_ref.write_control_port(data as u16)?;
             }
+        } else if (PSG_PORT..PSG_PORT + 8).contains(&address) {
+            // the psg is connected to the lower byte of the data bus and mirrored up to 0xC00017
+            if address & 0x01 != 0 || amount > 1 {
+                self.psg_ports.borrow_mut().write(data as u8);
+            }
         } else if address >= 0xFF0000 && address <= 0xFFFFFF {
             let address = (address & 0xFFFF) as usize;
             self.m68k_ram[address..address + amount].copy_from_slice(chunk);
@@ -150,3 +251,8 @@ where
         Ok(())
     }
 }
+
+// the adapter registers, the vdp, the palette and the id of the 32X
+fn sega_32x_register(address: u32) -> bool {
+    (SEGA_32X_REGISTERS..SEGA_32X_REGISTERS_END).contains(&address) || (MARS_ID..MARS_ID + 4).contains(&address)
+}
@@ -1,37 +1,39 @@
 extern crate spriter;
 
 use std::{
-    cell::RefCell,
-    collections::HashMap,
     env,
     fs::File,
     io::{stdin, Read, Write},
-    rc::Rc,
+    path::{Path, PathBuf},
     str,
+    sync::mpsc::{self, Receiver},
+    thread,
 };
 
-use controller::Controller;
 use log::info;
-use m68k_emu::cpu::M68k;
 
-use memory_space::MemorySpace;
-use signal_bus::{Signal, SignalBus};
-use spriter::{if_pressed, Key};
-use vdp_emu::vdp_emu::Vdp;
-use z80_emu::cpu::Z80;
-
-use crate::{vdp_emu::DisplayMod, ym2612::ym2612::Ym2612};
-
-mod controller;
-mod m68k_bus;
-mod memory_space;
-mod signal_bus;
-mod vdp_bus;
-mod vdp_emu;
-mod z80_bus;
-mod ym2612;
+use segaret::{
+    audio::{wav_sink::WavSink, SAMPLE_RATE_44100},
+    cartridge::{
+        cartridge::Cartridge,
+        patch::{apply_patch, find_patch, fix_checksum},
+        rom_database::{RomDatabase, RomInfo},
+        rom_loader::{load_master_system_rom, load_rom},
+    },
+    cheat::{parse_cheat_file, write_cheat_file, Cheat},
+    controller::{Button, Controller},
+    genesis::{Genesis, VRAM_TABLE_HEIGHT, VRAM_TABLE_WIDTH},
+    mega_cd::disc::Disc,
+    movie::{Movie, MovieMode},
+    ram_search::{RamSearch, SearchCommand, SearchError},
+    vgm::vgm_player::VgmPlayer,
+};
+use spriter::{if_holded, if_pressed, Canvas, Color, Key};
 
-const VDP_CLOCK_PER_CPU: f32 = 1.75;
+// the save data is written every ~10 seconds of the emulated time
+const SAVE_DATA_FLUSH_FRAMES: u32 = 600;
+// the candidates printed by `search list`
+const SEARCH_LIST_SIZE: usize = 32;
 
 fn main() {
     env_logger::init();
@@ -40,61 +42,167 @@ fn main() {
         info!("no file to run");
         return;
     }
+    if args[1] == "--vgm" {
+        play_vgm(&args);
+        return;
+    }
+    // `segaret info <rom>` prints the cartridge header
+    if args[1] == "info" && args.len() > 2 {
+        match load_rom(Path::new(&args[2])) {
+            Ok((rom, format)) => {
+                let cartridge = Cartridge::new(&rom);
+                println!("{}", cartridge);
+                println!("Dump format:    {:?}", format);
+                println!("{}", RomInfo::new(&rom, &cartridge, &RomDatabase::bundled()));
+            }
+            Err(error) => println!("{}", error),
+        }
+        return;
+    }
 
-    let (runner, mut window) = spriter::init("segaret", 916 + 256, 1024);
-
-    let mut file = File::open(&args[1]).unwrap();
-    let mut rom = Vec::new();
-    let _ = file.read_to_end(&mut rom);
-    let region_code = rom[0x1F0];
-    let display_mod = match region_code {
-        45 => DisplayMod::PAL, // "E" == 45
-        _ => DisplayMod::NTSC, // "JU"
+    let disc_image = Path::new(&args[1])
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ["cue", "iso"].iter().any(|disc| extension.eq_ignore_ascii_case(disc)));
+    let sega_32x = Path::new(&args[1])
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("32x"));
+    // the Master System and the Game Gear cartridges run in the Power Base Converter mode
+    let master_system = Path::new(&args[1])
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| ["sms", "gg"].into_iter().find(|system| extension.eq_ignore_ascii_case(system)));
+    let genesis = if disc_image {
+        load_mega_cd(&args)
+    } else if sega_32x {
+        load_32x(&args)
+    } else if let Some(system) = master_system {
+        load_cartridge(&args, true).map(|rom| Genesis::with_master_system(rom, system == "gg"))
+    } else {
+        load_cartridge(&args, false).map(Genesis::new)
     };
+    let Some(mut genesis) = genesis else {
+        return;
+    };
+    let (runner, mut window) = spriter::init("segaret", 916 + 256, 1024);
 
-    let controller_a = Rc::new(RefCell::new(Controller::new()));
-    let controller_b = Rc::new(RefCell::new(Controller::new()));
-    let signal_bus = Rc::new(RefCell::new(SignalBus::new()));
-    let vdp = Rc::new(RefCell::new(Vdp::new(
-        &mut window,
-        signal_bus.clone(),
-        display_mod,
-    )));
-    let ym2612 = Rc::new(RefCell::new(Ym2612::new()));
-    let memory_space = Rc::new(RefCell::new(MemorySpace::new(
-        rom,
-        vdp.clone(),
-        ym2612.clone(),
-        controller_a.clone(),
-        controller_b.clone(),
-        signal_bus.clone(),
-    )));
-
-    vdp.borrow_mut().set_bus(memory_space.clone());
-
-    let mut m68k = M68k::new();
+    let state_path = format!("{}.state", args[1]);
+    let vgm_path = format!("{}.vgm", args[1]);
+    // the movies start from the cleared save data with no cheats, they are not part of the recording
+    let movie_requested = option_value(&args, "--record").is_some() || option_value(&args, "--play").is_some();
+    let save_data_path = Path::new(&args[1]).with_extension("srm");
+    let mut save_data = Vec::new();
+    if movie_requested {
+        info!("Save data and cheats are not loaded for the movie");
+    } else if File::open(&save_data_path).and_then(|mut f| f.read_to_end(&mut save_data)).is_ok() {
+        genesis.load_save_data(&save_data);
+        info!("Save data is loaded from {}", save_data_path.display());
+    }
+    let mut frames_to_flush = SAVE_DATA_FLUSH_FRAMES;
+    let cheats_path = Path::new(&args[1]).with_extension("cht");
+    let cheats_text = (!movie_requested).then(|| std::fs::read_to_string(&cheats_path).ok()).flatten();
+    if let Some(text) = cheats_text {
+        match parse_cheat_file(&text) {
+            Ok(cheats) => {
+                for cheat in cheats {
+                    genesis.add_cheat(cheat);
+                }
+                info!("Cheats are loaded from {}", cheats_path.display());
+            }
+            Err(e) => info!("Cheats loading is failed: {}", e),
+        }
+    }
+    let rom_info = genesis.rom_info();
+    if !rom_info.checksum_valid && !genesis.master_system_attached() {
+        info!("Rom checksum {:04X} does not match the header one", rom_info.checksum);
+    }
+    match &rom_info.entry {
+        Some(entry) => info!("Rom is found in the database: {}", entry.name),
+        None => info!("Rom is not found in the database, CRC32 {:08X}", rom_info.crc32),
+    }
+    // `segaret <rom> --wav <file>` records the sound
+    if let Some(wav_path) = option_value(&args, "--wav") {
+        let wav_sink = match WavSink::create(wav_path, SAMPLE_RATE_44100) {
+            Ok(wav_sink) => wav_sink,
+            Err(error) => {
+                println!("can't create the wav file {}: {}", wav_path, error);
+                return;
+            }
+        };
+        genesis.set_audio_sink(Box::new(wav_sink));
+        info!("Sound is recorded to {}", wav_path);
+    }
+    // `--tmss` locks the vdp until the game unlocks it, `--bios <file>` also runs the boot rom
+    if let Some(bios_path) = option_value(&args, "--bios") {
+        let mut bios = Vec::new();
+        if let Err(error) = File::open(bios_path).and_then(|mut f| f.read_to_end(&mut bios)) {
+            println!("can't read the bios {}: {}", bios_path, error);
+            return;
+        }
+        genesis.enable_tmss(Some(bios));
+        info!("TMSS is enabled with the boot rom {}", bios_path);
+    } else if args.iter().any(|arg| arg == "--tmss") {
+        genesis.enable_tmss(None);
+        info!("TMSS is enabled");
+    }
+    // `--record <file>` records the input from the power on, `--play <file>` replays it
+    let record_path = option_value(&args, "--record").map(PathBuf::from);
+    if record_path.is_some() {
+        genesis.start_movie_recording();
+        info!("Movie recording is started");
+    }
+    if let Some(play_path) = option_value(&args, "--play") {
+        let mut data = Vec::new();
+        if let Err(error) = File::open(play_path).and_then(|mut f| f.read_to_end(&mut data)) {
+            println!("can't read the movie {}: {}", play_path, error);
+            return;
+        }
+        match Movie::decode(&data).and_then(|movie| genesis.play_movie(movie)) {
+            Ok(()) => info!("Movie {} is playing", play_path),
+            Err(error) => {
+                println!("{}: {}", play_path, error);
+                return;
+            }
+        }
+    }
+    let mut movie_finished = false;
     let mut break_points: Vec<u32> = vec![];
     // let mut break_points = vec![0xB74, 0x1006, 0x1854];
-    m68k.set_breakpoints(&break_points);
-
-    m68k.set_bus(memory_space.clone());
-    m68k.reset();
+    genesis.set_breakpoints(&break_points);
 
-    let mut z80 = Z80::new();
-    z80.set_bus(memory_space.clone());
+    let mut screen_size = genesis.screen_size();
+    let mut screen = window.create_canvas(
+        0,
+        0,
+        640,
+        screen_size.1 as u32 * 2,
+        screen_size.0 as u32,
+        screen_size.1 as u32,
+    );
+    screen.set_clear_color(Color::from_u32(0xAAAAAA));
+    screen.clear();
+    let mut vram_table = window.create_canvas(
+        660,
+        0,
+        512,
+        1024,
+        VRAM_TABLE_WIDTH as u32,
+        VRAM_TABLE_HEIGHT as u32,
+    );
+    vram_table.set_clear_color(Color::from_u32(0xAAAACC));
+    vram_table.clear();
 
     let mut clock_allowed = false;
     let mut manual_clock = false;
     let mut by_frame = false;
-    let mut vdp_clocks_remainder = 0.0f32;
 
-    let mut values_map: HashMap<u8, Vec<u32>> = HashMap::new();
-    let mut downgraded_values: Vec<u8> = vec![];
-
-    let mut z80_bus_request = false;
-    let mut clock_counter = 0;
+    let mut ram_search: Option<RamSearch> = None;
+    let commands = spawn_console();
 
     runner.run(window, move |_| {
+        while let Ok(line) = commands.try_recv() {
+            run_command(&line, &mut genesis, &mut ram_search, &mut break_points, &cheats_path);
+        }
         if_pressed!(Key::A, {
             clock_allowed = !clock_allowed;
             info!("Auto Clock mode = {}", clock_allowed);
@@ -109,157 +217,418 @@ fn main() {
             by_frame = true;
         });
         if_pressed!(Key::U, {
-            vdp.borrow_mut().update_vram_table_on_screen();
+            draw_buffer(&mut vram_table, &genesis.vram_table(), VRAM_TABLE_WIDTH);
         });
-        if_pressed!(Key::V, {
-            info!("Break point manage command ('<address> a' - add break point, '<address> d - delete break point'");
-            let mut buf = String::new();
-            stdin().read_line(&mut buf).unwrap();
-            let parts = buf.split(" ").collect::<Vec<&str>>();
-            if parts.len() == 2 {
-                let break_point = u32::from_str_radix(parts[0], 16).unwrap();
-                let oparation = parts[1].trim();
-                match oparation {
-                    "a" | "A" => {
-                        break_points.push(break_point);
-                        info!("break point set: {:08X}", break_point)
-                    }
-                    "d" | "D" => {
-                        if let Some(position) = break_points.iter().position(|b| *b == break_point)
-                        {
-                            break_points.swap_remove(position);
-                            info!("break point remove: {:08X}", break_point);
-                        }
-                    }
-                    _ => (),
-                }
-                m68k.set_breakpoints(&break_points);
-                info!("break points list: {:08X?}", break_points);
+        if_pressed!(Key::Z, {
+            let mut dump_file = File::create("z80_dump").unwrap();
+            dump_file.write_all(&genesis.z80_ram()).unwrap();
+        });
+        if_pressed!(Key::K, {
+            match File::create(&state_path).and_then(|mut f| f.write_all(&genesis.save_state())) {
+                Ok(()) => info!("State is saved to {}", state_path),
+                Err(e) => info!("State saving is failed: {}", e),
             }
         });
-        if_pressed!(Key::S, {
-            info!("Search value address ('<value>' - init searching addresses, '<first value> <new value>' - search changes)");
-            let mut buf = String::new();
-            stdin().read_line(&mut buf).unwrap();
-            let parts = buf.split(" ").map(|p| p.trim()).collect::<Vec<&str>>();
-            match parts.len() {
-                1 => {
-                    let byte = u8::from_str_radix(parts[0], 16).unwrap();
-                    let addresses = memory_space
-                        .borrow()
-                        .m68k_ram
-                        .iter()
-                        .enumerate()
-                        .filter(|b| *b.1 == byte)
-                        .map(|a| a.0 as u32)
-                        .collect::<Vec<u32>>();
-                    values_map.insert(byte, addresses);
-                }
-                2 => {
-                    let last_value = u8::from_str_radix(parts[0], 16).unwrap();
-                    let new_value = u8::from_str_radix(parts[1], 16).unwrap();
-                    let addresses = values_map
-                        .get(&last_value)
-                        .unwrap()
-                        .iter()
-                        .filter(|a| memory_space.borrow().m68k_ram[(**a) as usize] == new_value)
-                        .map(|a| *a)
-                        .collect::<Vec<u32>>();
-                    values_map.insert(last_value, addresses);
+        if_pressed!(Key::L, {
+            let mut state = Vec::new();
+            if genesis.movie_mode().is_some() {
+                // the movie is read only, the state would break the recorded input
+                info!("State loading is disabled by the movie");
+            } else {
+                match File::open(&state_path).and_then(|mut f| f.read_to_end(&mut state)) {
+                    Ok(_) => match genesis.load_state(&state) {
+                        Ok(()) => info!("State is loaded from {}", state_path),
+                        Err(e) => info!("State loading is failed: {}", e),
+                    },
+                    Err(e) => info!("State loading is failed: {}", e),
                 }
-                _ => (),
-            }
-            for (key, val) in values_map.iter() {
-                info!("value {:02X}: {:08X?}", key, val);
             }
         });
-        if_pressed!(Key::D, {
-            info!("Searching values downgraded by one");
-            if downgraded_values.len() == 0 {
-                downgraded_values = memory_space.borrow().m68k_ram.clone();
+        if_pressed!(Key::R, {
+            if let Some(vgm) = genesis.stop_vgm_log() {
+                match File::create(&vgm_path).and_then(|mut f| f.write_all(&vgm)) {
+                    Ok(()) => info!("VGM log is saved to {}", vgm_path),
+                    Err(e) => info!("VGM log saving is failed: {}", e),
+                }
             } else {
-                let addresses = downgraded_values
-                    .iter()
-                    .enumerate()
-                    .filter(|v| (*v.1 - 1) == memory_space.borrow().m68k_ram[v.0])
-                    .map(|v| v.0 as u32)
-                    .collect::<Vec<u32>>();
-                info!("downgraded addresses {:08X?}", addresses);
-                downgraded_values = memory_space.borrow().m68k_ram.clone();
+                genesis.start_vgm_log();
+                info!("VGM logging is started");
             }
         });
-        if_pressed!(Key::Z, {
-            let mut dump_file = File::create("z80_dump").unwrap();
-            dump_file.write_all(&memory_space.borrow().z80_ram).unwrap();
-        });
+        // F1-F9 toggle the cheats of the list
+        for (index, key) in [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9]
+            .into_iter()
+            .enumerate()
+        {
+            if_pressed!(key, {
+                if genesis.movie_mode().is_some() {
+                    info!("Cheats are disabled by the movie");
+                } else if let Some(cheat) = genesis.cheats().get(index) {
+                    let enabled = !cheat.enabled;
+                    info!("Cheat {} \"{}\" enabled = {}", cheat.code, cheat.description, enabled);
+                    genesis.set_cheat_enabled(index, enabled);
+                }
+            });
+        }
         if_pressed!(Key::Escape, {
+            genesis.take_audio_sink(); // completes the recording
+            flush_save_data(&mut genesis, &save_data_path);
+            if let Some(record_path) = &record_path {
+                save_movie(&mut genesis, record_path);
+            }
             spriter::program_stop();
             info!("Exit from segaret");
         });
         if clock_allowed {
-            let mut update_screen = false;
-            while !update_screen && clock_counter < 71680 {
-                let mut vdp_clocks = 1;
-                if signal_bus.borrow_mut().handle_signal(Signal::VInterrupt) {
-                    m68k.interrupt(6);
-                }
-                if signal_bus.borrow_mut().handle_signal(Signal::HInterrupt) {
-                    m68k.interrupt(4);
-                }
-                if signal_bus.borrow_mut().handle_signal(Signal::Z80BusRequest) {
-                    z80_bus_request = true;
-                }
-                if signal_bus.borrow_mut().handle_signal(Signal::Z80BusFree) {
-                    z80_bus_request = false;
-                }
-                if signal_bus.borrow_mut().handle_signal(Signal::Z80Reset) {
-                    z80.restart();
-                }
-                if signal_bus.borrow_mut().handle_signal(Signal::Z80INT) {
-                    z80.int(0);
-                }
-                if !signal_bus.borrow_mut().handle_signal(Signal::CpuHalt) {
-                    let vdp_clocks_rational =
-                        m68k.clock() as f32 * VDP_CLOCK_PER_CPU + vdp_clocks_remainder;
-                    vdp_clocks = vdp_clocks_rational.trunc() as i32;
-                    vdp_clocks_remainder = vdp_clocks_rational.fract();
-
-                    if clock_counter % 15 == 0 {
-                        if !z80_bus_request {
-                            z80.clock();
-                        }
-                    }
-                }
-                for _ in 0..vdp_clocks {
-                    let update = vdp.borrow_mut().clock();
-                    if !update_screen {
-                        update_screen = update;
-                    }
-                }
-                clock_counter += vdp_clocks;
-                if m68k.breakpoint_hit {
-                    info!("CPU hits breakpoint");
-                    clock_allowed = false;
-                    break;
+            if genesis.movie_mode() != Some(MovieMode::Playing) {
+                poll_keyboard(&mut genesis.controller_1());
+            }
+            if manual_clock {
+                genesis.clock();
+                clock_allowed = false;
+                manual_clock = false;
+            } else {
+                genesis.run_frame();
+                frames_to_flush -= 1;
+                if frames_to_flush == 0 {
+                    frames_to_flush = SAVE_DATA_FLUSH_FRAMES;
+                    flush_save_data(&mut genesis, &save_data_path);
                 }
-                if manual_clock {
-                    clock_allowed = false;
-                    manual_clock = false;
-                    break;
+            }
+            if genesis.movie_mode() == Some(MovieMode::Finished) && !movie_finished {
+                movie_finished = true;
+                match genesis.movie_desync() {
+                    Some(frame) => info!("Movie is finished, desync at the frame {}", frame),
+                    None => info!("Movie is finished in sync"),
                 }
             }
+            if genesis.breakpoint_hit() {
+                info!("CPU hits breakpoint");
+                clock_allowed = false;
+            }
             if by_frame {
                 clock_allowed = !clock_allowed;
                 by_frame = false;
                 info!("Frame done")
             }
-            if clock_counter >= 71680 {
-                clock_counter = 0;
+            if screen_size != genesis.screen_size() {
+                screen_size = genesis.screen_size();
+                screen.resize_texture(screen_size.0 as u32, screen_size.1 as u32);
             }
-            controller_a.borrow_mut().clock();
+            draw_buffer(&mut screen, &genesis.frame_buffer(), screen_size.0);
+            draw_buffer(&mut vram_table, &genesis.vram_table(), VRAM_TABLE_WIDTH);
             true
         } else {
             false
         }
     });
 }
+
+// the console lines are read by a separate thread, the render loop takes them without waiting
+fn spawn_console() -> Receiver<String> {
+    info!("Console commands:");
+    info!("  break add|del <address> - manage the breakpoints");
+    info!("  cheat <code> [description] - add a Game Genie or Pro Action Replay code");
+    info!("  search start [m68k|z80|vram] [byte|word|long] [signed] - start the ram search");
+    info!("  search changed|unchanged|increased|decreased|diff <value>|<eq|ne|lt|gt|le|ge> [<value>]");
+    info!("  search undo|reset|list");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdin().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn run_command(
+    line: &str,
+    genesis: &mut Genesis,
+    ram_search: &mut Option<RamSearch>,
+    break_points: &mut Vec<u32>,
+    cheats_path: &Path,
+) {
+    let line = line.trim();
+    let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+    let arguments = arguments.trim();
+    match command {
+        "break" => {
+            let parts = arguments.split_whitespace().collect::<Vec<&str>>();
+            let Some(break_point) = parts.get(1).and_then(|address| u32::from_str_radix(address, 16).ok()) else {
+                info!("break point address is expected");
+                return;
+            };
+            match parts[0] {
+                "add" => {
+                    break_points.push(break_point);
+                    info!("break point set: {:08X}", break_point)
+                }
+                "del" => {
+                    if let Some(position) = break_points.iter().position(|b| *b == break_point) {
+                        break_points.swap_remove(position);
+                        info!("break point remove: {:08X}", break_point);
+                    }
+                }
+                _ => info!("unknown break point command {}", parts[0]),
+            }
+            genesis.set_breakpoints(break_points);
+            info!("break points list: {:08X?}", break_points);
+        }
+        "cheat" if genesis.movie_mode().is_some() => info!("Cheats are disabled by the movie"),
+        "cheat" => {
+            let (code, description) = arguments.split_once(' ').unwrap_or((arguments, ""));
+            match Cheat::new(code, description.trim()) {
+                Ok(cheat) => {
+                    genesis.add_cheat(cheat);
+                    let text = write_cheat_file(genesis.cheats());
+                    match File::create(cheats_path).and_then(|mut f| f.write_all(text.as_bytes())) {
+                        Ok(()) => info!("Cheats are saved to {}", cheats_path.display()),
+                        Err(e) => info!("Cheats saving is failed: {}", e),
+                    }
+                }
+                Err(e) => info!("{}", e),
+            }
+        }
+        "search" => match SearchCommand::parse(arguments) {
+            Ok(command) => run_search_command(command, genesis, ram_search),
+            Err(e) => info!("{}", e),
+        },
+        _ => info!("unknown command {}", command),
+    }
+}
+
+fn run_search_command(command: SearchCommand, genesis: &Genesis, ram_search: &mut Option<RamSearch>) {
+    if let SearchCommand::Start(region, size, signed) = command {
+        *ram_search = Some(RamSearch::new(region, size, signed, &genesis.memory(region)));
+    }
+    let Some(search) = ram_search.as_mut() else {
+        info!("{}", SearchError::NotStarted);
+        return;
+    };
+    let memory = genesis.memory(search.region());
+    match command {
+        SearchCommand::Start(..) => (),
+        SearchCommand::Filter(filter) => search.filter(filter, &memory),
+        SearchCommand::Undo => {
+            if !search.undo() {
+                info!("nothing to undo");
+            }
+        }
+        SearchCommand::Reset => search.reset(&memory),
+        SearchCommand::List => {
+            for candidate in search.candidates().iter().take(SEARCH_LIST_SIZE) {
+                let address = search.region().base_address() + candidate.offset as u32;
+                let value = search.value(&memory, candidate.offset);
+                info!("{:06X}: {} (previous {})", address, value, candidate.previous);
+            }
+        }
+    }
+    info!("search candidates: {}", search.candidates().len());
+}
+
+// the rom with the patch applied
+fn load_cartridge(args: &[String], master_system: bool) -> Option<Vec<u8>> {
+    let loaded = if master_system {
+        load_master_system_rom(Path::new(&args[1]))
+    } else {
+        load_rom(Path::new(&args[1]))
+    };
+    let mut rom = match loaded {
+        Ok((rom, format)) => {
+            info!("Rom is loaded as {:?}", format);
+            rom
+        }
+        Err(error) => {
+            println!("{}", error);
+            return None;
+        }
+    };
+    // `--patch <file>` or a patch with the rom name, `--fix-checksum` updates the header after it
+    let patch_path = option_value(args, "--patch")
+        .map(PathBuf::from)
+        .or_else(|| find_patch(Path::new(&args[1])));
+    if let Some(patch_path) = patch_path {
+        let mut patch = Vec::new();
+        if let Err(error) = File::open(&patch_path).and_then(|mut f| f.read_to_end(&mut patch)) {
+            println!("can't read the patch {}: {}", patch_path.display(), error);
+            return None;
+        }
+        match apply_patch(&rom, &patch) {
+            Ok(patched) => {
+                rom = patched;
+                info!("Patch {} is applied", patch_path.display());
+            }
+            Err(error) => {
+                println!("{}: {}", patch_path.display(), error);
+                return None;
+            }
+        }
+    }
+    // the Master System cartridges have no Mega Drive header
+    if !master_system && args.iter().any(|arg| arg == "--fix-checksum") {
+        fix_checksum(&mut rom);
+    }
+    Some(rom)
+}
+
+// `segaret <disc.cue|disc.iso> --cd-bios <file>` runs the disc on the Mega-CD
+fn load_mega_cd(args: &[String]) -> Option<Genesis> {
+    let Some(bios_path) = option_value(args, "--cd-bios") else {
+        println!("the Mega-CD bios is not set, use --cd-bios <file>");
+        return None;
+    };
+    let mut bios = Vec::new();
+    if let Err(error) = File::open(bios_path).and_then(|mut f| f.read_to_end(&mut bios)) {
+        println!("can't read the bios {}: {}", bios_path, error);
+        return None;
+    }
+    match Disc::open(Path::new(&args[1])) {
+        Ok(disc) => {
+            info!("Disc is loaded with {} tracks", disc.tracks().len());
+            Some(Genesis::with_mega_cd(bios, Some(disc)))
+        }
+        Err(error) => {
+            println!("{}", error);
+            None
+        }
+    }
+}
+
+// `segaret <rom.32x> --32x-bios <dir>` runs the cartridge on the 32X, the directory keeps the boot
+// roms of the m68k and both sh2s
+fn load_32x(args: &[String]) -> Option<Genesis> {
+    let Some(bios_dir) = option_value(args, "--32x-bios") else {
+        println!("the 32X bios directory is not set, use --32x-bios <dir>");
+        return None;
+    };
+    let mut bios = Vec::new();
+    for name in ["32X_G_BIOS.BIN", "32X_M_BIOS.BIN", "32X_S_BIOS.BIN"] {
+        let path = Path::new(bios_dir).join(name);
+        let mut data = Vec::new();
+        if let Err(error) = File::open(&path).and_then(|mut f| f.read_to_end(&mut data)) {
+            println!("can't read the bios {}: {}", path.display(), error);
+            return None;
+        }
+        bios.push(data);
+    }
+    let rom = load_cartridge(args, false)?;
+    let [m68k_bios, master_bios, slave_bios] = <[Vec<u8>; 3]>::try_from(bios).ok()?;
+    Some(Genesis::with_32x(rom, m68k_bios, master_bios, slave_bios))
+}
+
+// the argument after the option name
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == name)?;
+    args.get(position + 1).map(|value| value.as_str())
+}
+
+// `segaret --vgm <file.vgm|file.vgz> [--wav <file>]` renders the track into a wav file without the window
+fn play_vgm(args: &[String]) {
+    if args.len() < 3 {
+        info!("no vgm file to play");
+        return;
+    }
+    let wav_path = match args.get(3).map(|arg| arg.as_str()) {
+        Some("--wav") if args.len() > 4 => args[4].clone(),
+        _ => format!("{}.wav", args[2]),
+    };
+    let mut data = Vec::new();
+    if let Err(e) = File::open(&args[2]).and_then(|mut f| f.read_to_end(&mut data)) {
+        info!("VGM reading is failed: {}", e);
+        return;
+    }
+    let mut player = match VgmPlayer::new(data) {
+        Ok(player) => player,
+        Err(e) => {
+            info!("VGM loading is failed: {}", e);
+            return;
+        }
+    };
+    let wav_sink = match WavSink::create(&wav_path, SAMPLE_RATE_44100) {
+        Ok(wav_sink) => wav_sink,
+        Err(e) => {
+            info!("WAV file creating is failed: {}", e);
+            return;
+        }
+    };
+    player.set_audio_sink(Box::new(wav_sink));
+    match player.play() {
+        Ok(()) => info!("VGM is rendered to {}", wav_path),
+        Err(e) => info!("VGM playing is failed: {}", e),
+    }
+    player.take_audio_sink(); // completes the wav file
+}
+
+// writes the cartridge memory if the game has changed it, the save data of a movie is not kept
+fn flush_save_data(genesis: &mut Genesis, path: &Path) {
+    if genesis.movie_mode().is_some() || !genesis.save_data_changed() {
+        return;
+    }
+    if let Some(save_data) = genesis.save_data() {
+        match File::create(path).and_then(|mut f| f.write_all(&save_data)) {
+            Ok(()) => info!("Save data is written to {}", path.display()),
+            Err(e) => info!("Save data writing is failed: {}", e),
+        }
+    }
+}
+
+fn save_movie(genesis: &mut Genesis, path: &Path) {
+    let frames = genesis.movie_frame();
+    if let Some(movie) = genesis.stop_movie() {
+        match File::create(path).and_then(|mut f| f.write_all(&movie.encode())) {
+            Ok(()) => info!("Movie of {} frames is saved to {}", frames, path.display()),
+            Err(e) => info!("Movie saving is failed: {}", e),
+        }
+    }
+}
+
+fn draw_buffer(canvas: &mut Canvas, buffer: &[u32], width: usize) {
+    for (i, color) in buffer.iter().enumerate() {
+        let x = (i % width) as i32;
+        let y = (i / width) as i32;
+        canvas.set_pixel(x, y, Color::from_u32(*color)).unwrap();
+    }
+}
+
+fn poll_keyboard(controller: &mut Controller) {
+    controller.release_all();
+    if_holded!(Key::Space, {
+        controller.press_button(Button::Start);
+    });
+    if_holded!(Key::Right, {
+        controller.press_button(Button::Right);
+    });
+    if_holded!(Key::Left, {
+        controller.press_button(Button::Left);
+    });
+    if_holded!(Key::Up, {
+        controller.press_button(Button::Up);
+    });
+    if_holded!(Key::Down, {
+        controller.press_button(Button::Down);
+    });
+    if_holded!(Key::B, {
+        controller.press_button(Button::A);
+    });
+    if_holded!(Key::N, {
+        controller.press_button(Button::B);
+    });
+    if_holded!(Key::M, {
+        controller.press_button(Button::C);
+    });
+    if_holded!(Key::G, {
+        controller.press_button(Button::X);
+    });
+    if_holded!(Key::H, {
+        controller.press_button(Button::Y);
+    });
+    if_holded!(Key::J, {
+        controller.press_button(Button::Z);
+    });
+    if_holded!(Key::T, {
+        controller.press_button(Button::Mode);
+    });
+}
@@ -0,0 +1,279 @@
+use std::{cell::RefCell, rc::Rc};
+
+use log::debug;
+
+use crate::{
+    controller::{Button, Controller},
+    save_state::{StateError, StateReader, StateWriter},
+    sn76489::{sn76489::Sn76489, PsgPorts},
+    vdp_emu::vdp_emu::Vdp,
+};
+
+const RAM_SIZE: usize = 0x2000;
+const CARTRIDGE_RAM_SIZE: usize = 0x8000;
+const BANK_SIZE: usize = 0x4000;
+// the first kilobyte keeps the interrupt vectors whatever the slot 0 bank is
+const FIXED_ROM_SIZE: u16 = 0x0400;
+const MAPPER_REGISTERS: u16 = 0xFFFC;
+
+// the mapper control register bits
+const CARTRIDGE_RAM_ENABLED: u8 = 0x08;
+const CARTRIDGE_RAM_BANK: u8 = 0x04;
+
+// the Master System (or the Game Gear) of the Power Base Converter mode: the z80 runs the game
+// from the cartridge with the Sega mapper and reaches the vdp, the psg and the pads by the io ports
+pub(crate) struct MasterSystem {
+    rom: Rc<[u8]>, // the cartridge of the memory space
+    ram: Vec<u8>,
+    cartridge_ram: Vec<u8>,
+    cartridge_ram_used: bool,
+    cartridge_ram_changed: bool, // written since the last `take_changed`
+    mapper: [u8; 4], // 0xFFFC-0xFFFF: the ram control and the banks of the slots 0-2
+    io_control: u8,  // TH and TR directions and levels of both ports
+    game_gear: bool,
+    pause_pressed: bool,
+
+    vdp: Rc<RefCell<Vdp>>,
+    psg: Rc<RefCell<Sn76489>>,
+    controller_1: Rc<RefCell<Controller>>,
+    controller_2: Rc<RefCell<Controller>>,
+}
+
+impl MasterSystem {
+    pub(crate) fn new(
+        rom: Rc<[u8]>,
+        game_gear: bool,
+        vdp: Rc<RefCell<Vdp>>,
+        psg: Rc<RefCell<Sn76489>>,
+        controller_1: Rc<RefCell<Controller>>,
+        controller_2: Rc<RefCell<Controller>>,
+    ) -> Self {
+        Self {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            cartridge_ram: vec![0; CARTRIDGE_RAM_SIZE],
+            cartridge_ram_used: false,
+            cartridge_ram_changed: false,
+            mapper: [0, 0, 1, 2],
+            io_control: 0xFF,
+            game_gear,
+            pause_pressed: false,
+            vdp,
+            psg,
+            controller_1,
+            controller_2,
+        }
+    }
+
+    pub(crate) fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // the battery backed ram, only if the game has enabled it
+    pub(crate) fn save_data(&self) -> Option<&[u8]> {
+        self.cartridge_ram_used.then_some(self.cartridge_ram.as_slice())
+    }
+
+    pub(crate) fn load_save_data(&mut self, data: &[u8]) {
+        let size = data.len().min(CARTRIDGE_RAM_SIZE);
+        self.cartridge_ram[..size].copy_from_slice(&data[..size]);
+        self.cartridge_ram_used = true;
+        self.cartridge_ram_changed = false;
+    }
+
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.cartridge_ram_changed)
+    }
+
+    // the pause button of the Master System raises the nmi once per press,
+    // the Game Gear reads its start button by the port 0x00
+    pub(crate) fn take_pause(&mut self) -> bool {
+        let pressed = self.controller_1.borrow().buttons() & (1 << Button::Start as u16) != 0;
+        let pause = pressed && !self.pause_pressed && !self.game_gear;
+        self.pause_pressed = pressed;
+        pause
+    }
+
+    fn rom_byte(&self, bank: u8, address: u16) -> u8 {
+        let banks = self.rom.len().div_ceil(BANK_SIZE).max(1);
+        let offset = bank as usize % banks * BANK_SIZE + (address as usize & (BANK_SIZE - 1));
+        self.rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    fn cartridge_ram_offset(&self, address: u16) -> usize {
+        let bank = (self.mapper[0] & CARTRIDGE_RAM_BANK != 0) as usize;
+        bank * BANK_SIZE + (address as usize & (BANK_SIZE - 1))
+    }
+
+    pub(crate) fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..FIXED_ROM_SIZE => self.rom.get(address as usize).copied().unwrap_or(0xFF),
+            0x0400..=0x3FFF => self.rom_byte(self.mapper[1], address),
+            0x4000..=0x7FFF => self.rom_byte(self.mapper[2], address),
+            0x8000..=0xBFFF if self.mapper[0] & CARTRIDGE_RAM_ENABLED != 0 => {
+                self.cartridge_ram[self.cartridge_ram_offset(address)]
+            }
+            0x8000..=0xBFFF => self.rom_byte(self.mapper[3], address),
+            _ => self.ram[address as usize & (RAM_SIZE - 1)],
+        }
+    }
+
+    // the mapper registers are written through to the ram
+    pub(crate) fn write(&mut self, address: u16, data: u8) {
+        match address {
+            0x8000..=0xBFFF if self.mapper[0] & CARTRIDGE_RAM_ENABLED != 0 => {
+                let offset = self.cartridge_ram_offset(address);
+                self.cartridge_ram_changed |= self.cartridge_ram[offset] != data;
+                self.cartridge_ram[offset] = data;
+            }
+            0xC000..=0xFFFF => {
+                self.ram[address as usize & (RAM_SIZE - 1)] = data;
+                if address >= MAPPER_REGISTERS {
+                    self.mapper[(address - MAPPER_REGISTERS) as usize] = data;
+                    self.cartridge_ram_used |= self.mapper[0] & CARTRIDGE_RAM_ENABLED != 0;
+                    debug!("Master System: mapper register {:04X} is set to {:02X}", address, data);
+                }
+            }
+            _ => debug!("Master System: write {:02X} to the rom at {:04X} is ignored", data, address),
+        }
+    }
+
+    // the ports are decoded by the address bits 7, 6 and 0
+    pub(crate) fn io_read(&mut self, port: u16) -> u8 {
+        let port = port as u8;
+        if self.game_gear && port == 0x00 {
+            // START is active low, the export console with the NTSC video
+            let start = self.controller_1.borrow().buttons() & (1 << Button::Start as u16) != 0;
+            return (!start as u8) << 7 | 0x40;
+        }
+        match port & 0xC1 {
+            0x40 => self.vdp.borrow().mode4_v_counter(),
+            0x41 => self.vdp.borrow().mode4_h_counter(),
+            0x80 => self.vdp.borrow_mut().mode4_read_data(),
+            0x81 => self.vdp.borrow_mut().mode4_read_control(),
+            0xC0 => self.port_a(),
+            0xC1 => self.port_b(),
+            _ => 0xFF,
+        }
+    }
+
+    pub(crate) fn io_write(&mut self, port: u16, data: u8) {
+        let port = port as u8;
+        if self.game_gear && port < 0x07 {
+            debug!("Master System: write {:02X} to the Game Gear port {:02X} is ignored", data, port);
+            return;
+        }
+        match port & 0xC1 {
+            0x00 => debug!("Master System: memory control {:02X} is ignored", data),
+            0x01 => self.io_control = data,
+            0x40 | 0x41 => self.psg.borrow_mut().write(data),
+            0x80 => self.vdp.borrow_mut().mode4_write_data(data),
+            0x81 => self.vdp.borrow_mut().mode4_write_control(data),
+            _ => debug!("Master System: write {:02X} to the port {:02X} is ignored", data, port),
+        }
+    }
+
+    // the directions of the first pad, its buttons 1 and 2 (B and C) and up and down of the second pad
+    fn port_a(&self) -> u8 {
+        let buttons_1 = self.controller_1.borrow().buttons() as u8;
+        let buttons_2 = self.controller_2.borrow().buttons() as u8;
+        !(buttons_1 & 0x3F | (buttons_2 & 0x03) << 6)
+    }
+
+    // the rest of the second pad, the reset button and the TH lines, the output lines read back
+    // their levels
+    fn port_b(&self) -> u8 {
+        let buttons_2 = self.controller_2.borrow().buttons() as u8;
+        let th = |direction: u8, level: u8| self.io_control & direction != 0 || self.io_control & level != 0;
+        let th_a = th(0x02, 0x20) as u8;
+        let th_b = th(0x08, 0x80) as u8;
+        !(buttons_2 >> 2) & 0x0F | 0x30 | th_a << 6 | th_b << 7
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+        writer.write_bytes(&self.cartridge_ram);
+        writer.write_bool(self.cartridge_ram_used);
+        writer.write_bytes(&self.mapper);
+        writer.write_u8(self.io_control);
+        writer.write_bool(self.pause_pressed);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.ram)?;
+        reader.read_bytes(&mut self.cartridge_ram)?;
+        self.cartridge_ram_used = reader.read_bool()?;
+        reader.read_bytes(&mut self.mapper)?;
+        self.io_control = reader.read_u8()?;
+        self.pause_pressed = reader.read_bool()?;
+        self.cartridge_ram_changed = true; // the save file follows the loaded state
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::MasterSystem;
+    use crate::{
+        controller::{Button, Controller},
+        signal_bus::SignalBus,
+        sn76489::sn76489::Sn76489,
+        vdp_emu::{vdp_emu::Vdp, DisplayMod},
+    };
+
+    fn master_system(rom: Vec<u8>) -> MasterSystem {
+        let vdp = Vdp::new(Rc::new(RefCell::new(SignalBus::new())), DisplayMod::NTSC);
+        MasterSystem::new(
+            rom.into(),
+            false,
+            Rc::new(RefCell::new(vdp)),
+            Rc::new(RefCell::new(Sn76489::new())),
+            Rc::new(RefCell::new(Controller::new())),
+            Rc::new(RefCell::new(Controller::new())),
+        )
+    }
+
+    #[test]
+    fn mapper_switches_the_banks_and_the_cartridge_ram() {
+        // each 16 KB bank starts with its number
+        let mut rom = vec![0; 0x4000 * 8];
+        for (bank, data) in rom.chunks_mut(0x4000).enumerate() {
+            data[0x0400] = bank as u8;
+            data[0x3FFF] = bank as u8;
+        }
+        let mut master_system = master_system(rom);
+        assert_eq!(master_system.read(0x0400), 0);
+        assert_eq!(master_system.read(0x7FFF), 1);
+        assert_eq!(master_system.read(0xBFFF), 2);
+
+        master_system.write(0xFFFD, 5);
+        master_system.write(0xFFFF, 9); // the bank number wraps around the rom size
+        assert_eq!(master_system.read(0x0400), 5);
+        assert_eq!(master_system.read(0x0000), 0);
+        assert_eq!(master_system.read(0xBFFF), 1);
+        assert_eq!(master_system.read(0xDFFF), 9); // the registers are written through to the ram
+
+        master_system.write(0xFFFC, 0x08);
+        master_system.write(0x8000, 0x5A);
+        assert_eq!(master_system.read(0x8000), 0x5A);
+        assert_eq!(master_system.save_data().map(|data| data[0]), Some(0x5A));
+        master_system.write(0xFFFC, 0x00);
+        assert_eq!(master_system.read(0x8000), 0);
+    }
+
+    #[test]
+    fn pads_and_th_lines_are_read_by_the_io_ports() {
+        let mut master_system = master_system(vec![0; 0x8000]);
+        master_system.controller_1.borrow_mut().press_button(Button::C);
+        master_system.controller_2.borrow_mut().press_button(Button::Down);
+        master_system.controller_2.borrow_mut().press_button(Button::Right);
+        assert_eq!(master_system.io_read(0xDC), 0x5F);
+        assert_eq!(master_system.io_read(0xDD), 0xFD);
+
+        // TH A and TH B are outputs, A is low and B is high
+        master_system.io_write(0x3F, 0x80);
+        assert_eq!(master_system.io_read(0xDD) & 0xC0, 0x80);
+    }
+}
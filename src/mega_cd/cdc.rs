@@ -0,0 +1,221 @@
+use log::debug;
+
+use crate::{
+    mega_cd::disc::SECTOR_SIZE,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+const BUFFER_SIZE: usize = 0x4000;
+
+// IFSTAT and IFCTRL bits, the status ones are active low
+const DTEI: u8 = 0x40; // data transfer end
+const DECI: u8 = 0x20; // decoder
+const DTBSY: u8 = 0x08;
+const DOUTEN: u8 = 0x02;
+// CTRL0
+const DECEN: u8 = 0x80;
+const WRRQ: u8 = 0x04;
+
+// the destinations of the data transfer, the DD bits of the cdc mode register
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Destination {
+    MainCpu,
+    SubCpu,
+    Pcm,
+    ProgramRam,
+    WordRam,
+    None,
+}
+
+impl Destination {
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        match bits & 0x07 {
+            2 => Destination::MainCpu,
+            3 => Destination::SubCpu,
+            4 => Destination::Pcm,
+            5 => Destination::ProgramRam,
+            7 => Destination::WordRam,
+            _ => Destination::None,
+        }
+    }
+}
+
+// LC8951 data controller: decodes the sectors into its 16 KB buffer and transfers them to the host
+pub(crate) struct Cdc {
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) register_address: u8,
+    ifstat: u8,
+    ifctrl: u8,
+    pub(crate) dbc: u16, // the transfer length minus one
+    pub(crate) dac: u16, // the transfer address in the buffer
+    wa: u16,            // the decoder write address
+    pt: u16,            // the header of the last decoded sector
+    ctrl: [u8; 2],
+    head: [u8; 4],
+    stat: [u8; 4],
+    pub(crate) data_ready: bool, // DSR, the host data register has a word
+    pub(crate) transfer_end: bool, // EDT
+}
+
+impl Cdc {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: vec![0; BUFFER_SIZE],
+            register_address: 0,
+            ifstat: 0xFF,
+            ifctrl: 0,
+            dbc: 0,
+            dac: 0,
+            wa: 0,
+            pt: 0,
+            ctrl: [0; 2],
+            head: [0; 4],
+            stat: [0; 4],
+            data_ready: false,
+            transfer_end: false,
+        }
+    }
+
+    // the register pointed by the address register, the address is incremented except for the register 0
+    pub(crate) fn read_register(&mut self) -> u8 {
+        let data = match self.register_address {
+            0x01 => self.ifstat,
+            0x02 => self.dbc as u8,
+            0x03 => (self.dbc >> 8) as u8,
+            0x04..=0x07 => self.head[self.register_address as usize - 0x04],
+            0x08 => self.pt as u8,
+            0x09 => (self.pt >> 8) as u8,
+            0x0A => self.wa as u8,
+            0x0B => (self.wa >> 8) as u8,
+            0x0C..=0x0F => self.stat[self.register_address as usize - 0x0C],
+            _ => 0xFF,
+        };
+        if self.register_address == 0x0F {
+            // reading STAT3 clears the decoder interrupt
+            self.ifstat |= DECI;
+        }
+        self.next_register();
+        data
+    }
+
+    // returns true if the write starts the data transfer
+    pub(crate) fn write_register(&mut self, data: u8) -> bool {
+        let mut transfer = false;
+        match self.register_address {
+            0x01 => {
+                self.ifctrl = data;
+                if data & DOUTEN == 0 {
+                    self.ifstat |= DTBSY | DTEI;
+                }
+            }
+            0x02 => self.dbc = self.dbc & 0xFF00 | data as u16,
+            0x03 => self.dbc = self.dbc & 0x00FF | (data as u16 & 0x0F) << 8,
+            0x04 => self.dac = self.dac & 0xFF00 | data as u16,
+            0x05 => self.dac = self.dac & 0x00FF | (data as u16) << 8,
+            0x06 if self.ifctrl & DOUTEN != 0 => {
+                self.ifstat &= !DTBSY;
+                self.transfer_end = false;
+                transfer = true;
+            }
+            0x07 => self.ifstat |= DTEI, // DTACK
+            0x08 => self.wa = self.wa & 0xFF00 | data as u16,
+            0x09 => self.wa = self.wa & 0x00FF | (data as u16) << 8,
+            0x0A => self.ctrl[0] = data,
+            0x0B => self.ctrl[1] = data,
+            0x0C => self.pt = self.pt & 0xFF00 | data as u16,
+            0x0D => self.pt = self.pt & 0x00FF | (data as u16) << 8,
+            0x0F => {
+                let buffer = std::mem::take(&mut self.buffer);
+                *self = Self { buffer, ..Self::new() };
+            }
+            _ => (),
+        }
+        self.next_register();
+        transfer
+    }
+
+    fn next_register(&mut self) {
+        if self.register_address != 0 {
+            self.register_address = (self.register_address + 1) & 0x1F;
+        }
+    }
+
+    // returns true if the decoder interrupt is raised
+    pub(crate) fn decode(&mut self, sector: &[u8; SECTOR_SIZE]) -> bool {
+        if self.ctrl[0] & DECEN == 0 {
+            return false;
+        }
+        self.head.copy_from_slice(&sector[12..16]);
+        self.stat = [0x80, 0x00, 0x00, 0x00]; // the error correction has found no errors
+        if self.ctrl[0] & WRRQ != 0 {
+            self.pt = self.wa.wrapping_add(12);
+            for (index, byte) in sector.iter().enumerate() {
+                self.buffer[(self.wa as usize + index) % BUFFER_SIZE] = *byte;
+            }
+            self.wa = self.wa.wrapping_add(SECTOR_SIZE as u16);
+        }
+        self.ifstat &= !DECI;
+        debug!("CDC: sector {:02X}:{:02X}:{:02X} is decoded", self.head[0], self.head[1], self.head[2]);
+        self.ifctrl & DECI != 0
+    }
+
+    // the next word of the transfer, returns None after the last one
+    pub(crate) fn transfer_word(&mut self) -> Option<u16> {
+        if self.transfer_end {
+            return None;
+        }
+        let address = self.dac as usize % BUFFER_SIZE;
+        let word = u16::from_be_bytes([self.buffer[address], self.buffer[(address + 1) % BUFFER_SIZE]]);
+        self.dac = self.dac.wrapping_add(2);
+        let (dbc, end) = self.dbc.overflowing_sub(2);
+        self.dbc = dbc & 0x0FFF;
+        if end {
+            self.finish_transfer();
+        }
+        Some(word)
+    }
+
+    fn finish_transfer(&mut self) {
+        self.transfer_end = true;
+        self.data_ready = false;
+        self.ifstat |= DTBSY;
+        self.ifstat &= !DTEI;
+    }
+
+    pub(crate) fn transfer_interrupt_enabled(&self) -> bool {
+        self.ifctrl & DTEI != 0
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.buffer);
+        writer.write_u8(self.register_address);
+        writer.write_u8(self.ifstat);
+        writer.write_u8(self.ifctrl);
+        writer.write_u16(self.dbc);
+        writer.write_u16(self.dac);
+        writer.write_u16(self.wa);
+        writer.write_u16(self.pt);
+        writer.write_bytes(&self.ctrl);
+        writer.write_bytes(&self.head);
+        writer.write_bytes(&self.stat);
+        writer.write_bool(self.data_ready);
+        writer.write_bool(self.transfer_end);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.buffer)?;
+        self.register_address = reader.read_u8()? & 0x1F;
+        self.ifstat = reader.read_u8()?;
+        self.ifctrl = reader.read_u8()?;
+        self.dbc = reader.read_u16()?;
+        self.dac = reader.read_u16()?;
+        self.wa = reader.read_u16()?;
+        self.pt = reader.read_u16()?;
+        reader.read_bytes(&mut self.ctrl)?;
+        reader.read_bytes(&mut self.head)?;
+        reader.read_bytes(&mut self.stat)?;
+        self.data_ready = reader.read_bool()?;
+        self.transfer_end = reader.read_bool()?;
+        Ok(())
+    }
+}
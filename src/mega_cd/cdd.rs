@@ -0,0 +1,236 @@
+use log::{debug, warn};
+
+use crate::{
+    mega_cd::disc::{lba_to_msf, Disc, TrackKind, LEAD_IN_FRAMES, SECTOR_SIZE},
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+// the status codes of the first report nibble
+const NO_DISC: u8 = 0x0;
+const PLAYING: u8 = 0x1;
+const SEEKING: u8 = 0x2;
+const PAUSED: u8 = 0x4;
+const TRAY_OPEN: u8 = 0x5;
+const STOPPED: u8 = 0x9;
+const READING_TOC: u8 = 0xC;
+
+// a sector passed by the drive at the 75 Hz rate
+pub(crate) enum DriveOutput {
+    Data(Box<[u8; SECTOR_SIZE]>),
+    Audio(Box<[u8; SECTOR_SIZE]>),
+}
+
+// the drive controller: the host writes 10 nibble commands and reads 10 nibble status reports,
+// the last nibble of both is the inverted sum of the others
+pub(crate) struct Cdd {
+    pub(crate) disc: Option<Disc>,
+    pub(crate) status: [u8; 10],
+    pub(crate) command: [u8; 10],
+    state: u8,
+    lba: i32, // the pregap of the first track is negative
+    report: u8, // the requested report of the status
+    report_track: u8,
+}
+
+impl Cdd {
+    pub(crate) fn new(disc: Option<Disc>) -> Self {
+        let mut cdd = Self {
+            state: if disc.is_some() { STOPPED } else { NO_DISC },
+            disc,
+            status: [0; 10],
+            command: [0; 10],
+            lba: 0,
+            report: 0,
+            report_track: 0,
+        };
+        cdd.update_status();
+        cdd
+    }
+
+    // the host writes the last command nibble
+    pub(crate) fn execute(&mut self) {
+        if checksum(&self.command) != self.command[9] {
+            warn!("CDD: command {:X?} has a wrong checksum", self.command);
+        }
+        let command = self.command;
+        debug!("CDD: command {:X?}", command);
+        match command[0] {
+            0x0 => (), // status request
+            0x1 => self.state = if self.disc.is_some() { STOPPED } else { NO_DISC },
+            0x2 => {
+                self.report = command[3];
+                self.report_track = command[4] * 10 + command[5];
+            }
+            0x3 | 0x4 if self.disc.is_some() => {
+                let frames = (command[2] as u32 * 10 + command[3] as u32) * 60 * 75
+                    + (command[4] as u32 * 10 + command[5] as u32) * 75
+                    + command[6] as u32 * 10
+                    + command[7] as u32;
+                self.lba = frames as i32 - LEAD_IN_FRAMES as i32;
+                self.state = if command[0] == 0x3 { PLAYING } else { PAUSED };
+                self.report = 0;
+            }
+            0x6 if self.disc.is_some() => self.state = PAUSED,
+            0x7 if self.disc.is_some() => self.state = PLAYING,
+            0x8 => self.lba += 75, // the fast forward and the rewind skip a second per command
+            0x9 => self.lba = (self.lba - 75).max(-(LEAD_IN_FRAMES as i32)),
+            0xC => self.state = if self.disc.is_some() { STOPPED } else { NO_DISC },
+            0xD => self.state = TRAY_OPEN,
+            _ => debug!("CDD: command {:X} is ignored", command[0]),
+        }
+        self.update_status();
+    }
+
+    // the next sector of the disc, called at the 75 Hz rate
+    pub(crate) fn clock_sector(&mut self) -> Option<DriveOutput> {
+        let output = match (&mut self.disc, self.state) {
+            (Some(disc), PLAYING) => {
+                if self.lba >= disc.lead_out() as i32 {
+                    self.state = STOPPED;
+                    None
+                } else if self.lba < 0 {
+                    None
+                } else {
+                    let mut sector = Box::new([0; SECTOR_SIZE]);
+                    match disc.read_sector(self.lba as u32, &mut sector) {
+                        Ok(TrackKind::Data { .. }) => Some(DriveOutput::Data(sector)),
+                        Ok(TrackKind::Audio) => Some(DriveOutput::Audio(sector)),
+                        Err(error) => {
+                            warn!("CDD: sector {} reading is failed: {}", self.lba, error);
+                            None
+                        }
+                    }
+                }
+            }
+            _ => None,
+        };
+        if self.state == PLAYING {
+            self.lba += 1;
+        }
+        self.update_status();
+        output
+    }
+
+    fn update_status(&mut self) {
+        let mut status = [0; 10];
+        status[0] = self.state;
+        status[1] = self.report;
+        if let Some(disc) = &self.disc {
+            let absolute = (self.lba + LEAD_IN_FRAMES as i32).max(0) as u32;
+            let track = self.lba.max(0) as u32;
+            let track = disc.track_at(track).or_else(|| disc.tracks().first());
+            let data_flag = |kind: TrackKind| if matches!(kind, TrackKind::Data { .. }) { 0x4 } else { 0x0 };
+            match self.report {
+                0x0 => {
+                    write_msf(&mut status, absolute);
+                    status[8] = track.map_or(0, |track| data_flag(track.kind));
+                }
+                0x1 => {
+                    let relative = track.map_or(0, |track| (self.lba - track.start as i32).unsigned_abs());
+                    write_msf(&mut status, relative);
+                    status[8] = track.map_or(0, |track| data_flag(track.kind));
+                }
+                0x2 => {
+                    let number = track.map_or(0, |track| track.number);
+                    status[2] = number / 10;
+                    status[3] = number % 10;
+                }
+                0x3 => write_msf(&mut status, disc.lead_out() + LEAD_IN_FRAMES),
+                0x4 => {
+                    let last = disc.tracks().last().map_or(0, |track| track.number);
+                    status[2] = 0;
+                    status[3] = 1;
+                    status[4] = last / 10;
+                    status[5] = last % 10;
+                }
+                0x5 => {
+                    let track = disc.tracks().iter().find(|track| track.number == self.report_track);
+                    if let Some(track) = track {
+                        write_msf(&mut status, track.start + LEAD_IN_FRAMES);
+                        // the data tracks are marked by the bit 3 of the seconds tens
+                        status[6] |= data_flag(track.kind) << 1;
+                        status[8] = track.number % 10;
+                    }
+                }
+                _ => (),
+            }
+        } else if self.state != TRAY_OPEN {
+            status[0] = NO_DISC;
+        }
+        if self.state == PLAYING && self.lba < 0 {
+            status[0] = SEEKING;
+        }
+        if self.state == STOPPED && self.report == 0x4 {
+            status[0] = READING_TOC;
+        }
+        status[9] = checksum(&status);
+        self.status = status;
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.status);
+        writer.write_bytes(&self.command);
+        writer.write_u8(self.state);
+        writer.write_u32(self.lba as u32);
+        writer.write_u8(self.report);
+        writer.write_u8(self.report_track);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.status)?;
+        reader.read_bytes(&mut self.command)?;
+        self.state = reader.read_u8()?;
+        self.lba = reader.read_u32()? as i32;
+        self.report = reader.read_u8()?;
+        self.report_track = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+fn write_msf(status: &mut [u8; 10], frames: u32) {
+    let (minutes, seconds, frames) = lba_to_msf(frames);
+    for (index, value) in [minutes, seconds, frames].into_iter().enumerate() {
+        status[2 + index * 2] = value / 10;
+        status[3 + index * 2] = value % 10;
+    }
+}
+
+fn checksum(nibbles: &[u8; 10]) -> u8 {
+    !nibbles[..9].iter().fold(0u8, |sum, nibble| sum.wrapping_add(*nibble)) & 0x0F
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{checksum, Cdd, DriveOutput, PAUSED, PLAYING};
+    use crate::mega_cd::disc::{Disc, DATA_SIZE};
+
+    fn send(cdd: &mut Cdd, command: [u8; 9]) {
+        cdd.command[..9].copy_from_slice(&command);
+        cdd.command[9] = checksum(&cdd.command);
+        cdd.execute();
+    }
+
+    #[test]
+    fn reads_toc_and_sectors() {
+        let disc = Disc::from_iso(Box::new(Cursor::new(vec![0x77; DATA_SIZE * 300]))).unwrap();
+        let mut cdd = Cdd::new(Some(disc));
+
+        send(&mut cdd, [0x2, 0x0, 0x0, 0x3, 0x0, 0x0, 0x0, 0x0, 0x0]);
+        assert_eq!(&cdd.status[..8], &[0x9, 0x3, 0x0, 0x0, 0x0, 0x6, 0x0, 0x0]); // 00:06:00 lead out
+        assert_eq!(cdd.status[9], checksum(&cdd.status));
+
+        // plays from 00:02:01, the lba 1
+        send(&mut cdd, [0x3, 0x0, 0x0, 0x0, 0x0, 0x2, 0x0, 0x1, 0x0]);
+        assert_eq!(cdd.status[0], PLAYING);
+        match cdd.clock_sector() {
+            Some(DriveOutput::Data(sector)) => assert_eq!((&sector[12..15], sector[16]), (&[0x00, 0x02, 0x01][..], 0x77)),
+            _ => panic!("no data sector"),
+        }
+        assert_eq!(&cdd.status[2..8], &[0x0, 0x0, 0x0, 0x2, 0x0, 0x2]);
+        send(&mut cdd, [0x6, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0]);
+        assert_eq!(cdd.status[0], PAUSED);
+        assert!(cdd.clock_sector().is_none());
+    }
+}
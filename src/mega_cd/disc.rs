@@ -0,0 +1,291 @@
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+pub const SECTOR_SIZE: usize = 2352; // raw sector with the sync, the header and the error correction
+pub const DATA_SIZE: usize = 2048; // user data of a mode 1 sector
+// the lba 0 is at 00:02:00 of the disc time
+pub const LEAD_IN_FRAMES: u32 = 150;
+const SYNC: [u8; 12] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+#[derive(Debug)]
+pub enum DiscError {
+    Io(std::io::Error),
+    InvalidCue(usize, String),
+    UnsupportedTrack(String),
+    NoTracks,
+}
+
+impl Display for DiscError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscError::Io(error) => write!(f, "can't read the disc image: {}", error),
+            DiscError::InvalidCue(line, text) => write!(f, "invalid cue sheet line {}: \"{}\"", line, text),
+            DiscError::UnsupportedTrack(kind) => write!(f, "unsupported track type {}", kind),
+            DiscError::NoTracks => write!(f, "disc image has no tracks"),
+        }
+    }
+}
+
+impl From<std::io::Error> for DiscError {
+    fn from(error: std::io::Error) -> Self {
+        DiscError::Io(error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrackKind {
+    Data { sector_size: usize }, // mode 1, 2048 or 2352 bytes per sector in the file
+    Audio,                       // 16 bit stereo little endian samples at 44.1 kHz
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track {
+    pub number: u8,
+    pub kind: TrackKind,
+    pub start: u32, // lba
+    pub length: u32, // sectors
+    file: usize,
+    offset: u64, // the position of the first sector in the file
+}
+
+impl Track {
+    fn sector_size(&self) -> usize {
+        match self.kind {
+            TrackKind::Data { sector_size } => sector_size,
+            TrackKind::Audio => SECTOR_SIZE,
+        }
+    }
+}
+
+pub trait DiscFile: Read + Seek {}
+
+impl<T: Read + Seek> DiscFile for T {}
+
+pub struct Disc {
+    tracks: Vec<Track>,
+    files: Vec<Box<dyn DiscFile>>,
+}
+
+impl Disc {
+    // a cue sheet with its bin files or a plain iso image
+    pub fn open(path: &Path) -> Result<Self, DiscError> {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        if extension.eq_ignore_ascii_case("cue") {
+            let text = std::fs::read_to_string(path)?;
+            let directory = path.parent().unwrap_or(Path::new("."));
+            parse_cue(&text, |name| Ok(Box::new(File::open(directory.join(name))?)))
+        } else {
+            Self::from_iso(Box::new(File::open(path)?))
+        }
+    }
+
+    // a single data track of 2048 bytes sectors
+    pub fn from_iso(mut file: Box<dyn DiscFile>) -> Result<Self, DiscError> {
+        let length = (file.seek(SeekFrom::End(0))? / DATA_SIZE as u64) as u32;
+        if length == 0 {
+            return Err(DiscError::NoTracks);
+        }
+        let track = Track {
+            number: 1,
+            kind: TrackKind::Data { sector_size: DATA_SIZE },
+            start: 0,
+            length,
+            file: 0,
+            offset: 0,
+        };
+        Ok(Self { tracks: vec![track], files: vec![file] })
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    // the first sector after the last track
+    pub fn lead_out(&self) -> u32 {
+        self.tracks.last().map_or(0, |track| track.start + track.length)
+    }
+
+    pub fn track_at(&self, lba: u32) -> Option<&Track> {
+        self.tracks.iter().find(|track| (track.start..track.start + track.length).contains(&lba))
+    }
+
+    // the raw sector, the data sectors of the 2048 bytes images get the sync and the header
+    pub(crate) fn read_sector(&mut self, lba: u32, buffer: &mut [u8; SECTOR_SIZE]) -> Result<TrackKind, DiscError> {
+        let Some(track) = self.track_at(lba).cloned() else {
+            buffer.fill(0);
+            return Ok(TrackKind::Audio); // the pregaps and the lead out are silent
+        };
+        let file = &mut self.files[track.file];
+        let position = track.offset + (lba - track.start) as u64 * track.sector_size() as u64;
+        file.seek(SeekFrom::Start(position))?;
+        match track.kind {
+            TrackKind::Data { sector_size: DATA_SIZE } => {
+                buffer.fill(0);
+                buffer[..12].copy_from_slice(&SYNC);
+                buffer[12..15].copy_from_slice(&msf_bcd(lba + LEAD_IN_FRAMES));
+                buffer[15] = 0x01; // mode 1
+                file.read_exact(&mut buffer[16..16 + DATA_SIZE])?;
+            }
+            _ => file.read_exact(buffer)?,
+        }
+        Ok(track.kind)
+    }
+}
+
+// minutes, seconds and frames of the absolute disc time
+pub fn lba_to_msf(frames: u32) -> (u8, u8, u8) {
+    ((frames / 75 / 60) as u8, (frames / 75 % 60) as u8, (frames % 75) as u8)
+}
+
+fn msf_bcd(frames: u32) -> [u8; 3] {
+    let (minutes, seconds, frames) = lba_to_msf(frames);
+    let bcd = |value: u8| ((value / 10) << 4) | (value % 10);
+    [bcd(minutes), bcd(seconds), bcd(frames)]
+}
+
+fn parse_msf(text: &str) -> Option<u32> {
+    let mut parts = text.split(':').map(|part| part.parse::<u32>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    Some((minutes * 60 + seconds) * 75 + frames)
+}
+
+// `FILE "game.bin" BINARY`, `TRACK 01 MODE1/2352`, `PREGAP 00:02:00` and `INDEX 01 00:00:00` lines,
+// the files are opened by the `open` callback
+pub(crate) fn parse_cue<F>(text: &str, mut open: F) -> Result<Disc, DiscError>
+where
+    F: FnMut(&str) -> Result<Box<dyn DiscFile>, DiscError>,
+{
+    let mut files: Vec<Box<dyn DiscFile>> = vec![];
+    // the tracks with the start relative to the file and the pregap before them
+    let mut entries: Vec<(Track, u32)> = vec![];
+    let mut pregap = 0;
+    for (index, line) in text.lines().enumerate() {
+        let invalid = || DiscError::InvalidCue(index + 1, line.trim().to_string());
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        match words.first().map(|word| word.to_uppercase()).as_deref() {
+            Some("FILE") => {
+                let name = line.split('"').nth(1).or(words.get(1).copied()).ok_or_else(invalid)?;
+                files.push(open(name)?);
+            }
+            Some("TRACK") => {
+                let number = words.get(1).and_then(|number| number.parse().ok()).ok_or_else(invalid)?;
+                let kind = match words.get(2).map(|kind| kind.to_uppercase()).as_deref() {
+                    Some("MODE1/2048") => TrackKind::Data { sector_size: DATA_SIZE },
+                    Some("MODE1/2352") => TrackKind::Data { sector_size: SECTOR_SIZE },
+                    Some("AUDIO") => TrackKind::Audio,
+                    Some(kind) => return Err(DiscError::UnsupportedTrack(kind.to_string())),
+                    None => return Err(invalid()),
+                };
+                if files.is_empty() {
+                    return Err(invalid());
+                }
+                let track = Track { number, kind, start: 0, length: 0, file: files.len() - 1, offset: 0 };
+                entries.push((track, 0));
+            }
+            Some("PREGAP") => pregap += words.get(1).and_then(|msf| parse_msf(msf)).ok_or_else(invalid)?,
+            Some("INDEX") => {
+                let (track, track_pregap) = entries.last_mut().ok_or_else(invalid)?;
+                let start = words.get(2).and_then(|msf| parse_msf(msf)).ok_or_else(invalid)?;
+                if words.get(1) == Some(&"01") {
+                    track.offset = start as u64 * track.sector_size() as u64;
+                    *track_pregap = pregap;
+                }
+            }
+            _ => (), // REM, CATALOG, TITLE and the others
+        }
+    }
+    if entries.is_empty() {
+        return Err(DiscError::NoTracks);
+    }
+
+    // a track ends at the next track of the same file or at the end of the file
+    let mut tracks: Vec<Track> = vec![];
+    let mut file_start = 0;
+    for index in 0..entries.len() {
+        let (track, pregap) = &entries[index];
+        let sector_size = track.sector_size() as u64;
+        let end = match entries.get(index + 1) {
+            Some((next, _)) if next.file == track.file => next.offset / next.sector_size() as u64 * sector_size,
+            _ => files[track.file].seek(SeekFrom::End(0))?,
+        };
+        let first_sector = (track.offset / sector_size) as u32;
+        // the pregaps are a running total, so the next file starts at the raw end of the previous one
+        if index > 0 && entries[index - 1].0.file != track.file {
+            let previous_pregap = entries[index - 1].1;
+            file_start = tracks.last().map_or(0, |previous: &Track| previous.start - previous_pregap + previous.length);
+        }
+        let mut track = track.clone();
+        track.start = file_start + first_sector + pregap;
+        track.length = (end.saturating_sub(track.offset) / sector_size) as u32;
+        tracks.push(track);
+    }
+    Ok(Disc { tracks, files })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{parse_cue, Disc, DiscError, DiscFile, TrackKind, DATA_SIZE, SECTOR_SIZE};
+
+    #[test]
+    fn parses_cue_sheet_tracks() {
+        let data_track = vec![0x11u8; SECTOR_SIZE * 10];
+        let audio_tracks = vec![0x22u8; SECTOR_SIZE * 30];
+        let cue = "FILE \"game (track 1).bin\" BINARY\n  TRACK 01 MODE1/2352\n    INDEX 01 00:00:00\n\
+                   FILE \"game (track 2).bin\" BINARY\n  TRACK 02 AUDIO\n    PREGAP 00:02:00\n    INDEX 01 00:00:00\n\
+                   \x20 TRACK 03 AUDIO\n    INDEX 00 00:00:10\n    INDEX 01 00:00:12\n";
+        let mut disc = parse_cue(cue, |name| {
+            let data = if name.contains("track 1") { data_track.clone() } else { audio_tracks.clone() };
+            Ok(Box::new(Cursor::new(data)) as Box<dyn DiscFile>)
+        })
+        .unwrap();
+
+        let tracks = disc.tracks();
+        assert_eq!(tracks.len(), 3);
+        assert_eq!((tracks[0].start, tracks[0].length), (0, 10));
+        assert_eq!((tracks[1].start, tracks[1].length, tracks[1].kind), (160, 12, TrackKind::Audio));
+        assert_eq!((tracks[2].start, tracks[2].length), (172, 18));
+        assert_eq!(disc.lead_out(), 190);
+
+        let mut sector = [0; SECTOR_SIZE];
+        assert_eq!(disc.read_sector(161, &mut sector).unwrap(), TrackKind::Audio);
+        assert_eq!(sector[0], 0x22);
+        assert!(matches!(
+            parse_cue("TRACK 01 MODE2/2352", |_| unreachable!()),
+            Err(DiscError::UnsupportedTrack(_))
+        ));
+    }
+
+    #[test]
+    fn pregaps_of_several_files_are_counted_once() {
+        let cue = "FILE \"track 1.bin\" BINARY\n  TRACK 01 MODE1/2352\n    INDEX 01 00:00:00\n\
+                   FILE \"track 2.bin\" BINARY\n  TRACK 02 AUDIO\n    PREGAP 00:02:00\n    INDEX 01 00:00:00\n\
+                   FILE \"track 3.bin\" BINARY\n  TRACK 03 AUDIO\n    PREGAP 00:02:00\n    INDEX 01 00:00:00\n";
+        let disc = parse_cue(cue, |name| {
+            let sectors = if name.contains("track 1") { 10 } else if name.contains("track 2") { 20 } else { 30 };
+            Ok(Box::new(Cursor::new(vec![0u8; SECTOR_SIZE * sectors])) as Box<dyn DiscFile>)
+        })
+        .unwrap();
+
+        let tracks = disc.tracks();
+        assert_eq!((tracks[0].start, tracks[0].length), (0, 10));
+        assert_eq!((tracks[1].start, tracks[1].length), (160, 20));
+        assert_eq!((tracks[2].start, tracks[2].length), (330, 30));
+        assert_eq!(disc.lead_out(), 360);
+    }
+
+    #[test]
+    fn iso_sectors_get_header() {
+        let mut image = vec![0; DATA_SIZE * 200];
+        image[DATA_SIZE * 180] = 0x5A;
+        let mut disc = Disc::from_iso(Box::new(Cursor::new(image))).unwrap();
+        let mut sector = [0; SECTOR_SIZE];
+        disc.read_sector(180, &mut sector).unwrap();
+        assert_eq!(&sector[12..17], &[0x00, 0x04, 0x30, 0x01, 0x5A]); // 00:04:30 mode 1
+    }
+}
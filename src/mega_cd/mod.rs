@@ -0,0 +1,669 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+};
+
+use log::debug;
+use m68k_emu::bus::BusM68k;
+
+use crate::{
+    mega_cd::{
+        cdc::{Cdc, Destination},
+        cdd::{Cdd, DriveOutput},
+        disc::{Disc, SECTOR_SIZE},
+    },
+    memory_space::write_byte,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+mod cdc;
+mod cdd;
+pub mod disc;
+
+pub(crate) const SUB_CPU_CLOCK: u64 = 12_500_000;
+pub(crate) const MAIN_REGISTERS: u32 = 0xA12000;
+const SUB_REGISTERS: u32 = 0xFF8000;
+const SECTOR_CLOCKS: u64 = SUB_CPU_CLOCK / 75;
+const TIMER_CLOCKS: u64 = 384; // 30.72 us, the timer and the stopwatch step
+const CDDA_RATE: f64 = 44100.0;
+const CDDA_BUFFER_SIZE: usize = SECTOR_SIZE; // samples, about 4 sectors
+
+const BIOS_SIZE: usize = 0x20000;
+const PROGRAM_RAM_SIZE: usize = 0x80000;
+const PROGRAM_BANK_SIZE: usize = 0x20000;
+const WORD_RAM_SIZE: usize = 0x40000;
+const BACKUP_RAM_SIZE: usize = 0x2000;
+
+// the add-on board: the sub cpu memory, the gate array registers of both cpus, the cd drive and
+// the data controller; the pcm chip and the graphics rotation are not emulated
+pub(crate) struct MegaCd {
+    bios: Vec<u8>,
+    pub(crate) program_ram: Vec<u8>,
+    pub(crate) word_ram: Vec<u8>,
+    backup_ram: Vec<u8>,
+    backup_ram_changed: bool, // written since the last `take_changed`
+    cdc: RefCell<Cdc>, // the host data reads move the transfer
+    cdd: Cdd,
+
+    // the main cpu controls
+    sub_reset: bool, // SRES, the sub cpu is held in reset while it is false
+    sub_bus_request: bool,
+    reset_pending: bool,
+    write_protect: u8, // the program ram below `write_protect * 0x200` is read only for the sub cpu
+    program_bank: u8,
+    hint_vector: u16,
+
+    // the word ram is owned by the main cpu when RET is set in the 2M mode,
+    // in the 1M mode RET selects the bank of the main cpu
+    mode_1m: bool,
+    ret: bool,
+    dmna: bool,
+    priority_mode: u8,
+
+    comm_flags: u16, // the main cpu flags in the high byte, the sub cpu ones in the low byte
+    comm_command: [u16; 8],
+    comm_status: [u16; 8],
+
+    // the sub cpu side
+    led: u8,
+    destination: u8,
+    dma_address: u16,
+    stopwatch: u16,
+    timer: u8,
+    timer_counter: u8,
+    interrupt_mask: u8, // bit per level
+    pending_interrupts: Cell<u8>,
+    cdd_control: u8, // HOCK enables the status interrupt of the drive
+    fader: u16,
+    font_color: u8,
+    font_bits: u16,
+
+    sector_clocks: u64,
+    timer_clocks: u64,
+    stopwatch_clocks: u64,
+
+    cdda: VecDeque<(i16, i16)>,
+    cdda_step: f64, // cdda samples per mixer sample
+    cdda_position: f64,
+    cdda_sample: (i16, i16),
+}
+
+impl MegaCd {
+    pub(crate) fn new(bios: Vec<u8>, disc: Option<Disc>, sample_rate: f64) -> Self {
+        Self {
+            bios,
+            program_ram: vec![0; PROGRAM_RAM_SIZE],
+            word_ram: vec![0; WORD_RAM_SIZE],
+            backup_ram: vec![0; BACKUP_RAM_SIZE],
+            backup_ram_changed: false,
+            cdc: RefCell::new(Cdc::new()),
+            cdd: Cdd::new(disc),
+
+            sub_reset: false,
+            sub_bus_request: true,
+            reset_pending: false,
+            write_protect: 0,
+            program_bank: 0,
+            hint_vector: 0xFFFF,
+
+            mode_1m: false,
+            ret: true,
+            dmna: false,
+            priority_mode: 0,
+
+            comm_flags: 0,
+            comm_command: [0; 8],
+            comm_status: [0; 8],
+
+            led: 0,
+            destination: 0,
+            dma_address: 0,
+            stopwatch: 0,
+            timer: 0,
+            timer_counter: 0,
+            interrupt_mask: 0,
+            pending_interrupts: Cell::new(0),
+            cdd_control: 0,
+            fader: 0x4000,
+            font_color: 0,
+            font_bits: 0,
+
+            sector_clocks: 0,
+            timer_clocks: 0,
+            stopwatch_clocks: 0,
+
+            cdda: VecDeque::new(),
+            cdda_step: CDDA_RATE / sample_rate,
+            cdda_position: 0.0,
+            cdda_sample: (0, 0),
+        }
+    }
+
+    pub(crate) fn sub_running(&self) -> bool {
+        self.sub_reset && !self.sub_bus_request
+    }
+
+    // the sub cpu reads its vectors when the main cpu releases the reset
+    pub(crate) fn take_sub_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset_pending)
+    }
+
+    fn raise_interrupt(&self, level: u8) {
+        if self.interrupt_mask & (1 << level) != 0 {
+            self.pending_interrupts.set(self.pending_interrupts.get() | 1 << level);
+        }
+    }
+
+    pub(crate) fn interrupt_pending(&self) -> bool {
+        self.pending_interrupts.get() != 0
+    }
+
+    // the highest pending level above the cpu mask
+    pub(crate) fn take_interrupt(&mut self, mask: u8) -> Option<u8> {
+        let pending = self.pending_interrupts.get();
+        let level = (mask + 1..=6).rev().find(|level| pending & (1 << level) != 0)?;
+        self.pending_interrupts.set(pending & !(1 << level));
+        Some(level)
+    }
+
+    // the drive, the timer and the stopwatch time is counted in the sub cpu clocks
+    pub(crate) fn clock(&mut self, cycles: u64) {
+        self.sector_clocks += cycles;
+        if self.sector_clocks >= SECTOR_CLOCKS {
+            self.sector_clocks -= SECTOR_CLOCKS;
+            self.clock_sector();
+        }
+        if self.timer != 0 {
+            self.timer_clocks += cycles;
+            while self.timer_clocks >= TIMER_CLOCKS {
+                self.timer_clocks -= TIMER_CLOCKS;
+                self.timer_counter = self.timer_counter.wrapping_sub(1);
+                if self.timer_counter == 0 {
+                    self.timer_counter = self.timer;
+                    self.raise_interrupt(3);
+                }
+            }
+        }
+        self.stopwatch_clocks += cycles;
+        while self.stopwatch_clocks >= TIMER_CLOCKS {
+            self.stopwatch_clocks -= TIMER_CLOCKS;
+            self.stopwatch = (self.stopwatch + 1) & 0x0FFF;
+        }
+    }
+
+    fn clock_sector(&mut self) {
+        match self.cdd.clock_sector() {
+            Some(DriveOutput::Data(sector)) if self.cdc.get_mut().decode(&sector) => self.raise_interrupt(5),
+            Some(DriveOutput::Audio(sector)) if self.cdda.len() < CDDA_BUFFER_SIZE => {
+                self.cdda.extend(sector.chunks(4).map(|sample| {
+                    (i16::from_le_bytes([sample[0], sample[1]]), i16::from_le_bytes([sample[2], sample[3]]))
+                }));
+            }
+            _ => (),
+        }
+        // the drive reports its status every sector
+        if self.cdd_control & 0x04 != 0 {
+            self.raise_interrupt(4);
+        }
+    }
+
+    // the cd audio sample for the next mixer sample, scaled by the fader
+    pub(crate) fn cdda_sample(&mut self) -> (i32, i32) {
+        self.cdda_position += self.cdda_step;
+        while self.cdda_position >= 1.0 {
+            self.cdda_position -= 1.0;
+            self.cdda_sample = self.cdda.pop_front().unwrap_or((0, 0));
+        }
+        let volume = ((self.fader >> 4) & 0x7FF) as i32;
+        let scale = |sample: i16| sample as i32 * volume / 0x400 / 2;
+        (scale(self.cdda_sample.0), scale(self.cdda_sample.1))
+    }
+
+    // the offset of the byte of the 1M bank in the 2M layout, the banks interleave by words
+    fn bank_offset(bank: bool, offset: u32) -> usize {
+        (((offset & 0x1FFFF) >> 1) << 2 | (bank as u32) << 1 | offset & 0x01) as usize
+    }
+
+    // the byte of the cartridge area of the main cpu
+    pub(crate) fn main_read(&self, address: u32) -> u8 {
+        match address & 0x3FFFFF {
+            // the level 4 vector points to the ram, its low word is set by the main cpu
+            0x70 | 0x71 => 0xFF,
+            0x72 => (self.hint_vector >> 8) as u8,
+            0x73 => self.hint_vector as u8,
+            address @ 0x000000..=0x1FFFFF => match address & 0x3FFFF {
+                address @ 0x00000..=0x1FFFF => self.bios.get(address as usize % BIOS_SIZE).copied().unwrap_or(0xFF),
+                address => self.program_ram[self.program_bank as usize * PROGRAM_BANK_SIZE + (address & 0x1FFFF) as usize],
+            },
+            address => match (self.mode_1m, self.ret) {
+                (false, true) => self.word_ram[(address & 0x3FFFF) as usize],
+                // TODO the cell image of the 1M bank at 0x220000
+                (true, _) => self.word_ram[Self::bank_offset(self.ret, address)],
+                (false, false) => 0xFF, // the sub cpu owns the word ram
+            },
+        }
+    }
+
+    pub(crate) fn main_write(&mut self, address: u32, data: u8) {
+        match address & 0x3FFFFF {
+            address @ 0x000000..=0x1FFFFF => {
+                if address & 0x3FFFF >= 0x20000 {
+                    let offset = self.program_bank as usize * PROGRAM_BANK_SIZE + (address & 0x1FFFF) as usize;
+                    self.program_ram[offset] = data;
+                }
+            }
+            address => match (self.mode_1m, self.ret) {
+                (false, true) => self.word_ram[(address & 0x3FFFF) as usize] = data,
+                (true, _) => self.word_ram[Self::bank_offset(self.ret, address)] = data,
+                (false, false) => debug!("MegaCd: main cpu writes the word ram of the sub cpu"),
+            },
+        }
+    }
+
+    pub(crate) fn read_main_register(&self, address: u32) -> u16 {
+        let offset = address & 0x3E;
+        match offset {
+            0x00 => {
+                let ien2 = (self.interrupt_mask & 0x04 != 0) as u16;
+                let ifl2 = (self.pending_interrupts.get() & 0x04 != 0) as u16;
+                ien2 << 15 | ifl2 << 8 | (self.sub_bus_request as u16) << 1 | self.sub_reset as u16
+            }
+            0x02 => {
+                (self.write_protect as u16) << 8
+                    | (self.program_bank as u16) << 6
+                    | (self.mode_1m as u16) << 2
+                    | (self.dmna as u16) << 1
+                    | self.ret as u16
+            }
+            0x04 => self.cdc_mode(),
+            0x06 => self.hint_vector,
+            0x08 => self.host_data(Destination::MainCpu),
+            0x0C => self.stopwatch,
+            _ => self.read_comm(offset),
+        }
+    }
+
+    pub(crate) fn write_main_register(&mut self, address: u32, data: u8) {
+        match address & 0x3F {
+            0x00 => {
+                if data & 0x01 != 0 {
+                    self.raise_interrupt(2);
+                }
+            }
+            0x01 => {
+                let sub_reset = data & 0x01 != 0;
+                if sub_reset && !self.sub_reset {
+                    self.reset_pending = true;
+                }
+                self.sub_reset = sub_reset;
+                self.sub_bus_request = data & 0x02 != 0;
+            }
+            0x02 => self.write_protect = data,
+            0x03 => {
+                self.program_bank = (data >> 6) & 0x03;
+                if data & 0x02 != 0 {
+                    self.dmna = true;
+                    // the 2M word ram goes to the sub cpu, the 1M banks are swapped by the sub cpu
+                    if !self.mode_1m {
+                        self.ret = false;
+                    }
+                }
+            }
+            0x06 => self.hint_vector = self.hint_vector & 0x00FF | (data as u16) << 8,
+            0x07 => self.hint_vector = self.hint_vector & 0xFF00 | data as u16,
+            0x0E => self.comm_flags = self.comm_flags & 0x00FF | (data as u16) << 8,
+            offset @ 0x10..=0x1F => write_byte(&mut self.comm_command[(offset as usize - 0x10) / 2], offset, data),
+            _ => debug!("MegaCd: main cpu write {:02X} to {:06X} is ignored", data, address),
+        }
+    }
+
+    fn read_comm(&self, offset: u32) -> u16 {
+        match offset {
+            0x0E => self.comm_flags,
+            0x10..=0x1E => self.comm_command[(offset as usize - 0x10) / 2],
+            0x20..=0x2E => self.comm_status[(offset as usize - 0x20) / 2],
+            _ => 0,
+        }
+    }
+
+    fn cdc_mode(&self) -> u16 {
+        let cdc = self.cdc.borrow();
+        (cdc.transfer_end as u16) << 15
+            | (cdc.data_ready as u16) << 14
+            | (self.destination as u16) << 8
+            | cdc.register_address as u16
+    }
+
+    // the host data register, the word of the running transfer to the cpu
+    fn host_data(&self, reader: Destination) -> u16 {
+        let mut cdc = self.cdc.borrow_mut();
+        if Destination::from_bits(self.destination) != reader || !cdc.data_ready {
+            return 0;
+        }
+        let word = cdc.transfer_word().unwrap_or(0);
+        if cdc.transfer_end && cdc.transfer_interrupt_enabled() {
+            self.raise_interrupt(5);
+        }
+        word
+    }
+
+    // the dma destinations are filled at once, the cpus read the host data register
+    fn start_transfer(&mut self) {
+        let destination = Destination::from_bits(self.destination);
+        let cdc = self.cdc.get_mut();
+        match destination {
+            Destination::MainCpu | Destination::SubCpu => {
+                cdc.data_ready = true;
+                return;
+            }
+            Destination::None => return,
+            _ => (),
+        }
+        let mut address = self.dma_address as u32 * 8;
+        while let Some(word) = cdc.transfer_word() {
+            let [high, low] = word.to_be_bytes();
+            match destination {
+                Destination::ProgramRam => {
+                    let offset = (address as usize) % PROGRAM_RAM_SIZE;
+                    self.program_ram[offset] = high;
+                    self.program_ram[offset + 1] = low;
+                }
+                Destination::WordRam if self.mode_1m => {
+                    self.word_ram[Self::bank_offset(!self.ret, address)] = high;
+                    self.word_ram[Self::bank_offset(!self.ret, address + 1)] = low;
+                }
+                Destination::WordRam => {
+                    let offset = (address as usize) % WORD_RAM_SIZE;
+                    self.word_ram[offset] = high;
+                    self.word_ram[offset + 1] = low;
+                }
+                _ => (), // the pcm wave ram
+            }
+            address += 2;
+        }
+        if cdc.transfer_interrupt_enabled() {
+            self.raise_interrupt(5);
+        }
+    }
+
+    fn read_sub_register(&self, address: u32) -> u16 {
+        let offset = address & 0x1FE;
+        match offset {
+            0x00 => (self.led as u16) << 8 | 0x01, // the drive is ready
+            0x02 => {
+                (self.write_protect as u16) << 8
+                    | (self.priority_mode as u16) << 3
+                    | (self.mode_1m as u16) << 2
+                    | (self.dmna as u16) << 1
+                    | self.ret as u16
+            }
+            0x04 => self.cdc_mode(),
+            0x06 => self.cdc.borrow_mut().read_register() as u16,
+            0x08 => self.host_data(Destination::SubCpu),
+            0x0A => self.dma_address,
+            0x0C => self.stopwatch,
+            0x30 => self.timer as u16,
+            0x32 => self.interrupt_mask as u16,
+            0x34 => self.fader,
+            0x36 => self.cdd_control as u16,
+            0x38..=0x40 => u16::from_be_bytes([self.cdd.status[offset as usize - 0x38], self.cdd.status[offset as usize - 0x37]]),
+            0x42..=0x4A => u16::from_be_bytes([self.cdd.command[offset as usize - 0x42], self.cdd.command[offset as usize - 0x41]]),
+            0x4C => self.font_color as u16,
+            0x4E => self.font_bits,
+            0x50..=0x56 => self.font_data(offset),
+            _ => self.read_comm(offset),
+        }
+    }
+
+    // 4 bits per pixel: the high color nibble for the set font bits, the low one for the others
+    fn font_data(&self, offset: u32) -> u16 {
+        let first_bit = 15 - (offset - 0x50) * 2;
+        (0..4).fold(0, |data, pixel| {
+            let bit = self.font_bits >> (first_bit - pixel) & 0x01;
+            let color = if bit != 0 { self.font_color >> 4 } else { self.font_color & 0x0F };
+            data << 4 | color as u16
+        })
+    }
+
+    fn write_sub_register(&mut self, address: u32, data: u8) {
+        let offset = address & 0x1FF;
+        match offset {
+            0x00 => self.led = data & 0x03,
+            0x02 => (), // the write protection is set by the main cpu
+            0x03 => {
+                self.mode_1m = data & 0x04 != 0;
+                self.priority_mode = (data >> 3) & 0x03;
+                let ret = data & 0x01 != 0;
+                if self.mode_1m {
+                    // the banks are swapped, the swap request is completed
+                    self.ret = ret;
+                    self.dmna = false;
+                } else if ret {
+                    // the 2M word ram is returned to the main cpu
+                    self.ret = true;
+                    self.dmna = false;
+                }
+            }
+            0x04 => {
+                self.destination = data & 0x07;
+                let cdc = self.cdc.get_mut();
+                cdc.data_ready = false;
+                cdc.transfer_end = false;
+            }
+            0x05 => self.cdc.get_mut().register_address = data & 0x1F,
+            0x07 => {
+                if self.cdc.get_mut().write_register(data) {
+                    self.start_transfer();
+                }
+            }
+            0x0A | 0x0B => write_byte(&mut self.dma_address, offset, data),
+            0x0C | 0x0D => self.stopwatch = 0,
+            0x0F => self.comm_flags = self.comm_flags & 0xFF00 | data as u16,
+            0x20..=0x2F => write_byte(&mut self.comm_status[(offset as usize - 0x20) / 2], offset, data),
+            0x31 => {
+                self.timer = data;
+                self.timer_counter = data;
+                self.timer_clocks = 0;
+            }
+            0x33 => {
+                self.interrupt_mask = data & 0x7E;
+                self.pending_interrupts.set(self.pending_interrupts.get() & self.interrupt_mask);
+            }
+            0x34 | 0x35 => write_byte(&mut self.fader, offset, data),
+            0x37 => self.cdd_control = data & 0x07,
+            0x42..=0x4B => {
+                self.cdd.command[offset as usize - 0x42] = data & 0x0F;
+                if offset == 0x4B {
+                    self.cdd.execute();
+                }
+            }
+            0x4D => self.font_color = data,
+            0x4E | 0x4F => write_byte(&mut self.font_bits, offset, data),
+            _ => debug!("MegaCd: sub cpu write {:02X} to {:06X} is ignored", data, address),
+        }
+    }
+
+    fn sub_read_byte(&self, address: u32) -> u8 {
+        match address {
+            0x000000..=0x07FFFF => self.program_ram[address as usize],
+            0x080000..=0x0BFFFF if !self.mode_1m && !self.ret => self.word_ram[(address & 0x3FFFF) as usize],
+            // TODO the dot image of the 1M bank at 0x080000
+            0x0C0000..=0x0DFFFF if self.mode_1m => self.word_ram[Self::bank_offset(!self.ret, address)],
+            0xFE0000..=0xFEFFFF if address & 0x01 != 0 => self.backup_ram[(address as usize >> 1) % BACKUP_RAM_SIZE],
+            _ => 0xFF,
+        }
+    }
+
+    fn sub_write_byte(&mut self, address: u32, data: u8) {
+        match address {
+            0x000000..=0x07FFFF if address >= self.write_protect as u32 * 0x200 => {
+                self.program_ram[address as usize] = data;
+            }
+            0x080000..=0x0BFFFF if !self.mode_1m && !self.ret => self.word_ram[(address & 0x3FFFF) as usize] = data,
+            0x0C0000..=0x0DFFFF if self.mode_1m => self.word_ram[Self::bank_offset(!self.ret, address)] = data,
+            0xFE0000..=0xFEFFFF if address & 0x01 != 0 => {
+                let offset = (address as usize >> 1) % BACKUP_RAM_SIZE;
+                self.backup_ram_changed |= self.backup_ram[offset] != data;
+                self.backup_ram[offset] = data;
+            }
+            _ => debug!("MegaCd: sub cpu write {:02X} to {:06X} is ignored", data, address),
+        }
+    }
+
+    // the internal backup ram keeps the saves of all the games
+    pub(crate) fn backup_ram(&self) -> &[u8] {
+        &self.backup_ram
+    }
+
+    pub(crate) fn load_backup_ram(&mut self, data: &[u8]) {
+        let size = data.len().min(BACKUP_RAM_SIZE);
+        self.backup_ram[..size].copy_from_slice(&data[..size]);
+        self.backup_ram_changed = false;
+    }
+
+    pub(crate) fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.backup_ram_changed)
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.program_ram);
+        writer.write_bytes(&self.word_ram);
+        writer.write_bytes(&self.backup_ram);
+        self.cdc.borrow().save_state(writer);
+        self.cdd.save_state(writer);
+        writer.write_bool(self.sub_reset);
+        writer.write_bool(self.sub_bus_request);
+        writer.write_bool(self.reset_pending);
+        writer.write_u8(self.write_protect);
+        writer.write_u8(self.program_bank);
+        writer.write_u16(self.hint_vector);
+        writer.write_bool(self.mode_1m);
+        writer.write_bool(self.ret);
+        writer.write_bool(self.dmna);
+        writer.write_u8(self.priority_mode);
+        writer.write_u16(self.comm_flags);
+        for word in self.comm_command.iter().chain(self.comm_status.iter()) {
+            writer.write_u16(*word);
+        }
+        writer.write_u8(self.led);
+        writer.write_u8(self.destination);
+        writer.write_u16(self.dma_address);
+        writer.write_u16(self.stopwatch);
+        writer.write_u8(self.timer);
+        writer.write_u8(self.timer_counter);
+        writer.write_u8(self.interrupt_mask);
+        writer.write_u8(self.pending_interrupts.get());
+        writer.write_u8(self.cdd_control);
+        writer.write_u16(self.fader);
+        writer.write_u8(self.font_color);
+        writer.write_u16(self.font_bits);
+        writer.write_u64(self.sector_clocks);
+        writer.write_u64(self.timer_clocks);
+        writer.write_u64(self.stopwatch_clocks);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.program_ram)?;
+        reader.read_bytes(&mut self.word_ram)?;
+        reader.read_bytes(&mut self.backup_ram)?;
+        self.backup_ram_changed = true; // the save file follows the loaded state
+        self.cdc.get_mut().load_state(reader)?;
+        self.cdd.load_state(reader)?;
+        self.sub_reset = reader.read_bool()?;
+        self.sub_bus_request = reader.read_bool()?;
+        self.reset_pending = reader.read_bool()?;
+        self.write_protect = reader.read_u8()?;
+        self.program_bank = reader.read_u8()? & 0x03;
+        self.hint_vector = reader.read_u16()?;
+        self.mode_1m = reader.read_bool()?;
+        self.ret = reader.read_bool()?;
+        self.dmna = reader.read_bool()?;
+        self.priority_mode = reader.read_u8()?;
+        self.comm_flags = reader.read_u16()?;
+        for word in self.comm_command.iter_mut().chain(self.comm_status.iter_mut()) {
+            *word = reader.read_u16()?;
+        }
+        self.led = reader.read_u8()?;
+        self.destination = reader.read_u8()?;
+        self.dma_address = reader.read_u16()?;
+        self.stopwatch = reader.read_u16()?;
+        self.timer = reader.read_u8()?;
+        self.timer_counter = reader.read_u8()?;
+        self.interrupt_mask = reader.read_u8()?;
+        self.pending_interrupts.set(reader.read_u8()? & 0x7E);
+        self.cdd_control = reader.read_u8()?;
+        self.fader = reader.read_u16()?;
+        self.font_color = reader.read_u8()?;
+        self.font_bits = reader.read_u16()?;
+        self.sector_clocks = reader.read_u64()?;
+        self.timer_clocks = reader.read_u64()?;
+        self.stopwatch_clocks = reader.read_u64()?;
+        self.cdda.clear();
+        Ok(())
+    }
+}
+
+// the bus of the sub cpu, the gate array registers are mirrored up to 0xFFFFFF
+impl BusM68k for MegaCd {
+    fn read(&self, address: u32, amount: usize) -> Result<u32, ()> {
+        let address = address & 0x00FFFFFF;
+        if address >= SUB_REGISTERS {
+            let data = match amount {
+                1 => self.read_sub_register(address).to_be_bytes()[(address & 0x01) as usize] as u32,
+                2 => self.read_sub_register(address) as u32,
+                _ => (self.read_sub_register(address) as u32) << 16 | self.read_sub_register(address + 2) as u32,
+            };
+            return Ok(data);
+        }
+        Ok((0..amount as u32).fold(0, |data, i| data << 8 | self.sub_read_byte(address + i) as u32))
+    }
+
+    fn write(&mut self, data: u32, address: u32, amount: usize) -> Result<(), ()> {
+        let address = address & 0x00FFFFFF;
+        let bytes = data.to_be_bytes();
+        for (i, byte) in bytes[4 - amount..].iter().enumerate() {
+            let address = address + i as u32;
+            if address >= SUB_REGISTERS {
+                self.write_sub_register(address, *byte);
+            } else {
+                self.sub_write_byte(address, *byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use m68k_emu::bus::BusM68k;
+
+    use super::MegaCd;
+
+    #[test]
+    fn word_ram_and_comm_registers_are_shared() {
+        let mut mega_cd = MegaCd::new(vec![0x11; 0x20000], None, 53267.0);
+        assert_eq!(mega_cd.main_read(0x000100), 0x11);
+
+        // the main cpu fills the 2M word ram and gives it to the sub cpu
+        mega_cd.main_write(0x200010, 0x5A);
+        mega_cd.write_main_register(0xA12003, 0x02);
+        assert_eq!(mega_cd.main_read(0x200010), 0xFF);
+        assert_eq!(mega_cd.read(0x080010, 1), Ok(0x5A));
+        // the sub cpu returns it
+        mega_cd.write(0x01, 0xFF8003, 1).unwrap();
+        assert_eq!(mega_cd.read_main_register(0xA12002) & 0x03, 0x01);
+
+        mega_cd.write_main_register(0xA12010, 0x12);
+        mega_cd.write_main_register(0xA12011, 0x34);
+        assert_eq!(mega_cd.read(0xFF8010, 2), Ok(0x1234));
+        mega_cd.write(0xABCD, 0xFF8020, 2).unwrap();
+        assert_eq!(mega_cd.read_main_register(0xA12020), 0xABCD);
+
+        // the level 2 interrupt of the main cpu is masked until the sub cpu enables it
+        mega_cd.write_main_register(0xA12000, 0x01);
+        assert!(!mega_cd.interrupt_pending());
+        mega_cd.write(0x04, 0xFF8033, 1).unwrap();
+        mega_cd.write_main_register(0xA12000, 0x01);
+        assert_eq!(mega_cd.take_interrupt(0), Some(2));
+    }
+}
@@ -1,9 +1,14 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{controller::Controller, signal_bus::SignalBus, vdp_emu::vdp_port::VdpPorts, ym2612::Ym2612Ports};
-
-pub struct MemorySpace<T, Y> where T: VdpPorts, Y: Ym2612Ports {
-    pub(crate) rom: Vec<u8>,
+use crate::{cartridge::{eeprom::Eeprom, mapper::{LinearMapper, Mapper}, sram::Sram, Region}, controller::Controller, master_system::MasterSystem, mega_cd::MegaCd, multitap::FourWayPlay, save_state::{StateError, StateReader, StateWriter}, sega_32x::Sega32x, signal_bus::SignalBus, sn76489::PsgPorts, tmss::Tmss, vdp_emu::vdp_port::VdpPorts, ym2612::Ym2612Ports};
+
+pub struct MemorySpace<T, Y, P> where T: VdpPorts, Y: Ym2612Ports, P: PsgPorts {
+    pub(crate) rom: Rc<[u8]>, // shared with the 32x
+    pub(crate) mapper: Box<dyn Mapper>,
+    pub(crate) rom_patches: Vec<(u32, u16)>, // the cheats, the words at the even addresses
+    pub(crate) region: Region,
+    pub(crate) sram: Option<Sram>,
+    pub(crate) eeprom: Option<Eeprom>,
     pub(crate) m68k_ram: Vec<u8>,
     pub(crate) z80_ram: Vec<u8>,
 
@@ -15,24 +20,36 @@ pub struct MemorySpace<T, Y> where T: VdpPorts, Y: Ym2612Ports {
 
     pub(crate) vdp_ports: Rc<RefCell<T>>,
     pub(crate) ym2612_ports: Rc<RefCell<Y>>,
+    pub(crate) psg_ports: Rc<RefCell<P>>,
 
     pub(crate) controller_1: Rc<RefCell<Controller>>,
     pub(crate) controller_2: Rc<RefCell<Controller>>,
+    pub(crate) four_way_play: Option<FourWayPlay>,
+    pub(crate) tmss: Option<Tmss>,
+    // the add-on takes the cartridge area, its state is saved by the owner
+    pub(crate) mega_cd: Option<Rc<RefCell<MegaCd>>>,
+    pub(crate) sega_32x: Option<Rc<RefCell<Sega32x>>>,
+    // the z80 bus of the Master System mode
+    pub(crate) master_system: Option<Rc<RefCell<MasterSystem>>>,
 
     pub(crate) signal_bus: Rc<RefCell<SignalBus>>,
 
     pub(crate) bank_register: RefCell<u16>,
 }
 
-impl<T,Y> MemorySpace<T, Y>
+impl<T, Y, P> MemorySpace<T, Y, P>
 where
     T: VdpPorts,
     Y: Ym2612Ports,
+    P: PsgPorts,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         rom: Vec<u8>,
+        region: Region,
         vdp_ports: Rc<RefCell<T>>,
         ym2612_ports: Rc<RefCell<Y>>,
+        psg_ports: Rc<RefCell<P>>,
         controller_1: Rc<RefCell<Controller>>,
         controller_2: Rc<RefCell<Controller>>,
         signal_bus: Rc<RefCell<SignalBus>>,
@@ -40,7 +57,12 @@ where
         let mut io_area_read = [0; 0x20];
         io_area_read[1] = 0x0090; // `setup version register
         Self {
-            rom: rom,
+            rom: rom.into(),
+            mapper: Box::new(LinearMapper),
+            rom_patches: vec![],
+            region,
+            sram: None,
+            eeprom: None,
             z80_ram: vec![0; 0x10000],  // $A00000	$A0FFFF
             m68k_ram: vec![0; 0x10000], // $FF0000	$FFFFFF
 
@@ -52,9 +74,15 @@ where
 
             vdp_ports: vdp_ports,
             ym2612_ports: ym2612_ports,
+            psg_ports,
 
             controller_1: controller_1,
             controller_2: controller_2,
+            four_way_play: None,
+            tmss: None,
+            mega_cd: None,
+            sega_32x: None,
+            master_system: None,
 
             signal_bus: signal_bus,
 
@@ -62,6 +90,120 @@ where
         }
     }
 
+    // the rom and the connected devices are saved by their owners
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.m68k_ram);
+        writer.write_bytes(&self.z80_ram);
+        writer.write_bool(*self.z80_bus_req.borrow());
+        writer.write_bool(*self.z80_res_req.borrow());
+        writer.write_bytes(&self.io_area_read);
+        writer.write_bytes(&self.io_area_m68k);
+        writer.write_u16(*self.bank_register.borrow());
+        if let Some(sram) = &self.sram {
+            sram.save_state(writer);
+        }
+        if let Some(eeprom) = &self.eeprom {
+            eeprom.save_state(writer);
+        }
+        self.mapper.save_state(writer);
+        writer.write_bool(self.four_way_play.is_some());
+        if let Some(four_way_play) = &self.four_way_play {
+            four_way_play.save_state(writer);
+        }
+        writer.write_bool(self.tmss.is_some());
+        if let Some(tmss) = &self.tmss {
+            tmss.save_state(writer);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.m68k_ram)?;
+        reader.read_bytes(&mut self.z80_ram)?;
+        *self.z80_bus_req.borrow_mut() = reader.read_bool()?;
+        *self.z80_res_req.borrow_mut() = reader.read_bool()?;
+        reader.read_bytes(&mut self.io_area_read)?;
+        reader.read_bytes(&mut self.io_area_m68k)?;
+        *self.bank_register.borrow_mut() = reader.read_u16()?;
+        if let Some(sram) = self.sram.as_mut() {
+            sram.load_state(reader)?;
+        }
+        if let Some(eeprom) = self.eeprom.as_mut() {
+            eeprom.load_state(reader)?;
+        }
+        self.mapper.load_state(reader)?;
+        if reader.read_bool()? != self.four_way_play.is_some() {
+            return Err(StateError::InvalidValue("multitap"));
+        }
+        if let Some(four_way_play) = self.four_way_play.as_mut() {
+            four_way_play.load_state(reader)?;
+        }
+        if reader.read_bool()? != self.tmss.is_some() {
+            return Err(StateError::InvalidValue("tmss"));
+        }
+        if let Some(tmss) = self.tmss.as_mut() {
+            tmss.load_state(reader)?;
+        }
+        Ok(())
+    }
+
+    // the boot rom hides the cartridge memories
+    pub(crate) fn bios_mapped(&self) -> bool {
+        self.tmss.as_ref().is_some_and(|tmss| tmss.bios_mapped())
+    }
+
+    // true if the access is blocked by the tmss
+    pub(crate) fn vdp_locked(&self, address: u32) -> bool {
+        match &self.tmss {
+            Some(tmss) if !tmss.vdp_unlocked() => {
+                tmss.block_vdp_access(address);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // a byte of the cartridge area, the unconnected addresses read as 0xFF
+    pub(crate) fn read_rom(&self, address: u32) -> u8 {
+        if let Some(mega_cd) = &self.mega_cd {
+            return mega_cd.borrow().main_read(address);
+        }
+        if let Some(data) = self.tmss.as_ref().and_then(|tmss| tmss.read_bios(address)) {
+            return data;
+        }
+        if let Some(data) = self.sega_32x.as_ref().and_then(|sega_32x| sega_32x.borrow().read_vector(address)) {
+            return data;
+        }
+        self.read_cartridge(address)
+    }
+
+    // a byte of the cartridge rom with the cheats applied
+    pub(crate) fn read_cartridge(&self, address: u32) -> u8 {
+        if let Some((_, data)) = self.rom_patches.iter().find(|(patch_address, _)| *patch_address == address & !0x01) {
+            return data.to_be_bytes()[(address & 0x01) as usize];
+        }
+        let offset = self.mapper.rom_offset(address);
+        self.rom.get(offset).copied().unwrap_or(0xFF)
+    }
+
+    // data ports of the controllers, 0 - port A, 1 - port B
+    pub(crate) fn read_controller_port(&self, port: usize) -> u8 {
+        match (&self.four_way_play, port) {
+            (Some(four_way_play), 0) => four_way_play.read_port_a(),
+            (Some(four_way_play), _) => four_way_play.read_port_b(),
+            (None, 0) => self.controller_1.borrow().read(),
+            (None, _) => self.controller_2.borrow().read(),
+        }
+    }
+
+    pub(crate) fn write_controller_port(&mut self, port: usize, data: u8) {
+        match (self.four_way_play.as_mut(), port) {
+            (Some(four_way_play), 0) => four_way_play.write_port_a(data),
+            (Some(four_way_play), _) => four_way_play.write_port_b(data),
+            (None, 0) => self.controller_1.borrow_mut().write(data),
+            (None, _) => self.controller_2.borrow_mut().write(data),
+        }
+    }
+
     pub(crate) fn push_bank_register_bit(&self, data: u16) {
         let mut bank_register = *self.bank_register.borrow();
         bank_register = (bank_register << 1) | data & 0x01; // push single bit to the register end
@@ -69,3 +211,12 @@ where
         *self.bank_register.borrow_mut() = bank_register;
     }
 }
+
+// the byte writes to the word registers of the add-ons, the even offset is the high byte
+pub(crate) fn write_byte(register: &mut u16, offset: u32, data: u8) {
+    if offset & 0x01 == 0 {
+        *register = *register & 0x00FF | (data as u16) << 8;
+    } else {
+        *register = *register & 0xFF00 | data as u16;
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::Display;
+
+use log::warn;
+
+use crate::{
+    controller::Controller,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+// header of the movie file: magic, format version, rom crc32 and sha1, then the start kind
+const MOVIE_MAGIC: &[u8; 4] = b"SGRM";
+pub const MOVIE_VERSION: u16 = 1;
+// frames between the ram hashes of the desync detection
+pub const SYNC_INTERVAL: usize = 60;
+
+#[derive(Debug, PartialEq)]
+pub enum MovieError {
+    WrongMagic,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    NotPowerOn,
+    State(StateError),
+}
+
+impl Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::WrongMagic => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => write!(f, "unsupported movie version {}", version),
+            MovieError::RomMismatch => write!(f, "movie belongs to another rom"),
+            MovieError::NotPowerOn => write!(f, "movie starts from the power on, the console is already running"),
+            MovieError::State(error) => write!(f, "movie state: {}", error),
+        }
+    }
+}
+
+impl From<StateError> for MovieError {
+    fn from(error: StateError) -> Self {
+        MovieError::State(error)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MovieStart {
+    PowerOn,
+    SaveState(Vec<u8>),
+}
+
+// the pressed buttons of both ports per frame, see `Controller::buttons`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Movie {
+    pub rom_crc32: u32,
+    pub rom_sha1: String,
+    pub start: MovieStart,
+    pub frames: Vec<[u16; 2]>,
+    // the crc32 of the m68k ram at the input poll of the frame
+    pub sync_hashes: Vec<(u32, u32)>,
+}
+
+impl Movie {
+    pub fn new(rom_crc32: u32, rom_sha1: &str, start: MovieStart) -> Self {
+        Self {
+            rom_crc32,
+            rom_sha1: rom_sha1.to_string(),
+            start,
+            frames: vec![],
+            sync_hashes: vec![],
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = StateWriter::without_header();
+        writer.write_bytes(MOVIE_MAGIC);
+        writer.write_u16(MOVIE_VERSION);
+        writer.write_u32(self.rom_crc32);
+        writer.write_u8(self.rom_sha1.len() as u8);
+        writer.write_bytes(self.rom_sha1.as_bytes());
+        match &self.start {
+            MovieStart::PowerOn => writer.write_bool(false),
+            MovieStart::SaveState(state) => {
+                writer.write_bool(true);
+                writer.write_u32(state.len() as u32);
+                writer.write_bytes(state);
+            }
+        }
+        writer.write_u32(self.frames.len() as u32);
+        for [port_1, port_2] in &self.frames {
+            writer.write_u16(*port_1);
+            writer.write_u16(*port_2);
+        }
+        writer.write_u32(self.sync_hashes.len() as u32);
+        for (frame, hash) in &self.sync_hashes {
+            writer.write_u32(*frame);
+            writer.write_u32(*hash);
+        }
+        writer.finish()
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = StateReader::without_header(data);
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic).map_err(|_| MovieError::WrongMagic)?;
+        if &magic != MOVIE_MAGIC {
+            return Err(MovieError::WrongMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_crc32 = reader.read_u32()?;
+        let mut rom_sha1 = vec![0; reader.read_u8()? as usize];
+        reader.read_bytes(&mut rom_sha1)?;
+        let rom_sha1 = String::from_utf8(rom_sha1).map_err(|_| StateError::InvalidValue("rom sha1"))?;
+        let start = if reader.read_bool()? {
+            // the length is checked before the allocation, a corrupt file could ask for gigabytes
+            let length = reader.read_u32()? as usize;
+            if length > reader.remaining() {
+                return Err(MovieError::State(StateError::InvalidValue("save state length")));
+            }
+            let mut state = vec![0; length];
+            reader.read_bytes(&mut state)?;
+            MovieStart::SaveState(state)
+        } else {
+            MovieStart::PowerOn
+        };
+        let frames = (0..reader.read_u32()?)
+            .map(|_| Ok([reader.read_u16()?, reader.read_u16()?]))
+            .collect::<Result<Vec<[u16; 2]>, StateError>>()?;
+        let sync_hashes = (0..reader.read_u32()?)
+            .map(|_| Ok((reader.read_u32()?, reader.read_u32()?)))
+            .collect::<Result<Vec<(u32, u32)>, StateError>>()?;
+        if !reader.is_finished() {
+            return Err(MovieError::State(StateError::InvalidValue("movie length")));
+        }
+        Ok(Self {
+            rom_crc32,
+            rom_sha1,
+            start,
+            frames,
+            sync_hashes,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    // the playback reached the last frame, the controllers are free again
+    Finished,
+}
+
+// the movie attached to the console, the input is polled once at the start of each frame
+pub(crate) struct MovieSession {
+    pub(crate) movie: Movie,
+    pub(crate) mode: MovieMode,
+    pub(crate) frame: usize,
+    pub(crate) desync: Option<usize>, // the first frame with another ram hash
+}
+
+impl MovieSession {
+    pub(crate) fn new(movie: Movie, mode: MovieMode) -> Self {
+        Self {
+            movie,
+            mode,
+            frame: 0,
+            desync: None,
+        }
+    }
+
+    // the ram hash is taken only at these frames
+    pub(crate) fn sync_frame(&self) -> bool {
+        self.mode != MovieMode::Finished && self.frame.is_multiple_of(SYNC_INTERVAL)
+    }
+
+    pub(crate) fn poll(&mut self, controllers: [&mut Controller; 2], ram_hash: Option<u32>) {
+        let frame = self.frame;
+        match self.mode {
+            MovieMode::Recording => {
+                self.movie.frames.push([controllers[0].buttons(), controllers[1].buttons()]);
+                if let Some(hash) = ram_hash {
+                    self.movie.sync_hashes.push((frame as u32, hash));
+                }
+            }
+            MovieMode::Playing => {
+                let Some(buttons) = self.movie.frames.get(frame) else {
+                    self.mode = MovieMode::Finished;
+                    controllers.into_iter().for_each(|controller| controller.set_buttons(0));
+                    return;
+                };
+                for (controller, buttons) in controllers.into_iter().zip(buttons) {
+                    controller.set_buttons(*buttons);
+                }
+                let expected = self.movie.sync_hashes.iter().find(|(sync_frame, _)| *sync_frame as usize == frame);
+                if let (Some((_, expected)), Some(hash)) = (expected, ram_hash) {
+                    if *expected != hash && self.desync.is_none() {
+                        warn!("Movie: desync at the frame {}", frame);
+                        self.desync = Some(frame);
+                    }
+                }
+            }
+            MovieMode::Finished => return,
+        }
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Movie, MovieError, MovieMode, MovieSession, MovieStart};
+    use crate::{
+        controller::{Button, Controller},
+        save_state::StateError,
+    };
+
+    #[test]
+    fn encodes_and_replays_frames() {
+        let mut movie = Movie::new(0x12345678, "abcdef", MovieStart::SaveState(vec![1, 2, 3]));
+        movie.frames = vec![[0x0001, 0x0080], [0x0FFF, 0]];
+        movie.sync_hashes = vec![(0, 0xCAFE)];
+        let data = movie.encode();
+        assert_eq!(Movie::decode(&data), Ok(movie.clone()));
+        assert_eq!(Movie::decode(b"SGRS"), Err(MovieError::WrongMagic));
+        assert!(Movie::decode(&data[..data.len() - 1]).is_err());
+        let mut corrupt = data.clone();
+        corrupt[18..22].copy_from_slice(&u32::MAX.to_le_bytes()); // the save state length
+        assert_eq!(
+            Movie::decode(&corrupt),
+            Err(MovieError::State(StateError::InvalidValue("save state length")))
+        );
+
+        let mut session = MovieSession::new(movie, MovieMode::Playing);
+        let (mut port_1, mut port_2) = (Controller::new(), Controller::new());
+        port_1.press_button(Button::A);
+        session.poll([&mut port_1, &mut port_2], Some(0xBEEF));
+        assert_eq!(session.desync, Some(0));
+        assert_eq!((port_1.buttons(), port_2.buttons()), (0x0001, 0x0080));
+        session.poll([&mut port_1, &mut port_2], None);
+        session.poll([&mut port_1, &mut port_2], None);
+        assert_eq!(session.mode, MovieMode::Finished);
+        assert_eq!(port_1.buttons(), 0);
+    }
+}
@@ -0,0 +1,49 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    controller::Controller,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+// EA 4-Way Play: the port B selects a pad, the port A reads it
+pub(crate) struct FourWayPlay {
+    pads: [Rc<RefCell<Controller>>; 4],
+    select: u8, // bits 4-6 of the port B data, the bit 2 is set when no pad is selected
+}
+
+impl FourWayPlay {
+    pub(crate) fn new(pads: [Rc<RefCell<Controller>>; 4]) -> Self {
+        Self { pads, select: 0 }
+    }
+
+    pub(crate) fn read_port_a(&self) -> u8 {
+        if self.select & 0x04 != 0 {
+            0x7C // the adapter id
+        } else {
+            self.pads[self.select as usize].borrow().read()
+        }
+    }
+
+    pub(crate) fn write_port_a(&mut self, data: u8) {
+        for pad in &self.pads {
+            pad.borrow_mut().write(data);
+        }
+    }
+
+    pub(crate) fn read_port_b(&self) -> u8 {
+        0x7F
+    }
+
+    pub(crate) fn write_port_b(&mut self, data: u8) {
+        self.select = (data >> 4) & 0x07;
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.select);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.select = reader.read_u8()? & 0x07;
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use std::fmt::Display;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchRegion {
+    M68kRam,
+    Z80Ram,
+    Vram,
+}
+
+impl SearchRegion {
+    // the cpu address of the first byte, the vram has its own address space
+    pub fn base_address(&self) -> u32 {
+        match self {
+            SearchRegion::M68kRam => 0xFF0000,
+            SearchRegion::Z80Ram => 0xA00000,
+            SearchRegion::Vram => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValueSize {
+    Byte = 1,
+    Word = 2,
+    Long = 4,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn matches(&self, value: i64, operand: i64) -> bool {
+        match self {
+            Comparison::Equal => value == operand,
+            Comparison::NotEqual => value != operand,
+            Comparison::Less => value < operand,
+            Comparison::Greater => value > operand,
+            Comparison::LessOrEqual => value <= operand,
+            Comparison::GreaterOrEqual => value >= operand,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    Previous(Comparison), // the value of the previous search step
+    Value(Comparison, i64),
+    Difference(i64), // the current value minus the previous one
+}
+
+impl Filter {
+    pub const CHANGED: Filter = Filter::Previous(Comparison::NotEqual);
+    pub const UNCHANGED: Filter = Filter::Previous(Comparison::Equal);
+    pub const INCREASED: Filter = Filter::Previous(Comparison::Greater);
+    pub const DECREASED: Filter = Filter::Previous(Comparison::Less);
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SearchError {
+    UnknownCommand(String),
+    InvalidArgument(String),
+    NotStarted,
+}
+
+impl Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::UnknownCommand(command) => write!(f, "unknown search command \"{}\"", command),
+            SearchError::InvalidArgument(argument) => write!(f, "invalid search argument \"{}\"", argument),
+            SearchError::NotStarted => write!(f, "search is not started"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Candidate {
+    pub offset: usize,
+    pub previous: i64,
+}
+
+// the values of the memory in big endian, the word and long values are aligned to the even offsets
+pub struct RamSearch {
+    region: SearchRegion,
+    size: ValueSize,
+    signed: bool,
+    candidates: Vec<Candidate>,
+    history: Vec<Vec<Candidate>>,
+}
+
+impl RamSearch {
+    pub fn new(region: SearchRegion, size: ValueSize, signed: bool, memory: &[u8]) -> Self {
+        let mut search = Self {
+            region,
+            size,
+            signed,
+            candidates: vec![],
+            history: vec![],
+        };
+        search.reset(memory);
+        search
+    }
+
+    pub fn region(&self) -> SearchRegion {
+        self.region
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    // all addresses with their current values, the undo history is dropped
+    pub fn reset(&mut self, memory: &[u8]) {
+        let step = if self.size == ValueSize::Byte { 1 } else { 2 };
+        let size = self.size as usize;
+        self.candidates = (0..memory.len().saturating_sub(size - 1))
+            .step_by(step)
+            .map(|offset| Candidate { offset, previous: self.value(memory, offset) })
+            .collect();
+        self.history.clear();
+    }
+
+    pub fn value(&self, memory: &[u8], offset: usize) -> i64 {
+        let bytes = &memory[offset..offset + self.size as usize];
+        let unsigned = bytes.iter().fold(0u32, |value, byte| value << 8 | *byte as u32);
+        match (self.signed, self.size) {
+            (false, _) => unsigned as i64,
+            (true, ValueSize::Byte) => unsigned as u8 as i8 as i64,
+            (true, ValueSize::Word) => unsigned as u16 as i16 as i64,
+            (true, ValueSize::Long) => unsigned as i32 as i64,
+        }
+    }
+
+    // keeps the matching addresses and remembers their values for the next step
+    pub fn filter(&mut self, filter: Filter, memory: &[u8]) {
+        let candidates = self
+            .candidates
+            .iter()
+            .filter_map(|candidate| {
+                let value = self.value(memory, candidate.offset);
+                let matches = match filter {
+                    Filter::Previous(comparison) => comparison.matches(value, candidate.previous),
+                    Filter::Value(comparison, operand) => comparison.matches(value, operand),
+                    Filter::Difference(difference) => value - candidate.previous == difference,
+                };
+                matches.then_some(Candidate { offset: candidate.offset, previous: value })
+            })
+            .collect();
+        self.history.push(std::mem::replace(&mut self.candidates, candidates));
+    }
+
+    // returns false if there is no step to revert
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some(candidates) => {
+                self.candidates = candidates;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// the commands of the search console
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchCommand {
+    Start(SearchRegion, ValueSize, bool),
+    Filter(Filter),
+    Undo,
+    Reset,
+    List,
+}
+
+impl SearchCommand {
+    // "start [m68k|z80|vram] [byte|word|long] [signed]", "changed", "unchanged", "increased", "decreased",
+    // "<eq|ne|lt|gt|le|ge> [<value>]" - compares with the value or the previous one, "diff <value>",
+    // "undo", "reset" and "list", the values are decimal or hex with "$" or "0x"
+    pub fn parse(line: &str) -> Result<Self, SearchError> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let arguments = words.collect::<Vec<&str>>();
+        let comparison = match command {
+            "eq" => Some(Comparison::Equal),
+            "ne" => Some(Comparison::NotEqual),
+            "lt" => Some(Comparison::Less),
+            "gt" => Some(Comparison::Greater),
+            "le" => Some(Comparison::LessOrEqual),
+            "ge" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        };
+        if let Some(comparison) = comparison {
+            return match arguments.first() {
+                Some(value) => Ok(SearchCommand::Filter(Filter::Value(comparison, parse_value(value)?))),
+                None => Ok(SearchCommand::Filter(Filter::Previous(comparison))),
+            };
+        }
+        let command = match command {
+            "start" => {
+                let mut region = SearchRegion::M68kRam;
+                let mut size = ValueSize::Byte;
+                let mut signed = false;
+                for argument in arguments {
+                    match argument {
+                        "m68k" => region = SearchRegion::M68kRam,
+                        "z80" => region = SearchRegion::Z80Ram,
+                        "vram" => region = SearchRegion::Vram,
+                        "byte" => size = ValueSize::Byte,
+                        "word" => size = ValueSize::Word,
+                        "long" => size = ValueSize::Long,
+                        "signed" => signed = true,
+                        "unsigned" => signed = false,
+                        _ => return Err(SearchError::InvalidArgument(argument.to_string())),
+                    }
+                }
+                SearchCommand::Start(region, size, signed)
+            }
+            "changed" => SearchCommand::Filter(Filter::CHANGED),
+            "unchanged" => SearchCommand::Filter(Filter::UNCHANGED),
+            "increased" => SearchCommand::Filter(Filter::INCREASED),
+            "decreased" => SearchCommand::Filter(Filter::DECREASED),
+            "diff" => {
+                let value = arguments.first().ok_or(SearchError::InvalidArgument(String::new()))?;
+                SearchCommand::Filter(Filter::Difference(parse_value(value)?))
+            }
+            "undo" => SearchCommand::Undo,
+            "reset" => SearchCommand::Reset,
+            "list" => SearchCommand::List,
+            _ => return Err(SearchError::UnknownCommand(command.to_string())),
+        };
+        Ok(command)
+    }
+}
+
+fn parse_value(text: &str) -> Result<i64, SearchError> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix('$').or_else(|| digits.strip_prefix("0x")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| SearchError::InvalidArgument(text.to_string()))?;
+    Ok(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Comparison, Filter, RamSearch, SearchCommand, SearchRegion, ValueSize};
+
+    #[test]
+    fn filters_values_and_undoes() {
+        let mut memory = vec![0x00, 0x05, 0x00, 0x05, 0xFF, 0xFE];
+        let mut search = RamSearch::new(SearchRegion::M68kRam, ValueSize::Byte, false, &memory);
+        assert_eq!(search.candidates().len(), 6);
+
+        search.filter(Filter::Value(Comparison::Equal, 5), &memory);
+        assert_eq!(search.candidates().len(), 2);
+        memory[3] = 4;
+        search.filter(Filter::Difference(-1), &memory);
+        assert_eq!(search.candidates()[0].offset, 3);
+        assert!(search.undo());
+        assert_eq!(search.candidates().len(), 2);
+
+        // zero decreased by one is not an underflow
+        let mut search = RamSearch::new(SearchRegion::M68kRam, ValueSize::Word, true, &memory);
+        assert_eq!(search.candidates().len(), 3);
+        memory[1] = 0x00;
+        memory[5] = 0xFD;
+        search.filter(Filter::DECREASED, &memory);
+        assert_eq!(search.candidates().iter().map(|c| c.offset).collect::<Vec<usize>>(), [0, 4]);
+        search.filter(Filter::Value(Comparison::Less, 0), &memory);
+        assert_eq!(search.candidates()[0].previous, -3);
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            SearchCommand::parse("start vram long signed"),
+            Ok(SearchCommand::Start(SearchRegion::Vram, ValueSize::Long, true))
+        );
+        assert_eq!(
+            SearchCommand::parse("ge $1F"),
+            Ok(SearchCommand::Filter(Filter::Value(Comparison::GreaterOrEqual, 0x1F)))
+        );
+        assert_eq!(SearchCommand::parse("lt"), Ok(SearchCommand::Filter(Filter::DECREASED)));
+        assert_eq!(SearchCommand::parse("diff -1"), Ok(SearchCommand::Filter(Filter::Difference(-1))));
+        assert!(SearchCommand::parse("start rom").is_err());
+        assert!(SearchCommand::parse("find 1").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::Display;
+
+// header of the state file: magic, format version and the rom fingerprint
+const STATE_MAGIC: &[u8; 4] = b"SGRS";
+pub const STATE_VERSION: u16 = 12;
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    WrongMagic,
+    UnsupportedVersion(u16),
+    RomMismatch,
+    UnexpectedEnd,
+    InvalidValue(&'static str),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::WrongMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::RomMismatch => write!(f, "save state belongs to another rom"),
+            StateError::UnexpectedEnd => write!(f, "save state is truncated"),
+            StateError::InvalidValue(field) => write!(f, "save state has invalid {}", field),
+        }
+    }
+}
+
+// all values are stored in little endian byte order one by one, the layout is defined by the version
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new(rom_fingerprint: u32) -> Self {
+        let mut writer = Self { data: vec![] };
+        writer.write_bytes(STATE_MAGIC);
+        writer.write_u16(STATE_VERSION);
+        writer.write_u32(rom_fingerprint);
+        writer
+    }
+
+    // the same encoding for the other formats, they write their own header
+    pub(crate) fn without_header() -> Self {
+        Self { data: vec![] }
+    }
+
+    pub(crate) fn write_bool(&mut self, data: bool) {
+        self.write_u8(data as u8);
+    }
+
+    pub(crate) fn write_u8(&mut self, data: u8) {
+        self.data.push(data);
+    }
+
+    pub(crate) fn write_u16(&mut self, data: u16) {
+        self.write_bytes(&data.to_le_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, data: u32) {
+        self.write_bytes(&data.to_le_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, data: u64) {
+        self.write_bytes(&data.to_le_bytes());
+    }
+
+    pub(crate) fn write_bytes(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header and leaves the reader at the first component
+    pub(crate) fn new(data: &'a [u8], rom_fingerprint: u32) -> Result<Self, StateError> {
+        let mut reader = Self { data, position: 0 };
+        let mut magic = [0; 4];
+        reader
+            .read_bytes(&mut magic)
+            .map_err(|_| StateError::WrongMagic)?;
+        if &magic != STATE_MAGIC {
+            return Err(StateError::WrongMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if reader.read_u32()? != rom_fingerprint {
+            return Err(StateError::RomMismatch);
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn without_header(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue("boolean")),
+        }
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, StateError> {
+        let mut buffer = [0; 1];
+        self.read_bytes(&mut buffer)?;
+        Ok(buffer[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, StateError> {
+        let mut buffer = [0; 2];
+        self.read_bytes(&mut buffer)?;
+        Ok(u16::from_le_bytes(buffer))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut buffer = [0; 4];
+        self.read_bytes(&mut buffer)?;
+        Ok(u32::from_le_bytes(buffer))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut buffer = [0; 8];
+        self.read_bytes(&mut buffer)?;
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    pub(crate) fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        let end = self.position + buffer.len();
+        if end > self.data.len() {
+            return Err(StateError::UnexpectedEnd);
+        }
+        buffer.copy_from_slice(&self.data[self.position..end]);
+        self.position = end;
+        Ok(())
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+// FNV-1a hash of the rom, prevents loading the state of another game
+pub(crate) fn rom_fingerprint(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811C9DC5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
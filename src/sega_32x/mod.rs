@@ -0,0 +1,495 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use log::debug;
+use sh2_emu::bus::BusSh2;
+
+use crate::{
+    memory_space::write_byte,
+    save_state::{StateError, StateReader, StateWriter},
+    sega_32x::{
+        pwm::Pwm,
+        vdp::{Vdp32x, FRAME_BUFFER_SIZE},
+    },
+};
+
+mod pwm;
+mod vdp;
+
+pub(crate) const MAIN_REGISTERS: u32 = 0xA15100;
+pub(crate) const MAIN_REGISTERS_END: u32 = 0xA15400;
+pub(crate) const MARS_ID: u32 = 0xA130EC;
+pub(crate) const MASTER: usize = 0;
+pub(crate) const SLAVE: usize = 1;
+
+const SDRAM_SIZE: usize = 0x40000;
+const FIFO_SIZE: usize = 8;
+const ROM_WINDOW_SIZE: u32 = 0x400000;
+
+// the interrupt sources, the bits of the interrupt mask registers of the sh2s
+const PWM_INTERRUPT: u8 = 0x01;
+const CMD_INTERRUPT: u8 = 0x02;
+const H_INTERRUPT: u8 = 0x04;
+const V_INTERRUPT: u8 = 0x08;
+
+// DREQ control bits
+const RV: u16 = 0x0001;
+const M68S: u16 = 0x0004;
+
+// the add-on board: the sdram and the system registers of the sh2s, the adapter registers of the
+// m68k, the dreq fifo, the frame buffer controller and the pwm
+pub(crate) struct Sega32x {
+    m68k_bios: Vec<u8>,
+    sh2_bios: [Vec<u8>; 2],
+    rom: Rc<[u8]>, // the cartridge of the memory space
+    sdram: Vec<u8>,
+    vdp: Vdp32x,
+    pwm: Pwm,
+    pwm_latch: u8, // the high byte of the pwm register write
+
+    frame_mode: bool,        // FM, the sh2s own the vdp when it is set
+    adapter_enabled: bool,   // ADEN
+    sh2_reset: bool,         // RES, the sh2s are held in reset while it is false
+    reset_pending: bool,
+    bank: u8, // the megabyte of the rom at 0x900000
+
+    dreq_control: u16,
+    dreq_registers: [u16; 5], // the source, the destination and the length
+    fifo: RefCell<VecDeque<u16>>, // the sh2 reads pop the words
+    fifo_latch: u8,
+
+    comm: [u16; 8],
+    interrupt_masks: [u8; 2],
+    pending_interrupts: [u8; 2],
+    h_interrupt_in_vblank: bool, // HEN
+    h_count: u8,
+    h_counter: u8,
+    line: u16,
+}
+
+impl Sega32x {
+    pub(crate) fn new(rom: Rc<[u8]>, m68k_bios: Vec<u8>, master_bios: Vec<u8>, slave_bios: Vec<u8>, pal: bool) -> Self {
+        Self {
+            m68k_bios,
+            sh2_bios: [master_bios, slave_bios],
+            rom,
+            sdram: vec![0; SDRAM_SIZE],
+            vdp: Vdp32x::new(pal),
+            pwm: Pwm::new(),
+            pwm_latch: 0,
+
+            frame_mode: false,
+            adapter_enabled: false,
+            sh2_reset: false,
+            reset_pending: false,
+            bank: 0,
+
+            dreq_control: 0,
+            dreq_registers: [0; 5],
+            fifo: RefCell::new(VecDeque::new()),
+            fifo_latch: 0,
+
+            comm: [0; 8],
+            interrupt_masks: [0; 2],
+            pending_interrupts: [0; 2],
+            h_interrupt_in_vblank: false,
+            h_count: 0,
+            h_counter: 0,
+            line: 0,
+        }
+    }
+
+    pub(crate) fn sh2_running(&self) -> bool {
+        self.adapter_enabled && self.sh2_reset
+    }
+
+    // the sh2s read their vectors when the m68k releases the reset
+    pub(crate) fn take_sh2_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset_pending)
+    }
+
+    fn raise_interrupt(&mut self, source: u8) {
+        for (mask, pending) in self.interrupt_masks.iter().zip(self.pending_interrupts.iter_mut()) {
+            if mask & source != 0 {
+                *pending |= source;
+            }
+        }
+    }
+
+    // the interrupts are held until the cpu clears them: V - 12, H - 10, CMD - 8, PWM - 6
+    pub(crate) fn interrupt_level(&self, cpu: usize) -> Option<u8> {
+        let pending = self.pending_interrupts[cpu];
+        (0..4).rev().find(|bit| pending & (1 << bit) != 0).map(|bit| 6 + bit * 2)
+    }
+
+    // the master transfers the fifo by the dma channel 0
+    pub(crate) fn dreq_pending(&self) -> bool {
+        !self.fifo.borrow().is_empty()
+    }
+
+    // the pwm runs at the sh2 clock
+    pub(crate) fn clock(&mut self, cycles: u32) {
+        if self.pwm.clock(cycles) {
+            self.raise_interrupt(PWM_INTERRUPT);
+        }
+    }
+
+    pub(crate) fn pwm_sample(&self) -> (i32, i32) {
+        self.pwm.sample()
+    }
+
+    // follows the line of the Mega Drive vdp, the h interrupt counter runs in the display period
+    // and in the vblank with HEN
+    pub(crate) fn set_line(&mut self, line: u16, screen_height: u16, hblank: bool) {
+        self.vdp.hblank = hblank;
+        if line == self.line {
+            return;
+        }
+        self.line = line;
+        if line == screen_height {
+            self.vdp.start_vblank();
+            self.raise_interrupt(V_INTERRUPT);
+        } else if line == 0 {
+            self.vdp.vblank = false;
+        }
+        if line < screen_height || self.h_interrupt_in_vblank {
+            if self.h_counter == 0 {
+                self.h_counter = self.h_count;
+                self.raise_interrupt(H_INTERRUPT);
+            } else {
+                self.h_counter -= 1;
+            }
+        } else {
+            self.h_counter = self.h_count;
+        }
+    }
+
+    // the 32X vdp image over the Mega Drive dots of the line
+    pub(crate) fn compose_line(&self, line: usize, dots: &mut [u32], backdrop: &[bool]) {
+        self.vdp.compose_line(line, dots, backdrop);
+    }
+
+    // the m68k bios takes the vectors of the enabled adapter
+    pub(crate) fn read_vector(&self, address: u32) -> Option<u8> {
+        let mapped = self.adapter_enabled && self.dreq_control & RV == 0 && address < 0x100;
+        mapped.then(|| self.m68k_bios.get(address as usize).copied().unwrap_or(0xFF))
+    }
+
+    // the offset of the rom seen through the windows at 0x880000 and 0x900000
+    pub(crate) fn rom_offset(&self, address: u32) -> Option<u32> {
+        match address {
+            0x880000..=0x8FFFFF if self.adapter_enabled => Some(address - 0x880000),
+            0x900000..=0x9FFFFF if self.adapter_enabled => Some((self.bank as u32) << 20 | (address - 0x900000)),
+            _ => None,
+        }
+    }
+
+    // the frame buffer at 0x840000 and its overwrite image at 0x860000
+    pub(crate) fn frame_buffer_mapped(&self, address: u32) -> bool {
+        self.adapter_enabled && (0x840000..0x880000).contains(&address)
+    }
+
+    pub(crate) fn main_read(&self, address: u32) -> u8 {
+        match address {
+            MARS_ID..=0xA130EF => b"MARS"[(address - MARS_ID) as usize],
+            0x840000..=0x87FFFF => self.vdp.read_frame_buffer(address & 0x1FFFF),
+            0xA15180..=0xA151FF => self.vdp.read_register(address).to_be_bytes()[(address & 0x01) as usize],
+            0xA15200..=0xA153FF => self.vdp.read_palette(address & 0x1FF),
+            _ => self.read_main_register(address).to_be_bytes()[(address & 0x01) as usize],
+        }
+    }
+
+    pub(crate) fn main_write(&mut self, address: u32, data: u8) {
+        match address {
+            0x840000..=0x85FFFF => self.vdp.write_frame_buffer(address & 0x1FFFF, data, false),
+            0x860000..=0x87FFFF => self.vdp.write_frame_buffer(address & 0x1FFFF, data, true),
+            0xA15180..=0xA151FF => self.vdp.write_register(address, data),
+            0xA15200..=0xA153FF => self.vdp.write_palette(address & 0x1FF, data),
+            MAIN_REGISTERS..=0xA1517F => self.write_main_register(address, data),
+            _ => debug!("Sega32x: m68k write {:02X} to {:06X} is ignored", data, address),
+        }
+    }
+
+    fn read_main_register(&self, address: u32) -> u16 {
+        let offset = address & 0x3E;
+        match offset {
+            0x00 => (self.frame_mode as u16) << 15 | 0x80 | (self.sh2_reset as u16) << 1 | self.adapter_enabled as u16,
+            0x02 => {
+                let pending = |cpu: usize| (self.pending_interrupts[cpu] & CMD_INTERRUPT != 0) as u16;
+                pending(SLAVE) << 1 | pending(MASTER)
+            }
+            0x04 => self.bank as u16,
+            _ => self.read_common_register(offset),
+        }
+    }
+
+    fn write_main_register(&mut self, address: u32, data: u8) {
+        let offset = address & 0x3F;
+        match offset {
+            0x00 => self.frame_mode = data & 0x80 != 0,
+            0x01 => {
+                let sh2_reset = data & 0x02 != 0;
+                if sh2_reset && !self.sh2_reset {
+                    self.reset_pending = true;
+                }
+                self.sh2_reset = sh2_reset;
+                self.adapter_enabled = data & 0x01 != 0;
+            }
+            0x03 => {
+                for (cpu, bit) in [(MASTER, 0x01), (SLAVE, 0x02)] {
+                    if data & bit != 0 && self.interrupt_masks[cpu] & CMD_INTERRUPT != 0 {
+                        self.pending_interrupts[cpu] |= CMD_INTERRUPT;
+                    }
+                }
+            }
+            0x05 => self.bank = data & 0x03,
+            0x07 => {
+                if data as u16 & M68S != 0 && self.dreq_control & M68S == 0 {
+                    self.fifo.get_mut().clear();
+                }
+                self.dreq_control = data as u16 & (M68S | RV);
+            }
+            0x08..=0x11 => write_byte(&mut self.dreq_registers[(offset as usize - 0x08) / 2], offset, data),
+            0x12 => self.fifo_latch = data,
+            0x13 => self.push_fifo(u16::from_be_bytes([self.fifo_latch, data])),
+            _ => self.write_common_register(offset, data),
+        }
+    }
+
+    // the length counts the words, the transfer ends with the last one
+    fn push_fifo(&mut self, word: u16) {
+        let fifo = self.fifo.get_mut();
+        if self.dreq_control & M68S == 0 || fifo.len() == FIFO_SIZE {
+            debug!("Sega32x: fifo write {:04X} is dropped", word);
+            return;
+        }
+        fifo.push_back(word);
+        let length = &mut self.dreq_registers[4];
+        *length = length.wrapping_sub(1);
+        if *length == 0 {
+            self.dreq_control &= !M68S;
+        }
+    }
+
+    // the registers of both cpus: the dreq, the comm ports and the pwm
+    fn read_common_register(&self, offset: u32) -> u16 {
+        match offset {
+            0x06 => ((self.fifo.borrow().len() == FIFO_SIZE) as u16) << 7 | self.dreq_control,
+            0x08..=0x10 => self.dreq_registers[(offset as usize - 0x08) / 2],
+            0x20..=0x2E => self.comm[(offset as usize - 0x20) / 2],
+            0x30..=0x3E => self.pwm.read(offset),
+            _ => 0,
+        }
+    }
+
+    fn write_common_register(&mut self, offset: u32, data: u8) {
+        match offset {
+            0x20..=0x2F => write_byte(&mut self.comm[(offset as usize - 0x20) / 2], offset, data),
+            0x30..=0x3F if offset & 0x01 == 0 => self.pwm_latch = data,
+            0x30..=0x3F => self.pwm.write(offset, u16::from_be_bytes([self.pwm_latch, data])),
+            _ => debug!("Sega32x: write {:02X} to the register {:02X} is ignored", data, offset),
+        }
+    }
+
+    fn read_sh2_register(&self, cpu: usize, offset: u32) -> u16 {
+        match offset & 0x3E {
+            0x00 => {
+                // the CART bit is clear, the cartridge is inserted
+                (self.frame_mode as u16) << 15
+                    | (self.adapter_enabled as u16) << 9
+                    | (self.h_interrupt_in_vblank as u16) << 7
+                    | self.interrupt_masks[cpu] as u16
+            }
+            0x04 => self.h_count as u16,
+            0x12 => self.fifo.borrow_mut().pop_front().unwrap_or(0),
+            offset => self.read_common_register(offset),
+        }
+    }
+
+    fn write_sh2_register(&mut self, cpu: usize, offset: u32, data: u8) {
+        let offset = offset & 0x3F;
+        match offset {
+            0x00 => self.frame_mode = data & 0x80 != 0,
+            0x01 => {
+                self.h_interrupt_in_vblank = data & 0x80 != 0;
+                self.interrupt_masks[cpu] = data & 0x0F;
+            }
+            0x05 => self.h_count = data,
+            // the interrupt clear registers: VRES, V, H, CMD and PWM
+            0x14 | 0x15 => (),
+            0x16 | 0x17 => self.pending_interrupts[cpu] &= !V_INTERRUPT,
+            0x18 | 0x19 => self.pending_interrupts[cpu] &= !H_INTERRUPT,
+            0x1A | 0x1B => self.pending_interrupts[cpu] &= !CMD_INTERRUPT,
+            0x1C | 0x1D => self.pending_interrupts[cpu] &= !PWM_INTERRUPT,
+            _ => self.write_common_register(offset, data),
+        }
+    }
+
+    fn sh2_read_byte(&self, cpu: usize, address: u32) -> u8 {
+        match address {
+            0x00000000..=0x00003FFF => self.sh2_bios[cpu].get(address as usize).copied().unwrap_or(0xFF),
+            0x00004000..=0x000040FF => self.read_sh2_register(cpu, address).to_be_bytes()[(address & 0x01) as usize],
+            0x00004100..=0x000041FF => self.vdp.read_register(address).to_be_bytes()[(address & 0x01) as usize],
+            0x00004200..=0x000043FF => self.vdp.read_palette(address & 0x1FF),
+            0x02000000..=0x023FFFFF => self.rom.get((address % ROM_WINDOW_SIZE) as usize).copied().unwrap_or(0xFF),
+            0x04000000..=0x0403FFFF => self.vdp.read_frame_buffer(address % FRAME_BUFFER_SIZE as u32),
+            0x06000000..=0x0603FFFF => self.sdram[address as usize % SDRAM_SIZE],
+            _ => {
+                debug!("Sega32x: sh2 read of the unmapped address {:08X}", address);
+                0
+            }
+        }
+    }
+
+    fn sh2_write_byte(&mut self, cpu: usize, address: u32, data: u8) {
+        match address {
+            0x00004000..=0x000040FF => self.write_sh2_register(cpu, address, data),
+            0x00004100..=0x000041FF => self.vdp.write_register(address, data),
+            0x00004200..=0x000043FF => self.vdp.write_palette(address & 0x1FF, data),
+            0x04000000..=0x0401FFFF => self.vdp.write_frame_buffer(address % FRAME_BUFFER_SIZE as u32, data, false),
+            0x04020000..=0x0403FFFF => self.vdp.write_frame_buffer(address % FRAME_BUFFER_SIZE as u32, data, true),
+            0x06000000..=0x0603FFFF => self.sdram[address as usize % SDRAM_SIZE] = data,
+            _ => debug!("Sega32x: sh2 write {:02X} to {:08X} is ignored", data, address),
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.sdram);
+        self.vdp.save_state(writer);
+        self.pwm.save_state(writer);
+        writer.write_u8(self.pwm_latch);
+        writer.write_bool(self.frame_mode);
+        writer.write_bool(self.adapter_enabled);
+        writer.write_bool(self.sh2_reset);
+        writer.write_bool(self.reset_pending);
+        writer.write_u8(self.bank);
+        writer.write_u16(self.dreq_control);
+        for register in self.dreq_registers {
+            writer.write_u16(register);
+        }
+        let fifo = self.fifo.borrow();
+        writer.write_u8(fifo.len() as u8);
+        for word in fifo.iter() {
+            writer.write_u16(*word);
+        }
+        writer.write_u8(self.fifo_latch);
+        for word in self.comm {
+            writer.write_u16(word);
+        }
+        writer.write_bytes(&self.interrupt_masks);
+        writer.write_bytes(&self.pending_interrupts);
+        writer.write_bool(self.h_interrupt_in_vblank);
+        writer.write_u8(self.h_count);
+        writer.write_u8(self.h_counter);
+        writer.write_u16(self.line);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.sdram)?;
+        self.vdp.load_state(reader)?;
+        self.pwm.load_state(reader)?;
+        self.pwm_latch = reader.read_u8()?;
+        self.frame_mode = reader.read_bool()?;
+        self.adapter_enabled = reader.read_bool()?;
+        self.sh2_reset = reader.read_bool()?;
+        self.reset_pending = reader.read_bool()?;
+        self.bank = reader.read_u8()? & 0x03;
+        self.dreq_control = reader.read_u16()? & (M68S | RV);
+        for register in self.dreq_registers.iter_mut() {
+            *register = reader.read_u16()?;
+        }
+        let length = reader.read_u8()? as usize;
+        if length > FIFO_SIZE {
+            return Err(StateError::InvalidValue("dreq fifo"));
+        }
+        let fifo = self.fifo.get_mut();
+        fifo.clear();
+        for _ in 0..length {
+            fifo.push_back(reader.read_u16()?);
+        }
+        self.fifo_latch = reader.read_u8()?;
+        for word in self.comm.iter_mut() {
+            *word = reader.read_u16()?;
+        }
+        reader.read_bytes(&mut self.interrupt_masks)?;
+        reader.read_bytes(&mut self.pending_interrupts)?;
+        self.h_interrupt_in_vblank = reader.read_bool()?;
+        self.h_count = reader.read_u8()?;
+        self.h_counter = reader.read_u8()?;
+        self.line = reader.read_u16()?;
+        Ok(())
+    }
+}
+
+// the bus of a sh2, the system registers are banked by the cpu
+pub(crate) struct Sh2Bus {
+    system: Rc<RefCell<Sega32x>>,
+    cpu: usize,
+}
+
+impl Sh2Bus {
+    pub(crate) fn new(system: Rc<RefCell<Sega32x>>, cpu: usize) -> Self {
+        Self { system, cpu }
+    }
+}
+
+impl BusSh2 for Sh2Bus {
+    fn read(&self, address: u32, amount: usize) -> Result<u32, ()> {
+        let system = self.system.borrow();
+        // the fifo register pops a word per access
+        if (0x4012..0x4014).contains(&address) {
+            return Ok((0..amount.div_ceil(2)).fold(0, |data, _| data << 16 | system.read_sh2_register(self.cpu, 0x12) as u32));
+        }
+        Ok((0..amount as u32).fold(0, |data, i| data << 8 | system.sh2_read_byte(self.cpu, address + i) as u32))
+    }
+
+    fn write(&mut self, data: u32, address: u32, amount: usize) -> Result<(), ()> {
+        let mut system = self.system.borrow_mut();
+        for (i, byte) in data.to_be_bytes()[4 - amount..].iter().enumerate() {
+            system.sh2_write_byte(self.cpu, address + i as u32, *byte);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Sega32x, MASTER, SLAVE};
+
+    #[test]
+    fn comm_ports_and_cmd_interrupts_are_shared() {
+        let mut sega_32x = Sega32x::new(vec![0; 0x1000].into(), vec![0; 0x100], vec![], vec![], false);
+        assert_eq!(sega_32x.main_read(0xA130EC), b'M');
+
+        sega_32x.main_write(0xA15120, 0x4D);
+        sega_32x.main_write(0xA15121, 0x5F);
+        assert_eq!(sega_32x.sh2_read_byte(SLAVE, 0x4020), 0x4D);
+        sega_32x.sh2_write_byte(MASTER, 0x4023, 0x01);
+        assert_eq!(sega_32x.main_read(0xA15123), 0x01);
+
+        // the cmd interrupt is raised for the cpu which enables it
+        sega_32x.sh2_write_byte(SLAVE, 0x4001, 0x02);
+        sega_32x.main_write(0xA15103, 0x03);
+        assert_eq!(sega_32x.interrupt_level(MASTER), None);
+        assert_eq!(sega_32x.interrupt_level(SLAVE), Some(8));
+        sega_32x.sh2_write_byte(SLAVE, 0x401B, 0x00);
+        assert_eq!(sega_32x.interrupt_level(SLAVE), None);
+
+        // the 68S transfer ends after the length words
+        sega_32x.main_write(0xA15111, 0x02);
+        sega_32x.main_write(0xA15107, 0x04);
+        for word in [0x1234u16, 0x5678, 0x9ABC] {
+            let [high, low] = word.to_be_bytes();
+            sega_32x.main_write(0xA15112, high);
+            sega_32x.main_write(0xA15113, low);
+        }
+        assert!(sega_32x.dreq_pending());
+        assert_eq!(sega_32x.main_read(0xA15107) & 0x04, 0);
+        assert_eq!(sega_32x.read_sh2_register(MASTER, 0x12), 0x1234);
+        assert_eq!(sega_32x.read_sh2_register(MASTER, 0x12), 0x5678);
+        assert!(!sega_32x.dreq_pending());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+use log::debug;
+
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+const FIFO_SIZE: usize = 3;
+// the fifo status bits of the pulse width registers
+const FULL: u16 = 0x8000;
+const EMPTY: u16 = 0x4000;
+
+// the pulse width modulator: two 3 word fifos played at the rate set by the cycle register,
+// the output is the duty of the pulse relative to the half of the cycle
+pub(crate) struct Pwm {
+    control: u16, // TM in bits 11-8, RMD in bits 3-2, LMD in bits 1-0
+    cycle: u16,
+    left: VecDeque<u16>,
+    right: VecDeque<u16>,
+    output: (u16, u16),
+    clocks: u32,
+    interrupt_counter: u8,
+}
+
+impl Pwm {
+    pub(crate) fn new() -> Self {
+        Self {
+            control: 0,
+            cycle: 0,
+            left: VecDeque::new(),
+            right: VecDeque::new(),
+            output: (0, 0),
+            clocks: 0,
+            interrupt_counter: 0,
+        }
+    }
+
+    // the sh2 clocks per sample
+    fn period(&self) -> u32 {
+        match self.cycle.wrapping_sub(1) & 0x0FFF {
+            0 => 0x1000,
+            period => period as u32,
+        }
+    }
+
+    // the samples between the interrupts
+    fn interrupt_period(&self) -> u8 {
+        match (self.control >> 8) & 0x0F {
+            0 => 16,
+            period => period as u8,
+        }
+    }
+
+    fn fifo_status(fifo: &VecDeque<u16>) -> u16 {
+        match fifo.len() {
+            0 => EMPTY,
+            FIFO_SIZE => FULL,
+            _ => 0,
+        }
+    }
+
+    // the register at the offset from 0x30
+    pub(crate) fn read(&self, offset: u32) -> u16 {
+        match offset & 0x0E {
+            0x00 => self.control,
+            0x02 => self.cycle,
+            0x04 => Self::fifo_status(&self.left),
+            0x06 => Self::fifo_status(&self.right),
+            0x08 => Self::fifo_status(&self.left) | Self::fifo_status(&self.right),
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn write(&mut self, offset: u32, data: u16) {
+        let push = |fifo: &mut VecDeque<u16>| {
+            // the full fifo drops the oldest sample
+            if fifo.len() == FIFO_SIZE {
+                fifo.pop_front();
+            }
+            fifo.push_back(data & 0x0FFF);
+        };
+        match offset & 0x0E {
+            0x00 => self.control = data & 0x0F8F,
+            0x02 => self.cycle = data & 0x0FFF,
+            0x04 => push(&mut self.left),
+            0x06 => push(&mut self.right),
+            0x08 => {
+                push(&mut self.left);
+                push(&mut self.right);
+            }
+            _ => debug!("Pwm: write {:04X} to {:02X} is ignored", data, offset),
+        }
+    }
+
+    // returns true if the interrupt is raised
+    pub(crate) fn clock(&mut self, cycles: u32) -> bool {
+        if self.control & 0x0F == 0 {
+            return false;
+        }
+        let mut interrupt = false;
+        self.clocks += cycles;
+        while self.clocks >= self.period() {
+            self.clocks -= self.period();
+            if let Some(left) = self.left.pop_front() {
+                self.output.0 = left;
+            }
+            if let Some(right) = self.right.pop_front() {
+                self.output.1 = right;
+            }
+            self.interrupt_counter += 1;
+            if self.interrupt_counter >= self.interrupt_period() {
+                self.interrupt_counter = 0;
+                interrupt = true;
+            }
+        }
+        interrupt
+    }
+
+    // the stereo output routed by the LMD and RMD bits
+    pub(crate) fn sample(&self) -> (i32, i32) {
+        let period = self.period() as i32;
+        let level = |width: u16| (width as i32 - period / 2) * 0x4000 / period;
+        let (left, right) = (level(self.output.0), level(self.output.1));
+        let route = |mode: u16, direct: i32, swapped: i32| match mode {
+            1 => direct,
+            2 => swapped,
+            _ => 0,
+        };
+        (route(self.control & 0x03, left, right), route((self.control >> 2) & 0x03, right, left))
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.control);
+        writer.write_u16(self.cycle);
+        for fifo in [&self.left, &self.right] {
+            writer.write_u8(fifo.len() as u8);
+            for sample in fifo {
+                writer.write_u16(*sample);
+            }
+        }
+        writer.write_u16(self.output.0);
+        writer.write_u16(self.output.1);
+        writer.write_u32(self.clocks);
+        writer.write_u8(self.interrupt_counter);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.control = reader.read_u16()?;
+        self.cycle = reader.read_u16()?;
+        for fifo in [&mut self.left, &mut self.right] {
+            let length = reader.read_u8()? as usize;
+            if length > FIFO_SIZE {
+                return Err(StateError::InvalidValue("pwm fifo"));
+            }
+            fifo.clear();
+            for _ in 0..length {
+                fifo.push_back(reader.read_u16()?);
+            }
+        }
+        self.output = (reader.read_u16()?, reader.read_u16()?);
+        self.clocks = reader.read_u32()?;
+        self.interrupt_counter = reader.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use log::debug;
+
+use crate::{
+    memory_space::write_byte,
+    save_state::{StateError, StateReader, StateWriter},
+};
+
+pub(crate) const FRAME_BUFFER_SIZE: usize = 0x20000;
+pub(crate) const PALETTE_SIZE: usize = 0x200;
+const SCREEN_WIDTH: usize = 320;
+
+// the bitmap modes, the M bits of the mode register
+const BLANK: u16 = 0;
+const PACKED: u16 = 1;
+const DIRECT: u16 = 2;
+const RUN_LENGTH: u16 = 3;
+
+// mode register bits
+const PRIORITY: u16 = 0x0080;
+// the through bit of the colors inverts the priority of the dot
+const THROUGH: u16 = 0x8000;
+
+// the frame buffer controller of the 32X: two 128 KB frame buffers, one of them is displayed while
+// the other is drawn, each one starts with the table of 256 line addresses
+pub(crate) struct Vdp32x {
+    frame_buffers: Vec<u8>,
+    palette: Vec<u8>,
+    pal: bool,
+    mode: u16,
+    shift: u16,
+    fill_length: u16,
+    fill_address: u16,
+    fill_data: u16,
+    display_buffer: u8, // FS
+    swap_pending: bool, // the FS write in the display period waits for the vblank
+    pub(crate) vblank: bool,
+    pub(crate) hblank: bool,
+}
+
+impl Vdp32x {
+    pub(crate) fn new(pal: bool) -> Self {
+        Self {
+            frame_buffers: vec![0; FRAME_BUFFER_SIZE * 2],
+            palette: vec![0; PALETTE_SIZE],
+            pal,
+            mode: 0,
+            shift: 0,
+            fill_length: 0,
+            fill_address: 0,
+            fill_data: 0,
+            display_buffer: 0,
+            swap_pending: false,
+            vblank: false,
+            hblank: false,
+        }
+    }
+
+    // the cpus access the buffer which is not displayed
+    fn draw_offset(&self, offset: u32) -> usize {
+        (self.display_buffer ^ 1) as usize * FRAME_BUFFER_SIZE + offset as usize % FRAME_BUFFER_SIZE
+    }
+
+    // the register at the offset from 0x4100
+    pub(crate) fn read_register(&self, offset: u32) -> u16 {
+        match offset & 0x0E {
+            0x00 => (!self.pal as u16) << 15 | self.mode,
+            0x02 => self.shift,
+            0x04 => self.fill_length,
+            0x06 => self.fill_address,
+            0x08 => self.fill_data,
+            0x0A => {
+                // the fill is done at once, the palette is always accessible
+                let displayed = if self.swap_pending { self.display_buffer ^ 1 } else { self.display_buffer };
+                (self.vblank as u16) << 15 | (self.hblank as u16) << 14 | 0x2000 | displayed as u16
+            }
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn write_register(&mut self, offset: u32, data: u8) {
+        match offset & 0x0F {
+            0x00 | 0x01 => {
+                write_byte(&mut self.mode, offset, data);
+                self.mode &= 0x00C3;
+            }
+            0x02 | 0x03 => {
+                write_byte(&mut self.shift, offset, data);
+                self.shift &= 0x0001;
+            }
+            0x04 | 0x05 => {
+                write_byte(&mut self.fill_length, offset, data);
+                self.fill_length &= 0x00FF;
+            }
+            0x06 | 0x07 => write_byte(&mut self.fill_address, offset, data),
+            0x08 => write_byte(&mut self.fill_data, offset, data),
+            // the low byte completes the data and starts the fill
+            0x09 => {
+                write_byte(&mut self.fill_data, offset, data);
+                self.fill();
+            }
+            0x0B => {
+                let buffer = data & 0x01;
+                if self.vblank {
+                    self.display_buffer = buffer;
+                    self.swap_pending = false;
+                } else {
+                    self.swap_pending = buffer != self.display_buffer;
+                }
+            }
+            _ => debug!("Vdp32x: write {:02X} to {:02X} is ignored", data, offset),
+        }
+    }
+
+    // the address increments within the 256 word block
+    fn fill(&mut self) {
+        for _ in 0..=self.fill_length {
+            let offset = self.draw_offset(self.fill_address as u32 * 2);
+            self.frame_buffers[offset..offset + 2].copy_from_slice(&self.fill_data.to_be_bytes());
+            self.fill_address = self.fill_address & 0xFF00 | (self.fill_address as u8).wrapping_add(1) as u16;
+        }
+    }
+
+    pub(crate) fn read_frame_buffer(&self, offset: u32) -> u8 {
+        self.frame_buffers[self.draw_offset(offset)]
+    }
+
+    // the overwrite image skips the zero bytes
+    pub(crate) fn write_frame_buffer(&mut self, offset: u32, data: u8, overwrite: bool) {
+        if !overwrite || data != 0 {
+            let offset = self.draw_offset(offset);
+            self.frame_buffers[offset] = data;
+        }
+    }
+
+    pub(crate) fn read_palette(&self, offset: u32) -> u8 {
+        self.palette[offset as usize % PALETTE_SIZE]
+    }
+
+    pub(crate) fn write_palette(&mut self, offset: u32, data: u8) {
+        self.palette[offset as usize % PALETTE_SIZE] = data;
+    }
+
+    // the requested buffer is displayed from the vblank
+    pub(crate) fn start_vblank(&mut self) {
+        self.vblank = true;
+        if std::mem::take(&mut self.swap_pending) {
+            self.display_buffer ^= 1;
+        }
+    }
+
+    fn palette_color(&self, index: u8) -> u16 {
+        let offset = index as usize * 2;
+        u16::from_be_bytes([self.palette[offset], self.palette[offset + 1]])
+    }
+
+    // the colors of the line of the displayed buffer, None in the blank mode
+    fn render_line(&self, line: usize) -> Option<Vec<u16>> {
+        let buffer = &self.frame_buffers[self.display_buffer as usize * FRAME_BUFFER_SIZE..][..FRAME_BUFFER_SIZE];
+        let word = |offset: usize| u16::from_be_bytes([buffer[offset % FRAME_BUFFER_SIZE], buffer[(offset + 1) % FRAME_BUFFER_SIZE]]);
+        let start = word((line & 0xFF) * 2) as usize * 2;
+        match self.mode & 0x03 {
+            BLANK => None,
+            PACKED => {
+                let start = start + self.shift as usize;
+                Some((0..SCREEN_WIDTH).map(|x| self.palette_color(buffer[(start + x) % FRAME_BUFFER_SIZE])).collect())
+            }
+            DIRECT => Some((0..SCREEN_WIDTH).map(|x| word(start + x * 2)).collect()),
+            RUN_LENGTH => {
+                let mut colors = Vec::with_capacity(SCREEN_WIDTH);
+                let mut offset = start;
+                while colors.len() < SCREEN_WIDTH {
+                    let [count, index] = word(offset).to_be_bytes();
+                    let run = (count as usize + 1).min(SCREEN_WIDTH - colors.len());
+                    colors.extend(std::iter::repeat_n(self.palette_color(index), run));
+                    offset += 2;
+                }
+                Some(colors)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // draws the line over the Mega Drive dots, the 32X dots are behind them unless PRI is set,
+    // the through bit inverts it and the backdrop dots are always covered
+    pub(crate) fn compose_line(&self, line: usize, dots: &mut [u32], backdrop: &[bool]) {
+        let Some(colors) = self.render_line(line) else {
+            return;
+        };
+        let priority = self.mode & PRIORITY != 0;
+        for ((dot, backdrop), color) in dots.iter_mut().zip(backdrop).zip(colors) {
+            if *backdrop || priority != (color & THROUGH != 0) {
+                *dot = rgb888(color);
+            }
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.frame_buffers);
+        writer.write_bytes(&self.palette);
+        writer.write_u16(self.mode);
+        writer.write_u16(self.shift);
+        writer.write_u16(self.fill_length);
+        writer.write_u16(self.fill_address);
+        writer.write_u16(self.fill_data);
+        writer.write_u8(self.display_buffer);
+        writer.write_bool(self.swap_pending);
+        writer.write_bool(self.vblank);
+        writer.write_bool(self.hblank);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.frame_buffers)?;
+        reader.read_bytes(&mut self.palette)?;
+        self.mode = reader.read_u16()? & 0x00C3;
+        self.shift = reader.read_u16()? & 0x0001;
+        self.fill_length = reader.read_u16()? & 0x00FF;
+        self.fill_address = reader.read_u16()?;
+        self.fill_data = reader.read_u16()?;
+        self.display_buffer = reader.read_u8()? & 0x01;
+        self.swap_pending = reader.read_bool()?;
+        self.vblank = reader.read_bool()?;
+        self.hblank = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+// the 15 bit BGR color
+fn rgb888(color: u16) -> u32 {
+    let component = |shift: u16| {
+        let value = ((color >> shift) & 0x1F) as u32;
+        value << 3 | value >> 2
+    };
+    component(0) << 16 | component(5) << 8 | component(10)
+}
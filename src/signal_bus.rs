@@ -1,4 +1,6 @@
-#[derive(PartialEq)]
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+#[derive(PartialEq, Clone, Copy)]
 pub enum Signal {
     VInterrupt,
     HInterrupt,
@@ -9,6 +11,21 @@ pub enum Signal {
     Z80INT,
 }
 
+impl Signal {
+    fn from_u8(data: u8) -> Result<Self, StateError> {
+        match data {
+            0 => Ok(Signal::VInterrupt),
+            1 => Ok(Signal::HInterrupt),
+            2 => Ok(Signal::CpuHalt),
+            3 => Ok(Signal::Z80BusRequest),
+            4 => Ok(Signal::Z80BusFree),
+            5 => Ok(Signal::Z80Reset),
+            6 => Ok(Signal::Z80INT),
+            _ => Err(StateError::InvalidValue("signal")),
+        }
+    }
+}
+
 pub struct SignalBus {
     signal_que: Vec<Signal>,
 }
@@ -31,4 +48,20 @@ impl SignalBus {
         }
         false
     }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.signal_que.len() as u32);
+        for signal in &self.signal_que {
+            writer.write_u8(*signal as u8);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let length = reader.read_u32()?;
+        self.signal_que.clear();
+        for _ in 0..length {
+            self.signal_que.push(Signal::from_u8(reader.read_u8()?)?);
+        }
+        Ok(())
+    }
 }
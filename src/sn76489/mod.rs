@@ -0,0 +1,5 @@
+pub mod sn76489;
+
+pub trait PsgPorts {
+    fn write(&mut self, data: u8);
+}
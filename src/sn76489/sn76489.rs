@@ -0,0 +1,196 @@
+use std::{cell::RefCell, rc::Rc};
+
+use log::debug;
+
+use crate::{
+    save_state::{StateError, StateReader, StateWriter},
+    sn76489::PsgPorts,
+    vgm::vgm_logger::VgmLogger,
+};
+
+// the Sega variant has a 16 bit shift register with the white noise taps on the bits 0 and 3
+const NOISE_LFSR_RESET: u16 = 0x8000;
+const NOISE_WHITE_TAPS: u16 = 0x0009;
+
+// 2 dB per attenuation step, the value 15 turns the channel off
+const VOLUME_TABLE: [i32; 16] = [
+    2048, 1627, 1292, 1026, 815, 647, 514, 408, 325, 258, 205, 163, 129, 102, 81, 0,
+];
+
+// tone channels 0-2 and the noise channel 3
+pub struct Sn76489 {
+    tone_periods: [u16; 3], // 10 bits
+    noise_control: u8,      // bit 2 - white noise, bits 0-1 - shift rate
+    attenuations: [u8; 4],
+    latched_register: u8, // channel (bits 1-2) and volume flag (bit 0) of the last latch byte
+
+    counters: [u16; 4],
+    outputs: [bool; 4],
+    noise_lfsr: u16,
+
+    output_sum: i32,
+    output_count: u32,
+
+    vgm_logger: Option<Rc<RefCell<VgmLogger>>>,
+}
+
+impl Sn76489 {
+    pub fn new() -> Self {
+        Self {
+            tone_periods: [0; 3],
+            noise_control: 0,
+            attenuations: [0x0F; 4],
+            latched_register: 0,
+
+            counters: [0; 4],
+            outputs: [false; 4],
+            noise_lfsr: NOISE_LFSR_RESET,
+
+            output_sum: 0,
+            output_count: 0,
+
+            vgm_logger: None,
+        }
+    }
+
+    pub(crate) fn set_vgm_logger(&mut self, vgm_logger: Option<Rc<RefCell<VgmLogger>>>) {
+        self.vgm_logger = vgm_logger;
+    }
+
+    // latch and data bytes that bring another chip into the current state
+    pub(crate) fn register_dump(&self) -> Vec<u8> {
+        let mut data = vec![];
+        for (channel, period) in self.tone_periods.iter().enumerate() {
+            data.push(0x80 | (channel as u8) << 5 | (*period & 0x0F) as u8);
+            data.push((*period >> 4) as u8 & 0x3F);
+        }
+        data.push(0xE0 | self.noise_control);
+        for (channel, attenuation) in self.attenuations.iter().enumerate() {
+            data.push(0x90 | (channel as u8) << 5 | attenuation);
+        }
+        data
+    }
+
+    // a single tick of the chip, it takes 16 z80 clocks
+    pub(crate) fn clock(&mut self) {
+        for channel in 0..3 {
+            self.counters[channel] = self.counters[channel].saturating_sub(1);
+            if self.counters[channel] == 0 {
+                self.counters[channel] = self.tone_periods[channel];
+                self.outputs[channel] = !self.outputs[channel];
+            }
+        }
+
+        self.counters[3] = self.counters[3].saturating_sub(1);
+        if self.counters[3] == 0 {
+            self.counters[3] = match self.noise_control & 0x03 {
+                0 => 0x10,
+                1 => 0x20,
+                2 => 0x40,
+                _ => self.tone_periods[2],
+            };
+            self.outputs[3] = !self.outputs[3];
+            // the register shifts on the positive edge of the noise clock
+            if self.outputs[3] {
+                let feedback = if self.noise_control & 0x04 != 0 {
+                    (self.noise_lfsr & NOISE_WHITE_TAPS).count_ones() as u16 & 0x01
+                } else {
+                    self.noise_lfsr & 0x01
+                };
+                self.noise_lfsr = (self.noise_lfsr >> 1) | (feedback << 15);
+            }
+        }
+
+        self.output_sum += self.output();
+        self.output_count += 1;
+    }
+
+    // average output from the previous call, it smooths the high tones
+    pub(crate) fn sample(&mut self) -> i32 {
+        if self.output_count == 0 {
+            return self.output();
+        }
+        let sample = self.output_sum / self.output_count as i32;
+        self.output_sum = 0;
+        self.output_count = 0;
+        sample
+    }
+
+    fn output(&self) -> i32 {
+        let mut output = 0;
+        for channel in 0..4 {
+            let volume = VOLUME_TABLE[self.attenuations[channel] as usize];
+            let high = if channel == 3 {
+                self.noise_lfsr & 0x01 != 0
+            } else {
+                // periods 0 and 1 hold the output high, the games use it for the sample playback
+                self.tone_periods[channel] <= 1 || self.outputs[channel]
+            };
+            output += if high { volume } else { -volume };
+        }
+        output
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        for period in self.tone_periods {
+            writer.write_u16(period);
+        }
+        writer.write_u8(self.noise_control);
+        writer.write_bytes(&self.attenuations);
+        writer.write_u8(self.latched_register);
+
+        for counter in self.counters {
+            writer.write_u16(counter);
+        }
+        for output in self.outputs {
+            writer.write_bool(output);
+        }
+        writer.write_u16(self.noise_lfsr);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for period in self.tone_periods.iter_mut() {
+            *period = reader.read_u16()? & 0x3FF;
+        }
+        self.noise_control = reader.read_u8()? & 0x07;
+        reader.read_bytes(&mut self.attenuations)?;
+        for attenuation in self.attenuations.iter_mut() {
+            *attenuation &= 0x0F;
+        }
+        self.latched_register = reader.read_u8()? & 0x07;
+
+        for counter in self.counters.iter_mut() {
+            *counter = reader.read_u16()? & 0x3FF;
+        }
+        for output in self.outputs.iter_mut() {
+            *output = reader.read_bool()?;
+        }
+        self.noise_lfsr = reader.read_u16()?;
+        self.output_sum = 0;
+        self.output_count = 0;
+        Ok(())
+    }
+}
+
+impl PsgPorts for Sn76489 {
+    fn write(&mut self, data: u8) {
+        debug!("Sn76489: write {:02X}", data);
+        if let Some(vgm_logger) = &self.vgm_logger {
+            vgm_logger.borrow_mut().psg_write(data);
+        }
+        if data & 0x80 != 0 {
+            self.latched_register = (data >> 4) & 0x07;
+        }
+        let channel = (self.latched_register >> 1) as usize;
+        if self.latched_register & 0x01 != 0 {
+            self.attenuations[channel] = data & 0x0F;
+        } else if channel == 3 {
+            self.noise_control = data & 0x07;
+            self.noise_lfsr = NOISE_LFSR_RESET;
+        } else if data & 0x80 != 0 {
+            self.tone_periods[channel] = self.tone_periods[channel] & 0x3F0 | (data & 0x0F) as u16;
+        } else {
+            self.tone_periods[channel] = self.tone_periods[channel] & 0x0F | ((data & 0x3F) as u16) << 4;
+        }
+    }
+}
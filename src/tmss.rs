@@ -0,0 +1,74 @@
+use std::cell::Cell;
+
+use log::warn;
+
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+pub(crate) const TMSS_KEY: u32 = 0xA14000;
+pub(crate) const TMSS_CART_SWAP: u32 = 0xA14100;
+
+// Trademark Security System of the later model 1 consoles: the vdp is locked until "SEGA"
+// is written to 0xA14000, the optional boot rom is mapped over the cartridge until 0xA14101 bit 0 is set
+pub(crate) struct Tmss {
+    bios: Option<Vec<u8>>,
+    key: [u8; 4],
+    cartridge_mapped: bool,
+    vdp_blocked: Cell<bool>, // the game has accessed the locked vdp
+}
+
+impl Tmss {
+    pub(crate) fn new(bios: Option<Vec<u8>>) -> Self {
+        Self {
+            cartridge_mapped: bios.is_none(),
+            bios,
+            key: [0; 4],
+            vdp_blocked: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn vdp_unlocked(&self) -> bool {
+        &self.key == b"SEGA"
+    }
+
+    // the locked vdp does not answer, the real console hangs on the access
+    pub(crate) fn block_vdp_access(&self, address: u32) {
+        if !self.vdp_blocked.replace(true) {
+            warn!("TMSS: the vdp is accessed at {:06X} before the \"SEGA\" write", address);
+        }
+    }
+
+    pub(crate) fn vdp_blocked(&self) -> bool {
+        self.vdp_blocked.get()
+    }
+
+    // the 2 KB boot rom is mirrored over the cartridge area
+    pub(crate) fn read_bios(&self, address: u32) -> Option<u8> {
+        match &self.bios {
+            Some(bios) if !self.cartridge_mapped && !bios.is_empty() => Some(bios[address as usize % bios.len()]),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn bios_mapped(&self) -> bool {
+        !self.cartridge_mapped && self.bios.is_some()
+    }
+
+    pub(crate) fn write(&mut self, address: u32, data: u8) {
+        match address {
+            TMSS_KEY..=0xA14003 => self.key[(address - TMSS_KEY) as usize] = data,
+            0xA14101 => self.cartridge_mapped = data & 0x01 != 0,
+            _ => (),
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.key);
+        writer.write_bool(self.cartridge_mapped);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.key)?;
+        self.cartridge_mapped = reader.read_bool()?;
+        Ok(())
+    }
+}
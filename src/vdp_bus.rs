@@ -2,15 +2,16 @@ use log::debug;
 
 use super::vdp_emu::bus::BusVdp;
 
-use crate::{memory_space::MemorySpace, vdp_emu::vdp_port::VdpPorts, ym2612::Ym2612Ports};
+use crate::{memory_space::MemorySpace, sn76489::PsgPorts, vdp_emu::vdp_port::VdpPorts, ym2612::Ym2612Ports};
 
-impl<T, Y> BusVdp for MemorySpace<T, Y> where T: VdpPorts, Y: Ym2612Ports {
+impl<T, Y, P> BusVdp for MemorySpace<T, Y, P> where T: VdpPorts, Y: Ym2612Ports, P: PsgPorts {
     fn read(&self, address: u32) -> u16 {
         debug!("VDP reads address {:08X}", address);
+        if address < 0x400000 {
+            return u16::from_ne_bytes([self.read_rom(address), self.read_rom(address + 1)]);
+        }
         let data = unsafe {
-            if address < 0x400000 {
-                *(self.rom.as_ptr().offset(address as isize) as *const _ as *const u16)
-            } else if address >= 0xFF0000 {
+            if address >= 0xFF0000 {
                 let address = address & 0xFFFF;
                 *(self.m68k_ram.as_ptr().offset(address as isize) as *const _ as *const u16)
             } else {
@@ -1,5 +1,3 @@
-use spriter::Color;
-
 #[derive(PartialEq, Clone, Copy)]
 pub(crate) enum Priority {
     High,
@@ -7,12 +5,12 @@ pub(crate) enum Priority {
 }
 
 pub(crate) struct Dot {
-    pub(crate) color: Option<Color>,
+    pub(crate) color: Option<u32>,
     pub(crate) priority: Priority,
 }
 
 impl Dot {
-    pub(crate) fn new(color: Option<Color>, priority: Priority) -> Self {
+    pub(crate) fn new(color: Option<u32>, priority: Priority) -> Self {
         Self { color, priority }
     }
 }
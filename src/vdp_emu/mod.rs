@@ -2,6 +2,7 @@ use std::fmt::Display;
 
 pub mod bus;
 pub mod dot;
+mod mode4;
 mod sprite;
 mod tile;
 pub mod vdp_emu;
@@ -16,10 +17,10 @@ pub enum DisplayMod {
 }
 
 impl DisplayMod {
-    fn line_amount(&self) -> u32 {
+    pub(crate) fn lines_per_frame(&self) -> u16 {
         match self {
-            DisplayMod::PAL => 240,
-            DisplayMod::NTSC => 224,
+            DisplayMod::PAL => 313,
+            DisplayMod::NTSC => 262,
         }
     }
 }
@@ -59,6 +60,17 @@ impl RamAccessMode {
             _ => panic!("RamAccessMode: new: unexpected mode mask {:05b}", mask),
         }
     }
+
+    fn mask(&self) -> u16 {
+        match self {
+            RamAccessMode::VramR => 0b0000,
+            RamAccessMode::VramW => 0b0001,
+            RamAccessMode::CramW => 0b0011,
+            RamAccessMode::VSramR => 0b0100,
+            RamAccessMode::VSramW => 0b0101,
+            RamAccessMode::CramR => 0b1000,
+        }
+    }
 }
 
 #[derive(PartialEq)]
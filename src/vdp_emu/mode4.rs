@@ -0,0 +1,388 @@
+use log::debug;
+
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+use super::{vdp_emu::Vdp, DisplayMod};
+
+// the Master System vdp mode: 16 KB of vram, 8 bit ports, 4 bit tiles on the 32x28 name table
+// and 64 sprites of 8 dots width
+pub(crate) const MODE4_CLOCK_DIVIDER: u64 = 5; // a dot takes two serial clocks, 342 dots per line
+const DOTS_PER_LINE: u16 = 342;
+const SCREEN_WIDTH: usize = 256;
+const SCREEN_HEIGHT: usize = 192;
+const VRAM_MASK: u16 = 0x3FFF;
+const NAME_TABLE_ROWS: usize = 28;
+const SPRITES_PER_LINE: usize = 8;
+const SPRITE_LIST_END: u8 = 0xD0;
+// the frame interrupt flag is raised at the start of the line 0xC1
+const FRAME_INTERRUPT_LINE: u16 = 0xC1;
+// the Game Gear lcd shows the middle of the picture
+const GAME_GEAR_VIEWPORT: (usize, usize, usize, usize) = (48, 24, 160, 144); // x, y, width, height
+
+// the control port codes
+const VRAM_READ: u8 = 0;
+const REGISTER_WRITE: u8 = 2;
+const CRAM_WRITE: u8 = 3;
+
+// status bits
+const FRAME_INTERRUPT: u8 = 0x80;
+const SPRITE_OVERFLOW: u8 = 0x40;
+const SPRITE_COLLISION: u8 = 0x20;
+
+pub(crate) struct Mode4 {
+    game_gear: bool,
+    registers: [u8; 11],
+    code: u8,
+    address: u16,
+    first_byte: Option<u8>, // the control word waits for its second byte
+    read_buffer: u8,
+    cram_latch: u8, // the Game Gear color is written by the odd byte
+    status: u8,
+    line_interrupt: bool,
+    line_counter: u8,
+    v_scroll: u8, // latched at the frame start
+}
+
+impl Mode4 {
+    fn new(game_gear: bool) -> Self {
+        Self {
+            game_gear,
+            registers: [0; 11],
+            code: 0,
+            address: 0,
+            first_byte: None,
+            read_buffer: 0,
+            cram_latch: 0,
+            status: 0,
+            line_interrupt: false,
+            line_counter: 0,
+            v_scroll: 0,
+        }
+    }
+
+    fn register_bit(&self, register: usize, bit: u8) -> bool {
+        self.registers[register] & (1 << bit) != 0
+    }
+
+    fn name_table_address(&self) -> usize {
+        (self.registers[2] as usize & 0x0E) << 10
+    }
+
+    fn sprite_table_address(&self) -> usize {
+        (self.registers[5] as usize & 0x7E) << 7
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.register_bit(1, 1) { 16 } else { 8 }
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_u8(self.code);
+        writer.write_u16(self.address);
+        writer.write_bool(self.first_byte.is_some());
+        writer.write_u8(self.first_byte.unwrap_or(0));
+        writer.write_u8(self.read_buffer);
+        writer.write_u8(self.cram_latch);
+        writer.write_u8(self.status);
+        writer.write_bool(self.line_interrupt);
+        writer.write_u8(self.line_counter);
+        writer.write_u8(self.v_scroll);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.registers)?;
+        self.code = reader.read_u8()? & 0x03;
+        self.address = reader.read_u16()? & VRAM_MASK;
+        let first_byte_pending = reader.read_bool()?;
+        let first_byte = reader.read_u8()?;
+        self.first_byte = first_byte_pending.then_some(first_byte);
+        self.read_buffer = reader.read_u8()?;
+        self.cram_latch = reader.read_u8()?;
+        self.status = reader.read_u8()? & (FRAME_INTERRUPT | SPRITE_OVERFLOW | SPRITE_COLLISION);
+        self.line_interrupt = reader.read_bool()?;
+        self.line_counter = reader.read_u8()?;
+        self.v_scroll = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Vdp {
+    // switches the vdp to the Master System or the Game Gear mode
+    pub(crate) fn enable_mode4(&mut self, game_gear: bool) {
+        self.mode4 = Some(Mode4::new(game_gear));
+        self.v_counter = 0;
+        self.h_counter = 0;
+        self.line_clock = 0;
+    }
+
+    pub(crate) fn mode4_screen_size(&self) -> Option<(usize, usize)> {
+        let mode4 = self.mode4.as_ref()?;
+        if mode4.game_gear {
+            Some((GAME_GEAR_VIEWPORT.2, GAME_GEAR_VIEWPORT.3))
+        } else {
+            Some((SCREEN_WIDTH, SCREEN_HEIGHT))
+        }
+    }
+
+    // the interrupt line of the z80, it is held until the status is read
+    pub(crate) fn mode4_interrupt(&self) -> bool {
+        self.mode4.as_ref().is_some_and(|mode4| {
+            (mode4.status & FRAME_INTERRUPT != 0 && mode4.register_bit(1, 5))
+                || (mode4.line_interrupt && mode4.register_bit(0, 4))
+        })
+    }
+
+    pub(crate) fn mode4_read_data(&mut self) -> u8 {
+        let mode4 = self.mode4.as_mut().unwrap();
+        mode4.first_byte = None;
+        let data = mode4.read_buffer;
+        mode4.read_buffer = self.vram[mode4.address as usize];
+        mode4.address = (mode4.address + 1) & VRAM_MASK;
+        data
+    }
+
+    pub(crate) fn mode4_write_data(&mut self, data: u8) {
+        let mode4 = self.mode4.as_mut().unwrap();
+        mode4.first_byte = None;
+        if mode4.code == CRAM_WRITE {
+            if mode4.game_gear {
+                let address = mode4.address as usize & 0x3F;
+                if address & 0x01 == 0 {
+                    mode4.cram_latch = data;
+                } else {
+                    self.cram[address - 1] = mode4.cram_latch;
+                    self.cram[address] = data;
+                }
+            } else {
+                self.cram[mode4.address as usize & 0x1F] = data;
+            }
+        } else {
+            self.vram[mode4.address as usize] = data;
+        }
+        mode4.read_buffer = data;
+        mode4.address = (mode4.address + 1) & VRAM_MASK;
+    }
+
+    // the status read clears the interrupt and the sprite flags
+    pub(crate) fn mode4_read_control(&mut self) -> u8 {
+        let mode4 = self.mode4.as_mut().unwrap();
+        mode4.first_byte = None;
+        mode4.line_interrupt = false;
+        std::mem::take(&mut mode4.status)
+    }
+
+    pub(crate) fn mode4_write_control(&mut self, data: u8) {
+        let mode4 = self.mode4.as_mut().unwrap();
+        let Some(first_byte) = mode4.first_byte.take() else {
+            mode4.first_byte = Some(data);
+            mode4.address = mode4.address & 0x3F00 | data as u16;
+            return;
+        };
+        mode4.code = data >> 6;
+        mode4.address = (data as u16 & 0x3F) << 8 | first_byte as u16;
+        match mode4.code {
+            VRAM_READ => {
+                mode4.read_buffer = self.vram[mode4.address as usize];
+                mode4.address = (mode4.address + 1) & VRAM_MASK;
+            }
+            REGISTER_WRITE => {
+                let register = (data & 0x0F) as usize;
+                if register < mode4.registers.len() {
+                    mode4.registers[register] = first_byte;
+                    debug!("VDP: set mode 4 register {:02X} to value {:02X}", register, first_byte);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // the counter jumps back after 0xDA (NTSC) or 0xF2 (PAL), so it fits a byte
+    pub(crate) fn mode4_v_counter(&self) -> u8 {
+        let line = self.v_counter;
+        let value = match self.display_mod {
+            DisplayMod::NTSC if line > 0xDA => line - 6,
+            DisplayMod::PAL if line > 0xF2 => line - 57,
+            _ => line,
+        };
+        value as u8
+    }
+
+    // the counter of the dot pairs jumps from 0x93 to 0xE9
+    pub(crate) fn mode4_h_counter(&self) -> u8 {
+        let value = self.h_counter / 2;
+        if value > 0x93 { (value + 0x55) as u8 } else { value as u8 }
+    }
+
+    pub(crate) fn clock_mode4(&mut self) -> bool {
+        let mut frame_done = false;
+        if self.line_clock == 0 && (self.v_counter as usize) < SCREEN_HEIGHT {
+            self.render_mode4_line();
+        }
+        // the line counter runs at the end of the active part of the line
+        if self.line_clock == SCREEN_WIDTH as u16 * 2 {
+            let mode4 = self.mode4.as_mut().unwrap();
+            if self.v_counter as usize <= SCREEN_HEIGHT {
+                if mode4.line_counter == 0 {
+                    mode4.line_counter = mode4.registers[10];
+                    mode4.line_interrupt = true;
+                } else {
+                    mode4.line_counter -= 1;
+                }
+            } else {
+                mode4.line_counter = mode4.registers[10];
+            }
+        }
+
+        self.line_clock += 1;
+        self.h_counter = self.line_clock / 2;
+        if self.line_clock >= DOTS_PER_LINE * 2 {
+            self.line_clock = 0;
+            self.h_counter = 0;
+            self.v_counter += 1;
+            let mode4 = self.mode4.as_mut().unwrap();
+            if self.v_counter as usize == SCREEN_HEIGHT {
+                frame_done = true;
+            } else if self.v_counter == FRAME_INTERRUPT_LINE {
+                mode4.status |= FRAME_INTERRUPT;
+            } else if self.v_counter >= self.display_mod.lines_per_frame() {
+                self.v_counter = 0;
+                mode4.v_scroll = mode4.registers[9];
+            }
+        }
+        frame_done
+    }
+
+    fn render_mode4_line(&mut self) {
+        let mode4 = self.mode4.as_ref().unwrap();
+        let line = self.v_counter as usize;
+        let backdrop_color = self.mode4_color(16 + (mode4.registers[7] & 0x0F) as usize);
+        let mut dots = [(backdrop_color, true); SCREEN_WIDTH];
+        if mode4.register_bit(1, 6) {
+            let sprites = self.mode4_sprite_line(line);
+            let mode4 = self.mode4.as_ref().unwrap();
+            let h_scroll = if mode4.register_bit(0, 6) && line < 16 { 0 } else { mode4.registers[8] };
+            for (x, dot) in dots.iter_mut().enumerate() {
+                if mode4.register_bit(0, 5) && x < 8 {
+                    continue;
+                }
+                let v_scroll = if mode4.register_bit(0, 7) && x >= 192 { 0 } else { mode4.v_scroll };
+                let plane_x = (x as u8).wrapping_sub(h_scroll) as usize;
+                let plane_y = (line + v_scroll as usize) % (NAME_TABLE_ROWS * 8);
+                let entry_address = mode4.name_table_address() + (plane_y / 8 * 32 + plane_x / 8) * 2;
+                let entry = u16::from_le_bytes([self.vram[entry_address], self.vram[entry_address + 1]]);
+                let row = if entry & 0x0400 != 0 { 7 - plane_y % 8 } else { plane_y % 8 };
+                let column = if entry & 0x0200 != 0 { 7 - plane_x % 8 } else { plane_x % 8 };
+                let color_id = self.mode4_pattern_dot(entry as usize & 0x1FF, row, column);
+                let palette = if entry & 0x0800 != 0 { 16 } else { 0 };
+                let tile_priority = entry & 0x1000 != 0 && color_id != 0;
+                *dot = match sprites[x] {
+                    Some(sprite_color_id) if !tile_priority => (self.mode4_color(16 + sprite_color_id as usize), false),
+                    _ => (self.mode4_color(palette + color_id as usize), color_id == 0),
+                };
+            }
+        }
+
+        let mode4 = self.mode4.as_ref().unwrap();
+        let (x, width, row) = if mode4.game_gear {
+            let (x, y, width, height) = GAME_GEAR_VIEWPORT;
+            if line < y || line >= y + height {
+                return;
+            }
+            (x, width, line - y)
+        } else {
+            (0, SCREEN_WIDTH, line)
+        };
+        for (index, (color, backdrop)) in dots[x..x + width].iter().enumerate() {
+            self.screen[row * width + index] = *color;
+            self.backdrop[row * width + index] = *backdrop;
+        }
+    }
+
+    // the sprite color ids of the line, the first sprite in the table is on top
+    fn mode4_sprite_line(&mut self, line: usize) -> [Option<u8>; SCREEN_WIDTH] {
+        let mode4 = self.mode4.as_ref().unwrap();
+        let table = mode4.sprite_table_address();
+        let zoom = if mode4.register_bit(1, 0) { 2 } else { 1 };
+        let height = mode4.sprite_height() * zoom;
+        let shift = if mode4.register_bit(0, 3) { 8 } else { 0 };
+        let pattern_base = if mode4.register_bit(6, 2) { 0x100 } else { 0 };
+        let mut dots = [None; SCREEN_WIDTH];
+        let mut status = 0;
+        let mut count = 0;
+        for sprite in 0..64 {
+            let y = self.vram[table + sprite];
+            if y == SPRITE_LIST_END {
+                break;
+            }
+            let sprite_line = (line as u8).wrapping_sub(y.wrapping_add(1)) as u16;
+            if sprite_line >= height {
+                continue;
+            }
+            if count == SPRITES_PER_LINE {
+                status |= SPRITE_OVERFLOW;
+                break;
+            }
+            count += 1;
+            let x = self.vram[table + 0x80 + sprite * 2] as i32 - shift;
+            let mut pattern = pattern_base | self.vram[table + 0x81 + sprite * 2] as usize;
+            let mut row = (sprite_line / zoom) as usize;
+            if mode4.sprite_height() == 16 {
+                pattern = (pattern & !1) + row / 8;
+                row %= 8;
+            }
+            for column in 0..8 * zoom as i32 {
+                let screen_x = x + column;
+                if !(0..SCREEN_WIDTH as i32).contains(&screen_x) {
+                    continue;
+                }
+                let color_id = self.mode4_pattern_dot(pattern, row, (column / zoom as i32) as usize);
+                if color_id == 0 {
+                    continue;
+                }
+                match dots[screen_x as usize] {
+                    Some(_) => status |= SPRITE_COLLISION,
+                    None => dots[screen_x as usize] = Some(color_id),
+                }
+            }
+        }
+        self.mode4.as_mut().unwrap().status |= status;
+        dots
+    }
+
+    // the color id of the dot, the pattern keeps the four bit planes of each row one after another
+    fn mode4_pattern_dot(&self, pattern: usize, row: usize, column: usize) -> u8 {
+        let address = pattern * 32 + row * 4;
+        (0..4).fold(0, |color_id, plane| {
+            color_id | ((self.vram[address + plane] >> (7 - column)) & 0x01) << plane
+        })
+    }
+
+    // 6 bit colors of the Master System and 12 bit colors of the Game Gear
+    fn mode4_color(&self, index: usize) -> u32 {
+        let game_gear = self.mode4.as_ref().unwrap().game_gear;
+        let (r, g, b) = if game_gear {
+            let (low, high) = (self.cram[index * 2], self.cram[index * 2 + 1]);
+            let component = |value: u8| (value & 0x0F) as u32 * 0x11;
+            (component(low), component(low >> 4), component(high))
+        } else {
+            let color = self.cram[index];
+            let component = |value: u8| (value & 0x03) as u32 * 0x55;
+            (component(color), component(color >> 2), component(color >> 4))
+        };
+        r << 16 | g << 8 | b
+    }
+
+    pub(crate) fn save_mode4_state(&self, writer: &mut StateWriter) {
+        if let Some(mode4) = &self.mode4 {
+            mode4.save_state(writer);
+        }
+    }
+
+    pub(crate) fn load_mode4_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        if let Some(mode4) = self.mode4.as_mut() {
+            mode4.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
@@ -54,6 +54,15 @@ pub(crate) enum HCellMode {
     H40Cell = 40,
 }
 
+impl HCellMode {
+    pub(crate) fn serial_clock_divider(&self) -> u64 {
+        match self {
+            HCellMode::H32Cell => 5,
+            HCellMode::H40Cell => 4,
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Copy)]
 pub(crate) enum VCellMode {
     V30Cell = 30,
@@ -496,6 +505,10 @@ impl Status {
         self.data
     }
 
+    pub(crate) fn write(&mut self, data: u16) {
+        self.data = data;
+    }
+
     pub(crate) fn set_flag(&mut self, flag: StatusFlag, set: bool) {
         if set {
             self.data |= 1 << flag as u16;
@@ -573,4 +586,8 @@ impl RegisterSet {
     pub(crate) fn set_register_by_id(&mut self, reg_id: usize, data: u8) {
         self.raw_registers[reg_id] = data;
     }
+
+    pub(crate) fn raw_registers(&self) -> &[u8; 24] {
+        &self.raw_registers
+    }
 }
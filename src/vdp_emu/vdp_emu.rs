@@ -1,14 +1,17 @@
 use std::{cell::RefCell, rc::Rc};
 
-use spriter::{window::Window, Canvas, Color};
-
 use log::debug;
 
-use crate::{signal_bus::{Signal, SignalBus}, vdp_emu::{registers::{HCellMode, VCellMode}, DisplayMod}};
+use crate::{
+    save_state::{StateError, StateReader, StateWriter},
+    signal_bus::{Signal, SignalBus},
+    vdp_emu::{registers::{HCellMode, VCellMode}, DisplayMod},
+};
 
 use super::{
     bus::BusVdp,
     dot::{Dot, Priority},
+    mode4::{Mode4, MODE4_CLOCK_DIVIDER},
     registers::{
         HScrollMode, RegisterSet, StatusFlag, VScrollMode, WindowHPostion, WindowVPosition,
     },
@@ -17,8 +20,16 @@ use super::{
     DmaMode, RamAccessMode,
 };
 
+pub const VRAM_TABLE_WIDTH: usize = 256;
+pub const VRAM_TABLE_HEIGHT: usize = 512;
+
+const MASTER_CLOCKS_PER_LINE: u16 = 3420;
+
+const MAX_SCREEN_WIDTH: usize = 320;
+const MAX_SCREEN_HEIGHT: usize = 240;
+const SCREEN_CLEAR_COLOR: u32 = 0xAAAAAA;
+
 pub struct Vdp {
-    pub(crate) vram_table: Canvas,
     pub(crate) register_set: RegisterSet,
 
     pub(crate) vram: [u8; 0x10000],
@@ -27,6 +38,8 @@ pub struct Vdp {
 
     pub(crate) v_counter: u16,
     pub(crate) h_counter: u16,
+    pub(crate) line_clock: u16,
+    hinterrupt_line_counter: u8,
 
     pub(crate) dma_mode: Option<DmaMode>,
     pub(crate) dma_run: bool,
@@ -46,25 +59,23 @@ pub struct Vdp {
     pub(crate) dma_src_address: u32,
     pub(crate) dma_length: u16,
 
-    screen: Canvas,
+    pub(crate) display_mod: DisplayMod,
+    pub(crate) screen: Vec<u32>, // RGB888 dots, row stride is the current screen width
+    pub(crate) backdrop: Vec<bool>, // the dots of the background color, the 32X image covers them
     sprites: Vec<Sprite>,
     v_mode: VCellMode,
     h_mode: HCellMode,
+    pub(crate) mode4: Option<Mode4>, // the Master System mode replaces the Mega Drive one
 }
 
 impl Vdp{
-    pub fn new(window: &mut Window, signal_bus: Rc<RefCell<SignalBus>>, display_mod: DisplayMod) -> Self {
-        let height = display_mod.line_amount();
-        let mut screen = window.create_canvas(0, 0, 640, height * 2, 320, height);
-        screen.set_clear_color(Color::from_u32(0xAAAAAA));
-        screen.clear();
-        let mut vram_table = window.create_canvas(660, 0, 512, 1024, 256, 512);
-        vram_table.set_clear_color(Color::from_u32(0xAAAACC));
-        vram_table.clear();
+    pub fn new(signal_bus: Rc<RefCell<SignalBus>>, display_mod: DisplayMod) -> Self {
+        let screen = vec![SCREEN_CLEAR_COLOR; MAX_SCREEN_WIDTH * MAX_SCREEN_HEIGHT];
         let register_set = RegisterSet::new(display_mod);
         Self {
+            display_mod,
             screen,
-            vram_table,
+            backdrop: vec![true; MAX_SCREEN_WIDTH * MAX_SCREEN_HEIGHT],
 
             register_set,
 
@@ -74,6 +85,8 @@ impl Vdp{
 
             v_counter: 0,
             h_counter: 0,
+            line_clock: 0,
+            hinterrupt_line_counter: 0,
 
             dma_mode: None,
             dma_run: false,
@@ -96,6 +109,7 @@ impl Vdp{
             sprites: vec![],
             v_mode: VCellMode::V30Cell,
             h_mode: HCellMode::H40Cell,
+            mode4: None,
         }
     }
 
@@ -103,110 +117,236 @@ impl Vdp{
         self.bus = Some(bus);
     }
 
+    // the frame buffer and the sprites of the current line are not saved, they are rendered again
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.cram);
+        writer.write_bytes(&self.vsram);
+        writer.write_bytes(self.register_set.raw_registers());
+        writer.write_u16(self.register_set.status.read());
+
+        writer.write_u16(self.v_counter);
+        writer.write_u16(self.h_counter);
+        writer.write_u16(self.line_clock);
+        writer.write_u8(self.hinterrupt_line_counter);
+
+        writer.write_u8(match self.dma_mode {
+            None => 0,
+            Some(DmaMode::BusToRam) => 1,
+            Some(DmaMode::CopyRam) => 2,
+            Some(DmaMode::FillRam) => 3,
+        });
+        writer.write_bool(self.dma_run);
+        writer.write_bool(self.dma_data_wait);
+        writer.write_u32(self.dma_src_address);
+        writer.write_u16(self.dma_length);
+
+        writer.write_u16(self.ram_access_mode.mask());
+        writer.write_u32(self.vdp_ram_address);
+        writer.write_u16(self.data_port_reg);
+        writer.write_u32(self.address_setting_raw_word);
+        writer.write_bool(self.address_setting_latch);
+
+        writer.write_u8(self.v_mode as u8);
+        writer.write_u8(self.h_mode as u8);
+        self.save_mode4_state(writer);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        reader.read_bytes(&mut self.vram)?;
+        reader.read_bytes(&mut self.cram)?;
+        reader.read_bytes(&mut self.vsram)?;
+        let mut raw_registers = [0; 24];
+        reader.read_bytes(&mut raw_registers)?;
+        for (reg_id, data) in raw_registers.into_iter().enumerate() {
+            self.register_set.set_register_by_id(reg_id, data);
+        }
+        self.register_set.status.write(reader.read_u16()?);
+
+        self.v_counter = reader.read_u16()?;
+        self.h_counter = reader.read_u16()?;
+        self.line_clock = reader.read_u16()?;
+        self.hinterrupt_line_counter = reader.read_u8()?;
+
+        self.dma_mode = match reader.read_u8()? {
+            0 => None,
+            1 => Some(DmaMode::BusToRam),
+            2 => Some(DmaMode::CopyRam),
+            3 => Some(DmaMode::FillRam),
+            _ => return Err(StateError::InvalidValue("vdp dma mode")),
+        };
+        self.dma_run = reader.read_bool()?;
+        self.dma_data_wait = reader.read_bool()?;
+        self.dma_src_address = reader.read_u32()?;
+        self.dma_length = reader.read_u16()?;
+
+        self.ram_access_mode = match reader.read_u16()? {
+            mask @ (0b0000 | 0b0001 | 0b0011 | 0b0100 | 0b0101 | 0b1000) => RamAccessMode::new(mask),
+            _ => return Err(StateError::InvalidValue("vdp ram access mode")),
+        };
+        self.vdp_ram_address = reader.read_u32()?;
+        self.data_port_reg = reader.read_u16()?;
+        self.address_setting_raw_word = reader.read_u32()?;
+        self.address_setting_latch = reader.read_bool()?;
+
+        self.v_mode = match reader.read_u8()? {
+            28 => VCellMode::V28Cell,
+            30 => VCellMode::V30Cell,
+            _ => return Err(StateError::InvalidValue("vdp vertical cell mode")),
+        };
+        self.h_mode = match reader.read_u8()? {
+            32 => HCellMode::H32Cell,
+            40 => HCellMode::H40Cell,
+            _ => return Err(StateError::InvalidValue("vdp horizontal cell mode")),
+        };
+        self.sprites.clear();
+        self.load_mode4_state(reader)
+    }
+
+    // one vdp serial clock, each dot takes two of them
+    // returns true when the last visible line is rendered
     pub fn clock(&mut self) -> bool {
-        let mut update_screen = false;
-        if let Some(_) = self.dma_mode.as_ref() {
+        if self.mode4.is_some() {
+            return self.clock_mode4();
+        }
+        let mut frame_done = false;
+        if self.dma_mode.is_some() {
             self.dma_clock();
         }
 
-        if self.v_counter < 0xE0 {
-            let bg_palette_id = self.register_set.background_color.palette_id();
-            let bg_color_id = self.register_set.background_color.color_id();
-            let back_dot_color = self.get_color(bg_palette_id, bg_color_id);
+        let (screen_width, screen_height) = self.screen_size();
+        let h_active = (self.h_counter as usize) < screen_width;
+        if self.line_clock.is_multiple_of(2) && h_active && (self.v_counter as usize) < screen_height {
+            let (dot, backdrop) = self.render_dot();
+            let index = self.v_counter as usize * screen_width + self.h_counter as usize;
+            self.screen[index] = dot;
+            self.backdrop[index] = backdrop;
+        }
+        self.register_set
+            .status
+            .set_flag(StatusFlag::HBlanking, !h_active);
+
+        self.line_clock += 1;
+        self.h_counter = self.line_clock / 2;
+        if self.line_clock >= MASTER_CLOCKS_PER_LINE / self.clock_divider() as u16 {
+            self.line_clock = 0;
+            self.h_counter = 0;
+            frame_done = self.next_line();
+        }
+        frame_done
+    }
+
+    // master clocks per single vdp serial clock
+    pub(crate) fn clock_divider(&self) -> u64 {
+        if self.mode4.is_some() {
+            return MODE4_CLOCK_DIVIDER;
+        }
+        self.h_mode.serial_clock_divider()
+    }
+
+    // the color of the dot and true if it is the background color
+    fn render_dot(&mut self) -> (u32, bool) {
+        let bg_palette_id = self.register_set.background_color.palette_id();
+        let bg_color_id = self.register_set.background_color.color_id();
+        let back_dot_color = self.get_color(bg_palette_id, bg_color_id);
 
-            let sprite_dot = self.get_sprite_dot();
+        let sprite_dot = self.get_sprite_dot();
 
-            let plane_a_base_address = self.register_set.plane_a_table_location.address();
-            let plane_a_dot = self.get_plane_dot(plane_a_base_address, 0);
+        let plane_a_base_address = self.register_set.plane_a_table_location.address();
+        let plane_a_dot = self.get_plane_dot(plane_a_base_address, 0);
 
-            let plane_b_base_address = self.register_set.plane_b_table_location.address();
-            let plane_b_dot = self.get_plane_dot(plane_b_base_address, 2);
+        let plane_b_base_address = self.register_set.plane_b_table_location.address();
+        let plane_b_dot = self.get_plane_dot(plane_b_base_address, 2);
 
-            let window_dot = self.get_window_dot();
+        let window_dot = self.get_window_dot();
 
-            let dot = if self.register_set.mode_register.display_enabled() {
-                let mut color = sprite_dot
-                    .color
-                    .or_else(|| window_dot.color)
-                    .or_else(|| plane_a_dot.color)
-                    .or_else(|| plane_b_dot.color)
-                    .unwrap_or(back_dot_color);
-                if let Some(plane_color) = plane_b_dot.color {
-                    if plane_b_dot.priority == Priority::High {
-                        color = plane_color;
-                    }
-                }
-                if let Some(plane_color) = plane_a_dot.color {
-                    if plane_a_dot.priority == Priority::High {
-                        color = window_dot.color.unwrap_or(plane_color);
-                    }
-                }
-                if let Some(sprite_color) = sprite_dot.color {
-                    if sprite_dot.priority == Priority::High {
-                        color = sprite_color;
-                    }
+        if self.register_set.mode_register.display_enabled() {
+            let mut color = sprite_dot
+                .color
+                .or(window_dot.color)
+                .or(plane_a_dot.color)
+                .or(plane_b_dot.color)
+                .unwrap_or(back_dot_color);
+            if let Some(plane_color) = plane_b_dot.color {
+                if plane_b_dot.priority == Priority::High {
+                    color = plane_color;
                 }
-                color
-            } else {
-                back_dot_color
-            };
-            self.screen
-                .set_pixel(self.h_counter as i32, self.v_counter as i32, dot)
-                .unwrap();
-            self.h_counter += 1;
-            if self.h_counter >= self.h_mode as u16 * 8 {
-                self.h_counter = 0;
-                self.v_counter += 1;
-
-                self.collect_sprites();
             }
-            if self.v_counter == 0xE0 {
-                let v_mode = self.register_set.mode_register.vcell_mode();
-                let h_mode = self.register_set.mode_register.hcell_mode();
-                if self.v_mode != v_mode || self.h_mode != h_mode {
-                    self.screen.resize_texture(h_mode as u32 * 8, v_mode as u32 * 8);
-                    self.v_mode = v_mode;
-                    self.h_mode = h_mode;
+            if let Some(plane_color) = plane_a_dot.color {
+                if plane_a_dot.priority == Priority::High {
+                    color = window_dot.color.unwrap_or(plane_color);
                 }
-                update_screen = true;
-                self.update_vram_table_on_screen();
-
-                if self.register_set.mode_register.vinterrupt_enabled() {
-                    self.signal_bus
-                        .borrow_mut()
-                        .push_signal(Signal::VInterrupt);
-                    self.signal_bus
-                        .borrow_mut()
-                        .push_signal(Signal::Z80INT);
-                    self.register_set
-                        .status
-                        .set_flag(StatusFlag::VInterruptPending, true);
-                    debug!("VDP: send vinterrupt signtal");
+            }
+            if let Some(sprite_color) = sprite_dot.color {
+                if sprite_dot.priority == Priority::High {
+                    color = sprite_color;
                 }
-                self.register_set
-                    .status
-                    .set_flag(StatusFlag::Blanking, true);
             }
+            let backdrop = sprite_dot.color.is_none()
+                && window_dot.color.is_none()
+                && plane_a_dot.color.is_none()
+                && plane_b_dot.color.is_none();
+            (color, backdrop)
         } else {
-            self.h_counter += 1;
-            if self.h_counter >= self.h_mode as u16 * 8 {
-                let hinterrupt_counter = self.register_set.hinterrupt_counter.hinterrupt_counter() as u16;
-                if self.register_set.mode_register.hinterrupt_enabled() && self.v_counter % hinterrupt_counter == 0 {
+            (back_dot_color, true)
+        }
+    }
+
+    fn next_line(&mut self) -> bool {
+        let mut frame_done = false;
+        let screen_height = self.v_mode as u16 * 8;
+        // the hinterrupt counter runs through the visible lines and the first line of the vblank
+        if self.v_counter <= screen_height {
+            if self.hinterrupt_line_counter == 0 {
+                self.hinterrupt_line_counter =
+                    self.register_set.hinterrupt_counter.hinterrupt_counter();
+                if self.register_set.mode_register.hinterrupt_enabled() {
                     self.signal_bus
                         .borrow_mut()
                         .push_signal(Signal::HInterrupt);
                 }
-                self.h_counter = 0;
-                self.v_counter += 1;
+            } else {
+                self.hinterrupt_line_counter -= 1;
+            }
+        } else {
+            self.hinterrupt_line_counter = self.register_set.hinterrupt_counter.hinterrupt_counter();
+        }
+
+        self.v_counter += 1;
+        if self.v_counter == screen_height {
+            let v_mode = self.register_set.mode_register.vcell_mode();
+            let h_mode = self.register_set.mode_register.hcell_mode();
+            if self.v_mode != v_mode || self.h_mode != h_mode {
+                self.v_mode = v_mode;
+                self.h_mode = h_mode;
             }
-            if self.v_counter == 0x1FF {
-                self.v_counter = 0;
+            frame_done = true;
+
+            if self.register_set.mode_register.vinterrupt_enabled() {
+                self.signal_bus
+                    .borrow_mut()
+                    .push_signal(Signal::VInterrupt);
+                self.signal_bus
+                    .borrow_mut()
+                    .push_signal(Signal::Z80INT);
                 self.register_set
                     .status
-                    .set_flag(StatusFlag::Blanking, false);
+                    .set_flag(StatusFlag::VInterruptPending, true);
+                debug!("VDP: send vinterrupt signtal");
             }
+            self.register_set
+                .status
+                .set_flag(StatusFlag::Blanking, true);
+        } else if self.v_counter >= self.display_mod.lines_per_frame() {
+            self.v_counter = 0;
+            self.register_set
+                .status
+                .set_flag(StatusFlag::Blanking, false);
+        }
+        if self.v_counter < screen_height {
+            self.collect_sprites();
         }
-        update_screen
+        frame_done
     }
 
     fn dma_clock(&mut self) {
@@ -499,7 +639,7 @@ impl Vdp{
         Dot::default()
     }
 
-    fn get_color(&self, palette_id: usize, color_id: usize) -> Color {
+    fn get_color(&self, palette_id: usize, color_id: usize) -> u32 {
         let converter = |b: u16| -> u32 {
             match b {
                 0x0 => 0x00u32,
@@ -519,11 +659,35 @@ impl Vdp{
         let r = converter(raw_color & 0xF);
         let g = converter(raw_color >> 4 & 0xF);
         let b = converter(raw_color >> 8 & 0xF);
-        let color_code = r << 16 | g << 8 | b;
-        Color::from_u32(color_code)
+        r << 16 | g << 8 | b
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        if let Some(screen_size) = self.mode4_screen_size() {
+            return screen_size;
+        }
+        (self.h_mode as usize * 8, self.v_mode as usize * 8)
+    }
+
+    // last rendered frame as RGB888 dots, row by row
+    pub fn frame_buffer(&self) -> &[u32] {
+        let (width, height) = self.screen_size();
+        &self.screen[..width * height]
+    }
+
+    // draws an add-on image over the rendered frame line by line, the layer gets the line number,
+    // its dots and the background mask of them
+    pub(crate) fn overlay(&mut self, mut layer: impl FnMut(usize, &mut [u32], &[bool])) {
+        let (width, height) = self.screen_size();
+        let lines = self.screen[..width * height].chunks_mut(width);
+        for (line, (dots, backdrop)) in lines.zip(self.backdrop.chunks(width)).enumerate() {
+            layer(line, dots, backdrop);
+        }
     }
 
-    pub fn update_vram_table_on_screen(&mut self) {
+    // all 2048 vram tiles drawn with the first palette, 32 tiles per row (256x512 dots)
+    pub fn vram_table(&self) -> Vec<u32> {
+        let mut vram_table = vec![0; VRAM_TABLE_WIDTH * VRAM_TABLE_HEIGHT];
         let palette_id = 0;
         for tile_idx in 0..2048 {
             for byte_idx in 0..32 {
@@ -534,12 +698,11 @@ impl Vdp{
                     let y = (tile_idx / 32) * 8 + byte_idx / 4;
                     let color_byte = (data_byte >> (4 * pixel_num)) & 0xF;
                     let color = self.get_color(palette_id, color_byte.into());
-                    self.vram_table
-                        .set_pixel(x as i32, y as i32, color)
-                        .unwrap();
+                    vram_table[y * VRAM_TABLE_WIDTH + x] = color;
                 }
             }
         }
+        vram_table
     }
 
     fn get_tile_dot_byte(&self, tile_dot: TileDot) -> u8 {
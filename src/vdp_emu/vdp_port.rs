@@ -96,7 +96,7 @@ impl VdpPorts for Vdp{
     }
 
     fn read_hv_counters_port(&mut self) -> Result<u32, ()> {
-        Ok((self.v_counter << 8 | self.h_counter & 0xFF) as u32)
+        Ok(((self.v_counter & 0xFF) << 8 | (self.h_counter >> 1) & 0xFF) as u32)
     }
 }
 
@@ -0,0 +1,23 @@
+pub(crate) mod vgm_logger;
+pub mod vgm_player;
+
+// VGM time unit
+pub(crate) const VGM_SAMPLE_RATE: u64 = 44_100;
+
+pub(crate) const VGM_IDENT: &[u8; 4] = b"Vgm ";
+pub(crate) const VGM_VERSION: u32 = 0x150;
+pub(crate) const VGM_HEADER_SIZE: usize = 0x40;
+
+pub(crate) const CMD_PSG_WRITE: u8 = 0x50;
+pub(crate) const CMD_YM2612_PORT0_WRITE: u8 = 0x52;
+pub(crate) const CMD_YM2612_PORT1_WRITE: u8 = 0x53;
+pub(crate) const CMD_WAIT: u8 = 0x61;
+pub(crate) const CMD_WAIT_NTSC_FRAME: u8 = 0x62; // 735 samples
+pub(crate) const CMD_WAIT_PAL_FRAME: u8 = 0x63; // 882 samples
+pub(crate) const CMD_END: u8 = 0x66;
+pub(crate) const CMD_DATA_BLOCK: u8 = 0x67;
+pub(crate) const CMD_WAIT_SHORT: u8 = 0x70; // 0x7n - wait n + 1 samples
+pub(crate) const CMD_YM2612_DAC_WAIT: u8 = 0x80; // 0x8n - write the next data bank byte to 0x2A and wait n samples
+pub(crate) const CMD_DATA_BANK_SEEK: u8 = 0xE0;
+
+pub(crate) const DATA_BLOCK_YM2612_PCM: u8 = 0x00;
@@ -0,0 +1,160 @@
+use crate::ym2612::RegisterPart;
+
+use super::{
+    CMD_DATA_BANK_SEEK, CMD_DATA_BLOCK, CMD_END, CMD_PSG_WRITE, CMD_WAIT, CMD_WAIT_NTSC_FRAME,
+    CMD_WAIT_PAL_FRAME, CMD_WAIT_SHORT, CMD_YM2612_DAC_WAIT, CMD_YM2612_PORT0_WRITE,
+    CMD_YM2612_PORT1_WRITE, DATA_BLOCK_YM2612_PCM, VGM_HEADER_SIZE, VGM_IDENT, VGM_SAMPLE_RATE,
+    VGM_VERSION,
+};
+
+const DAC_REGISTER: u8 = 0x2A;
+
+// records the sound chips writes, the waits are taken from the master clock timestamps of the writes
+// the DAC bytes are collected into a PCM data block and played back by the 0x8n commands
+pub(crate) struct VgmLogger {
+    master_clock: u64,
+    frame_rate: u32,
+    psg_clock: u32,
+    ym2612_clock: u32,
+
+    start_cycle: u64,
+    cycle: u64,   // master clock timestamp of the next write
+    samples: u64, // vgm samples written by the wait commands
+
+    commands: Vec<u8>,
+    pcm_data: Vec<u8>,
+    dac_command: Option<usize>, // position of the last command if it is a DAC write
+}
+
+impl VgmLogger {
+    pub(crate) fn new(
+        master_clock: u64,
+        frame_rate: u32,
+        psg_clock: u32,
+        ym2612_clock: u32,
+        start_cycle: u64,
+    ) -> Self {
+        Self {
+            master_clock,
+            frame_rate,
+            psg_clock,
+            ym2612_clock,
+
+            start_cycle,
+            cycle: start_cycle,
+            samples: 0,
+
+            commands: vec![],
+            pcm_data: vec![],
+            dac_command: None,
+        }
+    }
+
+    pub(crate) fn set_cycle(&mut self, cycle: u64) {
+        self.cycle = cycle;
+    }
+
+    pub(crate) fn ym2612_write(&mut self, part: RegisterPart, register: u8, data: u8) {
+        self.sync();
+        match part {
+            RegisterPart::Fm1 if register == DAC_REGISTER => {
+                if self.pcm_data.is_empty() {
+                    self.commands.push(CMD_DATA_BANK_SEEK);
+                    self.commands.extend_from_slice(&0u32.to_le_bytes());
+                }
+                self.pcm_data.push(data);
+                self.commands.push(CMD_YM2612_DAC_WAIT);
+                self.dac_command = Some(self.commands.len() - 1);
+                return;
+            }
+            RegisterPart::Fm1 => self.commands.push(CMD_YM2612_PORT0_WRITE),
+            RegisterPart::Fm2 => self.commands.push(CMD_YM2612_PORT1_WRITE),
+        }
+        self.commands.push(register);
+        self.commands.push(data);
+        self.dac_command = None;
+    }
+
+    pub(crate) fn psg_write(&mut self, data: u8) {
+        self.sync();
+        self.commands.push(CMD_PSG_WRITE);
+        self.commands.push(data);
+        self.dac_command = None;
+    }
+
+    // builds the file: header, PCM data block, commands
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.sync();
+        let mut data = vec![0; VGM_HEADER_SIZE];
+        if !self.pcm_data.is_empty() {
+            data.push(CMD_DATA_BLOCK);
+            data.push(CMD_END); // compatibility byte
+            data.push(DATA_BLOCK_YM2612_PCM);
+            data.extend_from_slice(&(self.pcm_data.len() as u32).to_le_bytes());
+            data.extend_from_slice(&self.pcm_data);
+        }
+        data.extend_from_slice(&self.commands);
+        data.push(CMD_END);
+
+        let eof_offset = data.len() as u32 - 0x04;
+        let header_fields: [(usize, u32); 7] = [
+            (0x04, eof_offset),
+            (0x08, VGM_VERSION),
+            (0x0C, self.psg_clock),
+            (0x18, self.samples as u32),
+            (0x24, self.frame_rate),
+            (0x2C, self.ym2612_clock),
+            (0x34, VGM_HEADER_SIZE as u32 - 0x34), // data offset is relative to its own field
+        ];
+        data[0x00..0x04].copy_from_slice(VGM_IDENT);
+        for (offset, value) in header_fields {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        // the sega psg: white noise taps on the bits 0 and 3 of the 16 bit shift register
+        data[0x28..0x2A].copy_from_slice(&0x0009u16.to_le_bytes());
+        data[0x2A] = 16;
+        data
+    }
+
+    // emits the waits up to the time of the current write
+    fn sync(&mut self) {
+        let elapsed = self.cycle.saturating_sub(self.start_cycle) as u128;
+        let target = (elapsed * VGM_SAMPLE_RATE as u128 / self.master_clock as u128) as u64;
+        if target > self.samples {
+            self.wait(target - self.samples);
+            self.samples = target;
+        }
+    }
+
+    fn wait(&mut self, mut samples: u64) {
+        // a DAC write carries a wait of up to 15 samples
+        if let Some(position) = self.dac_command.take() {
+            let folded = samples.min(0x0F);
+            self.commands[position] |= folded as u8;
+            samples -= folded;
+        }
+        while samples > 0 {
+            match samples {
+                735 => {
+                    self.commands.push(CMD_WAIT_NTSC_FRAME);
+                    samples = 0;
+                }
+                882 => {
+                    self.commands.push(CMD_WAIT_PAL_FRAME);
+                    samples = 0;
+                }
+                1..=16 => {
+                    self.commands.push(CMD_WAIT_SHORT | (samples - 1) as u8);
+                    samples = 0;
+                }
+                _ => {
+                    let wait = samples.min(0xFFFF);
+                    self.commands.push(CMD_WAIT);
+                    self.commands
+                        .extend_from_slice(&(wait as u16).to_le_bytes());
+                    samples -= wait;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use std::{collections::HashMap, fmt::Display, io::Read};
+
+use flate2::read::GzDecoder;
+use log::warn;
+
+use crate::{
+    audio::{mixer::Mixer, AudioSink, SAMPLE_RATE_44100},
+    genesis::{
+        AUDIO_FLUSH_SIZE, M68K_CLOCK_DIVIDER, MASTER_CLOCK_NTSC, PSG_CLOCK_DIVIDER,
+        YM2612_CLOCK_DIVIDER, YM2612_SAMPLE_DIVIDER, Z80_CLOCK_DIVIDER,
+    },
+    sn76489::{sn76489::Sn76489, PsgPorts},
+    ym2612::{ym2612::Ym2612, RegisterPart, Ym2612Ports},
+};
+
+use super::{
+    CMD_DATA_BANK_SEEK, CMD_DATA_BLOCK, CMD_END, CMD_PSG_WRITE, CMD_WAIT, CMD_WAIT_NTSC_FRAME,
+    CMD_WAIT_PAL_FRAME, CMD_YM2612_PORT0_WRITE, CMD_YM2612_PORT1_WRITE, DATA_BLOCK_YM2612_PCM,
+    VGM_HEADER_SIZE, VGM_IDENT, VGM_SAMPLE_RATE,
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const DAC_REGISTER: u8 = 0x2A;
+// the stream chip type of the YM2612
+const STREAM_CHIP_YM2612: u8 = 0x02;
+
+#[derive(Debug, PartialEq)]
+pub enum VgmError {
+    WrongIdent,
+    BrokenGzip,
+    UnexpectedEnd,
+    UnknownCommand(u8, usize),
+}
+
+impl Display for VgmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VgmError::WrongIdent => write!(f, "not a vgm file"),
+            VgmError::BrokenGzip => write!(f, "broken gzip data"),
+            VgmError::UnexpectedEnd => write!(f, "vgm data is truncated"),
+            VgmError::UnknownCommand(command, position) => {
+                write!(f, "unknown vgm command {:02X} at {:08X}", command, position)
+            }
+        }
+    }
+}
+
+// DAC stream of the VGM 1.60 stream control commands, it writes the data bank bytes to a YM2612 register
+struct DacStream {
+    part: Option<RegisterPart>, // None if the stream targets another chip
+    register: u8,
+    step_size: usize,
+    step_base: usize,
+    frequency: u64,
+
+    active: bool,
+    looped: bool,
+    start: usize,
+    position: usize,
+    length: usize, // writes before the stop or the loop
+    played: usize,
+    accumulator: u64,
+}
+
+impl DacStream {
+    fn new() -> Self {
+        Self {
+            part: None,
+            register: DAC_REGISTER,
+            step_size: 1,
+            step_base: 0,
+            frequency: 0,
+
+            active: false,
+            looped: false,
+            start: 0,
+            position: 0,
+            length: 0,
+            played: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn start(&mut self, start: usize, length: usize, looped: bool) {
+        self.start = start + self.step_base;
+        self.position = self.start;
+        self.length = length;
+        self.looped = looped;
+        self.played = 0;
+        self.accumulator = 0;
+        self.active = true;
+    }
+}
+
+// plays the YM2612 and SN76489 commands of a VGM file without the cpus
+pub struct VgmPlayer {
+    ym2612: Ym2612,
+    psg: Sn76489,
+    mixer: Mixer,
+    audio_sink: Option<Box<dyn AudioSink>>,
+
+    data: Vec<u8>,
+    position: usize,
+    loop_offset: Option<usize>,
+    loops: u32,
+    finished: bool,
+
+    pcm_data: Vec<u8>,
+    pcm_blocks: Vec<(usize, usize)>, // offset and length of each data block in the bank
+    pcm_position: usize,
+    streams: HashMap<u8, DacStream>,
+
+    master_clock: u64,
+    samples: u64, // vgm samples played
+    total_samples: u64,
+    ym2612_cycle: u64,
+    psg_cycle: u64,
+}
+
+impl VgmPlayer {
+    // accepts a plain or gzip compressed (.vgz) file
+    pub fn new(data: Vec<u8>) -> Result<Self, VgmError> {
+        let data = if data.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = vec![];
+            GzDecoder::new(data.as_slice())
+                .read_to_end(&mut decompressed)
+                .map_err(|_| VgmError::BrokenGzip)?;
+            decompressed
+        } else {
+            data
+        };
+        if data.len() < VGM_HEADER_SIZE || &data[0x00..0x04] != VGM_IDENT {
+            return Err(VgmError::WrongIdent);
+        }
+        let read_u32 =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        let version = read_u32(0x08);
+        // the YM2612 clock shares the YM2413 field before the version 1.10
+        let ym2612_clock = if version < 0x110 {
+            read_u32(0x10)
+        } else {
+            read_u32(0x2C)
+        };
+        let psg_clock = read_u32(0x0C);
+        // the chips clocks are derived from the master clock the same way as in the console
+        let master_clock = match (ym2612_clock, psg_clock) {
+            (0, 0) => MASTER_CLOCK_NTSC,
+            (0, psg_clock) => (psg_clock & 0x3FFFFFFF) as u64 * Z80_CLOCK_DIVIDER,
+            (ym2612_clock, _) => (ym2612_clock & 0x3FFFFFFF) as u64 * M68K_CLOCK_DIVIDER,
+        };
+        let data_offset = match read_u32(0x34) {
+            0 => VGM_HEADER_SIZE,
+            offset if version >= 0x150 => 0x34 + offset as usize,
+            _ => VGM_HEADER_SIZE,
+        };
+        let loop_offset = match read_u32(0x1C) {
+            0 => None,
+            offset => Some(0x1C + offset as usize),
+        };
+
+        Ok(Self {
+            ym2612: Ym2612::new(),
+            psg: Sn76489::new(),
+            mixer: Mixer::new(
+                master_clock as f64 / YM2612_SAMPLE_DIVIDER as f64,
+                SAMPLE_RATE_44100,
+            ),
+            audio_sink: None,
+
+            total_samples: read_u32(0x18) as u64,
+            data,
+            position: data_offset,
+            loop_offset,
+            loops: 0,
+            finished: false,
+
+            pcm_data: vec![],
+            pcm_blocks: vec![],
+            pcm_position: 0,
+            streams: HashMap::new(),
+
+            master_clock,
+            samples: 0,
+            ym2612_cycle: 0,
+            psg_cycle: 0,
+        })
+    }
+
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        if audio_sink.sample_rate() != self.mixer.output_rate() {
+            self.mixer.set_output_rate(audio_sink.sample_rate());
+        }
+        self.audio_sink = Some(audio_sink);
+    }
+
+    pub fn take_audio_sink(&mut self) -> Option<Box<dyn AudioSink>> {
+        self.flush_audio();
+        self.audio_sink.take()
+    }
+
+    // times the looped part is repeated after the first pass
+    pub fn set_loops(&mut self, loops: u32) {
+        self.loops = loops;
+    }
+
+    // length of the track without the loops in 44100 Hz samples
+    pub fn total_samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    pub fn played_samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    // executes the commands until the end of the track
+    pub fn play(&mut self) -> Result<(), VgmError> {
+        while !self.finished {
+            self.step()?;
+        }
+        self.flush_audio();
+        Ok(())
+    }
+
+    // executes a single command
+    pub fn step(&mut self) -> Result<(), VgmError> {
+        if self.finished {
+            return Ok(());
+        }
+        let position = self.position;
+        let command = self.byte(0)?;
+        match command {
+            CMD_PSG_WRITE => {
+                self.psg.write(self.byte(1)?);
+                self.position += 2;
+            }
+            CMD_YM2612_PORT0_WRITE | CMD_YM2612_PORT1_WRITE => {
+                let part = if command == CMD_YM2612_PORT0_WRITE {
+                    RegisterPart::Fm1
+                } else {
+                    RegisterPart::Fm2
+                };
+                self.ym2612_write(part, self.byte(1)?, self.byte(2)?);
+                self.position += 3;
+            }
+            CMD_WAIT => {
+                let samples = self.read_u16(1)? as u64;
+                self.position += 3;
+                self.wait(samples);
+            }
+            CMD_WAIT_NTSC_FRAME => {
+                self.position += 1;
+                self.wait(735);
+            }
+            CMD_WAIT_PAL_FRAME => {
+                self.position += 1;
+                self.wait(882);
+            }
+            CMD_END => self.end_of_data(),
+            CMD_DATA_BLOCK => {
+                let block_type = self.byte(2)?;
+                let size = self.read_u32(3)? as usize;
+                let start = self.position + 7;
+                let block = self
+                    .data
+                    .get(start..start + size)
+                    .ok_or(VgmError::UnexpectedEnd)?;
+                if block_type == DATA_BLOCK_YM2612_PCM {
+                    self.pcm_blocks.push((self.pcm_data.len(), size));
+                    self.pcm_data.extend_from_slice(block);
+                }
+                self.position = start + size;
+            }
+            0x70..=0x7F => {
+                self.position += 1;
+                self.wait((command & 0x0F) as u64 + 1);
+            }
+            0x80..=0x8F => {
+                let data = self
+                    .pcm_data
+                    .get(self.pcm_position)
+                    .copied()
+                    .unwrap_or(0x80);
+                self.pcm_position += 1;
+                self.ym2612_write(RegisterPart::Fm1, DAC_REGISTER, data);
+                self.position += 1;
+                self.wait((command & 0x0F) as u64);
+            }
+            0x90..=0x95 => self.stream_control(command)?,
+            CMD_DATA_BANK_SEEK => {
+                self.pcm_position = self.read_u32(1)? as usize;
+                self.position += 5;
+            }
+            // commands of the other chips, only their lengths are known
+            0x30..=0x3F | 0x4F => self.position += 2,
+            0x40..=0x4E | 0x51 | 0x54..=0x5F | 0xA0..=0xBF => self.position += 3,
+            0x68 => self.position += 12,
+            0xC0..=0xDF => self.position += 4,
+            0xE1..=0xFF => self.position += 5,
+            _ => return Err(VgmError::UnknownCommand(command, position)),
+        }
+        if self.mixer.pending() >= AUDIO_FLUSH_SIZE {
+            self.flush_audio();
+        }
+        Ok(())
+    }
+
+    fn end_of_data(&mut self) {
+        match self.loop_offset {
+            Some(loop_offset) if self.loops > 0 => {
+                self.loops -= 1;
+                self.position = loop_offset;
+            }
+            _ => self.finished = true,
+        }
+    }
+
+    fn stream_control(&mut self, command: u8) -> Result<(), VgmError> {
+        let stream_id = self.byte(1)?;
+        match command {
+            // setup: chip type, port, register
+            0x90 => {
+                let chip = self.byte(2)? & 0x7F;
+                let port = self.byte(3)?;
+                let register = self.byte(4)?;
+                let stream = self.streams.entry(stream_id).or_insert_with(DacStream::new);
+                stream.register = register;
+                stream.part = match (chip, port) {
+                    (STREAM_CHIP_YM2612, 0) => Some(RegisterPart::Fm1),
+                    (STREAM_CHIP_YM2612, 1) => Some(RegisterPart::Fm2),
+                    _ => {
+                        warn!("VgmPlayer: stream of unsupported chip {:02X}", chip);
+                        None
+                    }
+                };
+                self.position += 5;
+            }
+            // data: bank, step size, step base
+            0x91 => {
+                let step_size = self.byte(3)?.max(1) as usize;
+                let step_base = self.byte(4)? as usize;
+                let stream = self.streams.entry(stream_id).or_insert_with(DacStream::new);
+                stream.step_size = step_size;
+                stream.step_base = step_base;
+                self.position += 5;
+            }
+            0x92 => {
+                let frequency = self.read_u32(2)? as u64;
+                self.streams
+                    .entry(stream_id)
+                    .or_insert_with(DacStream::new)
+                    .frequency = frequency;
+                self.position += 6;
+            }
+            // start: offset, length mode and length
+            0x93 => {
+                let offset = self.read_u32(2)?;
+                let mode = self.byte(6)?;
+                let length = self.read_u32(7)? as usize;
+                let bank_size = self.pcm_data.len();
+                let stream = self.streams.entry(stream_id).or_insert_with(DacStream::new);
+                let start = if offset == 0xFFFFFFFF {
+                    stream.start
+                } else {
+                    offset as usize
+                };
+                let length = match mode & 0x03 {
+                    1 => length,
+                    2 => length * stream.frequency as usize / 1000,
+                    3 => bank_size.saturating_sub(start) / stream.step_size,
+                    _ => stream.length,
+                };
+                stream.start(start, length, mode & 0x80 != 0);
+                self.position += 11;
+            }
+            0x94 => {
+                for (id, stream) in self.streams.iter_mut() {
+                    if stream_id == 0xFF || *id == stream_id {
+                        stream.active = false;
+                    }
+                }
+                self.position += 2;
+            }
+            // fast start of a data block
+            _ => {
+                let block_id = self.read_u16(2)? as usize;
+                let flags = self.byte(4)?;
+                let (start, size) = self.pcm_blocks.get(block_id).copied().unwrap_or((0, 0));
+                let stream = self.streams.entry(stream_id).or_insert_with(DacStream::new);
+                stream.start(start, size / stream.step_size, flags & 0x01 != 0);
+                self.position += 5;
+            }
+        }
+        Ok(())
+    }
+
+    fn ym2612_write(&mut self, part: RegisterPart, register: u8, data: u8) {
+        self.ym2612.register_set(part, register);
+        self.ym2612.register_data(part, data);
+    }
+
+    // runs the chips and the streams for `samples` of 44100 Hz
+    fn wait(&mut self, samples: u64) {
+        for _ in 0..samples {
+            self.clock_streams();
+            self.samples += 1;
+            let cycle =
+                (self.samples as u128 * self.master_clock as u128 / VGM_SAMPLE_RATE as u128) as u64;
+            self.run_until(cycle);
+        }
+    }
+
+    fn clock_streams(&mut self) {
+        let mut writes = vec![];
+        for stream in self.streams.values_mut() {
+            let Some(part) = stream.part.filter(|_| stream.active) else {
+                continue;
+            };
+            stream.accumulator += stream.frequency;
+            while stream.active && stream.accumulator >= VGM_SAMPLE_RATE {
+                stream.accumulator -= VGM_SAMPLE_RATE;
+                if stream.played == stream.length {
+                    if !stream.looped {
+                        stream.active = false;
+                        break;
+                    }
+                    stream.played = 0;
+                    stream.position = stream.start;
+                }
+                if let Some(data) = self.pcm_data.get(stream.position) {
+                    writes.push((part, stream.register, *data));
+                }
+                stream.position += stream.step_size;
+                stream.played += 1;
+            }
+        }
+        for (part, register, data) in writes {
+            self.ym2612_write(part, register, data);
+        }
+    }
+
+    fn run_until(&mut self, cycle: u64) {
+        while self.ym2612_cycle < cycle || self.psg_cycle < cycle {
+            if self.psg_cycle < self.ym2612_cycle {
+                self.psg.clock();
+                self.psg_cycle += PSG_CLOCK_DIVIDER;
+            } else {
+                if let Some(fm) = self.ym2612.clock() {
+                    self.mixer.push(fm, self.psg.sample());
+                }
+                self.ym2612_cycle += YM2612_CLOCK_DIVIDER;
+            }
+        }
+    }
+
+    fn flush_audio(&mut self) {
+        let samples = self.mixer.take_output();
+        if let Some(audio_sink) = self.audio_sink.as_mut() {
+            audio_sink.write(&samples);
+        }
+    }
+
+    // the end of the data without the end command is treated as the end of the track
+    fn byte(&self, offset: usize) -> Result<u8, VgmError> {
+        match self.data.get(self.position + offset) {
+            Some(byte) => Ok(*byte),
+            None if offset == 0 => Ok(CMD_END),
+            None => Err(VgmError::UnexpectedEnd),
+        }
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16, VgmError> {
+        Ok(u16::from_le_bytes([
+            self.byte(offset)?,
+            self.byte(offset + 1)?,
+        ]))
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32, VgmError> {
+        Ok(u32::from_le_bytes([
+            self.byte(offset)?,
+            self.byte(offset + 1)?,
+            self.byte(offset + 2)?,
+            self.byte(offset + 3)?,
+        ]))
+    }
+}
@@ -1,20 +1,160 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+use super::{
+    operator::{key_code, Operator},
+    tables::{WaveTables, LFO_AMS_SHIFTS},
+};
+
+// operators are stored in the numeric order (1, 2, 3, 4), the registers address them in the order 1, 3, 2, 4
 pub(crate) struct Channel {
-    pub(crate) octave: u16,
-    pub(crate) frequency: u16,
-    
-    pub(crate) operators: Vec<Opperator>,
+    pub(crate) octave: u8,
+    pub(crate) frequency: u16, // 11 bit F-number
+
+    pub(crate) operators: [Operator; 4],
+
+    pub(crate) algorithm: u8,
+    pub(crate) feedback: u8,
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+    pub(crate) ams: u8,
+    pub(crate) pms: u8,
+
+    feedback_history: [i32; 2], // last two outputs of the operator 1
 }
 
 impl Channel {
-    pub(crate) fn set_octave(&mut self, octave: u16) {
-        self.octave = octave;
+    pub(crate) fn new() -> Self {
+        Self {
+            octave: 0,
+            frequency: 0,
+
+            operators: [Operator::new(), Operator::new(), Operator::new(), Operator::new()],
+
+            algorithm: 0,
+            feedback: 0,
+            left: true,
+            right: true,
+            ams: 0,
+            pms: 0,
+
+            feedback_history: [0; 2],
+        }
     }
 
-    pub(crate) fn set_frequency(&mut self, frequency: u16) {
+    pub(crate) fn set_frequency(&mut self, octave: u8, frequency: u16) {
+        self.octave = octave;
         self.frequency = frequency;
     }
-}
 
-pub(crate) struct Opperator {
+    // frequencies are passed per operator because of the channel 3 special mode
+    pub(crate) fn set_keys(&mut self, keys: u8, frequencies: &[(u8, u16); 4]) {
+        for (i, operator) in self.operators.iter_mut().enumerate() {
+            let (octave, frequency) = frequencies[i];
+            operator.set_key(keys & (1 << i) != 0, key_code(frequency, octave));
+        }
+    }
+
+    pub(crate) fn clock_envelope(&mut self, counter: u32, frequencies: &[(u8, u16); 4]) {
+        for (i, operator) in self.operators.iter_mut().enumerate() {
+            let (octave, frequency) = frequencies[i];
+            operator.clock_envelope(counter, key_code(frequency, octave));
+        }
+    }
+
+    // returns the signed 9 bit output of the channel and moves the operators to the next sample
+    pub(crate) fn clock(
+        &mut self,
+        frequencies: &[(u8, u16); 4],
+        lfo_am: u16,
+        lfo_pm: u8,
+        wave_tables: &WaveTables,
+    ) -> i32 {
+        let am = lfo_am >> LFO_AMS_SHIFTS[self.ams as usize];
+        let [op1, op2, op3, op4] = &self.operators;
+        let out = |operator: &Operator, modulation: i32| operator.output(modulation, am, wave_tables);
+
+        let feedback = match self.feedback {
+            0 => 0,
+            feedback => (self.feedback_history[0] + self.feedback_history[1]) >> (10 - feedback),
+        };
+        let s1 = out(op1, feedback);
+        let output = match self.algorithm {
+            0 => {
+                let s2 = out(op2, s1 >> 1);
+                let s3 = out(op3, s2 >> 1);
+                accumulate(&[out(op4, s3 >> 1)])
+            }
+            1 => {
+                let s2 = out(op2, 0);
+                let s3 = out(op3, (s1 + s2) >> 1);
+                accumulate(&[out(op4, s3 >> 1)])
+            }
+            2 => {
+                let s2 = out(op2, 0);
+                let s3 = out(op3, s2 >> 1);
+                accumulate(&[out(op4, (s1 + s3) >> 1)])
+            }
+            3 => {
+                let s2 = out(op2, s1 >> 1);
+                let s3 = out(op3, 0);
+                accumulate(&[out(op4, (s2 + s3) >> 1)])
+            }
+            4 => {
+                let s3 = out(op3, 0);
+                accumulate(&[out(op2, s1 >> 1), out(op4, s3 >> 1)])
+            }
+            5 => accumulate(&[out(op2, s1 >> 1), out(op3, s1 >> 1), out(op4, s1 >> 1)]),
+            6 => accumulate(&[out(op2, s1 >> 1), out(op3, 0), out(op4, 0)]),
+            _ => accumulate(&[s1, out(op2, 0), out(op3, 0), out(op4, 0)]),
+        };
+        self.feedback_history = [self.feedback_history[1], s1];
+
+        for (i, operator) in self.operators.iter_mut().enumerate() {
+            let (octave, frequency) = frequencies[i];
+            operator.clock_phase(frequency, octave, lfo_pm, self.pms);
+        }
+        output
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.octave);
+        writer.write_u16(self.frequency);
+        for operator in &self.operators {
+            operator.save_state(writer);
+        }
+        writer.write_u8(self.algorithm);
+        writer.write_u8(self.feedback);
+        writer.write_bool(self.left);
+        writer.write_bool(self.right);
+        writer.write_u8(self.ams);
+        writer.write_u8(self.pms);
+        writer.write_u16(self.feedback_history[0] as u16);
+        writer.write_u16(self.feedback_history[1] as u16);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.octave = reader.read_u8()? & 0x07;
+        self.frequency = reader.read_u16()? & 0x07FF;
+        for operator in self.operators.iter_mut() {
+            operator.load_state(reader)?;
+        }
+        self.algorithm = reader.read_u8()? & 0x07;
+        self.feedback = reader.read_u8()? & 0x07;
+        self.left = reader.read_bool()?;
+        self.right = reader.read_bool()?;
+        self.ams = reader.read_u8()? & 0x03;
+        self.pms = reader.read_u8()? & 0x07;
+        self.feedback_history[0] = reader.read_u16()? as i16 as i32;
+        self.feedback_history[1] = reader.read_u16()? as i16 as i32;
+        Ok(())
+    }
+}
 
-}
\ No newline at end of file
+// the accumulator keeps 9 upper bits of each carrier and saturates
+fn accumulate(carriers: &[i32]) -> i32 {
+    carriers
+        .iter()
+        .map(|carrier| carrier >> 5)
+        .sum::<i32>()
+        .clamp(-256, 255)
+}
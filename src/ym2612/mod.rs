@@ -1,7 +1,10 @@
 pub mod ym2612;
 
 pub(crate) mod channel;
+mod operator;
+mod tables;
 
+#[derive(Clone, Copy)]
 pub enum RegisterPart {
     Fm1,
     Fm2,
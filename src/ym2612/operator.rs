@@ -0,0 +1,245 @@
+use crate::save_state::{StateError, StateReader, StateWriter};
+
+use super::tables::{
+    WaveTables, DETUNE_TABLE, ENVELOPE_INCREMENTS, LFO_PM_SHIFTS_1, LFO_PM_SHIFTS_2,
+};
+
+const MAX_ATTENUATION: i32 = 0x3FF;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum EnvelopePhase {
+    Attack = 0,
+    Decay = 1,
+    Sustain = 2,
+    Release = 3,
+}
+
+pub(crate) struct Operator {
+    pub(crate) detune: u8,
+    pub(crate) multiple: u8,
+    pub(crate) total_level: u8,
+    pub(crate) key_scale: u8,
+    pub(crate) attack_rate: u8,
+    pub(crate) am_enabled: bool,
+    pub(crate) decay_rate: u8,
+    pub(crate) sustain_rate: u8,
+    pub(crate) sustain_level: u8,
+    pub(crate) release_rate: u8,
+    pub(crate) ssg_eg: u8, // stored only, the SSG-EG mode is not emulated
+
+    key_on: bool,
+    phase: u32, // 20 bits, the upper 10 bits address the sine wave
+    envelope_phase: EnvelopePhase,
+    attenuation: i32, // 10 bits, 0 - max volume
+}
+
+impl Operator {
+    pub(crate) fn new() -> Self {
+        Self {
+            detune: 0,
+            multiple: 0,
+            total_level: 0,
+            key_scale: 0,
+            attack_rate: 0,
+            am_enabled: false,
+            decay_rate: 0,
+            sustain_rate: 0,
+            sustain_level: 0,
+            release_rate: 0,
+            ssg_eg: 0,
+
+            key_on: false,
+            phase: 0,
+            envelope_phase: EnvelopePhase::Release,
+            attenuation: MAX_ATTENUATION,
+        }
+    }
+
+    pub(crate) fn set_key(&mut self, key_on: bool, key_code: u8) {
+        if key_on && !self.key_on {
+            self.phase = 0;
+            self.envelope_phase = EnvelopePhase::Attack;
+            if self.envelope_rate(self.attack_rate, key_code) >= 62 {
+                self.attenuation = 0;
+                self.envelope_phase = EnvelopePhase::Decay;
+            }
+        } else if !key_on && self.key_on {
+            self.envelope_phase = EnvelopePhase::Release;
+        }
+        self.key_on = key_on;
+    }
+
+    pub(crate) fn key_on(&self) -> bool {
+        self.key_on
+    }
+
+    pub(crate) fn clock_phase(&mut self, frequency: u16, octave: u8, lfo_pm: u8, pms: u8) {
+        let mut f_number = (frequency as u32) << 1;
+        // phase modulation is a triangle of the LFO step scaled by the F-number upper bits
+        let f_number_h = (frequency >> 4) as u32;
+        let mut lfo_quarter = (lfo_pm & 0x0F) as usize;
+        if lfo_quarter & 0x08 != 0 {
+            lfo_quarter ^= 0x0F;
+        }
+        let pms = pms as usize;
+        let mut modulation = (f_number_h >> LFO_PM_SHIFTS_1[pms][lfo_quarter])
+            + (f_number_h >> LFO_PM_SHIFTS_2[pms][lfo_quarter]);
+        if pms > 5 {
+            modulation <<= pms - 5;
+        }
+        modulation >>= 2;
+        if lfo_pm & 0x10 != 0 {
+            f_number = f_number.wrapping_sub(modulation);
+        } else {
+            f_number += modulation;
+        }
+        f_number &= 0xFFF;
+
+        let mut base_frequency = (f_number << octave) >> 2;
+        let detune = DETUNE_TABLE[(self.detune & 0x03) as usize][key_code(frequency, octave) as usize] as u32;
+        if self.detune & 0x04 != 0 {
+            base_frequency = base_frequency.wrapping_sub(detune);
+        } else {
+            base_frequency += detune;
+        }
+        base_frequency &= 0x1FFFF;
+
+        // the multiple 0 means x0.5
+        let multiple = match self.multiple {
+            0 => 1,
+            multiple => multiple as u32 * 2,
+        };
+        self.phase = (self.phase + ((base_frequency * multiple) >> 1)) & 0xFFFFF;
+    }
+
+    // the envelope generator is clocked on each third sample
+    pub(crate) fn clock_envelope(&mut self, counter: u32, key_code: u8) {
+        let rate = match self.envelope_phase {
+            EnvelopePhase::Attack => self.attack_rate,
+            EnvelopePhase::Decay => self.decay_rate,
+            EnvelopePhase::Sustain => self.sustain_rate,
+            EnvelopePhase::Release => self.release_rate << 1 | 1,
+        };
+        let rate = self.envelope_rate(rate, key_code);
+        let increment = envelope_increment(rate, counter);
+        match self.envelope_phase {
+            EnvelopePhase::Attack => {
+                if rate >= 62 {
+                    self.attenuation = 0;
+                } else {
+                    self.attenuation += (!self.attenuation * increment) >> 4;
+                }
+                if self.attenuation <= 0 {
+                    self.attenuation = 0;
+                    self.envelope_phase = EnvelopePhase::Decay;
+                }
+            }
+            EnvelopePhase::Decay => {
+                self.attenuation = (self.attenuation + increment).min(MAX_ATTENUATION);
+                if self.attenuation >= self.sustain_attenuation() {
+                    self.envelope_phase = EnvelopePhase::Sustain;
+                }
+            }
+            EnvelopePhase::Sustain | EnvelopePhase::Release => {
+                self.attenuation = (self.attenuation + increment).min(MAX_ATTENUATION);
+            }
+        }
+    }
+
+    // signed 14 bit output, the modulation is added to the 10 bit phase
+    pub(crate) fn output(&self, modulation: i32, lfo_am: u16, wave_tables: &WaveTables) -> i32 {
+        let phase = ((self.phase >> 10) as i32 + modulation) as u32 & 0x3FF;
+        let mut attenuation = self.attenuation as u16 + ((self.total_level as u16) << 3);
+        if self.am_enabled {
+            attenuation += lfo_am;
+        }
+        wave_tables.sine(phase, attenuation.min(MAX_ATTENUATION as u16))
+    }
+
+    // 6 bit rate from the 5 bit register value and the key scaling
+    fn envelope_rate(&self, rate: u8, key_code: u8) -> u8 {
+        if rate == 0 {
+            0
+        } else {
+            (rate * 2 + (key_code >> (3 - self.key_scale))).min(63)
+        }
+    }
+
+    fn sustain_attenuation(&self) -> i32 {
+        match self.sustain_level {
+            0x0F => 0x3E0,
+            sustain_level => (sustain_level as i32) << 5,
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.detune);
+        writer.write_u8(self.multiple);
+        writer.write_u8(self.total_level);
+        writer.write_u8(self.key_scale);
+        writer.write_u8(self.attack_rate);
+        writer.write_bool(self.am_enabled);
+        writer.write_u8(self.decay_rate);
+        writer.write_u8(self.sustain_rate);
+        writer.write_u8(self.sustain_level);
+        writer.write_u8(self.release_rate);
+        writer.write_u8(self.ssg_eg);
+
+        writer.write_bool(self.key_on);
+        writer.write_u32(self.phase);
+        writer.write_u8(self.envelope_phase as u8);
+        writer.write_u16(self.attenuation as u16);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        self.detune = reader.read_u8()? & 0x07;
+        self.multiple = reader.read_u8()? & 0x0F;
+        self.total_level = reader.read_u8()? & 0x7F;
+        self.key_scale = reader.read_u8()? & 0x03;
+        self.attack_rate = reader.read_u8()? & 0x1F;
+        self.am_enabled = reader.read_bool()?;
+        self.decay_rate = reader.read_u8()? & 0x1F;
+        self.sustain_rate = reader.read_u8()? & 0x1F;
+        self.sustain_level = reader.read_u8()? & 0x0F;
+        self.release_rate = reader.read_u8()? & 0x0F;
+        self.ssg_eg = reader.read_u8()?;
+
+        self.key_on = reader.read_bool()?;
+        self.phase = reader.read_u32()? & 0xFFFFF;
+        self.envelope_phase = match reader.read_u8()? {
+            0 => EnvelopePhase::Attack,
+            1 => EnvelopePhase::Decay,
+            2 => EnvelopePhase::Sustain,
+            3 => EnvelopePhase::Release,
+            _ => return Err(StateError::InvalidValue("ym2612 envelope phase")),
+        };
+        self.attenuation = (reader.read_u16()? as i32).min(MAX_ATTENUATION);
+        Ok(())
+    }
+}
+
+// octave and the 4 upper bits of the F-number make the note key code
+pub(crate) fn key_code(frequency: u16, octave: u8) -> u8 {
+    let f11 = (frequency >> 10) & 0x01;
+    let f10 = (frequency >> 9) & 0x01;
+    let f9 = (frequency >> 8) & 0x01;
+    let f8 = (frequency >> 7) & 0x01;
+    let n3 = (f11 & (f10 | f9 | f8)) | ((f11 ^ 0x01) & f10 & f9 & f8);
+    octave << 2 | (f11 << 1 | n3) as u8
+}
+
+fn envelope_increment(rate: u8, counter: u32) -> i32 {
+    if rate < 2 {
+        return 0;
+    }
+    let (row, shift) = match rate / 4 {
+        0 => (0, 11),
+        group @ 1..=11 => ((rate % 4) as usize, 11 - group as u32),
+        12..=14 => ((rate - 44) as usize, 0),
+        _ => (16, 0),
+    };
+    if counter & ((1 << shift) - 1) != 0 {
+        return 0;
+    }
+    ENVELOPE_INCREMENTS[row][((counter >> shift) & 0x07) as usize] as i32
+}
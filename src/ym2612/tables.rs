@@ -0,0 +1,101 @@
+// frequency offsets of the detune (DT1) values 1-3 indexed by the key code, the value 0 has no offset
+pub(crate) const DETUNE_TABLE: [[u8; 32]; 4] = [
+    [0; 32],
+    [
+        0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, //
+        2, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 7, 8, 8, 8, 8,
+    ],
+    [
+        1, 1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, //
+        5, 6, 6, 7, 8, 8, 9, 10, 11, 12, 13, 14, 16, 16, 16, 16,
+    ],
+    [
+        2, 2, 2, 2, 2, 3, 3, 3, 4, 4, 4, 5, 5, 6, 6, 7, //
+        8, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 20, 22, 22, 22, 22,
+    ],
+];
+
+// attenuation increments of the envelope generator, a row is selected by the rate and a column by the counter
+pub(crate) const ENVELOPE_INCREMENTS: [[u8; 8]; 17] = [
+    [0, 1, 0, 1, 0, 1, 0, 1], // rates 0-47
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1], // rates 48-51
+    [1, 1, 1, 2, 1, 1, 1, 2],
+    [1, 2, 1, 2, 1, 2, 1, 2],
+    [1, 2, 2, 2, 1, 2, 2, 2],
+    [2, 2, 2, 2, 2, 2, 2, 2], // rates 52-55
+    [2, 2, 2, 4, 2, 2, 2, 4],
+    [2, 4, 2, 4, 2, 4, 2, 4],
+    [2, 4, 4, 4, 2, 4, 4, 4],
+    [4, 4, 4, 4, 4, 4, 4, 4], // rates 56-59
+    [4, 4, 4, 8, 4, 4, 4, 8],
+    [4, 8, 4, 8, 4, 8, 4, 8],
+    [4, 8, 8, 8, 4, 8, 8, 8],
+    [8, 8, 8, 8, 8, 8, 8, 8], // rates 60-63
+];
+
+// samples per LFO step for each frequency setting (3.98, 5.56, 6.02, 6.37, 6.88, 9.63, 48.1, 72.2 Hz)
+pub(crate) const LFO_PERIODS: [u8; 8] = [108, 77, 71, 67, 62, 44, 8, 5];
+
+// attenuation shifts of the LFO amplitude modulation for the AMS values (0, 1.4, 5.9, 11.8 dB)
+pub(crate) const LFO_AMS_SHIFTS: [u8; 4] = [8, 3, 1, 0];
+
+// shifts of the F-number for the LFO phase modulation, indexed by PMS and the LFO step inside a quarter
+pub(crate) const LFO_PM_SHIFTS_1: [[u8; 8]; 8] = [
+    [7, 7, 7, 7, 7, 7, 7, 7],
+    [7, 7, 7, 7, 7, 7, 7, 7],
+    [7, 7, 7, 7, 7, 7, 1, 1],
+    [7, 7, 7, 7, 1, 1, 1, 1],
+    [7, 7, 7, 1, 1, 1, 1, 0],
+    [7, 7, 1, 1, 0, 0, 0, 0],
+    [7, 7, 1, 1, 0, 0, 0, 0],
+    [7, 7, 1, 1, 0, 0, 0, 0],
+];
+
+pub(crate) const LFO_PM_SHIFTS_2: [[u8; 8]; 8] = [
+    [7, 7, 7, 7, 7, 7, 7, 7],
+    [7, 7, 7, 7, 2, 2, 2, 2],
+    [7, 7, 7, 2, 2, 2, 7, 7],
+    [7, 7, 2, 2, 7, 7, 2, 2],
+    [7, 7, 2, 7, 7, 7, 2, 7],
+    [7, 7, 7, 2, 7, 7, 2, 1],
+    [7, 7, 7, 2, 7, 7, 2, 1],
+    [7, 7, 7, 2, 7, 7, 2, 1],
+];
+
+// the chip keeps a quarter of the sine wave in the log2 form and converts it back by the exponent table
+pub(crate) struct WaveTables {
+    log_sin: [u16; 256],
+    exp: [u16; 256],
+}
+
+impl WaveTables {
+    pub(crate) fn new() -> Self {
+        let mut log_sin = [0; 256];
+        let mut exp = [0; 256];
+        for i in 0..256 {
+            let angle = (2 * i + 1) as f64 * std::f64::consts::PI / 1024.0;
+            log_sin[i] = (-angle.sin().log2() * 256.0).round() as u16;
+            exp[i] = ((2f64.powf(i as f64 / 256.0) - 1.0) * 1024.0).round() as u16;
+        }
+        Self { log_sin, exp }
+    }
+
+    // 10 bit phase and 10 bit attenuation (0.09375 dB steps) to the signed 14 bit level
+    pub(crate) fn sine(&self, phase: u32, attenuation: u16) -> i32 {
+        let quarter = if phase & 0x100 != 0 {
+            !phase & 0xFF
+        } else {
+            phase & 0xFF
+        };
+        let level = (self.log_sin[quarter as usize] as u32 + ((attenuation as u32) << 2)).min(0x1FFF);
+        let output = ((self.exp[(level & 0xFF) as usize ^ 0xFF] as u32 | 0x400) << 2) >> (level >> 8);
+        if phase & 0x200 != 0 {
+            -(output as i32)
+        } else {
+            output as i32
+        }
+    }
+}
@@ -1,6 +1,22 @@
+use std::{cell::RefCell, rc::Rc};
+
 use log::{info, warn};
 
-use crate::ym2612::{channel::Channel, RegisterPart, Ym2612Ports};
+use crate::{
+    save_state::{StateError, StateReader, StateWriter},
+    vgm::vgm_logger::VgmLogger,
+    ym2612::{channel::Channel, tables::{WaveTables, LFO_PERIODS}, RegisterPart, Ym2612Ports},
+};
+
+// internal cycles (6 m68k clocks each) per output sample
+const CYCLES_PER_SAMPLE: u8 = 24;
+// internal cycles of the busy state after the data write
+const BUSY_CYCLES: u8 = 32;
+// timer B counts every 16 samples
+const TIMER_B_DIVIDER: u8 = 16;
+
+// the channel 3 special mode operators take their frequencies from 0xA8-0xAA (operators 3, 1, 2)
+const CHANNEL_3_OPERATORS: [usize; 3] = [2, 0, 1];
 
 pub struct Ym2612 {
     channels: Vec<Channel>,
@@ -8,79 +24,449 @@ pub struct Ym2612 {
     register_fm1: u8,
     register_fm2: u8,
 
+    frequency_latch: u8, // octave and F-number msb, they are applied with the lsb write
+    channel_3_mode: u8,  // 0 - normal, 1 - special, 2 - CSM
+    channel_3_latch: u8,
+    channel_3_frequencies: [(u8, u16); 3], // octave and F-number of the operators 3, 1, 2
+
     dac_data: u8,
     dac_enabled: bool,
 
     lfo_enable: bool,
-    lfo_freq: f32, // Hz
+    lfo_freq: u8, // 3.98, 5.56, 6.02, 6.37, 6.88, 9.63, 48.1, 72.2 Hz
+    lfo_counter: u8,
+    lfo_step: u8, // 7 bits, the AM uses all of them and the PM uses 5 upper ones
+
+    envelope_divider: u8,
+    envelope_counter: u32,
 
     timer_a: u16, // 18 * (1024 - timer_a) ms (0x3FF=0.108 ms | 0x000=18.4ms)
     timer_b: u16, // 288 * (256 - timer_b) ms (0xFF=0.288 ms | 0x00=73.44ms)
+    timer_a_counter: u16,
+    timer_b_counter: u16,
+    timer_b_divider: u8,
+    timer_control: u8, // register 0x27: load (bits 0-1) and flag enable (bits 2-3) of the timers A and B
+    timer_status: u8,  // overflow flags, bit 0 - timer A, bit 1 - timer B
+    csm_key_on: bool,
+
+    sample_cycle: u8,
+    busy_cycles: u8,
+
+    wave_tables: WaveTables,
+
+    vgm_logger: Option<Rc<RefCell<VgmLogger>>>,
 }
 
 impl Ym2612 {
     pub fn new() -> Self {
         Self {
-            channels: vec![
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-                Channel { octave: 0, frequency: 0, operators: vec![] },
-            ],
+            channels: (0..6).map(|_| Channel::new()).collect(),
 
             register_fm1: 0,
             register_fm2: 0,
 
-            dac_data: 0,
+            frequency_latch: 0,
+            channel_3_mode: 0,
+            channel_3_latch: 0,
+            channel_3_frequencies: [(0, 0); 3],
+
+            dac_data: 0x80,
             dac_enabled: false,
 
             lfo_enable: false,
-            lfo_freq: 3.98,
+            lfo_freq: 0,
+            lfo_counter: 0,
+            lfo_step: 0,
+
+            envelope_divider: 0,
+            envelope_counter: 0,
 
             timer_a: 0,
             timer_b: 0,
+            timer_a_counter: 0,
+            timer_b_counter: 0,
+            timer_b_divider: 0,
+            timer_control: 0,
+            timer_status: 0,
+            csm_key_on: false,
+
+            sample_cycle: 0,
+            busy_cycles: 0,
+
+            wave_tables: WaveTables::new(),
+
+            vgm_logger: None,
+        }
+    }
+
+    pub(crate) fn set_vgm_logger(&mut self, vgm_logger: Option<Rc<RefCell<VgmLogger>>>) {
+        self.vgm_logger = vgm_logger;
+    }
+
+    // register writes that bring another chip into the current state, the timers are left stopped
+    pub(crate) fn register_dump(&self) -> Vec<(RegisterPart, u8, u8)> {
+        let mut registers = vec![
+            (RegisterPart::Fm1, 0x22, (self.lfo_enable as u8) << 3 | self.lfo_freq),
+            (RegisterPart::Fm1, 0x24, (self.timer_a >> 2) as u8),
+            (RegisterPart::Fm1, 0x25, self.timer_a as u8 & 0x03),
+            (RegisterPart::Fm1, 0x26, self.timer_b as u8),
+            (RegisterPart::Fm1, 0x27, self.channel_3_mode << 6),
+            (RegisterPart::Fm1, 0x2A, self.dac_data),
+            (RegisterPart::Fm1, 0x2B, (self.dac_enabled as u8) << 7),
+        ];
+        for (channel_num, channel) in self.channels.iter().enumerate() {
+            let part = if channel_num < 3 { RegisterPart::Fm1 } else { RegisterPart::Fm2 };
+            let offset = channel_num as u8 % 3;
+            for (slot, operator_num) in [0, 2, 1, 3].into_iter().enumerate() {
+                let operator = &channel.operators[operator_num];
+                let register = offset + (slot as u8) * 4;
+                registers.extend([
+                    (part, 0x30 + register, operator.detune << 4 | operator.multiple),
+                    (part, 0x40 + register, operator.total_level),
+                    (part, 0x50 + register, operator.key_scale << 6 | operator.attack_rate),
+                    (part, 0x60 + register, (operator.am_enabled as u8) << 7 | operator.decay_rate),
+                    (part, 0x70 + register, operator.sustain_rate),
+                    (part, 0x80 + register, operator.sustain_level << 4 | operator.release_rate),
+                    (part, 0x90 + register, operator.ssg_eg),
+                ]);
+            }
+            registers.extend([
+                (part, 0xA4 + offset, channel.octave << 3 | (channel.frequency >> 8) as u8),
+                (part, 0xA0 + offset, channel.frequency as u8),
+                (part, 0xB0 + offset, channel.feedback << 3 | channel.algorithm),
+                (
+                    part,
+                    0xB4 + offset,
+                    (channel.left as u8) << 7
+                        | (channel.right as u8) << 6
+                        | channel.ams << 4
+                        | channel.pms,
+                ),
+            ]);
+        }
+        for (i, (octave, frequency)) in self.channel_3_frequencies.iter().enumerate() {
+            registers.extend([
+                (RegisterPart::Fm1, 0xAC + i as u8, octave << 3 | (frequency >> 8) as u8),
+                (RegisterPart::Fm1, 0xA8 + i as u8, *frequency as u8),
+            ]);
+        }
+        for (channel_num, channel) in self.channels.iter().enumerate() {
+            let keys = channel
+                .operators
+                .iter()
+                .enumerate()
+                .fold(0, |keys, (i, operator)| keys | (operator.key_on() as u8) << (4 + i));
+            let channel_bits = if channel_num < 3 { channel_num } else { channel_num + 1 };
+            registers.push((RegisterPart::Fm1, 0x28, keys | channel_bits as u8));
+        }
+        registers
+    }
+
+    // a single internal cycle (6 m68k clocks), each 24th of them generates a stereo sample (~53 kHz)
+    pub(crate) fn clock(&mut self) -> Option<(i32, i32)> {
+        self.busy_cycles = self.busy_cycles.saturating_sub(1);
+        self.sample_cycle += 1;
+        if self.sample_cycle == CYCLES_PER_SAMPLE {
+            self.sample_cycle = 0;
+            self.clock_timers();
+            Some(self.generate_sample())
+        } else {
+            None
         }
     }
 
+    fn generate_sample(&mut self) -> (i32, i32) {
+        self.clock_lfo();
+
+        self.envelope_divider += 1;
+        if self.envelope_divider == 3 {
+            self.envelope_divider = 0;
+            self.envelope_counter = self.envelope_counter.wrapping_add(1);
+            for channel_num in 0..self.channels.len() {
+                let frequencies = self.operator_frequencies(channel_num);
+                self.channels[channel_num].clock_envelope(self.envelope_counter, &frequencies);
+            }
+        }
+
+        let lfo_am = if self.lfo_step < 64 {
+            self.lfo_step as u16 * 2
+        } else {
+            126 - (self.lfo_step as u16 & 0x3F) * 2
+        };
+        let lfo_pm = self.lfo_step >> 2;
+        let (mut left, mut right) = (0, 0);
+        for channel_num in 0..self.channels.len() {
+            let frequencies = self.operator_frequencies(channel_num);
+            let channel = &mut self.channels[channel_num];
+            let mut output = channel.clock(&frequencies, lfo_am, lfo_pm, &self.wave_tables);
+            if channel_num == 5 && self.dac_enabled {
+                output = (self.dac_data as i32 - 0x80) << 1;
+            }
+            if channel.left {
+                left += output;
+            }
+            if channel.right {
+                right += output;
+            }
+        }
+        // 6 channels of 9 bits leave some room for the other sound sources
+        (left << 4, right << 4)
+    }
+
+    fn clock_timers(&mut self) {
+        // the CSM mode releases the keys of the channel 3 on the next sample after the timer A overflow
+        if self.csm_key_on {
+            self.csm_key_on = false;
+            self.set_keys(0x02);
+        }
+        if self.timer_control & 0x01 != 0 {
+            self.timer_a_counter += 1;
+            if self.timer_a_counter == 0x400 {
+                self.timer_a_counter = self.timer_a;
+                if self.timer_control & 0x04 != 0 {
+                    self.timer_status |= 0x01;
+                }
+                if self.channel_3_mode == 2 {
+                    self.csm_key_on = true;
+                    self.set_keys(0xF2);
+                }
+            }
+        }
+        self.timer_b_divider += 1;
+        if self.timer_b_divider == TIMER_B_DIVIDER {
+            self.timer_b_divider = 0;
+            if self.timer_control & 0x02 != 0 {
+                self.timer_b_counter += 1;
+                if self.timer_b_counter == 0x100 {
+                    self.timer_b_counter = self.timer_b;
+                    if self.timer_control & 0x08 != 0 {
+                        self.timer_status |= 0x02;
+                    }
+                }
+            }
+        }
+    }
+
+    fn setup_timers(&mut self, data: u8) {
+        // the counters are reloaded when the timers are started
+        if data & 0x01 != 0 && self.timer_control & 0x01 == 0 {
+            self.timer_a_counter = self.timer_a;
+        }
+        if data & 0x02 != 0 && self.timer_control & 0x02 == 0 {
+            self.timer_b_counter = self.timer_b;
+        }
+        if data & 0x10 != 0 {
+            self.timer_status &= !0x01;
+        }
+        if data & 0x20 != 0 {
+            self.timer_status &= !0x02;
+        }
+        self.timer_control = data & 0x0F;
+        self.channel_3_mode = data >> 6;
+    }
+
+    fn clock_lfo(&mut self) {
+        if !self.lfo_enable {
+            self.lfo_counter = 0;
+            self.lfo_step = 0;
+            return;
+        }
+        self.lfo_counter += 1;
+        if self.lfo_counter >= LFO_PERIODS[self.lfo_freq as usize] {
+            self.lfo_counter = 0;
+            self.lfo_step = (self.lfo_step + 1) & 0x7F;
+        }
+    }
+
+    fn operator_frequencies(&self, channel_num: usize) -> [(u8, u16); 4] {
+        let channel = &self.channels[channel_num];
+        let mut frequencies = [(channel.octave, channel.frequency); 4];
+        if channel_num == 2 && self.channel_3_mode != 0 {
+            for (i, operator_num) in CHANNEL_3_OPERATORS.iter().enumerate() {
+                frequencies[*operator_num] = self.channel_3_frequencies[i];
+            }
+        }
+        frequencies
+    }
+
     fn setup_lfo(&mut self, data: u8) {
         self.lfo_enable = data & 0x08 != 0;
-        self.lfo_freq = match data & 0x07 {
-            0 => 3.98,
-            1 => 5.56,
-            2 => 6.02,
-            3 => 6.37,
-            4 => 6.88,
-            5 => 9.63,
-            6 => 48.1,
-            7 => 72.2,
-            _ => panic!("Ym2612: unexpected bit mask for setting the LFO frequency ({:02X})", data)
-        }
+        self.lfo_freq = data & 0x07;
     }
 
-    fn set_channel_msb_frequency(&mut self, part: RegisterPart, register: u8, data: u8) {
-        let offset = match part {
-            RegisterPart::Fm1 => 0,
-            RegisterPart::Fm2 => 3,
+    fn set_keys(&mut self, data: u8) {
+        let channel_num = match data & 0x07 {
+            channel_num @ 0..=2 => channel_num as usize,
+            channel_num @ 4..=6 => channel_num as usize - 1,
+            _ => {
+                warn!("Ym2612: key on/off for unexpected channel {:02X}", data);
+                return;
+            }
         };
-        let channel_num = (register & 0x03) + 1 + offset; // select channel bits
-        let channel = &mut self.channels[channel_num as usize];
-        let octave = (data as u16 >> 3) & 0x07;
-        channel.set_octave(octave);
-        let frequency = (data as u16 & 0x07) << 8;
-        channel.set_frequency(frequency);
+        let frequencies = self.operator_frequencies(channel_num);
+        self.channels[channel_num].set_keys(data >> 4, &frequencies);
     }
 
-    fn set_channel_lsb_frequency(&mut self, part: RegisterPart, register: u8, data: u8) {
+    fn channel_num(part: RegisterPart, register: u8) -> Option<usize> {
         let offset = match part {
             RegisterPart::Fm1 => 0,
             RegisterPart::Fm2 => 3,
         };
-        let channel_num = (register & 0x03) + 1 + offset; // select channel bits
-        let channel = &mut self.channels[channel_num as usize];
-        let frequency = channel.frequency | data as u16;
-        channel.set_frequency(frequency);
+        match register & 0x03 {
+            0x03 => None, // there is no fourth channel in a part
+            channel_num => Some((channel_num + offset) as usize),
+        }
+    }
+
+    // 0x30-0x9F, operators of the channel are addressed by the bits 2-3
+    fn set_operator_register(&mut self, part: RegisterPart, register: u8, data: u8) {
+        let Some(channel_num) = Self::channel_num(part, register) else {
+            return;
+        };
+        let operator_num = match (register >> 2) & 0x03 {
+            0 => 0,
+            1 => 2,
+            2 => 1,
+            _ => 3,
+        };
+        let operator = &mut self.channels[channel_num].operators[operator_num];
+        match register & 0xF0 {
+            0x30 => {
+                operator.detune = (data >> 4) & 0x07;
+                operator.multiple = data & 0x0F;
+            }
+            0x40 => operator.total_level = data & 0x7F,
+            0x50 => {
+                operator.key_scale = data >> 6;
+                operator.attack_rate = data & 0x1F;
+            }
+            0x60 => {
+                operator.am_enabled = data & 0x80 != 0;
+                operator.decay_rate = data & 0x1F;
+            }
+            0x70 => operator.sustain_rate = data & 0x1F,
+            0x80 => {
+                operator.sustain_level = data >> 4;
+                operator.release_rate = data & 0x0F;
+            }
+            _ => operator.ssg_eg = data & 0x0F,
+        }
+    }
+
+    // 0xA0-0xB6
+    fn set_channel_register(&mut self, part: RegisterPart, register: u8, data: u8) {
+        let is_fm1 = matches!(part, RegisterPart::Fm1);
+        let Some(channel_num) = Self::channel_num(part, register) else {
+            return;
+        };
+        let latch_octave = |latch: u8| (latch >> 3) & 0x07;
+        let latch_frequency = |latch: u8, data: u8| (latch as u16 & 0x07) << 8 | data as u16;
+        match register & 0xFC {
+            0xA0 => {
+                let octave = latch_octave(self.frequency_latch);
+                let frequency = latch_frequency(self.frequency_latch, data);
+                self.channels[channel_num].set_frequency(octave, frequency);
+            }
+            0xA4 => self.frequency_latch = data,
+            0xA8 if is_fm1 => {
+                let octave = latch_octave(self.channel_3_latch);
+                let frequency = latch_frequency(self.channel_3_latch, data);
+                self.channel_3_frequencies[channel_num] = (octave, frequency);
+            }
+            0xAC if is_fm1 => self.channel_3_latch = data,
+            0xB0 => {
+                let channel = &mut self.channels[channel_num];
+                channel.feedback = (data >> 3) & 0x07;
+                channel.algorithm = data & 0x07;
+            }
+            0xB4 => {
+                let channel = &mut self.channels[channel_num];
+                channel.left = data & 0x80 != 0;
+                channel.right = data & 0x40 != 0;
+                channel.ams = (data >> 4) & 0x03;
+                channel.pms = data & 0x07;
+            }
+            _ => warn!("Ym2612: set value to register number: {:02X}", register),
+        }
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        for channel in &self.channels {
+            channel.save_state(writer);
+        }
+        writer.write_u8(self.register_fm1);
+        writer.write_u8(self.register_fm2);
+
+        writer.write_u8(self.frequency_latch);
+        writer.write_u8(self.channel_3_mode);
+        writer.write_u8(self.channel_3_latch);
+        for (octave, frequency) in self.channel_3_frequencies {
+            writer.write_u8(octave);
+            writer.write_u16(frequency);
+        }
+
+        writer.write_u8(self.dac_data);
+        writer.write_bool(self.dac_enabled);
+
+        writer.write_bool(self.lfo_enable);
+        writer.write_u8(self.lfo_freq);
+        writer.write_u8(self.lfo_counter);
+        writer.write_u8(self.lfo_step);
+
+        writer.write_u8(self.envelope_divider);
+        writer.write_u32(self.envelope_counter);
+
+        writer.write_u16(self.timer_a);
+        writer.write_u16(self.timer_b);
+        writer.write_u16(self.timer_a_counter);
+        writer.write_u16(self.timer_b_counter);
+        writer.write_u8(self.timer_b_divider);
+        writer.write_u8(self.timer_control);
+        writer.write_u8(self.timer_status);
+        writer.write_bool(self.csm_key_on);
+
+        writer.write_u8(self.sample_cycle);
+        writer.write_u8(self.busy_cycles);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        for channel in self.channels.iter_mut() {
+            channel.load_state(reader)?;
+        }
+        self.register_fm1 = reader.read_u8()?;
+        self.register_fm2 = reader.read_u8()?;
+
+        self.frequency_latch = reader.read_u8()?;
+        self.channel_3_mode = reader.read_u8()? & 0x03;
+        self.channel_3_latch = reader.read_u8()?;
+        for (octave, frequency) in self.channel_3_frequencies.iter_mut() {
+            *octave = reader.read_u8()? & 0x07;
+            *frequency = reader.read_u16()? & 0x07FF;
+        }
+
+        self.dac_data = reader.read_u8()?;
+        self.dac_enabled = reader.read_bool()?;
+
+        self.lfo_enable = reader.read_bool()?;
+        self.lfo_freq = reader.read_u8()? & 0x07;
+        self.lfo_counter = reader.read_u8()?;
+        self.lfo_step = reader.read_u8()? & 0x7F;
+
+        self.envelope_divider = reader.read_u8()? % 3;
+        self.envelope_counter = reader.read_u32()?;
+
+        self.timer_a = reader.read_u16()? & 0x3FF;
+        self.timer_b = reader.read_u16()? & 0xFF;
+        self.timer_a_counter = reader.read_u16()? & 0x3FF;
+        self.timer_b_counter = reader.read_u16()? & 0xFF;
+        self.timer_b_divider = reader.read_u8()? % TIMER_B_DIVIDER;
+        self.timer_control = reader.read_u8()? & 0x0F;
+        self.timer_status = reader.read_u8()? & 0x03;
+        self.csm_key_on = reader.read_bool()?;
+
+        self.sample_cycle = reader.read_u8()? % CYCLES_PER_SAMPLE;
+        self.busy_cycles = reader.read_u8()?.min(BUSY_CYCLES);
+        Ok(())
     }
 }
 
@@ -99,26 +485,29 @@ impl Ym2612Ports for Ym2612 {
             RegisterPart::Fm2 => self.register_fm2,
         };
         info!("Ym2612: set value to register {:02X}", register);
-        match register {
-            0x22 => self.setup_lfo(data),
-            0x24 => self.timer_a = (data as u16) << 2,
-            0x25 => self.timer_a |= (data as u16) & 0x03,
-            0x26 => self.timer_b = data as u16,
-            0x27 => {}, // ch36_mode, timer settings
-            0x28 => {}, // setup pressed 'keys'
-            0x2A => self.dac_data = data,
-            0x2B => self.dac_enabled = data & 0x80 != 0,
-            // 0x30-0x90 // setups channels and its operators first part - channels 1-3, second part - channels 4-6 
-            0x30 => {} // 0x30+ setup detune (DT1) and multiple (MUL)
-            0x40 => {} // 0x40+ setup total level (TL)
-            0x50 => {} // 0x50+ setup rate scaling (RS) and atack rate (AR)
-            0xA0 | 0xA1 | 0xA2  => self.set_channel_lsb_frequency(part, register, data),
-            0xA4 | 0xA5 | 0xA6  => self.set_channel_msb_frequency(part, register, data),
+        if let Some(vgm_logger) = &self.vgm_logger {
+            vgm_logger.borrow_mut().ym2612_write(part, register, data);
+        }
+        self.busy_cycles = BUSY_CYCLES;
+        match (part, register) {
+            (RegisterPart::Fm1, 0x22) => self.setup_lfo(data),
+            (RegisterPart::Fm1, 0x24) => self.timer_a = (data as u16) << 2 | self.timer_a & 0x03,
+            (RegisterPart::Fm1, 0x25) => self.timer_a = self.timer_a & 0x3FC | (data as u16) & 0x03,
+            (RegisterPart::Fm1, 0x26) => self.timer_b = data as u16,
+            (RegisterPart::Fm1, 0x27) => self.setup_timers(data),
+            (RegisterPart::Fm1, 0x28) => self.set_keys(data),
+            (RegisterPart::Fm1, 0x2A) => self.dac_data = data,
+            (RegisterPart::Fm1, 0x2B) => self.dac_enabled = data & 0x80 != 0,
+            // first part - channels 1-3, second part - channels 4-6
+            (part, 0x30..=0x9F) => self.set_operator_register(part, register, data),
+            (part, 0xA0..=0xB6) => self.set_channel_register(part, register, data),
             _ => warn!("Ym2612: set value to register number: {:02X}", register)
         }
     }
 
+    // bit 7 - busy, bit 1 - timer B overflow, bit 0 - timer A overflow
     fn read_status(&self) -> u8 {
-        0x03 // 0x3 -> both timers are overflowed
+        let busy = if self.busy_cycles > 0 { 0x80 } else { 0x00 };
+        busy | self.timer_status
     }
 }
@@ -2,14 +2,22 @@ use log::{debug, info};
 use m68k_emu::bus::BusM68k;
 use z80_emu::bus::BusZ80;
 
-use crate::{memory_space::MemorySpace, vdp_emu::vdp_port::VdpPorts, ym2612::{RegisterPart, Ym2612Ports}};
+use crate::{memory_space::MemorySpace, sn76489::PsgPorts, vdp_emu::vdp_port::VdpPorts, ym2612::{RegisterPart, Ym2612Ports}};
 
-impl<T, Y> BusZ80 for MemorySpace<T, Y>
+impl<T, Y, P> BusZ80 for MemorySpace<T, Y, P>
 where
     T: VdpPorts,
     Y: Ym2612Ports,
+    P: PsgPorts,
 {
     fn read(&self, address: u16, amount: usize) -> Result<u16, ()> {
+        if let Some(master_system) = &self.master_system {
+            let master_system = master_system.borrow();
+            let data = (0..amount as u16).fold(0, |data, offset| {
+                data | (master_system.read(address.wrapping_add(offset)) as u16) << (offset * 8)
+            });
+            return Ok(data);
+        }
         let mut buff = [0u8; 2];
         // for Size::Byte, actual information contains in last buffer bytes
         let buff_chunk = &mut buff[..amount];
@@ -19,7 +27,7 @@ where
             let msb_address = (*self.bank_register.borrow() as u32) << 15;
             let lsb_address = (address & 0x7FFF) as u32;
             let m68k_address = msb_address | lsb_address;
-            <MemorySpace<T, Y> as BusM68k>::read(self, m68k_address, amount)? as u16
+            <MemorySpace<T, Y, P> as BusM68k>::read(self, m68k_address, amount)? as u16
         } else {
             let memory_chunk = &self.z80_ram[address as usize..address as usize + amount];
             buff_chunk.copy_from_slice(&memory_chunk);
@@ -33,6 +41,13 @@ where
     }
 
     fn write(&mut self, data: u16, address: u16, amount: usize) -> Result<(), ()> {
+        if let Some(master_system) = &self.master_system {
+            let mut master_system = master_system.borrow_mut();
+            for (offset, byte) in data.to_le_bytes()[..amount].iter().enumerate() {
+                master_system.write(address.wrapping_add(offset as u16), *byte);
+            }
+            return Ok(());
+        }
         if address == 0x4000 {
             self.ym2612_ports.borrow_mut().register_set(RegisterPart::Fm1, data as u8);
         } else if address == 0x4001 {
@@ -41,6 +56,9 @@ where
             self.ym2612_ports.borrow_mut().register_set(RegisterPart::Fm2, data as u8);
         } else if address == 0x4003 {
             self.ym2612_ports.borrow_mut().register_data(RegisterPart::Fm2, data as u8);
+        } else if (0x7F10..0x7F18).contains(&address) {
+            // the psg port is mirrored on the odd addresses
+            self.psg_ports.borrow_mut().write(data as u8);
         } else if address == 0x6000 {
             info!("Z80 bus: setup m68k bank: {:04X}", data);
             self.push_bank_register_bit(data);
@@ -48,7 +66,7 @@ where
             let msb_address = (*self.bank_register.borrow() as u32) << 15;
             let lsb_address = (address & 0x7FFF) as u32;
             let m68k_address = msb_address | lsb_address;
-            // <MemorySpace<T, Y> as BusM68k>::write(self, data as u32, m68k_address, amount)? // TODO z80 can override m68k programm?
+            // <MemorySpace<T, Y, P> as BusM68k>::write(self, data as u32, m68k_address, amount)? // TODO z80 can override m68k programm?
         } else {
             let bytes = data.to_le_bytes();
             // for Size::Byte, actual information contains in last data bytes
@@ -61,4 +79,18 @@ where
         }
         Ok(())
     }
+
+    // the Mega Drive leaves the z80 io space unconnected
+    fn io_read(&mut self, port: u16) -> u8 {
+        match &self.master_system {
+            Some(master_system) => master_system.borrow_mut().io_read(port),
+            None => 0xFF,
+        }
+    }
+
+    fn io_write(&mut self, port: u16, data: u8) {
+        if let Some(master_system) = &self.master_system {
+            master_system.borrow_mut().io_write(port, data);
+        }
+    }
 }
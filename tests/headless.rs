@@ -0,0 +1,822 @@
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use flate2::{write::GzEncoder, Compression};
+
+use segaret::{
+    audio::{AudioSink, SAMPLE_RATE_44100},
+    cartridge::rom_database::RomDatabase,
+    cheat::Cheat,
+    controller::{Button, PadType},
+    genesis::{Genesis, MASTER_CLOCK_NTSC},
+    movie::{Movie, MovieError, MovieMode, MovieStart},
+    save_state::StateError,
+    vgm::vgm_player::{VgmError, VgmPlayer},
+};
+
+// minimal cartridge: vectors, region byte and an endless `bra.s *` loop at 0x200
+fn idle_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x400];
+    rom[0x00..0x04].copy_from_slice(&0x00FFFE00u32.to_be_bytes()); // initial SP
+    rom[0x04..0x08].copy_from_slice(&0x00000200u32.to_be_bytes()); // initial PC
+    rom[0x1F0] = b'U';
+    rom[0x200..0x202].copy_from_slice(&0x60FEu16.to_be_bytes());
+    rom
+}
+
+#[test]
+fn run_frame_without_window() {
+    let mut genesis = Genesis::new(idle_rom());
+    genesis.run_frame();
+    genesis.run_frame();
+
+    let (width, height) = genesis.screen_size();
+    assert_eq!((width, height), (256, 224));
+    assert_eq!(genesis.frame_buffer().len(), width * height);
+}
+
+#[test]
+fn frame_takes_master_clocks_of_ntsc_frame() {
+    let mut genesis = Genesis::new(idle_rom());
+    // the first frame is finished in the power on display mode
+    genesis.run_frame();
+    genesis.run_frame();
+    let previous_frame_end = genesis.master_cycle();
+    genesis.run_frame();
+    let frame_cycles = genesis.master_cycle() - previous_frame_end;
+
+    let ntsc_frame_cycles = 3420 * 262;
+    // frames are finished on the m68k instruction boundary, bra.s takes 10 cpu clocks
+    assert!(frame_cycles.abs_diff(ntsc_frame_cycles) <= 10 * 7);
+}
+
+#[test]
+fn save_state_restores_machine() {
+    let mut genesis = Genesis::new(idle_rom());
+    genesis.run_frame();
+    let state = genesis.save_state();
+    let cycle = genesis.master_cycle();
+
+    genesis.run_frame();
+    genesis.load_state(&state).unwrap();
+    assert_eq!(genesis.master_cycle(), cycle);
+    assert_eq!(genesis.save_state(), state);
+}
+
+#[test]
+fn load_state_rejects_foreign_and_broken_states() {
+    let mut genesis = Genesis::new(idle_rom());
+    genesis.run_frame();
+    let state = genesis.save_state();
+
+    let mut other_rom = idle_rom();
+    other_rom[0x300] = 0xFF;
+    let mut other = Genesis::new(other_rom);
+    assert_eq!(other.load_state(&state), Err(StateError::RomMismatch));
+
+    genesis.run_frame();
+    let current = genesis.save_state();
+    assert_eq!(
+        genesis.load_state(&state[..state.len() - 1]),
+        Err(StateError::UnexpectedEnd)
+    );
+    assert_eq!(genesis.save_state(), current);
+}
+
+// writes the bytes with `move.b #data, (abs).l` and runs the `tail` code
+fn writes_rom(writes: &[(u32, u8)], tail: &[u16]) -> Vec<u8> {
+    let mut rom = idle_rom();
+    let mut program = vec![];
+    for (address, data) in writes {
+        program.extend_from_slice(&0x13FCu16.to_be_bytes());
+        program.extend_from_slice(&(*data as u16).to_be_bytes());
+        program.extend_from_slice(&address.to_be_bytes());
+    }
+    for word in tail {
+        program.extend_from_slice(&word.to_be_bytes());
+    }
+    rom.resize(0x200 + program.len(), 0);
+    rom[0x200..].copy_from_slice(&program);
+    rom
+}
+
+// writes the registers of the first FM part
+fn fm_rom(registers: &[(u8, u8)], tail: &[u16]) -> Vec<u8> {
+    let writes = registers
+        .iter()
+        .flat_map(|(register, data)| [(0x00A04000, *register), (0x00A04001, *data)])
+        .collect::<Vec<(u32, u8)>>();
+    writes_rom(&writes, tail)
+}
+
+// keeps the sound for the checks
+struct CaptureSink {
+    samples: Rc<RefCell<Vec<(i16, i16)>>>,
+}
+
+impl AudioSink for CaptureSink {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE_44100
+    }
+
+    fn write(&mut self, samples: &[(i16, i16)]) {
+        self.samples.borrow_mut().extend_from_slice(samples);
+    }
+}
+
+// skips the first frames (the first one is finished in the power on display mode) and records the third one
+fn record_third_frame(genesis: &mut Genesis) -> Vec<(i16, i16)> {
+    let samples = Rc::new(RefCell::new(vec![]));
+    genesis.set_audio_sink(Box::new(CaptureSink { samples: samples.clone() }));
+    genesis.run_frame();
+    genesis.run_frame();
+    samples.borrow_mut().clear();
+    genesis.run_frame();
+    let samples = samples.borrow().clone();
+    // 44100 Hz for the 3420 * 262 master clocks frame
+    assert!(samples.len().abs_diff(736) <= 2);
+    samples
+}
+
+// sign changes of the left channel
+fn sign_changes(samples: &[(i16, i16)]) -> usize {
+    samples
+        .windows(2)
+        .filter(|pair| (pair[0].0 < 0) != (pair[1].0 < 0))
+        .count()
+}
+
+#[test]
+fn fm_channel_produces_tone() {
+    let mut genesis = Genesis::new(fm_rom(&[
+        (0xB0, 0x07), // algorithm 7, every operator is a carrier
+        (0xB4, 0x80), // left speaker only
+        (0x30, 0x01), // operator 1 multiple
+        (0x50, 0x1F), // operator 1 attack rate
+        (0xA4, 0x24), // octave 4
+        (0xA0, 0x3C), // 440 Hz
+        (0x28, 0x10), // operator 1 of channel 1 key on
+    ], &[0x60FE]));
+    let samples = record_third_frame(&mut genesis);
+
+    assert!(samples.iter().all(|(_, right)| *right == 0));
+    let peak = samples.iter().map(|(left, _)| left.unsigned_abs()).max().unwrap();
+    assert!(peak > 2000);
+    // ~7.3 periods of 440 Hz in a 1/60 s frame
+    assert!((13..=16).contains(&sign_changes(&samples)));
+}
+
+#[test]
+fn fm_timer_a_overflow_and_busy_flags() {
+    let mut genesis = Genesis::new(fm_rom(
+        &[
+            (0x24, 0xFF), // timer A = 0x3FF, overflows on each sample
+            (0x25, 0x03),
+            (0x27, 0x05), // load and enable the timer A flag
+        ],
+        &[
+            0x13F9, 0x00A0, 0x4000, 0x00FF, 0x0000, // move.b ($A04000).l, ($FF0000).l
+            0x13F9, 0x00A0, 0x4000, 0x00FF, 0x0001, // move.b ($A04000).l, ($FF0001).l
+            0x60F4, // bra.s to the previous move
+        ],
+    ));
+    genesis.run_frame();
+
+    assert_eq!(genesis.m68k_ram()[0], 0x80); // busy right after the write, the timer is not overflowed yet
+    assert_eq!(genesis.m68k_ram()[1], 0x01);
+}
+
+#[test]
+fn psg_tone_is_mixed_into_both_channels() {
+    let mut genesis = Genesis::new(writes_rom(
+        &[
+            (0x00C00011, 0x8E), // tone 0 period lsb, 254 is 440 Hz
+            (0x00C00011, 0x0F), // tone 0 period msb
+            (0x00C00011, 0x90), // tone 0 attenuation 0
+            (0x00C00011, 0xFF), // noise is off
+        ],
+        &[0x60FE],
+    ));
+    let samples = record_third_frame(&mut genesis);
+
+    assert!(samples.iter().all(|(left, right)| left == right));
+    let peak = samples.iter().map(|(left, _)| left.unsigned_abs()).max().unwrap();
+    assert!(peak > 1000);
+    assert!((13..=16).contains(&sign_changes(&samples)));
+}
+
+#[test]
+fn vgm_log_records_sound_writes() {
+    let mut genesis = Genesis::new(writes_rom(
+        &[
+            (0x00A04000, 0xA4), // octave 4
+            (0x00A04001, 0x24),
+            (0x00A04000, 0x28), // operator 1 of channel 1 key on
+            (0x00A04001, 0x10),
+            (0x00A04000, 0x2B), // DAC on
+            (0x00A04001, 0x80),
+            (0x00A04000, 0x2A), // DAC samples
+            (0x00A04001, 0x40),
+            (0x00A04001, 0x41),
+            (0x00C00011, 0x9F), // tone 0 is off
+        ],
+        &[0x60FE],
+    ));
+    genesis.start_vgm_log();
+    for _ in 0..3 {
+        genesis.run_frame();
+    }
+    let vgm = genesis.stop_vgm_log().unwrap();
+    assert!(genesis.stop_vgm_log().is_none());
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(vgm[offset..offset + 4].try_into().unwrap());
+    assert_eq!(&vgm[0..4], b"Vgm ");
+    assert_eq!(read_u32(0x04) as usize, vgm.len() - 4);
+    assert_eq!(read_u32(0x08), 0x150);
+    assert_eq!(read_u32(0x0C), 3_579_545);
+    assert_eq!(read_u32(0x2C), 7_670_453);
+    let samples = genesis.master_cycle() as u128 * 44100 / MASTER_CLOCK_NTSC as u128;
+    assert_eq!(read_u32(0x18) as u128, samples);
+
+    // the data starts from the DAC bytes block: the power on value and two written samples
+    let data = &vgm[0x34 + read_u32(0x34) as usize..];
+    assert_eq!(&data[..10], &[0x67, 0x66, 0x00, 0x03, 0x00, 0x00, 0x00, 0x80, 0x40, 0x41]);
+    let commands = &data[10..];
+    let contains = |sequence: &[u8]| commands.windows(sequence.len()).any(|w| w == sequence);
+    assert!(contains(&[0x52, 0xA4, 0x24]));
+    assert!(contains(&[0x52, 0x28, 0x10]));
+    assert!(contains(&[0x52, 0x2B, 0x80]));
+    assert!(contains(&[0x50, 0x9F]));
+    assert!(contains(&[0xE0, 0x00, 0x00, 0x00, 0x00])); // the DAC bytes are played from the block start
+    assert_eq!(commands.last(), Some(&0x66));
+}
+
+// plays the vgm into the capture sink
+fn play_vgm(data: Vec<u8>) -> Vec<(i16, i16)> {
+    let samples = Rc::new(RefCell::new(vec![]));
+    let mut player = VgmPlayer::new(data).unwrap();
+    player.set_audio_sink(Box::new(CaptureSink { samples: samples.clone() }));
+    player.play().unwrap();
+    assert!(player.finished());
+    assert_eq!(player.played_samples(), player.total_samples());
+    let samples = samples.borrow().clone();
+    assert!(samples.len().abs_diff(player.total_samples() as usize) <= 2);
+    samples
+}
+
+#[test]
+fn vgm_player_replays_logged_tone() {
+    let mut genesis = Genesis::new(fm_rom(&[
+        (0xB0, 0x07), // algorithm 7, every operator is a carrier
+        (0xB4, 0x80), // left speaker only
+        (0x30, 0x01), // operator 1 multiple
+        (0x50, 0x1F), // operator 1 attack rate
+        (0xA4, 0x24), // octave 4
+        (0xA0, 0x3C), // 440 Hz
+        (0x28, 0x10), // operator 1 of channel 1 key on
+    ], &[0x60FE]));
+    genesis.start_vgm_log();
+    for _ in 0..3 {
+        genesis.run_frame();
+    }
+    let vgm = genesis.stop_vgm_log().unwrap();
+    let samples = play_vgm(vgm.clone());
+
+    let last_frame = &samples[samples.len() - 735..];
+    assert!(last_frame.iter().all(|(_, right)| *right == 0));
+    assert!((13..=16).contains(&sign_changes(last_frame)));
+
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&vgm).unwrap();
+    assert_eq!(play_vgm(encoder.finish().unwrap()), samples);
+
+    assert_eq!(VgmPlayer::new(vec![0; 0x40]).err(), Some(VgmError::WrongIdent));
+}
+
+#[test]
+fn four_way_play_selects_pads() {
+    let mut genesis = Genesis::new(writes_rom(
+        &[
+            (0x00A10005, 0x20), // select the pad 3
+            (0x00A10003, 0x40), // TH high
+        ],
+        &[
+            0x13F9, 0x00A1, 0x0003, 0x00FF, 0x0000, // move.b $A10003, $FF0000
+            0x13FC, 0x0070, 0x00A1, 0x0005, // move.b #$70, $A10005
+            0x13F9, 0x00A1, 0x0003, 0x00FF, 0x0001, // move.b $A10003, $FF0001
+            0x60FE,
+        ],
+    ));
+    genesis.connect_four_way_play();
+    genesis.controller_3().press_button(Button::C);
+    genesis.run_frame();
+
+    assert_eq!(genesis.m68k_ram()[0], 0x1F);
+    assert_eq!(genesis.m68k_ram()[1], 0x7C);
+}
+
+#[test]
+fn sram_is_mapped_from_header_descriptor() {
+    let mut rom = writes_rom(
+        &[
+            (0x00200001, 0x5A), // the first byte of the odd layout
+            (0x00200000, 0x11), // the even bytes are not connected
+            (0x000001F4, 0x22), // the rom is read only
+            (0x00A130F1, 0x03), // write protection
+            (0x00200003, 0x77),
+        ],
+        &[
+            0x13F9, 0x0020, 0x0001, 0x00FF, 0x0000, // move.b $200001, $FF0000
+            0x13F9, 0x0020, 0x0000, 0x00FF, 0x0001, // move.b $200000, $FF0001
+            0x13F9, 0x0000, 0x01F4, 0x00FF, 0x0002, // move.b $0001F4, $FF0002
+            0x60FE,
+        ],
+    );
+    rom[0x1B0..0x1BC].copy_from_slice(&[
+        b'R', b'A', 0xF8, 0x20, 0x00, 0x20, 0x00, 0x01, 0x00, 0x20, 0x3F, 0xFF,
+    ]);
+    let mut genesis = Genesis::new(rom);
+    genesis.load_save_data(&[0x00, 0x00, 0x33]);
+    genesis.run_frame();
+
+    assert_eq!(&genesis.m68k_ram()[0..3], &[0x5A, 0xFF, 0x00]);
+    assert!(genesis.save_data_changed());
+    assert!(!genesis.save_data_changed());
+    let save_data = genesis.save_data().unwrap();
+    assert_eq!(save_data.len(), 0x2000);
+    assert_eq!(&save_data[0..3], &[0x5A, 0x00, 0x33]);
+
+    assert_eq!(Genesis::new(idle_rom()).save_data(), None);
+}
+
+#[test]
+fn eeprom_is_selected_by_header_serial() {
+    let mut rom = writes_rom(
+        &[],
+        &[
+            0x13F9, 0x0020, 0x0001, 0x00FF, 0x0000, // move.b $200001, $FF0000
+            0x60FE,
+        ],
+    );
+    rom[0x180..0x18E].copy_from_slice(b"GM T-50396 -00"); // NHLPA Hockey 93
+    rom[0x1B0..0x1BC].copy_from_slice(&[
+        b'R', b'A', 0xE8, 0x40, 0x00, 0x20, 0x00, 0x01, 0x00, 0x20, 0x00, 0x01,
+    ]);
+    let mut genesis = Genesis::new(rom);
+    genesis.load_save_data(&[0x12, 0x34]);
+    genesis.run_frame();
+
+    // the released SDA line at bit 7 instead of the ram
+    assert_eq!(genesis.m68k_ram()[0], 0x80);
+    let save_data = genesis.save_data().unwrap();
+    assert_eq!(save_data.len(), 0x80);
+    assert_eq!(&save_data[0..3], &[0x12, 0x34, 0xFF]);
+}
+
+#[test]
+fn sega_mapper_switches_rom_banks_over_4_mb() {
+    let mut rom = writes_rom(
+        &[],
+        &[
+            0x13F9, 0x0038, 0x0000, 0x00FF, 0x0000, // move.b $380000, $FF0000
+            0x13FC, 0x0009, 0x00A1, 0x30FF, // move.b #$09, $A130FF
+            0x13F9, 0x0038, 0x0000, 0x00FF, 0x0001, // move.b $380000, $FF0001
+            0x60FE,
+        ],
+    );
+    rom.resize(0x500000, 0);
+    rom[0x380000] = 0x07;
+    rom[0x480000] = 0x09;
+    let mut genesis = Genesis::new(rom);
+    genesis.run_frame();
+
+    assert_eq!(&genesis.m68k_ram()[0..2], &[0x07, 0x09]);
+}
+
+#[test]
+fn rom_database_entry_configures_devices() {
+    let mut rom = writes_rom(
+        &[(0x00200001, 0x5A)],
+        &[
+            0x13F9, 0x0020, 0x0001, 0x00FF, 0x0000, // move.b $200001, $FF0000
+            0x13F9, 0x00A1, 0x0001, 0x00FF, 0x0001, // move.b $A10001, $FF0001
+            0x60FE,
+        ],
+    );
+    rom[0x18E..0x190].copy_from_slice(&0x1234u16.to_be_bytes());
+    let rom_info = Genesis::new(rom.clone()).rom_info().clone();
+    assert!(!rom_info.checksum_valid);
+    assert_eq!(rom_info.entry, None);
+    assert_eq!(rom_info.sha1.len(), 40);
+    assert_eq!(Genesis::new(rom.clone()).save_data(), None);
+
+    let database = RomDatabase::parse(&format!(
+        "game ( name \"Test (Europe)\" region \"Europe\" pad 6button \
+         sram ( start 200001 end 2003FF layout odd ) \
+         rom ( size {} crc {:08X} sha1 {} ) )",
+        rom.len(),
+        rom_info.crc32,
+        rom_info.sha1
+    ))
+    .unwrap();
+    let mut genesis = Genesis::with_database(rom, &database);
+    genesis.run_frame();
+
+    assert_eq!(genesis.rom_info().entry.as_ref().unwrap().name, "Test (Europe)");
+    assert_eq!(genesis.controller_1().pad_type(), PadType::SixButton);
+    assert_eq!(genesis.m68k_ram()[0], 0x5A);
+    assert_eq!(genesis.m68k_ram()[1], 0xC0); // the european version register
+    assert_eq!(genesis.save_data().unwrap().len(), 0x200);
+}
+
+#[test]
+fn cheats_patch_rom_and_ram() {
+    let rom = writes_rom(
+        &[(0x00FF0010, 0x01)],
+        &[
+            0x13F9, 0x0000, 0x0300, 0x00FF, 0x0000, // move.b $000300, $FF0000
+            0x60FE,
+        ],
+    );
+    let mut genesis = Genesis::new(rom);
+    genesis.add_cheat(Cheat::new("000300:5AA5", "").unwrap());
+    genesis.add_cheat(Cheat::new("FF0010:09", "").unwrap());
+    genesis.run_frame();
+    genesis.run_frame();
+
+    assert_eq!(genesis.m68k_ram()[0], 0x5A);
+    assert_eq!(genesis.m68k_ram()[0x10], 0x09);
+}
+
+// sums the pad reads in the ram, the sum depends on the whole input history
+fn pad_sum_rom() -> Vec<u8> {
+    writes_rom(
+        &[
+            (0x00A10009, 0x40), // TH is an output
+            (0x00A10003, 0x40), // TH high
+        ],
+        &[
+            0x1039, 0x00A1, 0x0003, // move.b $A10003, d0
+            0xD139, 0x00FF, 0x0000, // add.b d0, $FF0000
+            0x60F2, // bra.s to the read
+        ],
+    )
+}
+
+fn record_movie(genesis: &mut Genesis, frames: usize) -> Movie {
+    genesis.start_movie_recording();
+    for frame in 0..frames {
+        genesis.controller_1().release_all();
+        if (50..100).contains(&frame) {
+            genesis.controller_1().press_button(Button::Right);
+        }
+        genesis.run_frame();
+    }
+    genesis.stop_movie().unwrap()
+}
+
+#[test]
+fn movie_replays_recorded_input() {
+    let mut recorder = Genesis::new(pad_sum_rom());
+    let movie = record_movie(&mut recorder, 130);
+    assert_eq!(movie.start, MovieStart::PowerOn);
+    assert_eq!(movie.frames.len(), 130);
+    assert_eq!(movie.frames[50], [1 << Button::Right as u16, 0]);
+    assert_eq!(movie.sync_hashes.iter().map(|(frame, _)| *frame).collect::<Vec<u32>>(), [0, 60, 120]);
+
+    let mut player = Genesis::new(pad_sum_rom());
+    player.play_movie(Movie::decode(&movie.encode()).unwrap()).unwrap();
+    for _ in 0..130 {
+        // the keyboard input is replaced by the movie one
+        player.controller_1().press_button(Button::Start);
+        player.run_frame();
+    }
+    assert!(*player.m68k_ram() == *recorder.m68k_ram());
+    assert_eq!(player.movie_desync(), None);
+    player.run_frame();
+    assert_eq!(player.movie_mode(), Some(MovieMode::Finished));
+    assert_eq!(player.play_movie(movie.clone()), Err(MovieError::NotPowerOn));
+
+    let mut broken = movie.clone();
+    broken.sync_hashes[1].1 ^= 1;
+    let mut player = Genesis::new(pad_sum_rom());
+    player.play_movie(broken).unwrap();
+    for _ in 0..130 {
+        player.run_frame();
+    }
+    assert_eq!(player.movie_desync(), Some(60));
+
+    assert_eq!(Genesis::new(idle_rom()).play_movie(movie), Err(MovieError::RomMismatch));
+}
+
+#[test]
+fn movie_starts_from_save_state() {
+    let mut recorder = Genesis::new(pad_sum_rom());
+    for _ in 0..5 {
+        recorder.run_frame();
+    }
+    let movie = record_movie(&mut recorder, 70);
+    assert!(matches!(movie.start, MovieStart::SaveState(_)));
+
+    let mut player = Genesis::new(pad_sum_rom());
+    player.play_movie(movie).unwrap();
+    for _ in 0..70 {
+        player.run_frame();
+    }
+    assert!(*player.m68k_ram() == *recorder.m68k_ram());
+    assert_eq!(player.movie_desync(), None);
+}
+
+#[test]
+fn tmss_locks_vdp_until_sega_write() {
+    let vdp_write = [0x33FC, 0x8144, 0x00C0, 0x0004]; // move.w #$8144, $C00004
+    let mut genesis = Genesis::new(writes_rom(&[], &[&vdp_write[..], &[0x60FE]].concat()));
+    genesis.enable_tmss(None);
+    genesis.run_frame();
+    assert!(genesis.tmss_vdp_blocked());
+
+    let mut genesis = Genesis::new(writes_rom(
+        &[(0x00A14000, b'S'), (0x00A14001, b'E'), (0x00A14002, b'G'), (0x00A14003, b'A')],
+        &[
+            &vdp_write[..],
+            &[0x13F9, 0x00A1, 0x0001, 0x00FF, 0x0000], // move.b $A10001, $FF0000
+            &[0x60FE],
+        ]
+        .concat(),
+    ));
+    genesis.enable_tmss(None);
+    genesis.run_frame();
+    assert!(!genesis.tmss_vdp_blocked());
+    assert_eq!(genesis.m68k_ram()[0], 0x81); // the hardware version 1
+}
+
+#[test]
+fn tmss_boot_rom_swaps_to_cartridge() {
+    let mut bios = vec![0; 0x800];
+    bios[0x00..0x04].copy_from_slice(&0x00FFFE00u32.to_be_bytes());
+    bios[0x04..0x08].copy_from_slice(&0x00000100u32.to_be_bytes());
+    let boot = [
+        0x13F9u16, 0x0000, 0x0300, 0x00FF, 0x0000, // move.b $000300, $FF0000
+        0x13FC, 0x0001, 0x00A1, 0x4101, // move.b #$01, $A14101
+    ];
+    for (i, word) in boot.iter().enumerate() {
+        bios[0x100 + i * 2..0x102 + i * 2].copy_from_slice(&word.to_be_bytes());
+    }
+    bios[0x300] = 0xB1;
+
+    // the cpu continues from the next cartridge instruction
+    let mut rom = idle_rom();
+    rom[0x300] = 0xCA;
+    let game = [0x13F9u16, 0x0000, 0x0300, 0x00FF, 0x0001, 0x60FE]; // move.b $000300, $FF0001
+    for (i, word) in game.iter().enumerate() {
+        rom[0x112 + i * 2..0x114 + i * 2].copy_from_slice(&word.to_be_bytes());
+    }
+    let mut genesis = Genesis::new(rom);
+    genesis.enable_tmss(Some(bios));
+    genesis.run_frame();
+
+    assert_eq!(&genesis.m68k_ram()[0..2], &[0xB1, 0xCA]);
+}
+
+fn put_words(image: &mut [u8], address: usize, words: &[u16]) {
+    for (i, word) in words.iter().enumerate() {
+        image[address + i * 2..address + i * 2 + 2].copy_from_slice(&word.to_be_bytes());
+    }
+}
+
+// the main program loads the sub program into the program ram, starts the sub cpu and waits for
+// its status words, the second one is written by the level 2 interrupt handler
+fn mega_cd_bios() -> Vec<u8> {
+    let mut bios = vec![0; 0x20000];
+    bios[0x00..0x04].copy_from_slice(&0x00FFFE00u32.to_be_bytes());
+    bios[0x04..0x08].copy_from_slice(&0x00000200u32.to_be_bytes());
+    let main = [
+        0x13FCu16, 0x0002, 0x00A1, 0x2001, // move.b #$02, $A12001 the sub cpu bus request
+        0x41F9, 0x0000, 0x1000, // lea $001000, a0
+        0x43F9, 0x0002, 0x0000, // lea $020000, a1
+        0x303C, 0x00BF, // move.w #$BF, d0
+        0x22D8, // move.l (a0)+, (a1)+
+        0x51C8, 0xFFFC, // dbra d0, *-2
+        0x13FC, 0x0001, 0x00A1, 0x2001, // move.b #$01, $A12001 the sub cpu reset is released
+        0x4A79, 0x00A1, 0x2020, // tst.w $A12020
+        0x67F8, // beq.s *-6
+        0x13FC, 0x0001, 0x00A1, 0x2000, // move.b #$01, $A12000 the level 2 interrupt
+        0x4A79, 0x00A1, 0x2022, // tst.w $A12022
+        0x67F8, // beq.s *-6
+        0x23F9, 0x00A1, 0x2020, 0x00FF, 0x0000, // move.l $A12020, $FF0000
+        0x60FE,
+    ];
+    put_words(&mut bios, 0x200, &main);
+
+    let sub = &mut bios[0x1000..0x1300];
+    sub[0x00..0x04].copy_from_slice(&0x00070000u32.to_be_bytes());
+    sub[0x04..0x08].copy_from_slice(&0x00000100u32.to_be_bytes());
+    sub[0x68..0x6C].copy_from_slice(&0x00000200u32.to_be_bytes());
+    let program = [
+        0x13FCu16, 0x0004, 0x00FF, 0x8033, // move.b #$04, $FF8033 the level 2 is enabled
+        0x33FC, 0x1234, 0x00FF, 0x8020, // move.w #$1234, $FF8020
+        0x13FC, 0x005A, 0x00FE, 0x0001, // move.b #$5A, $FE0001 the first byte of the backup ram
+        0x46FC, 0x2000, // move.w #$2000, sr
+        0x60FE,
+    ];
+    put_words(sub, 0x100, &program);
+    put_words(sub, 0x200, &[0x33FC, 0x5678, 0x00FF, 0x8022, 0x4E73]); // move.w #$5678, $FF8022; rte
+    bios
+}
+
+#[test]
+fn mega_cd_sub_cpu_runs_program_ram() {
+    let mut genesis = Genesis::with_mega_cd(mega_cd_bios(), None);
+    genesis.run_frame();
+    assert_eq!(&genesis.m68k_ram()[0..4], &[0x12, 0x34, 0x56, 0x78]);
+
+    let state = genesis.save_state();
+    let mut restored = Genesis::with_mega_cd(mega_cd_bios(), None);
+    restored.load_state(&state).unwrap();
+    genesis.run_frame();
+    restored.run_frame();
+    assert!(*genesis.m68k_ram() == *restored.m68k_ram());
+    assert!(Genesis::new(idle_rom()).load_state(&state).is_err());
+}
+
+#[test]
+fn mega_cd_backup_ram_is_the_save_data() {
+    let mut genesis = Genesis::with_mega_cd(mega_cd_bios(), None);
+    genesis.load_save_data(&[0x11, 0x22]);
+    assert!(!genesis.save_data_changed());
+    genesis.run_frame();
+    assert!(genesis.save_data_changed());
+    assert!(!genesis.save_data_changed());
+    let save_data = genesis.save_data().unwrap();
+    assert_eq!(save_data.len(), 0x2000);
+    assert_eq!(&save_data[0..2], &[0x5A, 0x22]);
+}
+
+// the m68k enables the adapter, releases the sh2s, waits for the master and raises the command
+// interrupt of the slave; the master draws a red dot into the frame buffer and swaps the buffers
+fn sega_32x_images() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let mut rom = idle_rom();
+    let main = [
+        0x13FCu16, 0x0001, 0x00A1, 0x5101, // move.b #$01, $A15101 the adapter is enabled
+        0x13FC, 0x0003, 0x00A1, 0x5101, // move.b #$03, $A15101 the sh2 reset is released
+        0x4A79, 0x00A1, 0x5120, // tst.w $A15120
+        0x67F8, // beq.s *-6
+        0x13FC, 0x0002, 0x00A1, 0x5103, // move.b #$02, $A15103 the slave command interrupt
+        0x4A79, 0x00A1, 0x5122, // tst.w $A15122
+        0x67F8, // beq.s *-6
+        0x23F9, 0x00A1, 0x5120, 0x00FF, 0x0000, // move.l $A15120, $FF0000
+        0x60FE,
+    ];
+    put_words(&mut rom, 0x200, &main);
+
+    let mut master = vec![0; 0x800];
+    put_words(&mut master, 0x00, &[0x0000, 0x0200, 0x0604, 0x0000]); // the pc and the sp
+    let program = [
+        0xD11Fu16, // mov.l @(disp, pc), r1 the system registers
+        0xD220, // mov.l @(disp, pc), r2 the frame buffer
+        0xD320, // mov.l @(disp, pc), r3 the vdp
+        0xD421, // mov.l @(disp, pc), r4 the dot of the line 0
+        0xE002, 0x2301, // mov #2, r0; mov.w r0, @r3 the direct color mode
+        0xE001, 0x4018, 0x2201, // mov #1, r0; shll8 r0; mov.w r0, @r2 the line 0 address
+        0xE01F, 0x2401, // mov #$1F, r0; mov.w r0, @r4
+        0x730A, 0xE001, 0x2301, // add #$0A, r3; mov #1, r0; mov.w r0, @r3 the buffer swap
+        0xE012, 0x4018, 0x7034, // mov #$12, r0; shll8 r0; add #$34, r0
+        0x6513, 0x7520, 0x2501, // mov r1, r5; add #$20, r5; mov.w r0, @r5
+        0xAFFE, 0x0009, // bra *; nop
+    ];
+    put_words(&mut master, 0x200, &program);
+    put_words(&mut master, 0x280, &[0x2000, 0x4000, 0x2400, 0x0000, 0x2000, 0x4100, 0x2400, 0x0200]);
+
+    let mut slave = vec![0; 0x800];
+    put_words(&mut slave, 0x00, &[0x0000, 0x0200, 0x0603, 0xF000]);
+    put_words(&mut slave, 0x110, &[0x0000, 0x0240]); // the level 8 auto vector
+    let program = [
+        0xD11Fu16, // mov.l @(disp, pc), r1 the system registers
+        0xE002, 0x6513, 0x7501, 0x2500, // mov #2, r0; mov r1, r5; add #1, r5; mov.b r0, @r5
+        0xE000, 0x400E, // mov #0, r0; ldc r0, sr
+        0xAFFE, 0x0009, // bra *; nop
+    ];
+    put_words(&mut slave, 0x200, &program);
+    let handler = [
+        0xE056u16, 0x4018, 0x7078, // mov #$56, r0; shll8 r0; add #$78, r0
+        0x6513, 0x7522, 0x2501, // mov r1, r5; add #$22, r5; mov.w r0, @r5
+        0x6513, 0x751A, 0x2501, // mov r1, r5; add #$1A, r5; mov.w r0, @r5 the interrupt is cleared
+        0x002B, 0x0009, // rte; nop
+    ];
+    put_words(&mut slave, 0x240, &handler);
+    put_words(&mut slave, 0x280, &[0x2000, 0x4000]);
+    (rom, master, slave)
+}
+
+fn sega_32x() -> Genesis {
+    let (rom, master, slave) = sega_32x_images();
+    let m68k_bios = rom[..0x100].to_vec();
+    Genesis::with_32x(rom, m68k_bios, master, slave)
+}
+
+#[test]
+fn sega_32x_sh2s_share_comm_ports_and_frame_buffer() {
+    let mut genesis = sega_32x();
+    for _ in 0..3 {
+        genesis.run_frame();
+    }
+    assert_eq!(&genesis.m68k_ram()[0..4], &[0x12, 0x34, 0x56, 0x78]);
+    // the swapped buffer is displayed over the Mega Drive backdrop
+    assert_eq!(genesis.frame_buffer()[0], 0xFF0000);
+    assert_eq!(genesis.frame_buffer()[1], 0x000000);
+
+    let state = genesis.save_state();
+    let mut restored = sega_32x();
+    restored.load_state(&state).unwrap();
+    genesis.run_frame();
+    restored.run_frame();
+    assert!(*genesis.frame_buffer() == *restored.frame_buffer());
+    // the same rom without the add-on
+    assert!(Genesis::new(sega_32x_images().0).load_state(&state).is_err());
+}
+
+// the Master System program enables the display and the frame interrupt, sets the first color,
+// reads the mapped bank 5 and counts the interrupts
+fn master_system_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x4000 * 6];
+    let program = [
+        0xF3, // di
+        0x31, 0xF0, 0xDF, // ld sp, $DFF0
+        0xED, 0x56, // im 1
+        0x3E, 0xFF, 0xD3, 0xBF, 0x3E, 0x82, 0xD3, 0xBF, // the name table at $3800
+        0x3E, 0x60, 0xD3, 0xBF, 0x3E, 0x81, 0xD3, 0xBF, // the display and the frame interrupt
+        0xAF, 0xD3, 0xBF, 0x3E, 0xC0, 0xD3, 0xBF, // the cram address 0
+        0x3E, 0x0C, 0xD3, 0xBE, 0xD3, 0xBE, // the color is written twice for the Game Gear
+        0x3E, 0x05, 0x32, 0xFF, 0xFF, // ld ($FFFF), a the slot 2 bank
+        0x3A, 0x00, 0x80, 0x32, 0x01, 0xC0, // ld a, ($8000); ld ($C001), a
+        0xFB, // ei
+        0x18, 0xFE, // jr *
+    ];
+    rom[..program.len()].copy_from_slice(&program);
+    let handler = [
+        0xF5, // push af
+        0xDB, 0xBF, 0x32, 0x02, 0xC0, // in a, ($BF); ld ($C002), a
+        0x3A, 0x00, 0xC0, 0x3C, 0x32, 0x00, 0xC0, // the counter at $C000
+        0xF1, // pop af
+        0xFB, 0xED, 0x4D, // ei; reti
+    ];
+    rom[0x38..0x38 + handler.len()].copy_from_slice(&handler);
+    rom[0x4000 * 5] = 0xA5;
+    rom
+}
+
+#[test]
+fn master_system_mode_runs_z80_program_with_mode4_vdp() {
+    let mut genesis = Genesis::with_master_system(master_system_rom(), false);
+    assert!(genesis.master_system_attached());
+    for _ in 0..5 {
+        genesis.run_frame();
+    }
+    assert_eq!(genesis.screen_size(), (256, 192));
+    // the interrupt of the first frame is raised before the program enables it
+    let counter = genesis.z80_ram()[0];
+    assert!((4..=5).contains(&counter), "{} interrupts", counter);
+    assert_eq!(genesis.z80_ram()[1], 0xA5);
+    assert_eq!(genesis.z80_ram()[2] & 0x80, 0x80);
+    // the blank tiles are drawn with the first color, 6 bit green
+    assert!(genesis.frame_buffer().iter().all(|dot| *dot == 0x00FF00));
+
+    let state = genesis.save_state();
+    let mut restored = Genesis::with_master_system(master_system_rom(), false);
+    restored.load_state(&state).unwrap();
+    genesis.run_frame();
+    restored.run_frame();
+    assert_eq!(*genesis.z80_ram(), *restored.z80_ram());
+    assert!(Genesis::new(master_system_rom()).load_state(&state).is_err());
+}
+
+#[test]
+fn master_system_cartridge_ram_is_flushed_to_save_data() {
+    let mut rom = vec![0; 0x8000];
+    let program = [
+        0xF3, // di
+        0x3E, 0x08, 0x32, 0xFC, 0xFF, // the cartridge ram at $8000
+        0x3E, 0x5A, 0x32, 0x02, 0x80, // ld ($8002), a
+        0x18, 0xFE, // jr *
+    ];
+    rom[..program.len()].copy_from_slice(&program);
+    let mut genesis = Genesis::with_master_system(rom, false);
+    genesis.load_save_data(&[0x11]);
+    assert!(!genesis.save_data_changed());
+    genesis.run_frame();
+    assert!(genesis.save_data_changed());
+    assert!(!genesis.save_data_changed());
+    let save_data = genesis.save_data().unwrap();
+    assert_eq!(&save_data[0..3], &[0x11, 0x00, 0x5A]);
+}
+
+#[test]
+fn game_gear_shows_viewport_with_12_bit_colors() {
+    let mut genesis = Genesis::with_master_system(master_system_rom(), true);
+    for _ in 0..3 {
+        genesis.run_frame();
+    }
+    assert_eq!(genesis.screen_size(), (160, 144));
+    assert_eq!(genesis.frame_buffer().len(), 160 * 144);
+    // the low byte keeps red and green, the high one keeps blue
+    assert!(genesis.frame_buffer().iter().all(|dot| *dot == 0xCC00CC));
+}
@@ -1,4 +1,12 @@
 pub trait BusZ80 {
     fn read(&self, address: u16, amount: usize) -> Result<u16, ()>;
     fn write(&mut self, data: u16, address: u16, amount: usize) -> Result<(), ()>;
+
+    // the io space, the upper half of the port address is A or B of the instruction,
+    // nothing is connected by default and the data bus floats high
+    fn io_read(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn io_write(&mut self, _port: u16, _data: u8) {}
 }
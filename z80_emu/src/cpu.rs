@@ -2,8 +2,9 @@ use std::{cell::RefCell, rc::Rc};
 
 use log::{debug, info};
 
-use crate::{bus::BusZ80, opcode_table_generator::tables::{cb_opcode_table, dd_opcode_table, ddcb_opcode_table, ed_opcode_table, fd_opcode_table, fdcb_opcode_table, opcode_table}, register_set::{Register, RegisterSet, RegisterType}, Size};
+use crate::{bus::BusZ80, opcode_table_generator::tables::{cb_opcode_table, dd_opcode_table, ddcb_opcode_table, ed_opcode_table, fd_opcode_table, fdcb_opcode_table, opcode_table}, operation::Operation, register_set::{Register, RegisterSet, RegisterType}, timing, Size};
 
+#[derive(Clone, Copy)]
 enum IntMode {
     Mode0 = 0,
     Mode1 = 1,
@@ -21,6 +22,20 @@ impl IntMode {
     }
 }
 
+// the cpu state which is enough to resume the execution (used by save states)
+#[derive(Clone, Copy)]
+pub struct Z80State {
+    pub registers: [u8; 16], // F A C B E D L H and the shadow set in the same order
+    pub index_registers: [u16; 2], // IX IY
+    pub stack_pointer: u16,
+    pub interrupt_vector: u8,
+    pub memory_refresh: u8,
+    pub program_counter: u16,
+    pub interrupt_mode: u8,
+    pub iff1: u8,
+    pub iff2: u8,
+}
+
 pub struct Z80<T: BusZ80> {
     pub(crate) register_set: RegisterSet,
     pub  program_counter: u16,
@@ -30,6 +45,18 @@ pub struct Z80<T: BusZ80> {
     iff2: u8,
 
     bus: Option<Rc<RefCell<T>>>,
+
+    opcode_tables: Rc<OpcodeTables<T>>,
+}
+
+struct OpcodeTables<T: BusZ80> {
+    main: [Operation<T>; 0x100],
+    ed: [Operation<T>; 0x100],
+    cb: [Operation<T>; 0x100],
+    dd: [Operation<T>; 0x100],
+    ddcb: [Operation<T>; 0x100],
+    fd: [Operation<T>; 0x100],
+    fdcb: [Operation<T>; 0x100],
 }
 
 const NMI_VECTOR: u16 = 0x0066;
@@ -48,6 +75,15 @@ where
             iff1: 0,
             iff2: 0,
             bus: None,
+            opcode_tables: Rc::new(OpcodeTables {
+                main: opcode_table(),
+                ed: ed_opcode_table(),
+                cb: cb_opcode_table(),
+                dd: dd_opcode_table(),
+                ddcb: ddcb_opcode_table(),
+                fd: fd_opcode_table(),
+                fdcb: fdcb_opcode_table(),
+            }),
         }
     }
 
@@ -55,6 +91,32 @@ where
         self.bus = Some(bus)
     }
 
+    pub fn state(&self) -> Z80State {
+        Z80State {
+            registers: self.register_set.registers,
+            index_registers: self.register_set.index_register,
+            stack_pointer: self.register_set.stack_pointer,
+            interrupt_vector: self.register_set.interrupt_vector,
+            memory_refresh: self.register_set.memory_refresh,
+            program_counter: self.program_counter,
+            interrupt_mode: self.interrupt_mode as u8,
+            iff1: self.iff1,
+            iff2: self.iff2,
+        }
+    }
+
+    pub fn set_state(&mut self, state: Z80State) {
+        self.register_set.registers = state.registers;
+        self.register_set.index_register = state.index_registers;
+        self.register_set.stack_pointer = state.stack_pointer;
+        self.register_set.interrupt_vector = state.interrupt_vector;
+        self.register_set.memory_refresh = state.memory_refresh;
+        self.program_counter = state.program_counter;
+        self.interrupt_mode = IntMode::from_u32(state.interrupt_mode as u32);
+        self.iff1 = state.iff1;
+        self.iff2 = state.iff2;
+    }
+
     pub fn restart(&mut self) {
          self.program_counter = 0;
          debug!("Z80: restart")
@@ -79,8 +141,10 @@ where
         }
     }
 
-    pub fn clock(&mut self) {
+    // executes a single instruction and returns the amount of spent T-states
+    pub fn clock(&mut self) -> i32 {
         let pc = self.program_counter;
+        let opcode_tables = self.opcode_tables.clone();
         self.current_opcode = 0;
         let mut opcode = self.read_pc(Size::Byte);
         self.current_opcode |= opcode as u32;
@@ -89,13 +153,13 @@ where
                 opcode = self.read_pc(Size::Byte);
                 self.current_opcode <<= 8;
                 self.current_opcode |= opcode as u32;
-                ed_opcode_table()
+                &opcode_tables.ed
             },
             0xCB => {
                 opcode = self.read_pc(Size::Byte);
                 self.current_opcode <<= 8;
                 self.current_opcode |= opcode as u32;
-                cb_opcode_table()
+                &opcode_tables.cb
             },
             0xDD => {
                 opcode = self.read_pc(Size::Byte);
@@ -109,9 +173,9 @@ where
                         opcode = self.read_pc(Size::Byte);
                         self.current_opcode <<= 8;
                         self.current_opcode |= opcode as u32;
-                        ddcb_opcode_table()
+                        &opcode_tables.ddcb
                     }
-                    _ => dd_opcode_table(),
+                    _ => &opcode_tables.dd,
                 }
             },
             0xFD => {
@@ -126,12 +190,12 @@ where
                         opcode = self.read_pc(Size::Byte);
                         self.current_opcode <<= 8;
                         self.current_opcode |= opcode as u32;
-                        fdcb_opcode_table()
+                        &opcode_tables.fdcb
                     }
-                    _ => fd_opcode_table(),
+                    _ => &opcode_tables.fd,
                 }
             }
-            _ => opcode_table()
+            _ => &opcode_tables.main
         };
         let operation = &opcodes[opcode as usize];
         let mut operands = Vec::new();
@@ -143,9 +207,15 @@ where
             let operand = am.fetch(self);
             operands.push(operand);
         }
+        let next_pc = self.program_counter;
         operation.instruction.execute(self, operands);
         debug!("{:04X}: {}", pc, operation);
         debug!("{}", self.register_set);
+        let mut cycles = timing::instruction_cycles(self.current_opcode);
+        if self.program_counter != next_pc {
+            cycles += timing::taken_cycles(self.current_opcode);
+        }
+        cycles
     }
 
     fn write_interrupt_vector(&mut self, data: u8) {
@@ -186,6 +256,14 @@ where
         data
     }
 
+    pub(crate) fn io_read(&mut self, port: u16) -> u8 {
+        self.bus.as_ref().unwrap().borrow_mut().io_read(port)
+    }
+
+    pub(crate) fn io_write(&mut self, port: u16, data: u8) {
+        self.bus.as_ref().unwrap().borrow_mut().io_write(port, data);
+    }
+
     pub(crate) fn bus_share(&self) -> Rc<RefCell<T>> {
         self.bus.as_ref().unwrap().clone()
     }
@@ -1808,7 +1808,26 @@ impl<T> Instruction<T> for IN
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    // IN A,(n) puts A on the upper half of the port address, it does not change the flags
+    // IN r,(C) reads the port BC, IN (C) only sets the flags
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        let src_ptr = operands.last().unwrap();
+        if cpu.current_opcode == 0xDB {
+            let a = cpu.register_set.read_register(Register::General(RegisterType::A), Size::Byte);
+            let port = a << 8 | src_ptr.address.unwrap();
+            let data = cpu.io_read(port);
+            operands[0].write(data as u16).unwrap();
+            return;
+        }
+        let data = cpu.io_read(src_ptr.address.unwrap());
+        if operands.len() == 2 {
+            operands[0].write(data as u16).unwrap();
+        }
+        cpu.register_set.set_flag(Status::S, data & 0x80 != 0);
+        cpu.register_set.set_flag(Status::Z, data == 0);
+        cpu.register_set.set_flag(Status::H, false);
+        cpu.register_set.set_flag(Status::PV, data.count_ones().is_multiple_of(2));
+        cpu.register_set.set_flag(Status::N, false);
     }
 }
 
@@ -1818,13 +1837,61 @@ impl Display for IN {
     }
 }
 
+// INI and IND: the port BC is read to (HL), then B is decremented and HL is moved by the step
+fn block_in<T>(cpu: &mut Z80<T>, step: i16)
+where
+    T: 'static + BusZ80,
+{
+    let bc = cpu.register_set.read_register(Register::General(RegisterType::BC), Size::Word);
+    let hl = cpu.register_set.read_register(Register::General(RegisterType::HL), Size::Word);
+    let data = cpu.io_read(bc);
+    cpu.bus_share().borrow_mut().write(data as u16, hl, Size::Byte.into()).unwrap();
+    cpu.register_set.write_register(hl.wrapping_add_signed(step), Register::General(RegisterType::HL), Size::Word);
+    cpu.register_set.write_register((bc >> 8).wrapping_sub(1), Register::General(RegisterType::B), Size::Byte);
+    // the carry of the data and the incremented or decremented C
+    let k = data as u16 + (bc as u8).wrapping_add_signed(step as i8) as u16;
+    set_block_io_flags(cpu, data, k);
+}
+
+// OUTI and OUTD: B is decremented before (HL) is written to the port BC, then HL is moved by the step
+fn block_out<T>(cpu: &mut Z80<T>, step: i16)
+where
+    T: 'static + BusZ80,
+{
+    let b = cpu.register_set.read_register(Register::General(RegisterType::B), Size::Byte).wrapping_sub(1);
+    cpu.register_set.write_register(b, Register::General(RegisterType::B), Size::Byte);
+    let bc = cpu.register_set.read_register(Register::General(RegisterType::BC), Size::Word);
+    let hl = cpu.register_set.read_register(Register::General(RegisterType::HL), Size::Word);
+    let data = cpu.bus_share().borrow().read(hl, Size::Byte.into()).unwrap() as u8;
+    cpu.io_write(bc, data);
+    let hl = hl.wrapping_add_signed(step);
+    cpu.register_set.write_register(hl, Register::General(RegisterType::HL), Size::Word);
+    // the carry of the data and L after the move
+    let k = data as u16 + (hl & 0xFF);
+    set_block_io_flags(cpu, data, k);
+}
+
+fn set_block_io_flags<T>(cpu: &mut Z80<T>, data: u8, k: u16)
+where
+    T: 'static + BusZ80,
+{
+    let b = cpu.register_set.read_register(Register::General(RegisterType::B), Size::Byte) as u8;
+    cpu.register_set.set_flag(Status::S, b & 0x80 != 0);
+    cpu.register_set.set_flag(Status::Z, b == 0);
+    cpu.register_set.set_flag(Status::H, k > 0xFF);
+    cpu.register_set.set_flag(Status::C, k > 0xFF);
+    cpu.register_set.set_flag(Status::PV, ((k as u8 & 0x07) ^ b).count_ones().is_multiple_of(2));
+    cpu.register_set.set_flag(Status::N, data & 0x80 != 0);
+}
+
 pub(crate) struct INI();
 
 impl<T> Instruction<T> for INI
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, _: Vec<Operand>) {
+        block_in(cpu, 1);
     }
 }
 
@@ -1840,7 +1907,15 @@ impl<T> Instruction<T> for INIR
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        INI().execute(cpu, operands);
+
+        let b = cpu
+            .register_set
+            .read_register(Register::General(RegisterType::B), Size::Byte);
+        if b != 0 {
+            cpu.program_counter = cpu.program_counter.wrapping_sub(2);
+        }
     }
 }
 
@@ -1856,7 +1931,8 @@ impl<T> Instruction<T> for IND
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, _: Vec<Operand>) {
+        block_in(cpu, -1);
     }
 }
 
@@ -1872,7 +1948,15 @@ impl<T> Instruction<T> for INDR
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        IND().execute(cpu, operands);
+
+        let b = cpu
+            .register_set
+            .read_register(Register::General(RegisterType::B), Size::Byte);
+        if b != 0 {
+            cpu.program_counter = cpu.program_counter.wrapping_sub(2);
+        }
     }
 }
 
@@ -1888,7 +1972,20 @@ impl<T> Instruction<T> for OUT
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    // OUT (n),A puts A on the upper half of the port address
+    // OUT (C),r writes to the port BC, OUT (C),0 has no source operand
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        let dst_ptr = &operands[0];
+        let data = match operands.get(1) {
+            Some(src_ptr) => src_ptr.read().unwrap(),
+            None => 0,
+        };
+        let port = if cpu.current_opcode == 0xD3 {
+            data << 8 | dst_ptr.address.unwrap()
+        } else {
+            dst_ptr.address.unwrap()
+        };
+        cpu.io_write(port, data as u8);
     }
 }
 
@@ -1904,7 +2001,8 @@ impl<T> Instruction<T> for OUTI
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, _: Vec<Operand>) {
+        block_out(cpu, 1);
     }
 }
 
@@ -1920,7 +2018,15 @@ impl<T> Instruction<T> for OTIR
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        OUTI().execute(cpu, operands);
+
+        let b = cpu
+            .register_set
+            .read_register(Register::General(RegisterType::B), Size::Byte);
+        if b != 0 {
+            cpu.program_counter = cpu.program_counter.wrapping_sub(2);
+        }
     }
 }
 
@@ -1936,7 +2042,8 @@ impl<T> Instruction<T> for OUTD
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, _: Vec<Operand>) {
+        block_out(cpu, -1);
     }
 }
 
@@ -1952,7 +2059,15 @@ impl<T> Instruction<T> for OTDR
 where
     T: 'static + BusZ80,
 {
-    fn execute(&self, _: &mut Z80<T>, _: Vec<Operand>) {
+    fn execute(&self, cpu: &mut Z80<T>, operands: Vec<Operand>) {
+        OUTD().execute(cpu, operands);
+
+        let b = cpu
+            .register_set
+            .read_register(Register::General(RegisterType::B), Size::Byte);
+        if b != 0 {
+            cpu.program_counter = cpu.program_counter.wrapping_sub(2);
+        }
     }
 }
 
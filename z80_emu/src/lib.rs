@@ -7,6 +7,7 @@ mod opcode_table_generator;
 mod operation;
 mod primitives;
 mod register_set;
+mod timing;
 
 #[derive(Clone, Copy)]
 pub(crate) enum Size {
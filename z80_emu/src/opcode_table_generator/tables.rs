@@ -27,10 +27,10 @@ pub(crate) fn ed_opcode_table<T>() -> [Operation<T>; 0x100] where T: 'static + B
         op!(0x10: b!(XEP)),                     op!(0x11: b!(XEP)),                      op!(0x12: b!(XEP)),                      op!(0x13: b!(XEP)),                     op!(0x14: b!(XEP)),            op!(0x15: b!(XEP)),     op!(0x16: b!(XEP)),                     op!(0x17: b!(XEP)),                  op!(0x18: b!(XEP)),                      op!(0x19: b!(XEP)),                       op!(0x1A: b!(XEP)),                      op!(0x1B: b!(XEP)),                    op!(0x1C: b!(XEP)),    op!(0x1D: b!(XEP)),     op!(0x1E: b!(XEP)),                      op!(0x1F: b!(XEP)),
         op!(0x20: b!(XEP)),                     op!(0x21: b!(XEP)),                      op!(0x22: b!(XEP)),                      op!(0x23: b!(XEP)),                     op!(0x24: b!(XEP)),            op!(0x25: b!(XEP)),     op!(0x26: b!(XEP)),                     op!(0x27: b!(XEP)),                  op!(0x28: b!(XEP)),                      op!(0x29: b!(XEP)),                       op!(0x2A: b!(XEP)),                      op!(0x2B: b!(XEP)),                    op!(0x2C: b!(XEP)),    op!(0x2D: b!(XEP)),     op!(0x2E: b!(XEP)),                      op!(0x2F: b!(XEP)),
         op!(0x30: b!(XEP)),                     op!(0x31: b!(XEP)),                      op!(0x32: b!(XEP)),                      op!(0x33: b!(XEP)),                     op!(0x34: b!(XEP)),            op!(0x35: b!(XEP)),     op!(0x36: b!(XEP)),                     op!(0x37: b!(XEP)),                  op!(0x38: b!(XEP)),                      op!(0x39: b!(XEP)),                       op!(0x3A: b!(XEP)),                      op!(0x3B: b!(XEP)),                    op!(0x3C: b!(XEP)),    op!(0x3D: b!(XEP)),     op!(0x3E: b!(XEP)),                      op!(0x3F: b!(XEP)),
-        op!(0x40: b!(IN), rg!(B), rg!(C.b)),    op!(0x41: b!(OUT), rg!(C.b), rg!(B)),    op!(0x42: b!(SBC), rg!(HL), rg!(BC)),    op!(0x43: b!(LD), am!(RR.w), rg!(BC)),  op!(0x44: b!(NEG), rg!(A)),    op!(0x45: b!(RETN)),    op!(0x46: b!(IM 0)),                    op!(0x47: b!(LD), ri!(), rg!(A)),    op!(0x48: b!(IN), rg!(C), rg!(BC.b)),    op!(0x49: b!(OUT), rg!(BC.b), rg!(C)),    op!(0x4A: b!(ADC), rg!(HL), rg!(BC)),    op!(0x4B: b!(LD), rg!(BC), am!(RR.w)), op!(0x4C: b!(XEP)),    op!(0x4D: b!(RETI)),    op!(0x4E: b!(LD), rg!(C), rx!(X.b)),     op!(0x4F: b!(LD), rr!(), rg!(A)),
-        op!(0x50: b!(IN), rg!(D), rg!(C.b)),    op!(0x51: b!(OUT), rg!(C.b), rg!(D)),    op!(0x52: b!(SBC), rg!(HL), rg!(DE)),    op!(0x53: b!(LD), am!(RR.w), rg!(DE)),  op!(0x54: b!(XEP)),            op!(0x55: b!(XEP)),     op!(0x56: b!(IM 1)),                    op!(0x57: b!(LD), rg!(A), ri!()),    op!(0x58: b!(IN), rg!(E), rg!(BC.b)),    op!(0x59: b!(OUT), rg!(BC.b), rg!(E)),    op!(0x5A: b!(ADC), rg!(HL), rg!(DE)),    op!(0x5B: b!(LD), rg!(DE), am!(RR.w)), op!(0x5C: b!(XEP)),    op!(0x5D: b!(XEP)),     op!(0x5E: b!(IM 2)),                     op!(0x5F: b!(LD), rg!(A), rr!()),
-        op!(0x60: b!(IN), rg!(H), rg!(C.b)),    op!(0x61: b!(OUT), rg!(C.b), rg!(H)),    op!(0x62: b!(SBC), rg!(HL), rg!(HL)),    op!(0x63: b!(XEP)),                     op!(0x64: b!(XEP)),            op!(0x65: b!(XEP)),     op!(0x66: b!(LD), rg!(H), rx!(X.b)),    op!(0x67: b!(RRD), rg!(HL.b)),       op!(0x68: b!(IN), rg!(L), rg!(BC.b)),    op!(0x69: b!(OUT), rg!(BC.b), rg!(L)),    op!(0x6A: b!(ADC), rg!(HL), rg!(HL)),    op!(0x6B: b!(XEP)),                    op!(0x6C: b!(XEP)),    op!(0x6D: b!(XEP)),     op!(0x6E: b!(LD), rg!(L), rx!(X.b)),     op!(0x6F: b!(RLD), rg!(HL.b)),
-        op!(0x70: b!(XEP)),                     op!(0x71: b!(XEP)),                      op!(0x72: b!(SBC), rg!(HL), sp!()),      op!(0x73: b!(LD), am!(RR.w), sp!()),    op!(0x74: b!(XEP)),            op!(0x75: b!(XEP)),     op!(0x76: b!(XEP)),                     op!(0x77: b!(XEP)),                  op!(0x78: b!(IN), rg!(A), rg!(BC.b)),    op!(0x79: b!(OUT), rg!(BC.b), rg!(A)),    op!(0x7A: b!(ADC), rg!(HL), sp!()),      op!(0x7B: b!(LD), sp!(), am!(RR.w)),   op!(0x7C: b!(XEP)),    op!(0x7D: b!(XEP)),     op!(0x7E: b!(XEP)),                      op!(0x7F: b!(XEP)),
+        op!(0x40: b!(IN), rg!(B), rg!(BC.b)),    op!(0x41: b!(OUT), rg!(BC.b), rg!(B)),    op!(0x42: b!(SBC), rg!(HL), rg!(BC)),    op!(0x43: b!(LD), am!(RR.w), rg!(BC)),  op!(0x44: b!(NEG), rg!(A)),    op!(0x45: b!(RETN)),    op!(0x46: b!(IM 0)),                    op!(0x47: b!(LD), ri!(), rg!(A)),    op!(0x48: b!(IN), rg!(C), rg!(BC.b)),    op!(0x49: b!(OUT), rg!(BC.b), rg!(C)),    op!(0x4A: b!(ADC), rg!(HL), rg!(BC)),    op!(0x4B: b!(LD), rg!(BC), am!(RR.w)), op!(0x4C: b!(XEP)),    op!(0x4D: b!(RETI)),    op!(0x4E: b!(LD), rg!(C), rx!(X.b)),     op!(0x4F: b!(LD), rr!(), rg!(A)),
+        op!(0x50: b!(IN), rg!(D), rg!(BC.b)),    op!(0x51: b!(OUT), rg!(BC.b), rg!(D)),    op!(0x52: b!(SBC), rg!(HL), rg!(DE)),    op!(0x53: b!(LD), am!(RR.w), rg!(DE)),  op!(0x54: b!(XEP)),            op!(0x55: b!(XEP)),     op!(0x56: b!(IM 1)),                    op!(0x57: b!(LD), rg!(A), ri!()),    op!(0x58: b!(IN), rg!(E), rg!(BC.b)),    op!(0x59: b!(OUT), rg!(BC.b), rg!(E)),    op!(0x5A: b!(ADC), rg!(HL), rg!(DE)),    op!(0x5B: b!(LD), rg!(DE), am!(RR.w)), op!(0x5C: b!(XEP)),    op!(0x5D: b!(XEP)),     op!(0x5E: b!(IM 2)),                     op!(0x5F: b!(LD), rg!(A), rr!()),
+        op!(0x60: b!(IN), rg!(H), rg!(BC.b)),    op!(0x61: b!(OUT), rg!(BC.b), rg!(H)),    op!(0x62: b!(SBC), rg!(HL), rg!(HL)),    op!(0x63: b!(XEP)),                     op!(0x64: b!(XEP)),            op!(0x65: b!(XEP)),     op!(0x66: b!(LD), rg!(H), rx!(X.b)),    op!(0x67: b!(RRD), rg!(HL.b)),       op!(0x68: b!(IN), rg!(L), rg!(BC.b)),    op!(0x69: b!(OUT), rg!(BC.b), rg!(L)),    op!(0x6A: b!(ADC), rg!(HL), rg!(HL)),    op!(0x6B: b!(XEP)),                    op!(0x6C: b!(XEP)),    op!(0x6D: b!(XEP)),     op!(0x6E: b!(LD), rg!(L), rx!(X.b)),     op!(0x6F: b!(RLD), rg!(HL.b)),
+        op!(0x70: b!(IN), rg!(BC.b)),           op!(0x71: b!(OUT), rg!(BC.b)),           op!(0x72: b!(SBC), rg!(HL), sp!()),      op!(0x73: b!(LD), am!(RR.w), sp!()),    op!(0x74: b!(XEP)),            op!(0x75: b!(XEP)),     op!(0x76: b!(XEP)),                     op!(0x77: b!(XEP)),                  op!(0x78: b!(IN), rg!(A), rg!(BC.b)),    op!(0x79: b!(OUT), rg!(BC.b), rg!(A)),    op!(0x7A: b!(ADC), rg!(HL), sp!()),      op!(0x7B: b!(LD), sp!(), am!(RR.w)),   op!(0x7C: b!(XEP)),    op!(0x7D: b!(XEP)),     op!(0x7E: b!(XEP)),                      op!(0x7F: b!(XEP)),
         op!(0x80: b!(XEP)),                     op!(0x81: b!(XEP)),                      op!(0x82: b!(XEP)),                      op!(0x83: b!(XEP)),                     op!(0x84: b!(XEP)),            op!(0x85: b!(XEP)),     op!(0x86: b!(XEP)),                     op!(0x87: b!(XEP)),                  op!(0x88: b!(XEP)),                      op!(0x89: b!(XEP)),                       op!(0x8A: b!(XEP)),                      op!(0x8B: b!(XEP)),                    op!(0x8C: b!(XEP)),    op!(0x8D: b!(XEP)),     op!(0x8E: b!(XEP)),                      op!(0x8F: b!(XEP)),
         op!(0x90: b!(XEP)),                     op!(0x91: b!(XEP)),                      op!(0x92: b!(XEP)),                      op!(0x93: b!(XEP)),                     op!(0x94: b!(XEP)),            op!(0x95: b!(XEP)),     op!(0x96: b!(XEP)),                     op!(0x97: b!(XEP)),                  op!(0x98: b!(XEP)),                      op!(0x99: b!(XEP)),                       op!(0x9A: b!(XEP)),                      op!(0x9B: b!(XEP)),                    op!(0x9C: b!(XEP)),    op!(0x9D: b!(XEP)),     op!(0x9E: b!(XEP)),                      op!(0x9F: b!(XEP)),
         op!(0xA0: b!(LDI)),                     op!(0xA1: b!(CPI)),                      op!(0xA2: b!(INI)),                      op!(0xA3: b!(OUTI)),                    op!(0xA4: b!(XEP)),            op!(0xA5: b!(XEP)),     op!(0xA6: b!(XEP)),                     op!(0xA7: b!(XEP)),                  op!(0xA8: b!(LDD)),                      op!(0xA9: b!(CPD)),                       op!(0xAA: b!(IND)),                      op!(0xAB: b!(OUTD)),                   op!(0xAC: b!(XEP)),    op!(0xAD: b!(XEP)),     op!(0xAE: b!(XOR), rg!(A), rx!(X.b)),    op!(0xAF: b!(XEP)),
@@ -172,9 +172,9 @@ pub(crate) enum Status {
 }
 
 pub(crate) struct RegisterSet {
-    registers: [u8; 16],
-    index_register: [u16; 2],
-    stack_pointer: u16,
+    pub(crate) registers: [u8; 16],
+    pub(crate) index_register: [u16; 2],
+    pub(crate) stack_pointer: u16,
     pub(crate) interrupt_vector: u8,
     pub(crate) memory_refresh: u8,
 }
@@ -0,0 +1,95 @@
+// T-states of the instructions, the conditional ones are counted as not taken
+const MAIN_CYCLES: [i32; 0x100] = [
+    4, 10, 7, 6, 4, 4, 7, 4, 4, 11, 7, 6, 4, 4, 7, 4, // 0x00
+    8, 10, 7, 6, 4, 4, 7, 4, 12, 11, 7, 6, 4, 4, 7, 4, // 0x10
+    7, 10, 16, 6, 4, 4, 7, 4, 7, 11, 16, 6, 4, 4, 7, 4, // 0x20
+    7, 10, 13, 6, 11, 11, 10, 4, 7, 11, 13, 6, 4, 4, 7, 4, // 0x30
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0x40
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0x50
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0x60
+    7, 7, 7, 7, 7, 7, 4, 7, 4, 4, 4, 4, 4, 4, 7, 4, // 0x70
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0x80
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0x90
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0xA0
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, // 0xB0
+    5, 10, 10, 10, 10, 11, 7, 11, 5, 10, 10, 0, 10, 17, 7, 11, // 0xC0
+    5, 10, 10, 11, 10, 11, 7, 11, 5, 4, 10, 11, 10, 0, 7, 11, // 0xD0
+    5, 10, 10, 19, 10, 11, 7, 11, 5, 4, 10, 4, 10, 0, 7, 11, // 0xE0
+    5, 10, 10, 4, 10, 11, 7, 11, 5, 6, 10, 4, 10, 0, 7, 11, // 0xF0
+];
+
+// 0x40-0x7F part of the ED table, the rest of them are the block instructions or act as NOP
+const ED_CYCLES: [i32; 0x40] = [
+    12, 12, 15, 20, 8, 14, 8, 9, 12, 12, 15, 20, 8, 14, 8, 9, // 0x40
+    12, 12, 15, 20, 8, 14, 8, 9, 12, 12, 15, 20, 8, 14, 8, 9, // 0x50
+    12, 12, 15, 20, 8, 14, 8, 18, 12, 12, 15, 20, 8, 14, 8, 18, // 0x60
+    12, 12, 15, 20, 8, 14, 8, 8, 12, 12, 15, 20, 8, 14, 8, 8, // 0x70
+];
+
+pub(crate) fn instruction_cycles(opcode: u32) -> i32 {
+    if opcode <= 0xFF {
+        return MAIN_CYCLES[opcode as usize];
+    }
+    if opcode <= 0xFFFF {
+        let prefix = opcode >> 8;
+        let opcode = (opcode & 0xFF) as usize;
+        return match prefix {
+            0xED => ed_cycles(opcode),
+            0xCB => cb_cycles(opcode),
+            0xDD | 0xFD => index_cycles(opcode),
+            _ => panic!("Z80::timing: unexpected opcode prefix {:02X}", prefix),
+        };
+    }
+    // DDCB/FDCB: prefix, 0xCB, displacement and opcode
+    if (opcode & 0xC0) == 0x40 {
+        20 // BIT n,(ix+d)
+    } else {
+        23
+    }
+}
+
+// additional T-states when the conditional jump/call/return or the repeating block instruction is taken
+pub(crate) fn taken_cycles(opcode: u32) -> i32 {
+    match opcode {
+        0x10 | 0x20 | 0x28 | 0x30 | 0x38 => 5, // DJNZ, JR cc
+        0xC4 | 0xCC | 0xD4 | 0xDC | 0xE4 | 0xEC | 0xF4 | 0xFC => 7, // CALL cc
+        0xC0 | 0xC8 | 0xD0 | 0xD8 | 0xE0 | 0xE8 | 0xF0 | 0xF8 => 6, // RET cc
+        0xEDB0..=0xEDB3 | 0xEDB8..=0xEDBB => 5, // block repeat
+        _ => 0,
+    }
+}
+
+fn ed_cycles(opcode: usize) -> i32 {
+    match opcode {
+        0x40..=0x7F => ED_CYCLES[opcode - 0x40],
+        0xA0..=0xA3 | 0xA8..=0xAB | 0xB0..=0xB3 | 0xB8..=0xBB => 16,
+        _ => 8,
+    }
+}
+
+fn cb_cycles(opcode: usize) -> i32 {
+    if opcode & 0x07 != 0x06 {
+        8
+    } else if (opcode & 0xC0) == 0x40 {
+        12 // BIT n,(hl)
+    } else {
+        15
+    }
+}
+
+// the index prefix adds 4 T-states, the (ix+d) operand replaces (hl) one and needs the displacement calculation
+fn index_cycles(opcode: usize) -> i32 {
+    let hl_indirect = match opcode {
+        0x34 | 0x35 => return 23,
+        0x36 => return 19,
+        0x76 => false,
+        0x70..=0x77 => true,
+        0x40..=0xBF => opcode & 0x07 == 0x06,
+        _ => false,
+    };
+    if hl_indirect {
+        19
+    } else {
+        MAIN_CYCLES[opcode] + 4
+    }
+}
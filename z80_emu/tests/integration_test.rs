@@ -69,3 +69,139 @@ fn cpu_running() {
         cpu.clock();
     }
 }
+
+// a flat ram with the port writes recorded and the reads answered with the upper half of the port
+struct IoBus {
+    ram: Vec<u8>,
+    port_writes: Vec<(u16, u8)>,
+}
+
+impl BusZ80 for IoBus {
+    fn read(&self, address: u16, amount: usize) -> Result<u16, ()> {
+        let low = self.ram[address as usize] as u16;
+        match amount {
+            1 => Ok(low),
+            2 => Ok(low | (self.ram[address.wrapping_add(1) as usize] as u16) << 8),
+            _ => panic!("IoBus: read: wrong size"),
+        }
+    }
+
+    fn write(&mut self, data: u16, address: u16, amount: usize) -> Result<(), ()> {
+        for i in 0..amount as u16 {
+            self.ram[address.wrapping_add(i) as usize] = (data >> (i * 8)) as u8;
+        }
+        Ok(())
+    }
+
+    fn io_read(&mut self, port: u16) -> u8 {
+        (port >> 8) as u8
+    }
+
+    fn io_write(&mut self, port: u16, data: u8) {
+        self.port_writes.push((port, data));
+    }
+}
+
+#[test]
+fn io_instructions_use_the_port_bus() {
+    let program = [
+        0x01, 0xA0, 0x03, // LD BC,0x03A0
+        0x21, 0x00, 0x80, // LD HL,0x8000
+        0xED, 0xB3, // OTIR
+        0x01, 0xB0, 0x02, // LD BC,0x02B0
+        0x21, 0x00, 0x90, // LD HL,0x9000
+        0xED, 0xB2, // INIR
+        0x01, 0x10, 0x81, // LD BC,0x8110
+        0xED, 0x50, // IN D,(C)
+        0xED, 0x51, // OUT (C),D
+        0x3E, 0x12, // LD A,0x12
+        0xD3, 0x34, // OUT (0x34),A
+    ];
+    let mut ram = vec![0; 0x10000];
+    ram[..program.len()].copy_from_slice(&program);
+    ram[0x8000..0x8003].copy_from_slice(&[0x11, 0x22, 0x33]);
+    let bus = Rc::new(RefCell::new(IoBus { ram, port_writes: vec![] }));
+    let mut cpu = Z80::new();
+    cpu.set_bus(bus.clone());
+    cpu.restart();
+    while (cpu.program_counter as usize) < program.len() {
+        cpu.clock();
+    }
+
+    // OTIR decrements B before the port is put on the bus
+    assert_eq!(
+        bus.borrow().port_writes,
+        vec![(0x02A0, 0x11), (0x01A0, 0x22), (0x00A0, 0x33), (0x8110, 0x81), (0x1234, 0x12)]
+    );
+    // INIR reads the ports with B before the decrement
+    assert_eq!(bus.borrow().ram[0x9000..0x9002], [0x02, 0x01]);
+
+    // IN D,(C): S and PV (even parity) are set, Z, H and N are cleared
+    let state = cpu.state();
+    assert_eq!(state.registers[5], 0x81);
+    assert_eq!(state.registers[0] & 0xD6, 0x84);
+}
+
+#[test]
+fn block_io_and_port_c_instructions_set_flags() {
+    let program = [
+        0x21, 0xF0, 0x80, // LD HL,0x80F0
+        0x01, 0x10, 0x05, // LD BC,0x0510
+        0xED, 0xA3, // OUTI
+        0x21, 0x01, 0x90, // LD HL,0x9001
+        0x01, 0x20, 0x83, // LD BC,0x8320
+        0xED, 0xAB, // OUTD
+        0x21, 0x00, 0xA0, // LD HL,0xA000
+        0x01, 0xF0, 0x90, // LD BC,0x90F0
+        0xED, 0xA2, // INI
+        0x21, 0x10, 0xA0, // LD HL,0xA010
+        0x01, 0x00, 0x01, // LD BC,0x0100
+        0xED, 0xAA, // IND
+        0x21, 0x00, 0xB0, // LD HL,0xB000
+        0x01, 0x80, 0x02, // LD BC,0x0280
+        0xED, 0xB2, // INIR
+        0x21, 0x00, 0xB1, // LD HL,0xB100
+        0x01, 0xC0, 0x02, // LD BC,0x02C0
+        0xED, 0xB3, // OTIR
+        0x37, // SCF
+        0x01, 0x10, 0x00, // LD BC,0x0010
+        0xED, 0x70, // IN (C)
+        0x01, 0x34, 0x12, // LD BC,0x1234
+        0xED, 0x71, // OUT (C),0
+    ];
+    let mut ram = vec![0; 0x10000];
+    ram[..program.len()].copy_from_slice(&program);
+    ram[0x80F0] = 0x81;
+    ram[0x9001] = 0x7F;
+    ram[0xB100..0xB102].copy_from_slice(&[0xFF, 0x80]);
+    let bus = Rc::new(RefCell::new(IoBus { ram, port_writes: vec![] }));
+    let mut cpu = Z80::new();
+    cpu.set_bus(bus.clone());
+    cpu.restart();
+
+    // the flags S Z H PV N C after the instruction that ends at the address
+    let expected_flags = [
+        (0x08, 0x17), // OUTI: L + data carries, PV from (k & 7) ^ B, N from the data bit 7
+        (0x10, 0x80), // OUTD: L of the decremented HL, no carry
+        (0x18, 0x97), // INI: the data + C + 1 carries
+        (0x20, 0x55), // IND: the data + C - 1 carries, B is 0
+        (0x28, 0x40), // INIR: the flags of the last step
+        (0x30, 0x42), // OTIR
+        (0x36, 0x45), // IN (C): zero with the even parity, the carry is kept
+        (0x3B, 0x45), // OUT (C),0 keeps the flags
+    ];
+    for (address, flags) in expected_flags {
+        while cpu.program_counter != address {
+            cpu.clock();
+        }
+        assert_eq!(cpu.state().registers[0] & 0xD7, flags, "flags at {:04X}", address);
+    }
+
+    let bus = bus.borrow();
+    assert_eq!(
+        bus.port_writes,
+        vec![(0x0410, 0x81), (0x8220, 0x7F), (0x01C0, 0xFF), (0x00C0, 0x80), (0x1234, 0x00)]
+    );
+    assert_eq!((bus.ram[0xA000], bus.ram[0xA010]), (0x90, 0x01));
+    assert_eq!(bus.ram[0xB000..0xB002], [0x02, 0x01]);
+}